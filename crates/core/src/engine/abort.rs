@@ -0,0 +1,28 @@
+//! Stopping long analyses from another thread
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared "stop now" switch. Clones share the flag: hand one to the
+/// engine or detector doing the work and keep one to call `abort` on.
+#[derive(Debug, Clone, Default)]
+pub struct AbortFlag(Arc<AtomicBool>);
+
+impl AbortFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn abort(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Clears the flag so the next analysis runs normally
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
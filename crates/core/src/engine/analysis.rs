@@ -3,7 +3,7 @@
 use std::fmt;
 
 /// Represents a position evaluation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Evaluation {
     /// Centipawn score (positive = white advantage)
     Centipawns(i32),
@@ -41,6 +41,48 @@ impl Evaluation {
             }
         }
     }
+
+    /// White's chance of winning, 0.0 to 1.0, on the logistic curve
+    /// Lichess fits to centipawns; a mate is 1.0 or 0.0 outright
+    pub fn win_probability(&self) -> f64 {
+        match self {
+            Evaluation::Centipawns(cp) => 1.0 / (1.0 + (-WIN_PROBABILITY_SLOPE * *cp as f64).exp()),
+            Evaluation::Mate(moves) => if *moves > 0 { 1.0 } else { 0.0 },
+        }
+    }
+}
+
+/// Steepness of the centipawn-to-win-probability curve, per centipawn
+const WIN_PROBABILITY_SLOPE: f64 = 0.00368208;
+
+/// An eval curve, as (ply, White's eval) pairs, turned into White's win
+/// probability averaged over `window` points around each ply, for drawing.
+///
+/// A search at analysis depth swings by a pawn or so from one ply to the
+/// next without anything happening on the board; the average irons that
+/// out. Mates don't take part in it: a mate point stays at 1.0 or 0.0,
+/// and its neighbours average only their centipawn evals. Detection should
+/// keep using the raw curve. A `window` under 2 returns the curve unchanged
+/// but for the conversion; an even one counts one point further behind.
+pub fn smooth_eval_curve(curve: &[(u16, Evaluation)], window: usize) -> Vec<(u16, f64)> {
+    let behind = window / 2;
+    let ahead = window.saturating_sub(1) / 2;
+
+    curve
+        .iter()
+        .enumerate()
+        .map(|(i, (ply, eval))| {
+            if matches!(eval, Evaluation::Mate(_)) {
+                return (*ply, eval.win_probability());
+            }
+            let around = &curve[i.saturating_sub(behind)..(i + ahead + 1).min(curve.len())];
+            let (sum, count) = around
+                .iter()
+                .filter(|(_, e)| matches!(e, Evaluation::Centipawns(_)))
+                .fold((0.0, 0u32), |(sum, count), (_, e)| (sum + e.win_probability(), count + 1));
+            (*ply, sum / count as f64)
+        })
+        .collect()
 }
 
 impl fmt::Display for Evaluation {
@@ -54,13 +96,7 @@ impl fmt::Display for Evaluation {
                     write!(f, "{:.2}", score)
                 }
             }
-            Evaluation::Mate(moves) => {
-                if *moves > 0 {
-                    write!(f, "M{}", moves)
-                } else {
-                    write!(f, "M{}", moves) // Already negative
-                }
-            }
+            Evaluation::Mate(moves) => write!(f, "M{}", moves), // Negative when black mates
         }
     }
 }
@@ -80,6 +116,17 @@ pub struct MoveAnalysis {
     pub depth: u8,
 }
 
+/// One move of a line, with the eval of the position it leads to
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinePly {
+    /// The move in UCI notation (e.g., "g1f3")
+    pub uci: String,
+    /// The move in SAN notation (e.g., "Nf3")
+    pub san: String,
+    /// Evaluation after this move, from White's point of view
+    pub evaluation: Evaluation,
+}
+
 /// Complete analysis of a position
 #[derive(Debug, Clone)]
 pub struct PositionAnalysis {
@@ -95,6 +142,9 @@ pub struct PositionAnalysis {
     pub time_ms: u64,
     /// Nodes searched
     pub nodes: u64,
+    /// The reply the engine expects to `best_move`, from the `ponder` token
+    /// of its `bestmove` line; what `StockfishEngine::go_ponder` thinks on
+    pub ponder: Option<String>,
 }
 
 impl PositionAnalysis {
@@ -109,3 +159,47 @@ impl PositionAnalysis {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_win_probability() {
+        assert_eq!(Evaluation::Centipawns(0).win_probability(), 0.5);
+        let up = Evaluation::Centipawns(300).win_probability();
+        assert!(up > 0.7 && up < 0.8);
+        assert!((Evaluation::Centipawns(-300).win_probability() - (1.0 - up)).abs() < 1e-12);
+        assert_eq!(Evaluation::Mate(3).win_probability(), 1.0);
+        assert_eq!(Evaluation::Mate(-1).win_probability(), 0.0);
+    }
+
+    #[test]
+    fn test_smoothing_a_noisy_curve() {
+        // Level at +50, the search flipping the sign every other ply, then
+        // White finds a mate
+        let mut curve: Vec<(u16, Evaluation)> = (0..8)
+            .map(|ply| (ply, Evaluation::Centipawns(if ply % 2 == 0 { 150 } else { -50 })))
+            .collect();
+        curve.push((8, Evaluation::Mate(2)));
+        curve.push((9, Evaluation::Mate(1)));
+
+        let raw = smooth_eval_curve(&curve, 1);
+        assert_eq!(raw.len(), curve.len());
+        assert_eq!(raw[0], (0, Evaluation::Centipawns(150).win_probability()));
+
+        let smooth = smooth_eval_curve(&curve, 3);
+        let plies: Vec<u16> = smooth.iter().map(|(ply, _)| *ply).collect();
+        assert_eq!(plies, (0..10).collect::<Vec<_>>());
+        let swing = |points: &[(u16, f64)]| {
+            points.windows(2).take(7).map(|w| (w[1].1 - w[0].1).abs()).fold(0.0, f64::max)
+        };
+        assert!(swing(&smooth) < swing(&raw) / 2.0);
+        assert!(smooth[1..7].iter().all(|(_, p)| *p > 0.5 && *p < 0.6));
+
+        // The mates stay pinned, and the ply before them isn't dragged up
+        assert_eq!(smooth[8].1, 1.0);
+        assert_eq!(smooth[9].1, 1.0);
+        assert!(smooth[7].1 < 0.6);
+    }
+}
@@ -0,0 +1,82 @@
+//! The engine interface analysis is written against
+//!
+//! `PatternDetector` and the search helpers here only ever set a position
+//! and search it, so anything that can do that can stand in for Stockfish:
+//! another UCI engine such as Lc0, an HTTP analysis service, or a scripted
+//! engine in tests. Implement `ChessEngine` and hand it to
+//! `PatternDetector::with_chess_engine`.
+
+use shakmaty::{fen::Fen, san::SanPlus, uci::UciMove, Color, EnPassantMode, Position};
+
+use super::abort::AbortFlag;
+use super::analysis::{Evaluation, LinePly, PositionAnalysis};
+use super::stockfish::EngineError;
+use crate::util::parse_fen_lenient;
+
+/// A chess engine that can search a position.
+///
+/// Scores in every `PositionAnalysis` are from the point of view of the
+/// side to move, as UCI reports them, and moves are in UCI notation.
+pub trait ChessEngine {
+    /// Sets the position the next search runs on: `fen`, or the starting
+    /// position when `None`, followed by `moves` in UCI
+    fn set_position(&mut self, fen: Option<&str>, moves: Option<&[String]>) -> Result<(), EngineError>;
+
+    /// Searches the current position to `depth`
+    fn analyze(&mut self, depth: u8) -> Result<PositionAnalysis, EngineError>;
+
+    /// The `lines` best moves in the current position, best first, each
+    /// with its first PV move as `best_move`; fewer when the position has
+    /// fewer legal moves
+    fn analyze_multipv(&mut self, depth: u8, lines: u8) -> Result<Vec<PositionAnalysis>, EngineError>;
+
+    /// Lets `flag` cut a search short with `EngineError::Aborted`. Engines
+    /// that can't be interrupted mid-search may ignore it; the detector
+    /// still checks the flag between moves.
+    fn set_abort_flag(&mut self, _flag: AbortFlag) {}
+
+    /// Lowers the OS scheduling priority of the engine's work, if it runs
+    /// locally; `nice` follows Unix niceness
+    fn set_priority(&self, _nice: i32) -> Result<(), EngineError> {
+        Err(EngineError::ProtocolError("this engine has no process priority to set".into()))
+    }
+}
+
+/// The best move in `fen` and up to `length` plies of its continuation,
+/// in SAN, each with the eval of the position it leads to.
+///
+/// The line is the principal variation of one search at `depth`; every
+/// position along it is then searched at `depth` again for its eval, so
+/// the whole line costs `length + 2` searches. Evals are turned around
+/// to White's point of view, since UCI scores are for the side to move.
+pub fn best_line_san(
+    engine: &mut dyn ChessEngine,
+    fen: &str,
+    depth: u8,
+    length: usize,
+) -> Result<Vec<LinePly>, EngineError> {
+    let mut position = parse_fen_lenient(fen)
+        .map_err(|e| EngineError::InvalidInput(e.to_string()))?;
+    let start = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+    engine.set_position(Some(&start), None)?;
+    let pv = engine.analyze(depth)?.pv;
+
+    let mut line = Vec::new();
+    for uci in pv.iter().take(length + 1) {
+        let mv = match uci.parse::<UciMove>().ok().and_then(|u| u.to_move(&position).ok()) {
+            Some(m) => m,
+            None => break,
+        };
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, mv).to_string();
+
+        let fen = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+        engine.set_position(Some(&fen), None)?;
+        let evaluation = match (engine.analyze(depth)?.evaluation, position.turn()) {
+            (evaluation, Color::White) => evaluation,
+            (Evaluation::Centipawns(cp), Color::Black) => Evaluation::Centipawns(-cp),
+            (Evaluation::Mate(m), Color::Black) => Evaluation::Mate(-m),
+        };
+        line.push(LinePly { uci: uci.clone(), san, evaluation });
+    }
+    Ok(line)
+}
@@ -1,10 +1,17 @@
 //! Chess engine integration
 //! 
-//! Provides interface to UCI-compatible engines like Stockfish.
+//! Provides interface to UCI-compatible engines like Stockfish. Other
+//! engines plug in through the `ChessEngine` trait.
 
+pub mod abort;
 pub mod analysis;
+pub mod chess_engine;
+pub mod options;
 pub mod stockfish;
 
 // Re-export main types for convenience
-pub use analysis::{Evaluation, MoveAnalysis, PositionAnalysis};
-pub use stockfish::{EngineError, StockfishEngine};
+pub use abort::AbortFlag;
+pub use analysis::{smooth_eval_curve, Evaluation, LinePly, MoveAnalysis, PositionAnalysis};
+pub use chess_engine::{best_line_san, ChessEngine};
+pub use options::{EngineOption, OptionKind};
+pub use stockfish::{engine_args, engine_path, EngineError, StockfishEngine};
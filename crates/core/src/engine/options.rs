@@ -0,0 +1,147 @@
+//! UCI options an engine says it supports
+//!
+//! In reply to `uci` an engine lists its settings before `uciok`, one
+//! `option name <name> type <type> ...` line each, with the default and,
+//! for numbers and choices, what a value may be.
+
+use serde::Serialize;
+
+/// One setting from the engine's `option` lines
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EngineOption {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: OptionKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OptionKind {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    Combo { default: String, choices: Vec<String> },
+    /// An action such as "Clear Hash", sent without a value
+    Button,
+    String { default: String },
+}
+
+/// Words that start a field of an `option` line
+const KEYWORDS: [&str; 6] = ["name", "type", "default", "min", "max", "var"];
+
+impl EngineOption {
+    /// Reads one `option ...` line; `None` for other lines and for options
+    /// of an unknown type or missing the values their type needs
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("option") {
+            return None;
+        }
+
+        // Names and string defaults may contain spaces, so each field runs
+        // up to the next keyword
+        let mut fields: Vec<(&str, Vec<&str>)> = Vec::new();
+        for token in tokens {
+            match fields.last_mut() {
+                Some((key, words)) if !KEYWORDS.contains(&token) || (*key == "name" && words.is_empty()) => words.push(token),
+                _ if KEYWORDS.contains(&token) => fields.push((token, Vec::new())),
+                _ => return None,
+            }
+        }
+        let field = |key: &str| fields.iter().find(|(k, _)| *k == key).map(|(_, words)| words.join(" "));
+
+        let name = field("name").filter(|n| !n.is_empty())?;
+        let kind = match field("type")?.as_str() {
+            "check" => OptionKind::Check { default: field("default")?.parse().ok()? },
+            "spin" => OptionKind::Spin {
+                default: field("default")?.parse().ok()?,
+                min: field("min")?.parse().ok()?,
+                max: field("max")?.parse().ok()?,
+            },
+            "combo" => OptionKind::Combo {
+                default: field("default")?,
+                choices: fields.iter().filter(|(k, _)| *k == "var").map(|(_, words)| words.join(" ")).collect(),
+            },
+            "button" => OptionKind::Button,
+            // UCI writes an empty default as `<empty>`
+            "string" => OptionKind::String {
+                default: field("default").filter(|d| d != "<empty>").unwrap_or_default(),
+            },
+            _ => return None,
+        };
+        Some(EngineOption { name, kind })
+    }
+
+    /// Checks `value` against the option's type and range, with the reason
+    /// when it doesn't fit
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        match &self.kind {
+            OptionKind::Check { .. } if value == "true" || value == "false" => Ok(()),
+            OptionKind::Check { .. } => Err(format!("{} must be true or false, not '{}'", self.name, value)),
+            OptionKind::Spin { min, max, .. } => match value.parse::<i64>() {
+                Ok(n) if (*min..=*max).contains(&n) => Ok(()),
+                _ => Err(format!("{} must be a whole number from {} to {}, not '{}'", self.name, min, max, value)),
+            },
+            OptionKind::Combo { choices, .. } if choices.iter().any(|c| c.eq_ignore_ascii_case(value)) => Ok(()),
+            OptionKind::Combo { choices, .. } => Err(format!("{} must be one of {}, not '{}'", self.name, choices.join(", "), value)),
+            OptionKind::Button if value.is_empty() => Ok(()),
+            OptionKind::Button => Err(format!("{} is a button and takes no value", self.name)),
+            OptionKind::String { .. } => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stockfish_options() {
+        let hash = EngineOption::parse("option name Hash type spin default 16 min 1 max 33554432").unwrap();
+        assert_eq!(hash.name, "Hash");
+        assert_eq!(hash.kind, OptionKind::Spin { default: 16, min: 1, max: 33554432 });
+
+        let skill = EngineOption::parse("option name Skill Level type spin default 20 min 0 max 20").unwrap();
+        assert_eq!(skill.name, "Skill Level");
+
+        let ponder = EngineOption::parse("option name Ponder type check default false").unwrap();
+        assert_eq!(ponder.kind, OptionKind::Check { default: false });
+
+        let clear = EngineOption::parse("option name Clear Hash type button").unwrap();
+        assert_eq!((clear.name.as_str(), &clear.kind), ("Clear Hash", &OptionKind::Button));
+
+        let network = EngineOption::parse("option name EvalFile type string default nn-1111cefa1111.nnue").unwrap();
+        assert_eq!(network.kind, OptionKind::String { default: "nn-1111cefa1111.nnue".to_string() });
+        let empty = EngineOption::parse("option name Debug Log File type string default <empty>").unwrap();
+        assert_eq!(empty.kind, OptionKind::String { default: String::new() });
+
+        let style = EngineOption::parse("option name Style type combo default Normal var Solid var Normal var Risky").unwrap();
+        assert_eq!(style.kind, OptionKind::Combo {
+            default: "Normal".to_string(),
+            choices: vec!["Solid".to_string(), "Normal".to_string(), "Risky".to_string()],
+        });
+
+        assert_eq!(EngineOption::parse("id name Stockfish 17"), None);
+        assert_eq!(EngineOption::parse("option name Threads type spin default 1"), None);
+        assert_eq!(EngineOption::parse("option name Odd type slider default 3"), None);
+    }
+
+    #[test]
+    fn test_validate_values() {
+        let hash = EngineOption::parse("option name Hash type spin default 16 min 1 max 1024").unwrap();
+        assert!(hash.validate("256").is_ok());
+        assert!(hash.validate("0").is_err());
+        assert!(hash.validate("lots").is_err());
+
+        let ponder = EngineOption::parse("option name Ponder type check default false").unwrap();
+        assert!(ponder.validate("true").is_ok());
+        assert!(ponder.validate("yes").is_err());
+
+        let style = EngineOption::parse("option name Style type combo default Normal var Solid var Normal").unwrap();
+        assert!(style.validate("solid").is_ok());
+        assert_eq!(style.validate("Wild").unwrap_err(), "Style must be one of Solid, Normal, not 'Wild'");
+
+        let clear = EngineOption::parse("option name Clear Hash type button").unwrap();
+        assert!(clear.validate("").is_ok());
+        assert!(clear.validate("1").is_err());
+    }
+}
@@ -4,9 +4,15 @@
 
 use std::io::{BufRead, BufReader, Write};
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use super::analysis::{Evaluation, PositionAnalysis};
+use shakmaty::{fen::Fen, san::SanPlus, uci::UciMove, EnPassantMode};
+
+use super::abort::AbortFlag;
+use super::analysis::{Evaluation, LinePly, PositionAnalysis};
+use super::chess_engine::ChessEngine;
+use super::options::{EngineOption, OptionKind};
+use crate::util::parse_fen_lenient;
 
 /// Error type for engine operations
 #[derive(Debug)]
@@ -19,6 +25,10 @@ pub enum EngineError {
     ProtocolError(String),
     /// Engine not initialized
     NotInitialized,
+    /// A position or move from the caller that could not be used
+    InvalidInput(String),
+    /// The search was cut short through the engine's `AbortFlag`
+    Aborted,
 }
 
 impl std::fmt::Display for EngineError {
@@ -28,6 +38,8 @@ impl std::fmt::Display for EngineError {
             EngineError::IoError(e) => write!(f, "I/O error: {}", e),
             EngineError::ProtocolError(s) => write!(f, "Protocol error: {}", s),
             EngineError::NotInitialized => write!(f, "Engine not initialized"),
+            EngineError::InvalidInput(s) => write!(f, "Invalid input: {}", s),
+            EngineError::Aborted => write!(f, "Search aborted"),
         }
     }
 }
@@ -38,6 +50,70 @@ impl From<std::io::Error> for EngineError {
     }
 }
 
+/// Engine binary to launch: `$STOCKFISH_PATH` if set, otherwise `stockfish` from PATH
+pub fn engine_path() -> String {
+    std::env::var("STOCKFISH_PATH")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "stockfish".to_string())
+}
+
+/// Extra command-line arguments for the engine binary, split from
+/// `$STOCKFISH_ARGS` on whitespace
+pub fn engine_args() -> Vec<String> {
+    std::env::var("STOCKFISH_ARGS")
+        .map(|a| a.split_whitespace().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// How long the engine gets to answer `uci`, and each `isready`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many times `isready` is sent before the handshake is given up on.
+/// Engines loading a large network can be slow to answer the first one.
+const READY_ATTEMPTS: u32 = 3;
+
+/// Largest eval change between depths that `analyze_until_stable` ignores
+const STABLE_EVAL_MARGIN_CP: i32 = 10;
+
+/// Which MultiPV line an `info` line reports a finished iteration of (1 for
+/// the main line), or `None` if it isn't one: it needs a depth and a PV and
+/// mustn't be a bound from an aspiration re-search.
+fn iteration_line(line: &str) -> Option<usize> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let has = |token: &str| parts.contains(&token);
+    if parts.first() != Some(&"info") || !has("depth") || !has("pv") || has("lowerbound") || has("upperbound") {
+        return None;
+    }
+    match parts.iter().position(|&p| p == "multipv") {
+        Some(i) => parts.get(i + 1)?.parse().ok().filter(|&n| n >= 1),
+        None => Some(1),
+    }
+}
+
+/// The move and ponder move of a `bestmove e2e4 ponder e7e5` line
+fn parse_bestmove(line: &str) -> (String, Option<String>) {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let best_move = parts.get(1).map_or_else(String::new, |m| m.to_string());
+    let ponder = match parts.iter().position(|&p| p == "ponder") {
+        Some(i) => parts.get(i + 1).map(|m| m.to_string()),
+        None => None,
+    };
+    (best_move, ponder)
+}
+
+/// Whether an `info` line reports a finished iteration of the main line
+fn is_iteration_result(line: &str) -> bool {
+    iteration_line(line) == Some(1)
+}
+
+fn evals_agree(a: &Evaluation, b: &Evaluation) -> bool {
+    match (a, b) {
+        (Evaluation::Centipawns(a), Evaluation::Centipawns(b)) => (a - b).abs() <= STABLE_EVAL_MARGIN_CP,
+        (a, b) => a == b,
+    }
+}
+
 /// Wrapper around Stockfish chess engine
 pub struct StockfishEngine {
     /// The child process
@@ -48,6 +124,13 @@ pub struct StockfishEngine {
     stdout: BufReader<ChildStdout>,
     /// Whether UCI handshake completed
     initialized: bool,
+    abort: AbortFlag,
+    /// The last `position` command sent, which `go_ponder` extends
+    position: Option<String>,
+    /// A `go ponder` search is running
+    pondering: bool,
+    /// What the engine listed in reply to `uci`
+    options: Vec<EngineOption>,
 }
 
 impl StockfishEngine {
@@ -61,8 +144,27 @@ impl StockfishEngine {
     /// let mut engine = StockfishEngine::new("stockfish")?;
     /// ```
     pub fn new(path: &str) -> Result<Self, EngineError> {
+        Self::new_with_args(path, &[])
+    }
+
+    /// Creates an engine instance, passing `args` on the command line.
+    ///
+    /// Useful for engine builds that take their network file or other
+    /// settings as arguments rather than UCI options.
+    pub fn new_with_args(path: &str, args: &[&str]) -> Result<Self, EngineError> {
+        let mut engine = Self::spawn(path, args)?;
+
+        // Initialize UCI protocol
+        engine.init_uci(HANDSHAKE_TIMEOUT)?;
+
+        Ok(engine)
+    }
+
+    /// Starts the engine process without the UCI handshake
+    fn spawn(path: &str, args: &[&str]) -> Result<Self, EngineError> {
         // Spawn Stockfish process
         let mut process = Command::new(path)
+            .args(args)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::null()) // Ignore stderr
@@ -80,17 +182,42 @@ impl StockfishEngine {
             .take()
             .ok_or_else(|| EngineError::SpawnError("Failed to open stdout".into()))?;
 
-        let mut engine = StockfishEngine {
+        Ok(StockfishEngine {
             process,
             stdin,
             stdout: BufReader::new(stdout),
             initialized: false,
-        };
+            abort: AbortFlag::new(),
+            position: None,
+            pondering: false,
+            options: Vec::new(),
+        })
+    }
 
-        // Initialize UCI protocol
-        engine.init_uci()?;
+    /// Tells the engine the next search is for an unrelated position (UCI
+    /// `ucinewgame`, which clears its hash) and waits until it is ready
+    pub fn new_game(&mut self) -> Result<(), EngineError> {
+        if !self.initialized {
+            return Err(EngineError::NotInitialized);
+        }
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        if !self.read_until_deadline("readyok", Instant::now() + HANDSHAKE_TIMEOUT)? {
+            return Err(EngineError::ProtocolError("not ready after ucinewgame".into()));
+        }
+        Ok(())
+    }
 
-        Ok(engine)
+    /// Whether the engine process is still running
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.process.try_wait(), Ok(None))
+    }
+
+    /// Lets `flag` cut `analyze` short: once it is set, a running search is
+    /// sent `stop` and drained to its `bestmove`, so the engine is ready for
+    /// the next command, and `EngineError::Aborted` is returned.
+    pub fn set_abort_flag(&mut self, flag: AbortFlag) {
+        self.abort = flag;
     }
 
     /// Sends a command to the engine
@@ -103,34 +230,131 @@ impl StockfishEngine {
     /// Reads a line from the engine
     fn read_line(&mut self) -> Result<String, EngineError> {
         let mut line = String::new();
-        self.stdout.read_line(&mut line)?;
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(EngineError::ProtocolError("engine closed its output".into()));
+        }
         Ok(line.trim().to_string())
     }
 
-    /// Reads lines until we get the expected response
-    fn read_until(&mut self, expected: &str) -> Result<Vec<String>, EngineError> {
-        let mut lines = Vec::new();
+    /// Reads lines until one starts with `expected`. Returns `false` if
+    /// `deadline` passes first; without Unix `poll` the wait is unbounded.
+    fn read_until_deadline(&mut self, expected: &str, deadline: Instant) -> Result<bool, EngineError> {
+        self.read_until_deadline_with(expected, deadline, |_| {})
+    }
+
+    /// Like `read_until_deadline`, handing every line before `expected`
+    /// to `on_line`
+    fn read_until_deadline_with(
+        &mut self,
+        expected: &str,
+        deadline: Instant,
+        mut on_line: impl FnMut(&str),
+    ) -> Result<bool, EngineError> {
+        let mut line = Vec::new();
         loop {
-            let line = self.read_line()?;
-            let done = line.starts_with(expected);
-            lines.push(line);
-            if done {
-                break;
+            if self.stdout.buffer().is_empty() && !self.wait_readable(deadline)? {
+                return Ok(false);
+            }
+            let buf = self.stdout.fill_buf()?;
+            if buf.is_empty() {
+                return Err(EngineError::ProtocolError("engine closed its output".into()));
+            }
+
+            let (taken, complete) = match buf.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (buf.len(), false),
+            };
+            line.extend_from_slice(&buf[..taken]);
+            self.stdout.consume(taken);
+
+            if complete {
+                let text = String::from_utf8_lossy(&line);
+                if text.trim().starts_with(expected) {
+                    return Ok(true);
+                }
+                on_line(text.trim());
+                line.clear();
             }
         }
-        Ok(lines)
     }
 
-    /// Initialize UCI protocol
-    fn init_uci(&mut self) -> Result<(), EngineError> {
+    /// Waits for the engine's output to become readable; `false` on timeout
+    #[cfg(unix)]
+    fn wait_readable(&self, deadline: Instant) -> Result<bool, EngineError> {
+        use std::os::unix::io::AsRawFd;
+
+        let mut fd = libc::pollfd { fd: self.stdout.get_ref().as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let timeout_ms = remaining.as_millis().min(i32::MAX as u128) as i32;
+            // SAFETY: `fd` is a single valid pollfd for the duration of the call
+            match unsafe { libc::poll(&mut fd, 1, timeout_ms) } {
+                0 => return Ok(false),
+                n if n > 0 => return Ok(true),
+                _ => {
+                    let err = std::io::Error::last_os_error();
+                    if err.kind() != std::io::ErrorKind::Interrupted {
+                        return Err(EngineError::IoError(err));
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn wait_readable(&self, _deadline: Instant) -> Result<bool, EngineError> {
+        Ok(true)
+    }
+
+    /// Initialize UCI protocol, allowing `timeout` for `uciok` and for each
+    /// of up to `READY_ATTEMPTS` `isready`s
+    fn init_uci(&mut self, timeout: Duration) -> Result<(), EngineError> {
+        let handshake_timeout = || EngineError::ProtocolError("handshake timeout".into());
+
         self.send("uci")?;
-        self.read_until("uciok")?;
+        let mut options = Vec::new();
+        let answered = self.read_until_deadline_with("uciok", Instant::now() + timeout, |line| {
+            options.extend(EngineOption::parse(line));
+        })?;
+        if !answered {
+            return Err(handshake_timeout());
+        }
+        self.options = options;
 
-        self.send("isready")?;
-        self.read_until("readyok")?;
+        for _ in 0..READY_ATTEMPTS {
+            self.send("isready")?;
+            if self.read_until_deadline("readyok", Instant::now() + timeout)? {
+                self.initialized = true;
+                return Ok(());
+            }
+        }
+        Err(handshake_timeout())
+    }
 
-        self.initialized = true;
-        Ok(())
+    /// The options the engine listed during the handshake, in its order
+    pub fn available_options(&self) -> &[EngineOption] {
+        &self.options
+    }
+
+    /// Sets a UCI option after checking it against what the engine listed:
+    /// an option it doesn't have, or a value of the wrong type or outside
+    /// the range, is an `InvalidInput` error and nothing is sent. Names are
+    /// matched ignoring case, as UCI asks; buttons take an empty `value`.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), EngineError> {
+        if !self.initialized {
+            return Err(EngineError::NotInitialized);
+        }
+        let option = self.options
+            .iter()
+            .find(|o| o.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| EngineError::InvalidInput(format!("the engine has no option '{}'", name)))?;
+        option.validate(value).map_err(EngineError::InvalidInput)?;
+
+        let cmd = match option.kind {
+            OptionKind::Button => format!("setoption name {}", option.name),
+            _ => format!("setoption name {} value {}", option.name, value),
+        };
+        self.send(&cmd)
     }
 
     /// Sets a position from a FEN string
@@ -153,7 +377,58 @@ impl StockfishEngine {
             _ => pos_str,
         };
 
+        self.set_position_command(cmd)
+    }
+
+    /// Starts thinking on the opponent's time: plays `ponder_move` (UCI,
+    /// usually the `ponder` of the last analysis) after the position last
+    /// set and sends `go ponder` with a `depth` limit.
+    ///
+    /// The search runs until `ponderhit`, when the opponent did play that
+    /// move and it carries on as a normal search, or `stop_ponder`, when
+    /// they didn't. Nothing else may be sent to the engine in between.
+    pub fn go_ponder(&mut self, ponder_move: &str, depth: u8) -> Result<(), EngineError> {
+        if !self.initialized {
+            return Err(EngineError::NotInitialized);
+        }
+        if self.pondering {
+            return Err(EngineError::ProtocolError("already pondering".into()));
+        }
+        let position = self.position.clone().unwrap_or_else(|| "position startpos".to_string());
+        let separator = if position.contains(" moves ") { " " } else { " moves " };
+        self.set_position_command(format!("{}{}{}", position, separator, ponder_move))?;
+
+        self.send(&format!("go ponder depth {}", depth))?;
+        self.pondering = true;
+        Ok(())
+    }
+
+    /// The opponent played the ponder move: the ponder search becomes the
+    /// real one and its result is returned when it finishes
+    pub fn ponderhit(&mut self) -> Result<PositionAnalysis, EngineError> {
+        if !self.pondering {
+            return Err(EngineError::ProtocolError("not pondering".into()));
+        }
+        self.pondering = false;
+        self.send("ponderhit")?;
+        self.read_search()
+    }
+
+    /// The opponent played something else: ends the ponder search and
+    /// throws its result away. Set the real position before searching again.
+    pub fn stop_ponder(&mut self) -> Result<(), EngineError> {
+        if !self.pondering {
+            return Err(EngineError::ProtocolError("not pondering".into()));
+        }
+        self.pondering = false;
+        self.send("stop")?;
+        while !self.read_line()?.starts_with("bestmove") {}
+        Ok(())
+    }
+
+    fn set_position_command(&mut self, cmd: String) -> Result<(), EngineError> {
         self.send(&cmd)?;
+        self.position = Some(cmd);
         Ok(())
     }
 
@@ -163,15 +438,26 @@ impl StockfishEngine {
     /// * `depth` - How many moves ahead to search
     ///
     /// # Returns
-    /// Analysis results including best move and evaluation
+    /// Analysis results including best move and evaluation, or
+    /// `EngineError::Aborted` if the abort flag was set before or during
+    /// the search
     pub fn analyze(&mut self, depth: u8) -> Result<PositionAnalysis, EngineError> {
         if !self.initialized {
             return Err(EngineError::NotInitialized);
         }
+        if self.abort.is_aborted() {
+            return Err(EngineError::Aborted);
+        }
 
         self.send(&format!("go depth {}", depth))?;
+        self.read_search()
+    }
+
+    /// Reads a search's output up to its `bestmove`, sending `stop` if the
+    /// abort flag is set on the way
+    fn read_search(&mut self) -> Result<PositionAnalysis, EngineError> {
+        let mut stopped = false;
 
-        let mut best_move = String::new();
         let mut evaluation = Evaluation::Centipawns(0);
         let mut pv = Vec::new();
         let mut final_depth = 0u8;
@@ -179,21 +465,23 @@ impl StockfishEngine {
         let mut nodes = 0u64;
 
         // Read until we get bestmove
-        loop {
+        let (best_move, ponder) = loop {
             let line = self.read_line()?;
 
             if line.starts_with("bestmove") {
-                // Parse: "bestmove e2e4 ponder e7e5"
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 2 {
-                    best_move = parts[1].to_string();
+                if stopped {
+                    return Err(EngineError::Aborted);
                 }
-                break;
+                break parse_bestmove(&line);
+            } else if !stopped && self.abort.is_aborted() {
+                // Keep reading: the search isn't over until its bestmove
+                self.send("stop")?;
+                stopped = true;
             } else if line.starts_with("info") {
                 // Parse info line
                 self.parse_info_line(&line, &mut evaluation, &mut pv, &mut final_depth, &mut time_ms, &mut nodes);
             }
-        }
+        };
 
         Ok(PositionAnalysis {
             best_move,
@@ -202,9 +490,119 @@ impl StockfishEngine {
             pv,
             time_ms,
             nodes,
+            ponder,
         })
     }
 
+    /// Searches up to `max_depth`, stopping early once the best move and
+    /// eval have held for `stability` consecutive depths.
+    ///
+    /// Quiet positions settle after a few iterations while tactical ones
+    /// keep changing their minds, so this spends the time where it matters.
+    /// Evals within `STABLE_EVAL_MARGIN_CP` count as unchanged; mate scores
+    /// must match exactly. The result is the last iteration seen.
+    pub fn analyze_until_stable(&mut self, max_depth: u8, stability: u32) -> Result<PositionAnalysis, EngineError> {
+        if !self.initialized {
+            return Err(EngineError::NotInitialized);
+        }
+
+        self.send(&format!("go depth {}", max_depth))?;
+
+        let mut current = PositionAnalysis {
+            best_move: String::new(),
+            evaluation: Evaluation::Centipawns(0),
+            depth: 0,
+            pv: Vec::new(),
+            time_ms: 0,
+            nodes: 0,
+            ponder: None,
+        };
+        let mut stable_for = 0u32;
+        let mut stopped = false;
+
+        loop {
+            let line = self.read_line()?;
+
+            if line.starts_with("bestmove") {
+                // After a stop the engine may report a move from the
+                // unfinished iteration; keep the settled one
+                if !stopped {
+                    let (mv, ponder) = parse_bestmove(&line);
+                    if !mv.is_empty() {
+                        current.best_move = mv;
+                        current.ponder = ponder;
+                    }
+                }
+                break;
+            }
+            if stopped || !is_iteration_result(&line) {
+                continue;
+            }
+
+            let mut next = current.clone();
+            self.parse_info_line(&line, &mut next.evaluation, &mut next.pv,
+                &mut next.depth, &mut next.time_ms, &mut next.nodes);
+            next.best_move = next.pv.first().cloned().unwrap_or_default();
+
+            if next.best_move == current.best_move && evals_agree(&next.evaluation, &current.evaluation) {
+                stable_for += 1;
+            } else {
+                stable_for = 1;
+            }
+            current = next;
+
+            if stable_for >= stability {
+                self.send("stop")?;
+                stopped = true;
+            }
+        }
+
+        Ok(current)
+    }
+
+    /// Searches for the `lines` best moves at once (UCI `MultiPV`).
+    ///
+    /// Returns one analysis per line, best first, each with its first PV
+    /// move as `best_move`; fewer when the position has fewer legal moves.
+    /// `MultiPV` is set back to 1 afterwards so `analyze` is unaffected.
+    pub fn analyze_multipv(&mut self, depth: u8, lines: u8) -> Result<Vec<PositionAnalysis>, EngineError> {
+        if !self.initialized {
+            return Err(EngineError::NotInitialized);
+        }
+
+        self.send(&format!("setoption name MultiPV value {}", lines.max(1)))?;
+        self.send(&format!("go depth {}", depth))?;
+
+        let mut results: Vec<Option<PositionAnalysis>> = vec![None; lines.max(1) as usize];
+        loop {
+            let line = self.read_line()?;
+            if line.starts_with("bestmove") {
+                break;
+            }
+            let slot = match iteration_line(&line).and_then(|n| results.get_mut(n - 1)) {
+                Some(slot) => slot,
+                None => continue,
+            };
+
+            let mut analysis = PositionAnalysis {
+                best_move: String::new(),
+                evaluation: Evaluation::Centipawns(0),
+                depth: 0,
+                pv: Vec::new(),
+                time_ms: 0,
+                nodes: 0,
+                ponder: None,
+            };
+            self.parse_info_line(&line, &mut analysis.evaluation, &mut analysis.pv,
+                &mut analysis.depth, &mut analysis.time_ms, &mut analysis.nodes);
+            analysis.best_move = analysis.pv.first().cloned().unwrap_or_default();
+            *slot = Some(analysis);
+        }
+
+        self.send("setoption name MultiPV value 1")?;
+        Ok(results.into_iter().flatten().collect())
+    }
+
     /// Parses an info line from Stockfish
     fn parse_info_line(
         &self,
@@ -280,6 +678,61 @@ impl StockfishEngine {
         Ok(analysis.best_move == player_move)
     }
 
+    /// Check if `san` is the best move in the position `fen`.
+    ///
+    /// The move is converted to UCI against the FEN before comparing, so
+    /// "Nf3" and "g1f3" agree. An unparsable FEN, or SAN that isn't a legal
+    /// move there, is an `InvalidInput` error rather than `false`.
+    pub fn is_best_move_san(&mut self, fen: &str, san: &str, depth: u8) -> Result<bool, EngineError> {
+        let position = parse_fen_lenient(fen)
+            .map_err(|e| EngineError::InvalidInput(e.to_string()))?;
+        let mv = san
+            .trim_end_matches(['!', '?'])
+            .parse::<SanPlus>()
+            .map_err(|e| EngineError::InvalidInput(format!("'{}': {}", san, e)))?
+            .san
+            .to_move(&position)
+            .map_err(|e| EngineError::InvalidInput(format!("'{}': {}", san, e)))?;
+        let uci = UciMove::from_standard(mv).to_string();
+
+        let fen = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+        self.set_position(Some(&fen), None)?;
+        self.is_best_move(&uci, depth)
+    }
+
+    /// The best move in `fen` and up to `length` plies of its
+    /// continuation; see `chess_engine::best_line_san`
+    pub fn best_line_san(&mut self, fen: &str, depth: u8, length: usize) -> Result<Vec<LinePly>, EngineError> {
+        super::chess_engine::best_line_san(self, fen, depth, length)
+    }
+
+    /// Lowers the engine process's scheduling priority.
+    ///
+    /// `nice` follows Unix niceness: 0 is normal, 19 is the lowest priority.
+    /// Unprivileged users can only raise it. On other platforms this returns
+    /// an error and the engine keeps its default priority.
+    pub fn set_priority(&self, nice: i32) -> Result<(), EngineError> {
+        #[cfg(unix)]
+        {
+            // SAFETY: setpriority only reads its integer arguments
+            let rc = unsafe {
+                libc::setpriority(libc::PRIO_PROCESS as _, self.process.id() as libc::id_t, nice)
+            };
+            if rc != 0 {
+                return Err(EngineError::IoError(std::io::Error::last_os_error()));
+            }
+            Ok(())
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = nice;
+            Err(EngineError::ProtocolError(
+                "process priority is only supported on Unix".into(),
+            ))
+        }
+    }
+
     /// Quit the engine cleanly
     pub fn quit(&mut self) -> Result<(), EngineError> {
         self.send("quit")?;
@@ -290,6 +743,28 @@ impl StockfishEngine {
     }
 }
 
+impl ChessEngine for StockfishEngine {
+    fn set_position(&mut self, fen: Option<&str>, moves: Option<&[String]>) -> Result<(), EngineError> {
+        StockfishEngine::set_position(self, fen, moves)
+    }
+
+    fn analyze(&mut self, depth: u8) -> Result<PositionAnalysis, EngineError> {
+        StockfishEngine::analyze(self, depth)
+    }
+
+    fn analyze_multipv(&mut self, depth: u8, lines: u8) -> Result<Vec<PositionAnalysis>, EngineError> {
+        StockfishEngine::analyze_multipv(self, depth, lines)
+    }
+
+    fn set_abort_flag(&mut self, flag: AbortFlag) {
+        StockfishEngine::set_abort_flag(self, flag)
+    }
+
+    fn set_priority(&self, nice: i32) -> Result<(), EngineError> {
+        StockfishEngine::set_priority(self, nice)
+    }
+}
+
 impl Drop for StockfishEngine {
     fn drop(&mut self) {
         let _ = self.quit();
@@ -318,4 +793,66 @@ mod tests {
         println!("Best move: {}", analysis.best_move);
         println!("Evaluation: {}", analysis.evaluation);
     }
+
+    #[test]
+    fn test_parse_bestmove() {
+        assert_eq!(parse_bestmove("bestmove e2e4 ponder e7e5"), ("e2e4".to_string(), Some("e7e5".to_string())));
+        assert_eq!(parse_bestmove("bestmove e7e8q"), ("e7e8q".to_string(), None));
+        assert_eq!(parse_bestmove("bestmove (none)"), ("(none)".to_string(), None));
+        assert_eq!(parse_bestmove("bestmove a2a3 ponder"), ("a2a3".to_string(), None));
+    }
+
+    /// A shell pretending to be an engine, running `script`
+    #[cfg(unix)]
+    fn mock(script: &str) -> StockfishEngine {
+        StockfishEngine::spawn("sh", &["-c", script]).unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_silent_engine_times_out() {
+        let timeout = Duration::from_millis(200);
+
+        // Never answers anything
+        let mut engine = mock("cat > /dev/null");
+        let started = Instant::now();
+        assert!(matches!(engine.init_uci(timeout), Err(EngineError::ProtocolError(msg)) if msg == "handshake timeout"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Finishes `uci` but never gets ready, however often it's asked
+        let mut engine = mock("read line; echo 'id name Mock'; echo uciok; cat > /dev/null");
+        assert!(matches!(engine.init_uci(timeout), Err(EngineError::ProtocolError(msg)) if msg == "handshake timeout"));
+        assert!(!engine.initialized);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_options_from_the_handshake() {
+        let mut engine = mock(
+            "read line; echo 'id name Mock'; \
+             echo 'option name Hash type spin default 16 min 1 max 1024'; \
+             echo 'option name Clear Hash type button'; \
+             echo 'option name Bogus'; \
+             echo uciok; read line; echo readyok; cat > /dev/null",
+        );
+        assert!(matches!(engine.set_option("Hash", "64"), Err(EngineError::NotInitialized)));
+        engine.init_uci(Duration::from_millis(500)).unwrap();
+
+        let names: Vec<&str> = engine.available_options().iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, ["Hash", "Clear Hash"]);
+
+        engine.set_option("hash", "64").unwrap();
+        engine.set_option("Clear Hash", "").unwrap();
+        assert!(matches!(engine.set_option("Hash", "4096"), Err(EngineError::InvalidInput(_))));
+        assert!(matches!(engine.set_option("Threads", "2"), Err(EngineError::InvalidInput(_))));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_slow_ready_is_retried() {
+        // Ignores the first `isready` and answers the second
+        let mut engine = mock("read line; echo uciok; read line; read line; echo readyok; cat > /dev/null");
+        engine.init_uci(Duration::from_millis(500)).unwrap();
+        assert!(engine.initialized);
+    }
 }
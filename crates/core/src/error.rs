@@ -19,8 +19,14 @@ pub enum Error {
     #[error("PGN parsing error: {0}")]
     Pgn(String),
 
+    #[error("Invalid FEN: {0}")]
+    Fen(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("Analysis aborted")]
+    Aborted,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
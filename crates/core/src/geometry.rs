@@ -0,0 +1,78 @@
+//! Distances between squares
+//!
+//! Used by the drills (knight paths, nearest piece) and by heuristics that
+//! measure how far a king or piece is from where it needs to be.
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+
+use shakmaty::{attacks, Square};
+
+/// King moves from `a` to `b` on an empty board
+pub fn chebyshev_distance(a: Square, b: Square) -> u8 {
+    a.distance(b) as u8
+}
+
+/// Rook-style steps from `a` to `b`: files apart plus ranks apart
+pub fn manhattan_distance(a: Square, b: Square) -> u8 {
+    (a.file().distance(b.file()) + a.rank().distance(b.rank())) as u8
+}
+
+/// Fewest knight moves from `a` to `b` on an empty board
+pub fn knight_distance(a: Square, b: Square) -> u8 {
+    static TABLE: OnceLock<[[u8; 64]; 64]> = OnceLock::new();
+    TABLE.get_or_init(knight_table)[a.to_usize()][b.to_usize()]
+}
+
+/// Breadth-first search from every square. A knight reaches any square
+/// within six moves, so nothing is left unvisited.
+fn knight_table() -> [[u8; 64]; 64] {
+    let mut table = [[u8::MAX; 64]; 64];
+    for from in Square::ALL {
+        let row = &mut table[from.to_usize()];
+        row[from.to_usize()] = 0;
+        let mut queue = VecDeque::from([from]);
+        while let Some(sq) = queue.pop_front() {
+            let next = row[sq.to_usize()] + 1;
+            for to in attacks::knight_attacks(sq) {
+                if row[to.to_usize()] == u8::MAX {
+                    row[to.to_usize()] = next;
+                    queue.push_back(to);
+                }
+            }
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_king_and_rook_distances() {
+        assert_eq!(chebyshev_distance(Square::A1, Square::H8), 7);
+        assert_eq!(chebyshev_distance(Square::E4, Square::G5), 2);
+        assert_eq!(manhattan_distance(Square::A1, Square::H8), 14);
+        assert_eq!(manhattan_distance(Square::E4, Square::G5), 3);
+        assert_eq!(manhattan_distance(Square::D4, Square::D4), 0);
+    }
+
+    #[test]
+    fn test_knight_distance() {
+        assert_eq!(knight_distance(Square::A1, Square::A1), 0);
+        assert_eq!(knight_distance(Square::A1, Square::B3), 1);
+        assert_eq!(knight_distance(Square::A1, Square::H8), 6);
+        // The corner trap: one diagonal step costs four moves
+        assert_eq!(knight_distance(Square::A1, Square::B2), 4);
+        assert_eq!(knight_distance(Square::D4, Square::D5), 3);
+        assert_eq!(knight_distance(Square::G1, Square::E5), 2);
+
+        for a in Square::ALL {
+            for b in Square::ALL {
+                assert_eq!(knight_distance(a, b), knight_distance(b, a));
+                assert!(knight_distance(a, b) <= 6);
+            }
+        }
+    }
+}
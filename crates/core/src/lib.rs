@@ -4,11 +4,14 @@ use shakmaty::{Chess, Color, Position};
 
 pub mod engine;
 pub mod error;
+pub mod geometry;
 pub mod lichess;
 pub mod parser;
 pub mod patterns;
+pub mod positional;
 pub mod storage;
 pub mod training;
+pub mod util;
 
 pub use error::{Error, Result};
 pub use lichess::LichessClient;
@@ -8,6 +8,7 @@ use super::types::*;
 use crate::error::Result;
 
 const LICHESS_API_BASE: &str = "https://lichess.org/api";
+const LICHESS_TABLEBASE_URL: &str = "https://tablebase.lichess.ovh/standard";
 
 pub struct LichessClient {
     client: Client,
@@ -61,6 +62,8 @@ impl LichessClient {
                 ("pgnInJson", "true"),
                 ("opening", "true"),
                 ("moves", "true"),
+                ("evals", "true"),
+                ("clocks", "true"),
             ]);
 
         if let Some(max) = params.max {
@@ -78,6 +81,9 @@ impl LichessClient {
         if let Some(since) = params.since {
             request = request.query(&[("since", since.to_string())]);
         }
+        if let Some(until) = params.until {
+            request = request.query(&[("until", until.to_string())]);
+        }
 
         let response = request.send().await?;
         
@@ -116,6 +122,25 @@ impl LichessClient {
         Ok(eval)
     }
 
+    /// Probe the Lichess tablebase (up to 7 pieces, no castling rights)
+    pub async fn tablebase(&self, fen: &str) -> Result<TablebaseEntry> {
+        let response = self.client
+            .get(LICHESS_TABLEBASE_URL)
+            .query(&[("fen", fen)])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(crate::error::Error::Lichess(format!(
+                "Tablebase error: {}",
+                response.status()
+            )));
+        }
+
+        let entry: TablebaseEntry = response.json().await?;
+        Ok(entry)
+    }
+
     /// Get user profile
     pub async fn get_user(&self, username: &str) -> Result<LichessUser> {
         let url = format!("{}/user/{}", LICHESS_API_BASE, username);
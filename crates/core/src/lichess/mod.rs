@@ -1,7 +1,9 @@
 //! Lichess API integration
 
 mod client;
+mod sync;
 mod types;
 
 pub use client::LichessClient;
+pub use sync::{SyncProgress, SYNC_WINDOW_GAMES};
 pub use types::*;
@@ -0,0 +1,209 @@
+//! Fetching a user's whole Lichess history in windows
+//!
+//! One export of a large account can outlast the HTTP timeout, so
+//! `LichessClient::sync_all` walks back in time instead, `SYNC_WINDOW_GAMES`
+//! at a time, each window ending where the previous one reached. The cursor
+//! is saved to `user_settings` after every window, so an interrupted sync
+//! resumes there instead of starting over. A finished sync records when it
+//! began, and the next one stops at that point.
+
+use std::future::Future;
+use std::sync::Mutex;
+
+use super::client::LichessClient;
+use super::types::{GameExportParams, LichessGame};
+use crate::error::Result;
+use crate::storage::Database;
+
+/// Games requested per window; small enough to finish well inside the
+/// client's 30 second timeout
+pub const SYNC_WINDOW_GAMES: u32 = 200;
+
+/// Running totals for a windowed sync
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncProgress {
+    pub windows: u32,
+    pub fetched: usize,
+    /// As counted by `Database::insert_games`, which includes games that
+    /// were already stored
+    pub inserted: u32,
+    /// Where the next window ends, in Unix milliseconds
+    pub cursor: Option<u64>,
+}
+
+impl LichessClient {
+    /// Fetches every game of `username` not yet stored, newest first, in
+    /// windows of `SYNC_WINDOW_GAMES`. `on_window` is called after each
+    /// window is stored.
+    ///
+    /// The database is only locked between requests, never across one.
+    pub async fn sync_all(
+        &self,
+        db: &Mutex<Database>,
+        username: &str,
+        on_window: impl FnMut(&SyncProgress),
+    ) -> Result<SyncProgress> {
+        sync_windows(db, username, SYNC_WINDOW_GAMES, |params| async move {
+            self.get_user_games(username, &params).await
+        }, on_window).await
+    }
+}
+
+/// The windowing behind `sync_all`, with the request made by `fetch`
+async fn sync_windows<F, Fut>(
+    db: &Mutex<Database>,
+    username: &str,
+    window: u32,
+    mut fetch: F,
+    mut on_window: impl FnMut(&SyncProgress),
+) -> Result<SyncProgress>
+where
+    F: FnMut(GameExportParams) -> Fut,
+    Fut: Future<Output = Result<Vec<LichessGame>>>,
+{
+    let start = db.lock().unwrap().begin_sync(username)?;
+    let mut progress = SyncProgress { cursor: start.until, ..SyncProgress::default() };
+
+    loop {
+        let mut params = GameExportParams::new().max(window);
+        if let Some(since) = start.since {
+            params = params.since(since);
+        }
+        if let Some(until) = progress.cursor {
+            params = params.until(until);
+        }
+
+        let games = fetch(params).await?;
+        // A short window means there is nothing older left
+        let finished = games.len() < window as usize;
+        progress.windows += 1;
+        progress.fetched += games.len();
+        progress.cursor = next_cursor(&games, progress.cursor).or(progress.cursor);
+
+        {
+            let db = db.lock().unwrap();
+            progress.inserted += db.insert_games(&games)?;
+            match progress.cursor {
+                _ if finished => db.finish_sync(username)?,
+                Some(cursor) => db.advance_sync_cursor(username, cursor)?,
+                None => {}
+            }
+        }
+        on_window(&progress);
+
+        if finished {
+            return Ok(progress);
+        }
+    }
+}
+
+/// Where the window after `games` ends: the oldest `last_move_at` reached.
+///
+/// Lichess windows by start time, so a long game begun inside the window
+/// can end after it; the cursor then falls back to the oldest start so
+/// every window moves strictly back in time.
+fn next_cursor(games: &[LichessGame], until: Option<u64>) -> Option<u64> {
+    let oldest = games.iter().map(|g| g.last_move_at).min()?;
+    match until {
+        Some(until) if oldest >= until => {
+            let oldest_start = games.iter().map(|g| g.created_at).min()?;
+            Some(oldest_start.min(until.saturating_sub(1)))
+        }
+        _ => Some(oldest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    /// A game started at `created_at` and finished a minute later
+    fn game(id: usize, created_at: u64) -> LichessGame {
+        serde_json::from_value(serde_json::json!({
+            "id": format!("game{:04}", id),
+            "rated": true,
+            "variant": "standard",
+            "speed": "blitz",
+            "perf": "blitz",
+            "createdAt": created_at,
+            "lastMoveAt": created_at + 60_000,
+            "status": "resign",
+            "players": {
+                "white": { "user": { "name": "alice" }, "rating": 1500 },
+                "black": { "user": { "name": "bob" }, "rating": 1500 }
+            },
+            "winner": "white",
+            "moves": "e4 e5"
+        })).unwrap()
+    }
+
+    /// Answers an export the way Lichess does: newest first, filtered by
+    /// start time, at most `max` games
+    fn export(history: &[LichessGame], params: &GameExportParams) -> Vec<LichessGame> {
+        let mut games: Vec<LichessGame> = history
+            .iter()
+            .filter(|g| params.since.is_none_or(|since| g.created_at >= since))
+            .filter(|g| params.until.is_none_or(|until| g.created_at < until))
+            .cloned()
+            .collect();
+        games.sort_by_key(|g| std::cmp::Reverse(g.created_at));
+        games.truncate(params.max.unwrap_or(u32::MAX) as usize);
+        games
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_sync_resumes_from_cursor() {
+        let db = Mutex::new(Database::open_in_memory().unwrap());
+        // 250 games, one every ten minutes
+        let history: Vec<LichessGame> = (0..250).map(|i| game(i, 1_700_000_000_000 + i as u64 * 600_000)).collect();
+
+        // The third request fails, after two windows of 100 made it in
+        let mut requests = Vec::new();
+        let result = sync_windows(&db, "alice", 100, |params| {
+            requests.push(params.until);
+            let response = if requests.len() == 3 {
+                Err(Error::Lichess("timed out".to_string()))
+            } else {
+                Ok(export(&history, &params))
+            };
+            std::future::ready(response)
+        }, |_| {}).await;
+        assert!(result.is_err());
+        assert_eq!(requests[0], None);
+        // Each window ends at the oldest finish reached by the one before
+        assert_eq!(requests[1], Some(history[150].last_move_at));
+        // The game straddling each cursor is fetched twice but stored once
+        assert_eq!(db.lock().unwrap().get_all_games().unwrap().len(), 199);
+        assert_eq!(db.lock().unwrap().get_last_sync_time("alice").unwrap(), None);
+
+        let mut windows = Vec::new();
+        let progress = sync_windows(&db, "alice", 100, |params| {
+            assert_eq!(params.until, Some(history[51].last_move_at));
+            std::future::ready(Ok(export(&history, &params)))
+        }, |p| windows.push(p.clone())).await.unwrap();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(progress.fetched, 52);
+        assert_eq!(db.lock().unwrap().get_all_games().unwrap().len(), 250);
+        assert!(db.lock().unwrap().get_last_sync_time("alice").unwrap().is_some());
+
+        // Once finished, the next sync starts over from the newest game but
+        // stops where this one began
+        let cursor = db.lock().unwrap().begin_sync("alice").unwrap();
+        assert_eq!(cursor.until, None);
+        assert!(cursor.since.is_some());
+    }
+
+    #[test]
+    fn test_cursor_always_moves_back() {
+        let games = vec![game(1, 5_000_000), game(2, 4_000_000)];
+        assert_eq!(next_cursor(&games, None), Some(4_060_000));
+        assert_eq!(next_cursor(&games, Some(4_100_000)), Some(4_060_000));
+
+        // A two-day correspondence game started just inside the window
+        let mut long = game(3, 4_000_000);
+        long.last_move_at = 180_000_000;
+        assert_eq!(next_cursor(&[long], Some(4_050_000)), Some(4_000_000));
+        assert_eq!(next_cursor(&[], Some(4_050_000)), None);
+    }
+}
@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::parser::pgn::parse_pgn_string;
+
 #[derive(Debug, Clone, Default)]
 pub struct GameExportParams {
     pub max: Option<u32>,
@@ -9,6 +11,7 @@ pub struct GameExportParams {
     pub rated_only: bool,
     pub with_analysis: bool,
     pub since: Option<u64>,  // Unix timestamp in milliseconds
+    pub until: Option<u64>,  // Unix timestamp in milliseconds
 }
 
 impl GameExportParams {
@@ -40,6 +43,11 @@ impl GameExportParams {
         self.since = Some(timestamp);
         self
     }
+
+    pub fn until(mut self, timestamp: u64) -> Self {
+        self.until = Some(timestamp);
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -88,6 +96,12 @@ pub struct LichessGame {
     pub opening: Option<Opening>,
     #[serde(default)]
     pub clock: Option<Clock>,
+    /// Time left after each ply in centiseconds, for timed games
+    #[serde(default)]
+    pub clocks: Option<Vec<u32>>,
+    /// Per-ply server analysis, present for games analysed on Lichess
+    #[serde(default)]
+    pub analysis: Option<Vec<MoveEval>>,
 }
 
 impl LichessGame {
@@ -120,13 +134,57 @@ impl LichessGame {
         self.players.black.rating
     }
 
+    /// SAN moves from `moves`, or read from the embedded `pgn` when Lichess
+    /// left `moves` out
     pub fn move_list(&self) -> Vec<String> {
-        self.moves.as_ref()
-            .map(|m| m.split_whitespace().map(String::from).collect())
-            .unwrap_or_default()
+        match self.moves.as_deref().filter(|m| !m.trim().is_empty()) {
+            Some(moves) => moves.split_whitespace().map(String::from).collect(),
+            None => self.pgn.as_deref()
+                .and_then(|pgn| parse_pgn_string(pgn).ok())
+                .and_then(|games| games.into_iter().next())
+                .map(|game| game.moves)
+                .unwrap_or_default(),
+        }
     }
 }
 
+/// Lichess server analysis for one ply.
+///
+/// Entry `i` describes the position after move `i` was played. Evals are
+/// from White's perspective; `best` (UCI) is what should have been played
+/// instead of move `i` and is only present on judged moves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoveEval {
+    #[serde(default)]
+    pub eval: Option<i32>,
+    #[serde(default)]
+    pub mate: Option<i32>,
+    #[serde(default)]
+    pub best: Option<String>,
+    #[serde(default)]
+    pub variation: Option<String>,
+    #[serde(default)]
+    pub judgment: Option<Judgment>,
+}
+
+impl MoveEval {
+    /// Centipawns from White's perspective, with mates mapped to +/-10000
+    pub fn white_cp(&self) -> Option<i32> {
+        match (self.eval, self.mate) {
+            (Some(cp), _) => Some(cp),
+            (None, Some(m)) => Some(if m > 0 { 10000 } else { -10000 }),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Judgment {
+    pub name: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Players {
     pub white: Player,
@@ -201,3 +259,125 @@ pub struct GameCount {
     pub loss: u32,
     pub draw: u32,
 }
+
+/// Result with best play, from one side's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    Draw,
+    Win,
+}
+
+impl Wdl {
+    /// The same result from the other side's point of view
+    pub fn flip(self) -> Self {
+        match self {
+            Wdl::Loss => Wdl::Win,
+            Wdl::Draw => Wdl::Draw,
+            Wdl::Win => Wdl::Loss,
+        }
+    }
+}
+
+/// Tablebase verdict for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TablebaseCategory {
+    Win,
+    SyzygyWin,
+    MaybeWin,
+    /// Won, but not within the 50-move rule
+    CursedWin,
+    Draw,
+    /// Lost, but saved by the 50-move rule
+    BlessedLoss,
+    MaybeLoss,
+    SyzygyLoss,
+    Loss,
+    #[serde(other)]
+    Unknown,
+}
+
+impl TablebaseCategory {
+    /// The result under the 50-move rule, or `None` if the tablebase
+    /// doesn't know
+    pub fn wdl(self) -> Option<Wdl> {
+        match self {
+            TablebaseCategory::Win | TablebaseCategory::SyzygyWin | TablebaseCategory::MaybeWin => Some(Wdl::Win),
+            TablebaseCategory::CursedWin | TablebaseCategory::Draw | TablebaseCategory::BlessedLoss => Some(Wdl::Draw),
+            TablebaseCategory::Loss | TablebaseCategory::SyzygyLoss | TablebaseCategory::MaybeLoss => Some(Wdl::Loss),
+            TablebaseCategory::Unknown => None,
+        }
+    }
+}
+
+/// Lichess tablebase entry for a position
+#[derive(Debug, Clone, Deserialize)]
+pub struct TablebaseEntry {
+    pub category: TablebaseCategory,
+    /// Distance to zeroing (capture or pawn move), in plies
+    #[serde(default)]
+    pub dtz: Option<i32>,
+    /// Distance to mate, in plies; only known for up to 5 pieces
+    #[serde(default)]
+    pub dtm: Option<i32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANALYSED_GAME: &str = r#"{"id":"abcd1234","rated":true,"variant":"standard","speed":"blitz","perf":"blitz","createdAt":1700000000000,"lastMoveAt":1700000300000,"status":"mate","players":{"white":{"user":{"name":"Alice","id":"alice"},"rating":1500},"black":{"user":{"name":"Bob","id":"bob"},"rating":1480}},"winner":"white","moves":"e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7#","analysis":[{"eval":18},{"eval":25},{"eval":-10},{"eval":-5},{"eval":20},{"mate":1,"best":"g7g6","variation":"g6 Qf3 Nf6","judgment":{"name":"Blunder","comment":"Checkmate is now unavoidable. g6 was best."}}]}"#;
+
+    #[test]
+    fn test_moves_read_from_pgn_when_missing() {
+        let json = r#"{"id":"pgnonly1","rated":true,"variant":"standard","speed":"blitz","perf":"blitz","createdAt":1700000000000,"lastMoveAt":1700000300000,"status":"mate","players":{"white":{"user":{"name":"Alice","id":"alice"},"rating":1500},"black":{"user":{"name":"Bob","id":"bob"},"rating":1480}},"winner":"white","pgn":"[Event \"Rated blitz game\"]\n[White \"Alice\"]\n[Black \"Bob\"]\n[Result \"1-0\"]\n\n1. e4 { [%clk 0:03:00] } 1... e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n"}"#;
+        let mut game: LichessGame = serde_json::from_str(json).unwrap();
+        assert_eq!(game.moves, None);
+        assert_eq!(game.move_list(), ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7"]);
+
+        // `moves` wins when both are there, and nothing is made up
+        game.moves = Some("d4 d5".to_string());
+        assert_eq!(game.move_list(), ["d4", "d5"]);
+        game.moves = None;
+        game.pgn = None;
+        assert!(game.move_list().is_empty());
+    }
+
+    #[test]
+    fn test_deserialize_analysis() {
+        let game: LichessGame = serde_json::from_str(ANALYSED_GAME).unwrap();
+        let analysis = game.analysis.unwrap();
+
+        assert_eq!(analysis.len(), 6);
+        assert_eq!(analysis[0].white_cp(), Some(18));
+        assert!(analysis[0].judgment.is_none());
+
+        let blunder = &analysis[5];
+        assert_eq!(blunder.mate, Some(1));
+        assert_eq!(blunder.white_cp(), Some(10000));
+        assert_eq!(blunder.best.as_deref(), Some("g7g6"));
+        assert_eq!(blunder.judgment.as_ref().unwrap().name, "Blunder");
+    }
+
+    #[test]
+    fn test_analysis_is_optional() {
+        let json = ANALYSED_GAME.replace(r#","analysis":"#, r#","unused":"#);
+        let game: LichessGame = serde_json::from_str(&json).unwrap();
+        assert!(game.analysis.is_none());
+    }
+
+    #[test]
+    fn test_deserialize_tablebase_categories() {
+        let entry: TablebaseEntry = serde_json::from_str(r#"{"category":"syzygy-win","dtz":3,"dtm":null}"#).unwrap();
+        assert_eq!((entry.category, entry.dtz, entry.dtm), (TablebaseCategory::SyzygyWin, Some(3), None));
+
+        let category = |name: &str| serde_json::from_str::<TablebaseCategory>(&format!("\"{}\"", name)).unwrap();
+        assert_eq!(category("cursed-win"), TablebaseCategory::CursedWin);
+        assert_eq!(category("blessed-loss"), TablebaseCategory::BlessedLoss);
+        assert_eq!(category("maybe-loss"), TablebaseCategory::MaybeLoss);
+        assert_eq!(category("syzygy-loss"), TablebaseCategory::SyzygyLoss);
+        assert_eq!(category("unknown"), TablebaseCategory::Unknown);
+        assert_eq!(category("cursed-win").wdl(), Some(Wdl::Draw));
+    }
+}
@@ -0,0 +1,229 @@
+//! Where the clock went: time per move from `[%clk]` comments
+//!
+//! Lichess and most servers write the time left after every move as a
+//! `{ [%clk 0:02:51] }` comment. The difference between a side's
+//! consecutive clocks, plus the increment, is how long it thought.
+
+use serde::Serialize;
+use shakmaty::{san::San, Chess, Color, Position};
+
+use crate::positional::{game_phase, GamePhase};
+
+/// How many of the longest thinks `TimeUsage` keeps
+pub const LONGEST_THINKS: usize = 5;
+
+/// A move that took at least this many times the side's average is a
+/// long think
+pub const LONG_THINK_FACTOR: f64 = 2.0;
+
+/// Time spent over one game, in centiseconds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeUsage {
+    /// Time each ply took, ply 0 first; 0 for a side's first move when the
+    /// starting time isn't known
+    pub spent: Vec<u32>,
+    /// Totals per phase, in the order the game reached them
+    pub phases: Vec<PhaseTime>,
+    /// The `LONGEST_THINKS` longest moves, longest first
+    pub longest: Vec<LongThink>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhaseTime {
+    pub phase: GamePhase,
+    pub white: u32,
+    pub black: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LongThink {
+    /// Counting from 0 for White's first move
+    pub ply: usize,
+    pub san: String,
+    pub spent: u32,
+}
+
+/// Mistakes on long thinks against mistakes on the other moves, for one
+/// side. Fewer mistakes where the player took their time says the thinking
+/// paid off; more says the long thinks came in positions that confused them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ThinkCorrelation {
+    pub long_thinks: u32,
+    pub long_think_mistakes: u32,
+    pub other_moves: u32,
+    pub other_mistakes: u32,
+}
+
+impl ThinkCorrelation {
+    /// `Some(true)` when long thinks went wrong less often than the other
+    /// moves, `Some(false)` when more often; `None` when either group is
+    /// empty or the rates are equal
+    pub fn long_thinks_helped(&self) -> Option<bool> {
+        if self.long_thinks == 0 || self.other_moves == 0 {
+            return None;
+        }
+        let long = self.long_think_mistakes as f64 / self.long_thinks as f64;
+        let other = self.other_mistakes as f64 / self.other_moves as f64;
+        match long.partial_cmp(&other)? {
+            std::cmp::Ordering::Less => Some(true),
+            std::cmp::Ordering::Greater => Some(false),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+impl TimeUsage {
+    /// How `color`'s mistakes, given as the plies they were played at,
+    /// split between its long thinks and its other moves
+    pub fn think_correlation(&self, color: Color, mistake_plies: &[usize]) -> ThinkCorrelation {
+        let first = if color == Color::White { 0 } else { 1 };
+        let own: Vec<(usize, u32)> = self.spent.iter().copied().enumerate().skip(first).step_by(2).collect();
+        let mut correlation = ThinkCorrelation::default();
+        if own.is_empty() {
+            return correlation;
+        }
+
+        let average = own.iter().map(|&(_, spent)| spent as f64).sum::<f64>() / own.len() as f64;
+        for (ply, spent) in own {
+            let mistake = mistake_plies.contains(&ply);
+            if spent > 0 && spent as f64 >= average * LONG_THINK_FACTOR {
+                correlation.long_thinks += 1;
+                correlation.long_think_mistakes += mistake as u32;
+            } else {
+                correlation.other_moves += 1;
+                correlation.other_mistakes += mistake as u32;
+            }
+        }
+        correlation
+    }
+}
+
+/// Centiseconds left from a comment holding `[%clk H:MM:SS]`, with optional
+/// fractions of a second
+pub fn parse_clock(comment: &str) -> Option<u32> {
+    let start = comment.find("[%clk")? + "[%clk".len();
+    let value = comment[start..].split(']').next()?.trim();
+
+    let mut parts = value.split(':').rev();
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: u32 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    let hours: u32 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    if parts.next().is_some() || !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+    Some((hours * 3600 + minutes * 60) * 100 + (seconds * 100.0).round() as u32)
+}
+
+/// Starting time and increment in centiseconds from a `TimeControl` tag
+/// such as `"180+2"`; `None` for untimed (`"-"`) or unknown controls
+pub fn parse_time_control(tag: &str) -> Option<(u32, u32)> {
+    let (base, increment) = tag.split_once('+').unwrap_or((tag, "0"));
+    Some((base.parse::<u32>().ok()? * 100, increment.parse::<u32>().ok()? * 100))
+}
+
+/// Time usage of a game from the clock after each ply. `None` unless there
+/// is a clock for every move.
+pub fn time_usage(moves: &[String], clocks: &[u32], time_control: Option<&str>) -> Option<TimeUsage> {
+    if moves.is_empty() || clocks.len() < moves.len() {
+        return None;
+    }
+    let control = time_control.and_then(parse_time_control);
+
+    let spent: Vec<u32> = (0..moves.len())
+        .map(|ply| match (ply.checked_sub(2), control) {
+            (Some(previous), _) => (clocks[previous] + control.map_or(0, |(_, inc)| inc)).saturating_sub(clocks[ply]),
+            // Servers don't add the increment before a side's first move
+            (None, Some((base, _))) => base.saturating_sub(clocks[ply]),
+            (None, None) => 0,
+        })
+        .collect();
+
+    let mut phases: Vec<PhaseTime> = Vec::new();
+    let mut position = Chess::default();
+    for (ply, san) in moves.iter().enumerate() {
+        let phase = game_phase(&position);
+        let index = match phases.iter().position(|p| p.phase == phase) {
+            Some(i) => i,
+            None => {
+                phases.push(PhaseTime { phase, white: 0, black: 0 });
+                phases.len() - 1
+            }
+        };
+        match position.turn() {
+            Color::White => phases[index].white += spent[ply],
+            Color::Black => phases[index].black += spent[ply],
+        }
+
+        match san.parse::<San>().ok().and_then(|s| s.to_move(&position).ok()) {
+            Some(mv) => position.play_unchecked(mv),
+            None => break,
+        }
+    }
+
+    let mut longest: Vec<LongThink> = spent
+        .iter()
+        .enumerate()
+        .map(|(ply, &spent)| LongThink { ply, san: moves[ply].clone(), spent })
+        .collect();
+    longest.sort_by(|a, b| b.spent.cmp(&a.spent).then(a.ply.cmp(&b.ply)));
+    longest.truncate(LONGEST_THINKS);
+
+    Some(TimeUsage { spent, phases, longest })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::pgn::parse_pgn_string;
+
+    #[test]
+    fn test_parse_clock() {
+        assert_eq!(parse_clock("[%clk 0:03:00]"), Some(18000));
+        assert_eq!(parse_clock(" [%eval 0.3] [%clk 1:02:03.5] "), Some(372350));
+        assert_eq!(parse_clock("[%clk 0:75:00]"), Some(450000));
+        assert_eq!(parse_clock("[%clk 0:00:61]"), None);
+        assert_eq!(parse_clock("a good move"), None);
+
+        assert_eq!(parse_time_control("180+2"), Some((18000, 200)));
+        assert_eq!(parse_time_control("600"), Some((60000, 0)));
+        assert_eq!(parse_time_control("-"), None);
+    }
+
+    #[test]
+    fn test_where_the_clock_went() {
+        let pgn = r#"[White "Alice"]
+[Black "Bob"]
+[TimeControl "180+2"]
+[Result "1-0"]
+
+1. e4 { [%clk 0:03:00] } 1... e5 { [%clk 0:02:58] } 2. Nf3 { [%clk 0:02:59] }
+2... Nc6 { [%clk 0:02:20] } 3. Bb5 { [%clk 0:02:01] } 3... a6 { [%clk 0:02:18] }
+4. Ba4 { [%clk 0:02:01] } 4... Nf6 { [%clk 0:02:15] } 1-0
+"#;
+        let game = parse_pgn_string(pgn).unwrap().remove(0);
+        assert_eq!(game.time_control.as_deref(), Some("180+2"));
+        let usage = game.time_usage().unwrap();
+
+        // 2...Nc6 took 40 seconds and 3.Bb5 a minute
+        assert_eq!(usage.spent, [0, 200, 300, 4000, 6000, 400, 200, 500]);
+        assert_eq!(usage.phases, [PhaseTime { phase: GamePhase::Opening, white: 6500, black: 5100 }]);
+        assert_eq!(usage.longest.len(), LONGEST_THINKS);
+        assert_eq!((usage.longest[0].ply, usage.longest[0].san.as_str()), (4, "Bb5"));
+        assert_eq!(usage.longest[1].san, "Nc6");
+
+        // White's mistake came on the long think
+        let white = usage.think_correlation(Color::White, &[4]);
+        assert_eq!(white, ThinkCorrelation { long_thinks: 1, long_think_mistakes: 1, other_moves: 3, other_mistakes: 0 });
+        assert_eq!(white.long_thinks_helped(), Some(false));
+        // Black took their time and made their mistake on a quick move
+        let black = usage.think_correlation(Color::Black, &[7]);
+        assert_eq!(black.long_thinks, 1);
+        assert_eq!(black.long_thinks_helped(), Some(true));
+        assert_eq!(usage.think_correlation(Color::Black, &[]).long_thinks_helped(), None);
+
+        // Without clock comments there's nothing to report
+        let game = parse_pgn_string("[White \"Alice\"]\n\n1. e4 e5 2. Nf3 *\n").unwrap().remove(0);
+        assert_eq!(game.clocks, None);
+        assert_eq!(game.time_usage(), None);
+    }
+}
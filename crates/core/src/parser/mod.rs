@@ -2,9 +2,19 @@
 //! 
 //! Currently supports:
 //! - PGN (Portable Game Notation)
+//! - SAN to UCI move conversion
+//! - Reading typed moves in SAN, UCI or long algebraic
+//! - Quick statistics over large PGN files
+//! - Time per move from `[%clk]` comments
 
+pub mod clock;
+pub mod notation;
 pub mod pgn;
+pub mod stats;
 
 // Re-export commonly used items for convenience
 pub use pgn::PgnGame;
-pub use pgn::parse_pgn_file;
+pub use pgn::{detect_repetition_draws, infer_result, parse_pgn_bytes, parse_pgn_file, result_contradicts};
+pub use clock::{parse_clock, LongThink, PhaseTime, ThinkCorrelation, TimeUsage};
+pub use notation::{move_label, parse_user_move, replay_san_line, san_line_to_uci};
+pub use stats::{pgn_stats, PgnStats, StatsVisitor};
@@ -0,0 +1,163 @@
+//! Conversions between move notations
+
+use shakmaty::{san::San, uci::UciMove, Chess, Move, Position, Role};
+
+use super::pgn::PgnGame;
+
+/// How the move at `ply` is numbered in text: `"15."` for White's
+/// fifteenth move, `"15..."` for Black's
+pub fn move_label(ply: usize) -> String {
+    let dots = if ply.is_multiple_of(2) { "." } else { "..." };
+    format!("{}{}", PgnGame::ply_to_move_number(ply), dots)
+}
+
+/// Converts a SAN move list from the starting position to UCI by replaying it.
+///
+/// Stops at the first move that doesn't parse or isn't legal, so the result
+/// is always a playable prefix of the game.
+pub fn san_line_to_uci<I, S>(san_moves: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    replay_san_line(san_moves).0
+}
+
+/// Like `san_line_to_uci`, but also returns the position the replayed
+/// prefix ends in
+pub fn replay_san_line<I, S>(san_moves: I) -> (Vec<String>, Chess)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut position = Chess::default();
+    let mut uci_moves = Vec::new();
+
+    for san_str in san_moves {
+        let san_str = san_str.as_ref();
+        let mv = match san_str.parse::<San>().map(|san| san.to_move(&position)) {
+            Ok(Ok(m)) => m,
+            Ok(Err(e)) => {
+                eprintln!("Warning: Invalid move '{}': {}", san_str, e);
+                break;
+            }
+            Err(e) => {
+                eprintln!("Warning: Could not parse SAN '{}': {}", san_str, e);
+                break;
+            }
+        };
+
+        uci_moves.push(UciMove::from_standard(mv).to_string());
+        // `to_move` only returns legal moves
+        position.play_unchecked(mv);
+    }
+
+    (uci_moves, position)
+}
+
+/// Reads a move typed by a user in whatever notation came naturally:
+/// SAN (`Nf3`), UCI (`g1f3`) or long algebraic (`Ng1-f3`, `e7xd8=Q`).
+///
+/// Tries them in that order and returns the first legal reading. Check and
+/// annotation marks (`+`, `#`, `!`, `?`) are ignored, and SAN promotions
+/// may leave out the `=` (`e8Q`).
+pub fn parse_user_move(position: &Chess, input: &str) -> Option<Move> {
+    let input = input.trim().trim_end_matches(['+', '#', '!', '?']);
+    if input.is_empty() {
+        return None;
+    }
+
+    parse_san(position, input)
+        .or_else(|| input.parse::<UciMove>().ok()?.to_move(position).ok())
+        .or_else(|| parse_long_algebraic(position, input))
+}
+
+fn parse_san(position: &Chess, san: &str) -> Option<Move> {
+    let mut san = san.to_string();
+    let mut chars = san.chars().rev();
+    if let (Some(piece), Some(rank)) = (chars.next(), chars.next()) {
+        if "QRBNqrbn".contains(piece) && (rank == '1' || rank == '8') && !san.contains('=') {
+            san.insert(san.len() - 1, '=');
+        }
+    }
+
+    san.parse::<San>().ok()?.to_move(position).ok()
+}
+
+/// `Ng1-f3`, `Ng1xf3`, `e2-e4`, `e7-e8=Q`: the from and to squares of UCI,
+/// with a piece letter for anything but a pawn
+fn parse_long_algebraic(position: &Chess, text: &str) -> Option<Move> {
+    let (role, squares) = match text.chars().next()? {
+        c @ ('K' | 'Q' | 'R' | 'B' | 'N') => (Role::from_char(c.to_ascii_lowercase())?, &text[1..]),
+        _ => (Role::Pawn, text),
+    };
+    let uci: String = squares
+        .chars()
+        .filter(|c| !matches!(c, '-' | 'x' | ':' | '='))
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    let mv = uci.parse::<UciMove>().ok()?.to_move(position).ok()?;
+    (mv.role() == role).then_some(mv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_labels() {
+        assert_eq!(move_label(0), "1.");
+        assert_eq!(move_label(1), "1...");
+        assert_eq!(move_label(2), "2.");
+        assert_eq!(move_label(3), "2...");
+        assert_eq!(PgnGame::ply_to_move_number(3), 2);
+    }
+
+    #[test]
+    fn test_castling_and_promotion_convert() {
+        let uci = san_line_to_uci("e4 d5 exd5 c6 dxc6 Nf6 cxb7 Nbd7 bxa8=Q".split_whitespace());
+        assert_eq!(uci.last().unwrap(), "b7a8q");
+
+        assert_eq!(san_line_to_uci(["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "O-O"]).last().unwrap(), "e1g1");
+    }
+
+    #[test]
+    fn test_stops_at_illegal_move() {
+        assert_eq!(san_line_to_uci(["e4", "e4", "Nf3"]), vec!["e2e4"]);
+    }
+
+    #[test]
+    fn test_user_move_in_any_notation() {
+        let pos = Chess::default();
+        let nf3 = parse_user_move(&pos, "Nf3").unwrap();
+        assert_eq!(parse_user_move(&pos, "g1f3"), Some(nf3));
+        assert_eq!(parse_user_move(&pos, "Ng1-f3"), Some(nf3));
+        assert_eq!(parse_user_move(&pos, "Ng1xf3"), Some(nf3));
+        assert_eq!(parse_user_move(&pos, " Nf3!? "), Some(nf3));
+
+        let e4 = parse_user_move(&pos, "e4").unwrap();
+        assert_eq!(parse_user_move(&pos, "e2-e4"), Some(e4));
+        assert_eq!(parse_user_move(&pos, "e2e4"), Some(e4));
+
+        // The piece letter has to match what stands on the from square
+        assert_eq!(parse_user_move(&pos, "Bg1-f3"), None);
+        assert_eq!(parse_user_move(&pos, "Nf6"), None);
+        assert_eq!(parse_user_move(&pos, ""), None);
+    }
+
+    #[test]
+    fn test_user_promotion_and_castling() {
+        let (_, pos) = replay_san_line("e4 d5 exd5 c6 dxc6 Nf6 cxb7 Nbd7".split_whitespace());
+        let queen = parse_user_move(&pos, "bxa8=Q").unwrap();
+        assert_eq!(parse_user_move(&pos, "bxa8Q"), Some(queen));
+        assert_eq!(parse_user_move(&pos, "b7a8q"), Some(queen));
+        assert_eq!(parse_user_move(&pos, "b7xa8=Q+"), Some(queen));
+        assert_ne!(parse_user_move(&pos, "b7a8n"), Some(queen));
+
+        let (_, pos) = replay_san_line(["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6"]);
+        let castle = parse_user_move(&pos, "O-O").unwrap();
+        assert_eq!(parse_user_move(&pos, "e1g1"), Some(castle));
+        assert_eq!(parse_user_move(&pos, "Ke1-g1"), Some(castle));
+    }
+}
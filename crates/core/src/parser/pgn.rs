@@ -1,12 +1,15 @@
 //! PGN file parsing functionality
 
-use pgn_reader::{RawTag, SanPlus, Skip, Visitor};
-use shakmaty::{Chess, Position};
+use pgn_reader::{RawComment, RawTag, SanPlus, Skip, Visitor};
+use shakmaty::{san::San, Chess, Outcome, Position};
+use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Cursor};
 use std::ops::ControlFlow;
 use std::path::Path;
 
+use super::clock::{parse_clock, time_usage, TimeUsage};
+
 /// Represents a parsed chess game
 #[derive(Debug, Clone)]
 pub struct PgnGame {
@@ -18,7 +21,11 @@ pub struct PgnGame {
     pub result: Option<String>,
     pub white_elo: Option<u16>,
     pub black_elo: Option<u16>,
+    pub time_control: Option<String>,
     pub moves: Vec<String>,
+    /// Time left after each ply in centiseconds, from `[%clk]` comments;
+    /// `None` unless every move has one
+    pub clocks: Option<Vec<u32>>,
     pub final_position: Chess,
 }
 
@@ -27,12 +34,71 @@ impl PgnGame {
         self.moves.len()
     }
 
+    /// Full-move number of the move played at `ply`, counting plies from 0
+    /// for White's first move
+    pub fn ply_to_move_number(ply: usize) -> u16 {
+        (ply / 2 + 1) as u16
+    }
+
     pub fn summary(&self) -> String {
         let white = self.white.as_deref().unwrap_or("Unknown");
         let black = self.black.as_deref().unwrap_or("Unknown");
         let result = self.result.as_deref().unwrap_or("*");
         format!("{} vs {} - {}", white, black, result)
     }
+
+    /// Stable hash of the players, date and move list, used to spot the same
+    /// game imported from overlapping PGN files.
+    ///
+    /// Uses FNV-1a rather than `DefaultHasher` because the value is persisted
+    /// and must not change between Rust releases.
+    pub fn content_hash(&self) -> u64 {
+        let normalize = |s: &Option<String>| s.as_deref().unwrap_or("").trim().to_lowercase();
+
+        let content = format!(
+            "{}|{}|{}|{}",
+            normalize(&self.white),
+            normalize(&self.black),
+            normalize(&self.date),
+            self.moves.join(" "),
+        );
+
+        content.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    /// Unix timestamp (seconds) of the `Date` tag, if it is a complete `YYYY.MM.DD` date
+    pub fn played_at(&self) -> Option<u64> {
+        let date = self.date.as_deref()?;
+        let mut parts = date.split('.').map(|p| p.parse::<i64>().ok());
+        let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return None;
+        }
+
+        // Days since the Unix epoch for a proleptic Gregorian date
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let mp = (month + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        u64::try_from(days * 86400).ok()
+    }
+
+    /// How long each move took and where the clock went; `None` for games
+    /// without clock comments
+    pub fn time_usage(&self) -> Option<TimeUsage> {
+        time_usage(&self.moves, self.clocks.as_deref()?, self.time_control.as_deref())
+    }
+
+    /// Whether the `Result` tag contradicts the final position
+    pub fn result_suspect(&self) -> bool {
+        result_contradicts(self.result.as_deref().unwrap_or("*"), &self.final_position)
+    }
 }
 
 #[derive(Default)]
@@ -45,11 +111,13 @@ struct GameTags {
     result: Option<String>,
     white_elo: Option<u16>,
     black_elo: Option<u16>,
+    time_control: Option<String>,
 }
 
 struct GameMoves {
     tags: GameTags,
     moves: Vec<String>,
+    clocks: Vec<Option<u32>>,
     current_position: Chess,
     success: bool,
 }
@@ -72,7 +140,7 @@ impl Visitor for GameParser {
         value: RawTag<'_>,
     ) -> ControlFlow<Self::Output> {
         let name_str = String::from_utf8_lossy(name);
-        let value_str = value.decode_utf8_lossy().to_string();
+        let value_str = decode_text(&value.decode());
 
         match name_str.as_ref() {
             "Event" => tags.event = Some(value_str),
@@ -83,6 +151,7 @@ impl Visitor for GameParser {
             "Result" => tags.result = Some(value_str),
             "WhiteElo" => tags.white_elo = value_str.parse().ok(),
             "BlackElo" => tags.black_elo = value_str.parse().ok(),
+            "TimeControl" => tags.time_control = Some(value_str),
             _ => {}
         }
 
@@ -93,6 +162,7 @@ impl Visitor for GameParser {
         ControlFlow::Continue(GameMoves {
             tags,
             moves: Vec::new(),
+            clocks: Vec::new(),
             current_position: Chess::default(),
             success: true,
         })
@@ -104,6 +174,7 @@ impl Visitor for GameParser {
         }
 
         movetext.moves.push(san.san.to_string());
+        movetext.clocks.push(None);
 
         match san.san.to_move(&movetext.current_position) {
             Ok(m) => {
@@ -124,6 +195,14 @@ impl Visitor for GameParser {
         ControlFlow::Continue(())
     }
 
+    fn comment(&mut self, movetext: &mut Self::Movetext, comment: RawComment<'_>) -> ControlFlow<Self::Output> {
+        // A clock belongs to the move the comment follows
+        if let (Some(last), Some(clock)) = (movetext.clocks.last_mut(), parse_clock(&String::from_utf8_lossy(comment.as_bytes()))) {
+            *last = Some(clock);
+        }
+        ControlFlow::Continue(())
+    }
+
     fn begin_variation(
         &mut self,
         _movetext: &mut Self::Movetext,
@@ -142,7 +221,9 @@ impl Visitor for GameParser {
                 result: movetext.tags.result,
                 white_elo: movetext.tags.white_elo,
                 black_elo: movetext.tags.black_elo,
+                time_control: movetext.tags.time_control,
                 moves: movetext.moves,
+                clocks: movetext.clocks.into_iter().collect(),
                 final_position: movetext.current_position,
             })
         } else {
@@ -174,16 +255,95 @@ impl From<io::Error> for PgnError {
     }
 }
 
+/// The result a final position forces, in PGN notation: checkmate is
+/// decisive, stalemate and insufficient material are draws. `None` when play
+/// could have gone on, so the game ended by resignation, time or agreement.
+pub fn infer_result(position: &Chess) -> Option<&'static str> {
+    match position.outcome() {
+        Outcome::Known(outcome) => Some(outcome.as_str()),
+        Outcome::Unknown => None,
+    }
+}
+
+/// Plies (counting from 0) after which a position stood on the board for
+/// the third time, so either player could claim a draw by threefold
+/// repetition. Each repeated position is reported once, when it first
+/// became claimable; the starting position counts as its first occurrence.
+///
+/// Positions are compared with `util::position_key`: board, side to move,
+/// castling rights and a capturable en passant square, as the rule asks.
+/// The game is replayed up to the first move that doesn't parse.
+pub fn detect_repetition_draws(moves: &[String]) -> Vec<u16> {
+    let mut position = Chess::default();
+    let mut seen: HashMap<u64, u32> = HashMap::new();
+    seen.insert(crate::util::position_key(&position), 1);
+    let mut claims = Vec::new();
+
+    for (ply, move_str) in moves.iter().enumerate() {
+        let mv = match move_str.parse::<San>().ok().and_then(|san| san.to_move(&position).ok()) {
+            Some(m) => m,
+            None => break,
+        };
+        position.play_unchecked(mv);
+        let count = seen.entry(crate::util::position_key(&position)).or_insert(0);
+        *count += 1;
+        if *count == 3 {
+            claims.push(ply as u16);
+        }
+    }
+    claims
+}
+
+/// True if `result` disagrees with what `final_position` forces. An
+/// unknown result (`*`) contradicts nothing.
+pub fn result_contradicts(result: &str, final_position: &Chess) -> bool {
+    match infer_result(final_position) {
+        Some(inferred) => result != "*" && result != inferred,
+        None => false,
+    }
+}
+
+/// Windows-1252 characters for bytes 0x80 to 0x9F, where it differs from
+/// Latin-1. The five bytes it leaves undefined map to the C1 control of the
+/// same number, as browsers decode them.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// Tag text as UTF-8, or as Windows-1252 (a superset of Latin-1) when it
+/// isn't valid UTF-8, which is what older desktop software exports
+fn decode_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes
+            .iter()
+            .map(|&b| match b {
+                0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                _ => b as char,
+            })
+            .collect(),
+    }
+}
+
+/// Reads a PGN file in whatever encoding it was saved in; tags that aren't
+/// UTF-8 are decoded as Windows-1252
 pub fn parse_pgn_file<P: AsRef<Path>>(path: P) -> Result<Vec<PgnGame>, PgnError> {
-    let contents = fs::read_to_string(path)?;
-    parse_pgn_string(&contents)
+    let contents = fs::read(path)?;
+    parse_pgn_bytes(&contents)
 }
 
 pub fn parse_pgn_string(pgn: &str) -> Result<Vec<PgnGame>, PgnError> {
+    parse_pgn_bytes(pgn.as_bytes())
+}
+
+/// Like `parse_pgn_string`, for PGN text that may not be UTF-8. Each tag
+/// value is decoded on its own, so a file mixing encodings still reads.
+pub fn parse_pgn_bytes(pgn: &[u8]) -> Result<Vec<PgnGame>, PgnError> {
     let mut parser = GameParser;
     let mut games: Vec<PgnGame> = Vec::new();
 
-    let cursor = Cursor::new(pgn.as_bytes());
+    let cursor = Cursor::new(pgn);
     let mut reader = pgn_reader::Reader::new(cursor);
 
     loop {
@@ -237,6 +397,30 @@ mod tests {
         assert_eq!(summary, "Alice vs Bob - 1-0");
     }
 
+    #[test]
+    fn test_content_hash_ignores_case_and_whitespace() {
+        let games = parse_pgn_string(SAMPLE_PGN).unwrap();
+        let mut copy = games[0].clone();
+        copy.white = Some(" alice ".to_string());
+        copy.event = Some("Other event".to_string());
+        assert_eq!(games[0].content_hash(), copy.content_hash());
+
+        copy.moves.pop();
+        assert_ne!(games[0].content_hash(), copy.content_hash());
+    }
+
+    #[test]
+    fn test_played_at() {
+        let mut game = parse_pgn_string(SAMPLE_PGN).unwrap().remove(0);
+        assert_eq!(game.played_at(), None);
+
+        game.date = Some("2024.03.01".to_string());
+        assert_eq!(game.played_at(), Some(1709251200));
+
+        game.date = Some("2024.??.??".to_string());
+        assert_eq!(game.played_at(), None);
+    }
+
     #[test]
     fn test_position_tracking() {
         let games = parse_pgn_string(SAMPLE_PGN).unwrap();
@@ -244,4 +428,69 @@ mod tests {
         assert_eq!(game.final_position.turn(), Color::Black);
         assert_eq!(game.final_position.board().occupied().count(), 32);
     }
+
+    fn moves(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_repetition_draws() {
+        // Knights out and back twice: the start recurs after ply 3 and 7
+        let shuffle = moves("Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8");
+        assert_eq!(detect_repetition_draws(&shuffle), [7]);
+        assert!(detect_repetition_draws(&shuffle[..7]).is_empty());
+
+        // Going on shuffling: the position after 1.Nf3 is claimable after
+        // ply 8, and the one after 1...Nf6 after ply 9
+        let mut longer = shuffle.clone();
+        longer.extend(moves("Nf3 Nf6"));
+        assert_eq!(detect_repetition_draws(&longer), [7, 8, 9]);
+
+        // Kings home again without castling rights aren't back in the
+        // position before their trip
+        let king_walk = moves("e4 e5 Ke2 Ke7 Ke1 Ke8 Ke2 Ke7 Ke1 Ke8");
+        assert!(detect_repetition_draws(&king_walk).is_empty());
+        let mut once_more = king_walk.clone();
+        once_more.extend(moves("Ke2 Ke7 Ke1 Ke8"));
+        // 4.Ke2 was the first time White's king stood there with Black
+        // unable to castle, so ply 10 is only its second occurrence
+        assert_eq!(detect_repetition_draws(&once_more), [11, 12, 13]);
+    }
+
+    #[test]
+    fn test_result_contradicting_checkmate_is_suspect() {
+        let pgn = "[White \"Alice\"]\n[Black \"Bob\"]\n[Result \"1/2-1/2\"]\n\n1. f3 e5 2. g4 Qh4# 1/2-1/2\n";
+        let mut game = parse_pgn_string(pgn).unwrap().remove(0);
+        assert_eq!(infer_result(&game.final_position), Some("0-1"));
+        assert!(game.result_suspect());
+
+        game.result = Some("0-1".to_string());
+        assert!(!game.result_suspect());
+
+        // Nothing to infer before the mate, and `*` claims nothing
+        assert!(!result_contradicts("1-0", &Chess::default()));
+        assert!(!result_contradicts("*", &game.final_position));
+    }
+
+    #[test]
+    fn test_latin1_player_names() {
+        // "Jérôme" and "Müller" as ChessBase-era software saves them
+        let pgn = b"[White \"J\xe9r\xf4me\"]\n[Black \"M\xfcller\"]\n[Event \"\x93Open\x94\"]\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n";
+        let game = parse_pgn_bytes(pgn).unwrap().remove(0);
+        assert_eq!(game.white.as_deref(), Some("Jérôme"));
+        assert_eq!(game.black.as_deref(), Some("Müller"));
+        assert_eq!(game.event.as_deref(), Some("“Open”"));
+        assert_eq!(game.moves, ["e4", "e5"]);
+
+        // UTF-8 still reads as UTF-8
+        let game = parse_pgn_bytes("[White \"Jérôme\"]\n\n1. e4 *\n".as_bytes()).unwrap().remove(0);
+        assert_eq!(game.white.as_deref(), Some("Jérôme"));
+
+        // A whole file no longer fails on the first non-UTF-8 byte
+        let path = std::env::temp_dir().join(format!("chess-analyzer-latin1-{}.pgn", std::process::id()));
+        fs::write(&path, pgn).unwrap();
+        let games = parse_pgn_file(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(games[0].white.as_deref(), Some("Jérôme"));
+    }
 }
@@ -0,0 +1,193 @@
+//! Fast PGN statistics without replaying moves
+
+use pgn_reader::{KnownOutcome, Outcome, RawTag, SanPlus, Visitor};
+use shakmaty::Color;
+use std::io::Read;
+use std::ops::ControlFlow;
+
+use super::pgn::PgnError;
+
+/// What's in a PGN file, as counted by `pgn_stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PgnStats {
+    pub games: u32,
+    /// Mainline plies across all games; variations are skipped
+    pub total_plies: u64,
+    pub white_wins: u32,
+    pub black_wins: u32,
+    pub draws: u32,
+    /// Games with `*` or no result
+    pub unfinished: u32,
+    /// Number of `WhiteElo`/`BlackElo` tags that parsed, and their sum
+    pub rated_players: u32,
+    pub rating_sum: u64,
+}
+
+impl PgnStats {
+    pub fn average_plies(&self) -> Option<f64> {
+        (self.games > 0).then(|| self.total_plies as f64 / self.games as f64)
+    }
+
+    /// Mean rating over every player with a rating tag
+    pub fn average_rating(&self) -> Option<f64> {
+        (self.rated_players > 0).then(|| self.rating_sum as f64 / self.rated_players as f64)
+    }
+}
+
+/// Per-game tag state of `StatsVisitor`
+#[derive(Default)]
+pub struct StatTags {
+    result: Option<Outcome>,
+    ratings: Vec<u16>,
+}
+
+/// Per-game movetext state of `StatsVisitor`
+pub struct StatMovetext {
+    tags: StatTags,
+    plies: u64,
+    /// The termination marker after the moves, used when there is no Result tag
+    outcome: Option<Outcome>,
+}
+
+/// Counts games, plies, results and ratings. Moves are counted as tokens
+/// and never checked for legality, which is what makes it cheap.
+#[derive(Default)]
+pub struct StatsVisitor {
+    stats: PgnStats,
+}
+
+impl StatsVisitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_stats(self) -> PgnStats {
+        self.stats
+    }
+}
+
+impl Visitor for StatsVisitor {
+    type Tags = StatTags;
+    type Movetext = StatMovetext;
+    type Output = ();
+
+    fn begin_tags(&mut self) -> ControlFlow<Self::Output, Self::Tags> {
+        ControlFlow::Continue(StatTags::default())
+    }
+
+    fn tag(
+        &mut self,
+        tags: &mut Self::Tags,
+        name: &[u8],
+        value: RawTag<'_>,
+    ) -> ControlFlow<Self::Output> {
+        match name {
+            b"Result" => tags.result = Outcome::from_ascii(value.as_bytes()).ok(),
+            b"WhiteElo" | b"BlackElo" => {
+                if let Ok(rating) = value.decode_utf8_lossy().trim().parse() {
+                    tags.ratings.push(rating);
+                }
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn begin_movetext(&mut self, tags: Self::Tags) -> ControlFlow<Self::Output, Self::Movetext> {
+        ControlFlow::Continue(StatMovetext { tags, plies: 0, outcome: None })
+    }
+
+    fn san(&mut self, movetext: &mut Self::Movetext, _san: SanPlus) -> ControlFlow<Self::Output> {
+        movetext.plies += 1;
+        ControlFlow::Continue(())
+    }
+
+    fn outcome(&mut self, movetext: &mut Self::Movetext, outcome: Outcome) -> ControlFlow<Self::Output> {
+        movetext.outcome = Some(outcome);
+        ControlFlow::Continue(())
+    }
+
+    fn end_game(&mut self, movetext: Self::Movetext) -> Self::Output {
+        let stats = &mut self.stats;
+        stats.games += 1;
+        stats.total_plies += movetext.plies;
+
+        match movetext.tags.result.or(movetext.outcome) {
+            Some(Outcome::Known(KnownOutcome::Decisive { winner: Color::White })) => stats.white_wins += 1,
+            Some(Outcome::Known(KnownOutcome::Decisive { winner: Color::Black })) => stats.black_wins += 1,
+            Some(Outcome::Known(KnownOutcome::Draw)) => stats.draws += 1,
+            Some(Outcome::Unknown) | None => stats.unfinished += 1,
+        }
+
+        for rating in movetext.tags.ratings {
+            stats.rated_players += 1;
+            stats.rating_sum += rating as u64;
+        }
+    }
+}
+
+/// Statistics for every game in `reader`, without building `PgnGame`s
+pub fn pgn_stats<R: Read>(reader: R) -> Result<PgnStats, PgnError> {
+    let mut visitor = StatsVisitor::new();
+    let mut reader = pgn_reader::Reader::new(reader);
+
+    loop {
+        match reader.read_game(&mut visitor) {
+            Ok(Some(())) => {}
+            Ok(None) => break,
+            Err(e) => return Err(PgnError::ParseError(e.to_string())),
+        }
+    }
+
+    Ok(visitor.into_stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTI_GAME_PGN: &str = r#"[Event "Club night"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+[WhiteElo "1500"]
+[BlackElo "1400"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0
+
+[Event "Club night"]
+[White "Carol"]
+[Black "Alice"]
+[Result "1/2-1/2"]
+[WhiteElo "1600"]
+
+1. d4 d5 (1... Nf6 2. c4) 2. c4 e6 1/2-1/2
+
+[Event "Casual"]
+[White "Dave"]
+[Black "Erin"]
+
+1. e4 Ke7 2. Qh5 0-1
+"#;
+
+    #[test]
+    fn test_pgn_stats_over_multiple_games() {
+        let stats = pgn_stats(MULTI_GAME_PGN.as_bytes()).unwrap();
+
+        assert_eq!(stats.games, 3);
+        // Variations are skipped, and the third game's result comes from
+        // its termination marker since it has no Result tag
+        assert_eq!(stats.total_plies, 5 + 4 + 3);
+        assert_eq!((stats.white_wins, stats.black_wins, stats.draws, stats.unfinished), (1, 1, 1, 0));
+        assert_eq!(stats.average_rating(), Some(1500.0));
+        assert_eq!(stats.average_plies(), Some(4.0));
+    }
+
+    #[test]
+    fn test_pgn_stats_empty_input() {
+        let stats = pgn_stats("".as_bytes()).unwrap();
+        assert_eq!(stats, PgnStats::default());
+        assert_eq!(stats.average_plies(), None);
+        assert_eq!(stats.average_rating(), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Tablebase-checked endgame conversion
+//!
+//! Engines at analysis depth misjudge plenty of simple endgames, but a
+//! tablebase knows the exact result of every position with few enough
+//! pieces. Scoring the player's endgame moves against it shows whether
+//! they kept what the position was worth.
+
+use shakmaty::{Chess, Color, Position, san::San};
+
+use crate::error::Result;
+use crate::lichess::Wdl;
+
+/// Most pieces (kings included) the Lichess tablebase covers
+pub const TABLEBASE_MAX_PIECES: usize = 7;
+
+/// Share of `color`'s tablebase-range moves, 0.0 to 1.0, that kept the
+/// theoretical result: a win stayed a win and a draw stayed a draw.
+///
+/// `probe` returns the result for the side to move, `None` when the
+/// tablebase doesn't know; moves whose positions can't be probed are not
+/// counted. `None` overall when no move was judged. An error from `probe`
+/// (e.g. no network) stops the scoring and is returned.
+pub fn endgame_conversion<F>(moves: &[String], color: Color, probe: F) -> Result<Option<f32>>
+where
+    F: FnMut(&Chess) -> Result<Option<Wdl>>,
+{
+    conversion_from(Chess::default(), moves, color, probe)
+}
+
+fn conversion_from<F>(mut position: Chess, moves: &[String], color: Color, mut probe: F) -> Result<Option<f32>>
+where
+    F: FnMut(&Chess) -> Result<Option<Wdl>>,
+{
+    let (mut kept, mut judged) = (0u32, 0u32);
+
+    for move_str in moves {
+        let mv = match move_str.parse::<San>().ok().and_then(|san| san.to_move(&position).ok()) {
+            Some(m) => m,
+            None => break,
+        };
+        let before = position.clone();
+        position = match position.play(mv) {
+            Ok(p) => p,
+            Err(_) => break,
+        };
+        if before.turn() != color || !in_tablebase(&before) {
+            continue;
+        }
+
+        let worth = match probe(&before)? {
+            Some(w) => w,
+            None => continue,
+        };
+        // The position after is probed from the opponent's side
+        let kept_up = if position.is_checkmate() {
+            Some(Wdl::Win)
+        } else if position.is_stalemate() || position.is_insufficient_material() {
+            Some(Wdl::Draw)
+        } else {
+            probe(&position)?.map(Wdl::flip)
+        };
+        if let Some(after) = kept_up {
+            judged += 1;
+            if after >= worth {
+                kept += 1;
+            }
+        }
+    }
+
+    Ok((judged > 0).then(|| kept as f32 / judged as f32))
+}
+
+/// Whether the tablebase can answer for `position`
+fn in_tablebase(position: &Chess) -> bool {
+    position.board().occupied().count() <= TABLEBASE_MAX_PIECES && position.castles().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, CastlingMode};
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    fn line(moves: &str) -> Vec<String> {
+        moves.split_whitespace().map(String::from).collect()
+    }
+
+    /// Stand-in for the tablebase in king and queen against king: the
+    /// queen wins unless the lone king can take it
+    fn kqk(position: &Chess) -> Result<Option<Wdl>> {
+        let board = position.board();
+        let queen_side = match board.queens().first().and_then(|sq| board.color_at(sq)) {
+            Some(c) => c,
+            None => return Ok(Some(Wdl::Draw)),
+        };
+        let queen_hangs = position.turn() != queen_side && position.legal_moves().iter().any(|m| m.is_capture());
+        Ok(Some(match (queen_hangs, position.turn() == queen_side) {
+            (true, _) => Wdl::Draw,
+            (false, true) => Wdl::Win,
+            (false, false) => Wdl::Loss,
+        }))
+    }
+
+    #[test]
+    fn test_throwing_away_a_won_endgame() {
+        let start = position("8/8/8/4k3/8/8/3Q4/4K3 w - - 0 1");
+
+        // Qg2 keeps the win; Qd5+ puts the queen next to the king
+        let moves = line("Qg2 Kd6 Qd5+ Kxd5");
+        assert_eq!(conversion_from(start.clone(), &moves, Color::White, kqk).unwrap(), Some(0.5));
+        // Black never made a lost position worse, then took the free queen
+        assert_eq!(conversion_from(start.clone(), &moves, Color::Black, kqk).unwrap(), Some(1.0));
+
+        let moves = line("Qg2 Kd6 Qg6+ Kc5");
+        assert_eq!(conversion_from(start.clone(), &moves, Color::White, kqk).unwrap(), Some(1.0));
+
+        // A failed probe is reported rather than guessed around
+        let offline = |_: &Chess| Err(crate::error::Error::Lichess("offline".to_string()));
+        assert!(conversion_from(start, &moves, Color::White, offline).is_err());
+    }
+
+    #[test]
+    fn test_no_tablebase_positions() {
+        let moves = line("e4 e5 Nf3 Nc6");
+        let probe = |_: &Chess| -> Result<Option<Wdl>> { panic!("probed a full board") };
+        assert_eq!(endgame_conversion(&moves, Color::White, probe).unwrap(), None);
+
+        // Unknown positions aren't judged either
+        let start = position("8/8/8/4k3/8/8/3Q4/4K3 w - - 0 1");
+        let unknown = |_: &Chess| Ok(None);
+        assert_eq!(conversion_from(start, &line("Qg2 Kd6"), Color::White, unknown).unwrap(), None);
+    }
+}
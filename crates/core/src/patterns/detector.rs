@@ -1,20 +1,270 @@
 //! Pattern detection engine
 
-use shakmaty::{Chess, Position, Move, Role, fen::Fen, EnPassantMode, san::San};
+use shakmaty::{Bitboard, Chess, Color, File, Position, Move, Rank, Role, Square, fen::Fen, EnPassantMode, san::{San, SanPlus}, uci::UciMove};
 
+use super::tactics::{legal_attackers, material_offered, moved_into_pin, piece_value, PinKind};
 use super::types::*;
-use crate::engine::StockfishEngine;
+use crate::engine::{engine_args, engine_path, AbortFlag, ChessEngine, EngineError, StockfishEngine};
 use crate::error::{Result, Error};
+use crate::lichess::MoveEval;
+use crate::positional::{
+    back_rank_sealed, back_rank_shield, game_phase, is_likely_fortress, king_safety, pawn_ending_mistake, pawn_structure,
+    GamePhase, PawnEndingMistake,
+};
+use crate::parser::{detect_repetition_draws, move_label, PgnGame};
+use crate::util::{parse_fen_lenient, piece_name, player_color};
+
+/// Eval (from the player's perspective) above which a position counts as won
+const WINNING_THRESHOLD_CP: i32 = 500;
+
+/// Score used for a forced mate
+const MATE_SCORE_CP: i32 = 10000;
+
+/// Net material a move must offer to count as a sacrifice
+const SACRIFICE_MIN_MATERIAL_CP: i32 = 100;
+
+/// Longest forced mate (in moves) whose omission is always a blunder
+const MISSED_MATE_MAX_MOVES: i32 = 2;
+
+/// Eval drop that confirms a new pin actually costs material
+const MOVED_INTO_PIN_MIN_LOSS_CP: i32 = 100;
+
+/// Eval drop that confirms a style tip (a pawn storm in front of the
+/// player's own king, an overextended pawn, a piece moved twice in the
+/// opening) cost something, below the inaccuracy threshold
+const STYLE_TIP_MIN_LOSS_CP: i32 = 25;
+
+/// Rank, from the pusher's side, from which a pawn counts as deep in
+/// enemy territory
+const OVEREXTENDED_PAWN_MIN_RANK: Rank = Rank::Sixth;
+
+/// Eval, for the player, at or below which passing up a check for a quiet
+/// move counts as missing a defensive resource
+const MISSED_DEFENSIVE_MAX_EVAL_CP: i32 = -100;
+
+/// How much worse the second-best move must be for the best to be the only
+/// reasonable one
+const FORCED_MOVE_MARGIN_CP: i32 = 150;
+
+/// Lines `position_sharpness` compares: the best move and the ones it is
+/// measured against
+const SHARPNESS_LINES: u8 = 4;
+
+/// Most `position_sharpness` a position can have and still count as calm
+/// for a quiet blunder (`DetectedPattern::quiet`)
+const QUIET_BLUNDER_MAX_SHARPNESS: f32 = 0.15;
+
+/// Eval, either way, within which a position counts as level for
+/// a quiet blunder
+const QUIET_BLUNDER_MAX_EVAL_CP: i32 = 150;
+
+/// Material difference within which a position counts as level for
+/// a quiet blunder
+const QUIET_BLUNDER_MAX_MATERIAL_CP: i32 = 100;
+
+/// Share of their first recorded clock a player must still have for a
+/// blunder not to be put down to time
+const QUIET_BLUNDER_MIN_CLOCK_SHARE: f64 = 0.25;
+
+/// Castling is usually right, so calling it a mistake takes an eval drop
+/// of at least this much, and attackers around the new king position too
+const CASTLED_INTO_ATTACK_MIN_LOSS_CP: i32 = 100;
+
+/// Enemy pieces bearing on the castled king's zone that make an attack
+const CASTLED_INTO_ATTACK_ZONE_ATTACKERS: u8 = 2;
+
+/// Eval drop after castling large enough that one attacker, or one open
+/// line, around the new king position shows an attack
+const CASTLED_INTO_ATTACK_LOSS_CP: i32 = 200;
+
+/// Move after which a king still uncastled on its starting square counts
+/// as left in the centre
+const KING_IN_CENTER_AFTER_MOVE: u32 = 10;
+
+/// Eval drop that confirms the opponent can get at a king left in the
+/// centre
+const KING_IN_CENTER_MIN_LOSS_CP: i32 = 100;
+
+/// Enemy pieces bearing on the uncastled king's zone that show the eval
+/// drop came from the king
+const KING_IN_CENTER_ZONE_ATTACKERS: u8 = 2;
+
+/// Depth of every engine search, and of the second pass in `FastMode`
+const ANALYSIS_DEPTH: u8 = 12;
+
+/// Two-pass engine analysis for quick triage of many games.
+///
+/// Every player move is screened at `screen_depth`; only moves whose cp
+/// loss there exceeds `threshold_cp` are searched again at `deep_depth`.
+/// Everything else is judged on the screening evals, so big mistakes are
+/// still caught and confirmed but recall drops for smaller ones: a shallow
+/// search can miss an inaccuracy, or a mistake it scores under the
+/// threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastMode {
+    pub screen_depth: u8,
+    pub deep_depth: u8,
+    pub threshold_cp: i32,
+}
+
+impl Default for FastMode {
+    fn default() -> Self {
+        Self { screen_depth: 8, deep_depth: ANALYSIS_DEPTH, threshold_cp: 100 }
+    }
+}
+
+/// How long a gambit gets to win its material back before its move counts
+/// as an error.
+///
+/// A move that looks like an error is followed `plies` down the engine's
+/// reply line and searched again there. If the player's eval has come
+/// back to within `threshold_cp` of what it was before the move, the dip
+/// was temporary (a Queen's Gambit, a Marshall) and the move is judged on
+/// the recovered eval instead, which usually clears it. Costs one more
+/// search per suspect move; engine analysis only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryWindow {
+    pub plies: u8,
+    pub threshold_cp: i32,
+}
+
+impl Default for RecoveryWindow {
+    fn default() -> Self {
+        Self { plies: 6, threshold_cp: 50 }
+    }
+}
+
+/// Optional analysis behaviour; everything is off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DetectorConfig {
+    /// Also report preventative tips such as `NoLuft`, which point out a
+    /// risk rather than a mistake the eval punished
+    pub style_tips: bool,
+    /// Drop inaccuracies played in positions `is_likely_fortress` calls a
+    /// dead draw, where a smaller winning margin means nothing. The check is
+    /// a heuristic, so this can hide a genuine inaccuracy now and then.
+    pub fortress_check: bool,
+    /// Score endgame conversion against the Lichess tablebase. Needs the
+    /// network; the analysis worker does the probing, not the detector.
+    pub tablebase: bool,
+    /// Screen shallowly and only search suspect moves deeply; engine
+    /// analysis only
+    pub fast_mode: Option<FastMode>,
+    /// Fill `GameReport::eval_debug` with the evals and cp loss of every
+    /// judged move, for checking the sign conventions on real games
+    pub debug_evals: bool,
+    /// Let moves whose eval dip the engine's line wins back, such as
+    /// gambits, off as temporary sacrifices
+    pub sacrifice_recovery: Option<RecoveryWindow>,
+}
+
+impl DetectorConfig {
+    /// Reads `ANALYSIS_STYLE_TIPS`, `ANALYSIS_FORTRESS_CHECK`,
+    /// `ANALYSIS_TABLEBASE`, `ANALYSIS_FAST_MODE`, `ANALYSIS_DEBUG_EVALS`
+    /// and `ANALYSIS_SACRIFICE_RECOVERY` ("1" or "true" turns each on; fast
+    /// mode and the recovery window use their defaults)
+    pub fn from_env() -> Self {
+        Self {
+            style_tips: env_flag("ANALYSIS_STYLE_TIPS"),
+            fortress_check: env_flag("ANALYSIS_FORTRESS_CHECK"),
+            tablebase: env_flag("ANALYSIS_TABLEBASE"),
+            fast_mode: env_flag("ANALYSIS_FAST_MODE").then(FastMode::default),
+            debug_evals: env_flag("ANALYSIS_DEBUG_EVALS"),
+            sacrifice_recovery: env_flag("ANALYSIS_SACRIFICE_RECOVERY").then(RecoveryWindow::default),
+        }
+    }
+
+    /// Whether an error of `severity` played from `position` goes unreported
+    fn suppresses(&self, severity: Severity, position: &Chess) -> bool {
+        self.fortress_check && severity == Severity::Inaccuracy && is_likely_fortress(position)
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
 
 pub struct PatternDetector {
-    engine: StockfishEngine,
+    engine: Box<dyn ChessEngine + Send>,
+    config: DetectorConfig,
+    abort: AbortFlag,
 }
 
 impl PatternDetector {
     pub fn new() -> Result<Self> {
-        let engine = StockfishEngine::new("stockfish")
+        let args = engine_args();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        Self::with_engine_args(&engine_path(), &args)
+    }
+
+    /// Like `new`, but runs the UCI engine at `path` instead of `$STOCKFISH_PATH`
+    pub fn with_engine(path: &str) -> Result<Self> {
+        Self::with_engine_args(path, &[])
+    }
+
+    /// Runs the UCI engine at `path` with extra command-line `args`
+    pub fn with_engine_args(path: &str, args: &[&str]) -> Result<Self> {
+        let engine = StockfishEngine::new_with_args(path, args)
             .map_err(|e| Error::Lichess(format!("Failed to start Stockfish: {}", e)))?;
-        Ok(Self { engine })
+        Ok(Self::with_chess_engine(engine))
+    }
+
+    /// Analyzes with `engine` instead of a Stockfish process: Lc0, a remote
+    /// service, anything that implements `ChessEngine`
+    pub fn with_chess_engine(engine: impl ChessEngine + Send + 'static) -> Self {
+        Self { engine: Box::new(engine), config: DetectorConfig::default(), abort: AbortFlag::new() }
+    }
+
+    pub fn config(&self) -> DetectorConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: DetectorConfig) {
+        self.config = config;
+    }
+
+    /// Lets `flag` stop engine analysis: it is checked before each of the
+    /// player's moves and during every search, and a game cut short fails
+    /// with `Error::Aborted`. The engine is left ready for the next game.
+    pub fn set_abort_flag(&mut self, flag: AbortFlag) {
+        self.engine.set_abort_flag(flag.clone());
+        self.abort = flag;
+    }
+
+    /// Runs the engine at a lower OS priority; see `StockfishEngine::set_priority`.
+    /// Fails for engines with no local process.
+    pub fn set_engine_priority(&self, nice: i32) -> Result<()> {
+        self.engine.set_priority(nice)
+            .map_err(|e| Error::Lichess(format!("Failed to lower engine priority: {}", e)))
+    }
+
+    /// Share of the player's moves where only one move was reasonable; see
+    /// `forced_move_ratio`
+    pub fn forced_move_ratio(
+        &mut self,
+        moves: &[String],
+        username: &str,
+        white_player: &str,
+        depth: u8,
+    ) -> Result<f32> {
+        forced_move_ratio(self.engine.as_mut(), moves, player_color(username, white_player), depth)
+    }
+
+    /// How punishing a slip is in `fen`; see `position_sharpness`
+    pub fn position_sharpness(&mut self, fen: &str, depth: u8) -> Result<f32> {
+        position_sharpness(self.engine.as_mut(), fen, depth)
+    }
+
+    /// Totals over a run of `analyze_game_report` results
+    pub fn batch_report(reports: &[GameReport]) -> BatchReport {
+        BatchReport::new(reports)
+    }
+
+    /// Tags the quiet blunders among `patterns`; see `tag_quiet_blunders`
+    pub fn tag_quiet_blunders(&mut self, patterns: &mut [DetectedPattern], clocks: Option<&[u32]>) -> Result<usize> {
+        tag_quiet_blunders(self.engine.as_mut(), patterns, clocks, ANALYSIS_DEPTH)
     }
 
     /// Analyze a game and detect patterns
@@ -26,99 +276,689 @@ impl PatternDetector {
         username: &str,
         white_player: &str,
     ) -> Result<Vec<DetectedPattern>> {
-        let mut patterns = Vec::new();
-        let mut position = Chess::default();
-        let is_white = username.eq_ignore_ascii_case(white_player);
-        
-        let mut prev_eval: Option<i32> = None;
-
-        for (ply, move_str) in moves.iter().enumerate() {
-            let move_number = (ply / 2) + 1;
-            let is_player_move = (ply % 2 == 0) == is_white;
-
-            // Parse SAN move
-            let san: San = match move_str.parse() {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Warning: Could not parse SAN '{}' at ply {}: {}", move_str, ply, e);
-                    break;
+        self.analyze_game_report(moves, username, white_player)
+            .map(|report| report.patterns)
+    }
+
+    /// Like `analyze_game`, but also reports the player's average centipawn
+    /// loss and any sound sacrifices.
+    ///
+    /// Each of the player's moves is searched before and after it is played,
+    /// so its cp loss is exactly what that move cost. Opponent moves are only
+    /// replayed on the board, so a game of `n` plies costs `2 * ceil(n / 2)`
+    /// searches analyzed for White and `2 * floor(n / 2)` for Black, less any
+    /// positions that are already over (mate, stalemate, dead draw). With
+    /// `DetectorConfig::fast_mode` those searches are shallow, plus two deep
+    /// ones per move the screen flags; `sacrifice_recovery` adds one per
+    /// move that looks like an error.
+    ///
+    /// Every search is sent the game's moves from the start rather than a
+    /// FEN, so the engine sees repetitions and the real fifty-move count and
+    /// doesn't score a move that heads for a drawn repetition as a loss.
+    pub fn analyze_game_report(
+        &mut self,
+        moves: &[String],
+        username: &str,
+        white_player: &str,
+    ) -> Result<GameReport> {
+        self.analyze_game_report_as(moves, player_color(username, white_player))
+    }
+
+    /// Like `analyze_game_report`, for whoever played `player`; for
+    /// callers that know the side but not a username to match
+    pub fn analyze_game_report_as(&mut self, moves: &[String], player: Color) -> Result<GameReport> {
+        self.engine_report(moves, None, player)
+    }
+
+    /// Like `analyze_game_report`, with the game's moves also in UCI as
+    /// `Database::moves_uci` stores them. Those go to the engine as they
+    /// are; `moves` (SAN) is only used for display. A UCI line that doesn't
+    /// match `moves` in length is ignored and the SAN is converted instead.
+    pub fn analyze_stored_game_report(
+        &mut self,
+        moves: &[String],
+        moves_uci: &[String],
+        username: &str,
+        white_player: &str,
+    ) -> Result<GameReport> {
+        self.engine_report(moves, Some(moves_uci), player_color(username, white_player))
+    }
+
+    fn engine_report(&mut self, moves: &[String], moves_uci: Option<&[String]>, player: Color) -> Result<GameReport> {
+        let config = self.config;
+        analyze_moves(moves, moves_uci, player, &config, |_, before, after, played| {
+            if self.abort.is_aborted() {
+                return Err(Error::Aborted);
+            }
+            let earlier = &played[..played.len() - 1];
+            // Scores are relative to the side to move, so `reply` is the
+            // opponent's and the move's cp loss is their sum
+            let (search, reply, depth) = match config.fast_mode {
+                Some(fast) => {
+                    let screen = (
+                        self.search(before, earlier, fast.screen_depth)?,
+                        self.search(after, played, fast.screen_depth)?,
+                    );
+                    if screen.0.score + screen.1.score > fast.threshold_cp {
+                        let deep = (self.search(before, earlier, fast.deep_depth)?, self.search(after, played, fast.deep_depth)?);
+                        (deep.0, deep.1, fast.deep_depth)
+                    } else {
+                        (screen.0, screen.1, fast.screen_depth)
+                    }
                 }
+                None => (self.search(before, earlier, ANALYSIS_DEPTH)?, self.search(after, played, ANALYSIS_DEPTH)?, ANALYSIS_DEPTH),
             };
-
-            let mv = match san.to_move(&position) {
-                Ok(m) => m,
-                Err(e) => {
-                    eprintln!("Warning: Invalid move '{}' at ply {}: {}", move_str, ply, e);
-                    break;
+            let recovered = match config.sacrifice_recovery {
+                Some(window) if Severity::from_cp_loss(search.score + reply.score).is_some() => {
+                    self.recovered_eval(after, played, &reply.pv, window.plies, depth)?
                 }
+                _ => None,
+            };
+            Ok(Some(MoveEvals {
+                best_line: uci_line_to_san(before, &search.pv),
+                best_move: search.best_move,
+                before: search.score,
+                after: Some(-reply.score),
+                mate_before: search.mate,
+                mate_after: reply.mate.map(|m| -m),
+                recovered,
+            }))
+        })
+    }
+
+    /// The eval of the player who just reached `position` (by `played`),
+    /// `plies` down the reply line `pv`, or as far as its legal moves go.
+    /// `None` when the line is empty.
+    fn recovered_eval(&mut self, position: &Chess, played: &[String], pv: &[String], plies: u8, depth: u8) -> Result<Option<i32>> {
+        let mut position = position.clone();
+        let mut line = played.to_vec();
+        for uci in pv.iter().take(plies as usize) {
+            let mv = match uci.parse::<UciMove>().ok().and_then(|m| m.to_move(&position).ok()) {
+                Some(m) => m,
+                None => break,
+            };
+            position.play_unchecked(mv);
+            line.push(uci.clone());
+        }
+        let followed = line.len() - played.len();
+        if followed == 0 {
+            return Ok(None);
+        }
+        let score = self.search(&position, &line, depth)?.score;
+        // An even number of plies on, it's the opponent's move again
+        Ok(Some(if followed.is_multiple_of(2) { -score } else { score }))
+    }
+
+    /// Best move, score and line from the side to move's point of view
+    /// `position` is reached by `moves` (UCI) from the starting position.
+    fn search(&mut self, position: &Chess, moves: &[String], depth: u8) -> Result<Search> {
+        if position.is_checkmate() {
+            return Ok(Search::terminal(-MATE_SCORE_CP));
+        }
+        if position.is_stalemate() || position.is_insufficient_material() {
+            return Ok(Search::terminal(0));
+        }
+
+        self.engine.set_position(None, Some(moves))
+            .map_err(|e| Error::Lichess(format!("Engine error: {}", e)))?;
+        let analysis = self.engine.analyze(depth).map_err(|e| match e {
+            EngineError::Aborted => Error::Aborted,
+            e => Error::Lichess(format!("Analysis error: {}", e)),
+        })?;
+
+        let mate = match analysis.evaluation {
+            crate::engine::Evaluation::Mate(m) => Some(m),
+            crate::engine::Evaluation::Centipawns(_) => None,
+        };
+        let score = score_cp(&analysis.evaluation);
+        Ok(Search { best_move: analysis.best_move, score, mate, pv: analysis.pv })
+    }
+
+    /// True if `evals` has an entry for every move that can be judged.
+    /// Lichess omits the entry after the final move when the game ends in mate.
+    pub fn evals_cover(moves: &[String], evals: &[MoveEval]) -> bool {
+        !moves.is_empty() && evals.len() + 1 >= moves.len()
+    }
+
+    /// Detect patterns from Lichess server analysis, without an engine.
+    ///
+    /// The cp loss of a move is the eval drop across that move, so unlike
+    /// the engine path it needs no extra searches.
+    pub fn analyze_with_lichess_evals(
+        moves: &[String],
+        username: &str,
+        white_player: &str,
+        evals: &[MoveEval],
+    ) -> Result<Vec<DetectedPattern>> {
+        Self::lichess_evals_report(moves, username, white_player, evals, &DetectorConfig::default())
+            .map(|report| report.patterns)
+    }
+
+    /// Like `analyze_with_lichess_evals`, but also reports the player's
+    /// average centipawn loss and any sound sacrifices
+    pub fn lichess_evals_report(
+        moves: &[String],
+        username: &str,
+        white_player: &str,
+        evals: &[MoveEval],
+        config: &DetectorConfig,
+    ) -> Result<GameReport> {
+        let player = player_color(username, white_player);
+        let sign = if player == Color::White { 1 } else { -1 };
+        let player_cp = |idx: usize| evals.get(idx).and_then(|e| e.white_cp()).map(|cp| cp * sign);
+        let player_mate = |idx: usize| evals.get(idx).and_then(|e| e.mate).map(|m| m * sign);
+
+        analyze_moves(moves, None, player, config, |ply, _, _, _| {
+            // Lichess has no eval for the starting position
+            let before = match ply.checked_sub(1).and_then(player_cp) {
+                Some(b) => b,
+                None => return Ok(None),
             };
+            Ok(Some(MoveEvals {
+                best_move: evals.get(ply).and_then(|e| e.best.clone()).unwrap_or_default(),
+                best_line: evals.get(ply).and_then(|e| e.variation.clone()).unwrap_or_default(),
+                before,
+                after: player_cp(ply),
+                mate_before: player_mate(ply - 1),
+                mate_after: player_mate(ply),
+                recovered: None,
+            }))
+        })
+    }
+}
 
-            let fen_before = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+/// Share of `color`'s moves, 0.0 to 1.0, where there was effectively one
+/// reasonable move: the only legal one, or one the second-best move trails
+/// by more than `FORCED_MOVE_MARGIN_CP`.
+///
+/// A high ratio means the game mostly played itself (recaptures, forced
+/// sequences); a low one means the player kept having real choices. Each
+/// of the player's positions costs one two-line search at `depth`.
+pub fn forced_move_ratio(engine: &mut dyn ChessEngine, moves: &[String], color: Color, depth: u8) -> Result<f32> {
+    let mut position = Chess::default();
+    let (mut forced, mut judged) = (0u32, 0u32);
 
-            if is_player_move {
-                // Get Stockfish eval for this position
-                self.engine.set_position(Some(&fen_before), None)
+    for move_str in moves {
+        let mv = match move_str.parse::<San>().ok().and_then(|san| san.to_move(&position).ok()) {
+            Some(m) => m,
+            None => break,
+        };
+
+        if position.turn() == color {
+            judged += 1;
+            let only_move = if position.legal_moves().len() == 1 {
+                true
+            } else {
+                let fen = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+                engine.set_position(Some(&fen), None)
                     .map_err(|e| Error::Lichess(format!("Engine error: {}", e)))?;
-                
-                let analysis = self.engine.analyze(12)
+                let lines = engine.analyze_multipv(depth, 2)
                     .map_err(|e| Error::Lichess(format!("Analysis error: {}", e)))?;
+                match lines.as_slice() {
+                    [best, second, ..] => score_cp(&best.evaluation) - score_cp(&second.evaluation) > FORCED_MOVE_MARGIN_CP,
+                    _ => true,
+                }
+            };
+            if only_move {
+                forced += 1;
+            }
+        }
 
-                let best_move = &analysis.best_move;
-                let current_eval = match &analysis.evaluation {
-                    crate::engine::Evaluation::Centipawns(cp) => *cp,
-                    crate::engine::Evaluation::Mate(m) => if *m > 0 { 10000 } else { -10000 },
-                };
+        position = match position.play(mv) {
+            Ok(p) => p,
+            Err(_) => break,
+        };
+    }
 
-                // Adjust eval perspective (negative for black)
-                let eval_for_player = if is_white { current_eval } else { -current_eval };
-                let player_uci = move_to_uci(&mv);
-
-                // Check if player's move differs from best
-                if !best_move.is_empty() && *best_move != player_uci {
-                    // Calculate centipawn loss
-                    if let Some(prev) = prev_eval {
-                        let cp_loss = (prev - eval_for_player).max(0);
-                        
-                        if let Some(severity) = Severity::from_cp_loss(cp_loss) {
-                            let pattern_type = classify_pattern(&position, &mv, cp_loss);
-                            
-                            patterns.push(DetectedPattern {
-                                move_number: move_number as u16,
-                                ply: ply as u16,
-                                pattern_type,
-                                severity,
-                                cp_loss,
-                                player_move: move_str.clone(),
-                                best_move: best_move.clone(),
-                                fen_before: fen_before.clone(),
-                                fen_after: String::new(),
-                                description: format!(
-                                    "Move {}: played {} instead of {} (-{} cp)",
-                                    move_number, move_str, best_move, cp_loss
-                                ),
-                            });
-                        }
-                    }
-                }
+    Ok(if judged == 0 { 0.0 } else { forced as f32 / judged as f32 })
+}
 
-                prev_eval = Some(eval_for_player);
+/// How much a move other than the best costs in `fen`, 0.0 to 1.0: the
+/// side to move's win probability after the best move, less its average
+/// after the next `SHARPNESS_LINES - 1`.
+///
+/// Near 1.0 there is one good move and the rest lose (a tactic, a critical
+/// moment); near 0.0 most moves are about as good. Working in win
+/// probability keeps mates in range and makes a pawn matter less once the
+/// game is already won. A position with fewer than two legal moves leaves
+/// nothing to get wrong and scores 0.0 without a search; otherwise it costs
+/// one MultiPV search at `depth`.
+pub fn position_sharpness(engine: &mut dyn ChessEngine, fen: &str, depth: u8) -> Result<f32> {
+    let position = parse_fen_lenient(fen)?;
+    Ok(sharpness_search(engine, &position, depth)?.0)
+}
+
+/// `position_sharpness` of `position`, with the best line's score for the
+/// side to move; no score when there was nothing to search
+fn sharpness_search(engine: &mut dyn ChessEngine, position: &Chess, depth: u8) -> Result<(f32, Option<i32>)> {
+    if position.legal_moves().len() < 2 {
+        return Ok((0.0, None));
+    }
+
+    let fen = Fen::from_position(position, EnPassantMode::Legal).to_string();
+    engine.set_position(Some(&fen), None)
+        .map_err(|e| Error::Lichess(format!("Engine error: {}", e)))?;
+    let lines = engine.analyze_multipv(depth, SHARPNESS_LINES)
+        .map_err(|e| Error::Lichess(format!("Analysis error: {}", e)))?;
+
+    let (best, rest) = match lines.split_first() {
+        Some((best, rest)) if !rest.is_empty() => (best, rest),
+        Some((best, _)) => return Ok((0.0, Some(score_cp(&best.evaluation)))),
+        None => return Ok((0.0, None)),
+    };
+    let rest_average = rest.iter().map(|l| l.evaluation.win_probability()).sum::<f64>() / rest.len() as f64;
+    let sharpness = (best.evaluation.win_probability() - rest_average).clamp(0.0, 1.0) as f32;
+    Ok((sharpness, Some(score_cp(&best.evaluation))))
+}
+
+/// Marks the blunders in `patterns` made in calm, level positions with
+/// time to spare as `DetectedPattern::quiet`, and returns how many. Their
+/// pattern type is left as it was.
+///
+/// These are the pure calculation errors: nothing was sharp, nothing was
+/// at stake yet, and the clock wasn't the problem. A blunder is quiet when
+/// the position before it had material within `QUIET_BLUNDER_MAX_MATERIAL_CP`,
+/// an eval within `QUIET_BLUNDER_MAX_EVAL_CP` and a `position_sharpness` of
+/// at most `QUIET_BLUNDER_MAX_SHARPNESS`. `clocks` is the mover's remaining
+/// time after each ply in centiseconds, as Lichess exports it; when given,
+/// the player must still have had `QUIET_BLUNDER_MIN_CLOCK_SHARE` of their
+/// first recorded time after the move, and a ply it doesn't cover is never
+/// quiet. Costs one MultiPV search at `depth` per blunder on a level board.
+pub fn tag_quiet_blunders(
+    engine: &mut dyn ChessEngine,
+    patterns: &mut [DetectedPattern],
+    clocks: Option<&[u32]>,
+    depth: u8,
+) -> Result<usize> {
+    let mut tagged = 0;
+    for pattern in patterns.iter_mut().filter(|p| p.severity == Severity::Blunder) {
+        if let Some(clocks) = clocks {
+            let ply = pattern.ply as usize;
+            let ample = match (clocks.get(ply), clocks.get(ply % 2)) {
+                (Some(&left), Some(&start)) => left as f64 >= start as f64 * QUIET_BLUNDER_MIN_CLOCK_SHARE,
+                _ => false,
+            };
+            if !ample {
+                continue;
+            }
+        }
+
+        let position = parse_fen_lenient(&pattern.fen_before)?;
+        if material_balance(&position).abs() > QUIET_BLUNDER_MAX_MATERIAL_CP {
+            continue;
+        }
+        let (sharpness, eval) = sharpness_search(engine, &position, depth)?;
+        let level = eval.is_some_and(|cp| cp.abs() <= QUIET_BLUNDER_MAX_EVAL_CP);
+        if level && sharpness <= QUIET_BLUNDER_MAX_SHARPNESS {
+            pattern.quiet = true;
+            tagged += 1;
+        }
+    }
+    Ok(tagged)
+}
+
+/// White's material less Black's, in centipawns
+fn material_balance(position: &Chess) -> i32 {
+    let board = position.board();
+    board.occupied().into_iter()
+        .filter_map(|sq| board.piece_at(sq))
+        .map(|piece| match piece.color {
+            Color::White => piece_value(piece.role),
+            Color::Black => -piece_value(piece.role),
+        })
+        .sum()
+}
+
+/// An engine score in centipawns, with forced mates as `MATE_SCORE_CP`
+fn score_cp(evaluation: &crate::engine::Evaluation) -> i32 {
+    match *evaluation {
+        crate::engine::Evaluation::Centipawns(cp) => cp,
+        crate::engine::Evaluation::Mate(m) => if m > 0 { MATE_SCORE_CP } else { -MATE_SCORE_CP },
+    }
+}
+
+/// Result of one engine search
+struct Search {
+    best_move: String,
+    score: i32,
+    /// Moves to mate; negative when the side to move is getting mated
+    mate: Option<i32>,
+    /// Principal variation in UCI
+    pv: Vec<String>,
+}
+
+impl Search {
+    /// Game over: nothing to search, and no mate still to deliver
+    fn terminal(score: i32) -> Self {
+        Self { best_move: String::new(), score, mate: None, pv: Vec::new() }
+    }
+}
+
+/// Evals around one of the player's moves, from the player's point of view
+struct MoveEvals {
+    /// Best move in the position before, in UCI; empty if unknown
+    best_move: String,
+    /// Best line from the position before, in SAN; empty if unknown
+    best_line: String,
+    before: i32,
+    /// Missing when the source has no eval for the resulting position
+    after: Option<i32>,
+    /// Moves the player needs to force mate before and after the move;
+    /// negative when the player is the one getting mated
+    mate_before: Option<i32>,
+    mate_after: Option<i32>,
+    /// The player's eval further down the reply line, when
+    /// `DetectorConfig::sacrifice_recovery` looked
+    recovered: Option<i32>,
+}
+
+/// Walks the game and judges every move of `player`.
+///
+/// `eval_move` is called with the ply, the positions before and after each
+/// of the player's moves and the game so far in UCI, that move included;
+/// returning `None` skips the move. The UCI comes from `moves_uci` when it
+/// has a move for every one in `moves`, else from replaying the SAN.
+///
+/// A move that makes a threefold repetition claimable isn't judged: it
+/// heads for a draw the player could take, whatever the eval says. Play
+/// that goes on past the repetition is judged as usual.
+fn analyze_moves<F>(
+    moves: &[String],
+    moves_uci: Option<&[String]>,
+    player: Color,
+    config: &DetectorConfig,
+    mut eval_move: F,
+) -> Result<GameReport>
+where
+    F: FnMut(usize, &Chess, &Chess, &[String]) -> Result<Option<MoveEvals>>,
+{
+    let mut patterns = Vec::new();
+    let mut sacrifices = Vec::new();
+    let mut cp_losses = Vec::new();
+    let mut eval_debug = Vec::new();
+    let mut position = Chess::default();
+    let is_white = player == Color::White;
+    // Where the previous move captured, if it did
+    let mut last_capture: Option<Square> = None;
+    let mut played: Vec<String> = Vec::with_capacity(moves.len());
+    // Times the piece now on each square has moved, indexed by square
+    let mut piece_moves = [0u8; 64];
+    let moves_uci = moves_uci.filter(|uci| uci.len() == moves.len());
+    let repetition_claims = detect_repetition_draws(moves);
+
+    for (ply, move_str) in moves.iter().enumerate() {
+        let move_number = PgnGame::ply_to_move_number(ply);
+        let is_player_move = (ply % 2 == 0) == is_white;
+
+        let mv = match moves_uci {
+            Some(uci) => uci[ply].parse::<UciMove>().ok().and_then(|m| m.to_move(&position).ok()),
+            None => move_str.parse::<San>().ok().and_then(|san| san.to_move(&position).ok()),
+        };
+        let mv = match mv {
+            Some(m) => m,
+            None => {
+                eprintln!("Warning: Invalid move '{}' at ply {}", move_str, ply);
+                break;
             }
+        };
+        let recapture_square = last_capture.filter(|&sq| mv.is_capture() && mv.to() == sq);
+        last_capture = mv.is_capture().then(|| mv.to());
+        played.push(match moves_uci {
+            Some(uci) => uci[ply].clone(),
+            None => UciMove::from_standard(mv).to_string(),
+        });
+        let moved_before = mv.from().map_or(0, |from| piece_moves[usize::from(from)]);
+        track_piece_moves(&mut piece_moves, &mv);
 
-            // Apply the move
-            position = match position.play(mv.clone()) {
+        // Opponent moves need no evals or FENs, just the board
+        if !is_player_move {
+            position = match position.play(mv) {
                 Ok(p) => p,
                 Err(_) => break,
             };
+            continue;
+        }
 
-            // Update fen_after for last pattern
-            if let Some(last) = patterns.last_mut() {
-                if last.ply == ply as u16 {
-                    last.fen_after = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+        let position_before = position.clone();
+        position = match position.play(mv) {
+            Ok(p) => p,
+            Err(_) => break,
+        };
+        if repetition_claims.contains(&(ply as u16)) {
+            continue;
+        }
+
+        let evals = match eval_move(ply, &position_before, &position, &played)? {
+            Some(e) => e,
+            None => continue,
+        };
+        let fen_before = Fen::from_position(&position_before, EnPassantMode::Legal).to_string();
+        let fen_after = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+        let mut record = |eval_after: i32, cp_loss: i32| {
+            cp_losses.push(cp_loss);
+            if config.debug_evals {
+                eval_debug.push(EvalDebugRecord {
+                    ply: ply as u16,
+                    player_move: move_str.clone(),
+                    side: position_before.turn(),
+                    eval_before: evals.before,
+                    eval_after,
+                    cp_loss,
+                });
+            }
+        };
+
+        if allowed_stalemate(evals.before, &position) {
+            // Stalemate is a dead draw
+            record(0, evals.before);
+            patterns.push(DetectedPattern {
+                move_number,
+                ply: ply as u16,
+                pattern_type: PatternType::AllowedStalemate,
+                severity: Severity::Blunder,
+                cp_loss: evals.before,
+                player_move: move_str.clone(),
+                best_move: String::new(),
+                fen_before,
+                fen_after,
+                description: format!(
+                    "{} {} stalemates the opponent in a winning position (+{} cp thrown away)",
+                    move_label(ply), move_str, evals.before
+                ),
+                quiet: false,
+            });
+            continue;
+        }
+
+        if let Some(mate_in) = missed_mate(&evals, &position) {
+            let cp_loss = evals.after.map_or(0, |a| (evals.before - a).max(0));
+            let line = if evals.best_line.is_empty() { &evals.best_move } else { &evals.best_line };
+            record(evals.after.unwrap_or(evals.before), cp_loss);
+            patterns.push(DetectedPattern {
+                move_number,
+                ply: ply as u16,
+                pattern_type: PatternType::MissedMate,
+                severity: Severity::Blunder,
+                cp_loss,
+                player_move: move_str.clone(),
+                best_move: evals.best_move.clone(),
+                fen_before,
+                fen_after,
+                description: format!(
+                    "{} {} missed mate in {} ({})",
+                    move_label(ply), move_str, mate_in, line
+                ),
+                quiet: false,
+            });
+            continue;
+        }
+
+        let after = match evals.after {
+            // Playing the engine's move costs nothing, whatever a second search says
+            Some(_) if evals.best_move == move_to_uci(&mv) => evals.before,
+            Some(a) => a,
+            None => continue,
+        };
+        // A dip the engine's own line wins back is a gambit, not a loss
+        let after = match (config.sacrifice_recovery, evals.recovered) {
+            (Some(window), Some(recovered)) if evals.before - recovered <= window.threshold_cp => {
+                after.max(recovered.min(evals.before))
+            }
+            _ => after,
+        };
+        let cp_loss = (evals.before - after).max(0);
+        record(after, cp_loss);
+
+        match judge_move(&position_before, &mv, evals.before, after) {
+            MoveQuality::Sound => {
+                let tip = if !config.style_tips {
+                    None
+                } else if skipped_luft(&position_before, &mv, &evals.best_move) {
+                    Some((PatternType::NoLuft, "leaves the king boxed in on the back rank", "given it luft"))
+                } else if lost_castling(&position_before, &position, &evals.best_move) {
+                    Some((PatternType::LostCastling, "gives up the right to castle", "kept it"))
+                } else if weakening_pawn_storm(&position_before, &position, &mv, &evals.best_move, cp_loss) {
+                    Some((PatternType::WeakeningPawnStorm, "loosens the pawns in front of its own king", "kept them home"))
+                } else if overextended_pawn(&position_before, &position, &mv, &evals.best_move, cp_loss) {
+                    Some((PatternType::OverextendedPawn, "pushes the pawn where it's attacked more than defended", "consolidated first"))
+                } else if opening_piece_shuffle(&position_before, &position, &mv, moved_before, &evals.best_move, cp_loss) {
+                    Some((PatternType::OpeningPieceShuffle, "moves the same piece again before finishing development", "developed a new piece"))
+                } else {
+                    None
+                };
+                if let Some((pattern_type, problem, fix)) = tip {
+                    patterns.push(DetectedPattern {
+                        move_number,
+                        ply: ply as u16,
+                        pattern_type,
+                        severity: Severity::Inaccuracy,
+                        cp_loss,
+                        player_move: move_str.clone(),
+                        best_move: evals.best_move.clone(),
+                        fen_before,
+                        fen_after,
+                        description: format!(
+                            "{} {} {}; {} would have {}",
+                            move_label(ply), move_str, problem, evals.best_move, fix
+                        ),
+                        quiet: false,
+                    });
+                }
+            }
+            MoveQuality::Sacrifice { material } => sacrifices.push(Sacrifice {
+                move_number,
+                ply: ply as u16,
+                player_move: move_str.clone(),
+                material,
+                eval_before: evals.before,
+                eval_after: after,
+                fen_before,
+            }),
+            MoveQuality::Error(severity) if config.suppresses(severity, &position_before) => {}
+            MoveQuality::Error(severity) => {
+                let better_recapture = recapture_square
+                    .and_then(|sq| better_recapture(&position_before, &mv, &evals.best_move, sq));
+                let free_capture = match better_recapture {
+                    Some(_) => None,
+                    None => missed_free_capture(&position_before, &mv, &evals.best_move),
+                };
+                let defensive_check = match (better_recapture, free_capture) {
+                    (None, None) => missed_defensive_check(&position_before, &mv, &evals.best_move, evals.before),
+                    _ => None,
+                };
+                let pattern_type = match (better_recapture, free_capture, defensive_check) {
+                    (Some(_), _, _) => PatternType::WrongRecapture,
+                    (None, Some(_), _) => PatternType::MissedFreeCapture,
+                    (None, None, Some(_)) => PatternType::MissedDefensiveResource,
+                    (None, None, None) => classify_pattern(&position_before, &mv, cp_loss),
+                };
+                // Leaving a free piece is as bad as the piece is worth
+                let severity = match free_capture {
+                    Some((_, role)) => Severity::from_cp_loss(cp_loss.max(piece_value(role))).unwrap_or(severity),
+                    None => severity,
+                };
+                let mut description = format!(
+                    "{} {} instead of {} (-{} cp)",
+                    move_label(ply), move_str, evals.best_move, cp_loss
+                );
+                if let Some(better) = better_recapture {
+                    description.push_str(&format!(
+                        "; recapturing with {} was better",
+                        San::from_move(&position_before, better)
+                    ));
+                } else if let Some((square, role)) = free_capture {
+                    description.push_str(&format!("; the {} on {} was free to take", piece_name(role).to_lowercase(), square));
+                } else if let Some(check) = defensive_check {
+                    description.push_str(&format!(
+                        "; already worse, the check {} was the way to fight back",
+                        SanPlus::from_move_and_play_unchecked(&mut position_before.clone(), check)
+                    ));
+                } else if pattern_type == PatternType::CastledIntoAttack {
+                    let attackers = king_safety(&position, position_before.turn()).zone_attackers;
+                    description.push_str(&format!(
+                        "; castling put the king where {} enemy pieces already bear on it",
+                        attackers
+                    ));
+                } else if pattern_type == PatternType::KingInCenter {
+                    description.push_str(&format!(
+                        "; the king is still uncastled on move {} with the centre open",
+                        move_number
+                    ));
+                } else if pattern_type == PatternType::EndgameError {
+                    match pawn_ending_mistake(&position_before, &mv) {
+                        Some(PawnEndingMistake::LostOpposition) => description.push_str("; lost the opposition"),
+                        Some(PawnEndingMistake::PawnOutOfSquare(pawn)) => {
+                            description.push_str(&format!("; let the pawn on {} out of the square", pawn))
+                        }
+                        None => {}
+                    }
+                } else if pattern_type == PatternType::MovedIntoPin {
+                    if let Some(pin) = moved_into_pin(&position_before, &mv) {
+                        let (kind, target) = match pin.kind {
+                            PinKind::Absolute => ("absolute", "king"),
+                            PinKind::Relative => ("relative", "queen"),
+                        };
+                        description.push_str(&format!(
+                            "; {} pin: the piece on {} is pinned to the {} by {}",
+                            kind, pin.pinned, target, pin.pinner
+                        ));
+                    }
                 }
+                patterns.push(DetectedPattern {
+                    move_number,
+                    ply: ply as u16,
+                    pattern_type,
+                    severity,
+                    cp_loss,
+                    player_move: move_str.clone(),
+                    best_move: evals.best_move.clone(),
+                    fen_before,
+                    fen_after,
+                    description,
+                    quiet: false,
+                });
             }
         }
+    }
+
+    let mut report = GameReport::new(patterns, sacrifices, &cp_losses);
+    report.eval_debug = eval_debug;
+    report.repetition_claim = repetition_claims.first().copied();
+    Ok(report)
+}
 
-        Ok(patterns)
+/// Judges a move from the evals (player's point of view) either side of it.
+///
+/// A move that gives up material is only an error if the eval agrees;
+/// when the eval holds or improves it is a sacrifice.
+fn judge_move(position: &Chess, played_move: &Move, eval_before: i32, eval_after: i32) -> MoveQuality {
+    let severity = Severity::from_cp_loss((eval_before - eval_after).max(0));
+    let material = material_offered(position, played_move);
+
+    match severity {
+        None if material >= SACRIFICE_MIN_MATERIAL_CP => MoveQuality::Sacrifice { material },
+        None => MoveQuality::Sound,
+        Some(severity) => MoveQuality::Error(severity),
     }
 }
 
@@ -148,6 +988,369 @@ fn move_to_uci(mv: &Move) -> String {
     }
 }
 
+/// True if the player was winning before the move and it left the opponent stalemated
+fn allowed_stalemate(eval_before: i32, position_after: &Chess) -> bool {
+    eval_before > WINNING_THRESHOLD_CP && position_after.is_stalemate()
+}
+
+/// Length of the short forced mate the player had and the move let slip,
+/// whatever the centipawn scores say.
+///
+/// Keeping the mate means delivering it or leaving a strictly shorter one.
+fn missed_mate(evals: &MoveEvals, position_after: &Chess) -> Option<i32> {
+    let mate_in = evals.mate_before.filter(|&n| n > 0 && n <= MISSED_MATE_MAX_MOVES)?;
+    if position_after.is_checkmate() {
+        return None;
+    }
+    match evals.mate_after {
+        Some(m) if m > 0 && m < mate_in => None,
+        _ => Some(mate_in),
+    }
+}
+
+/// Renders a UCI line as SAN, stopping at the first move that doesn't parse
+fn uci_line_to_san(position: &Chess, line: &[String]) -> String {
+    let mut position = position.clone();
+    let mut sans = Vec::new();
+    for uci in line {
+        let mv = match uci.parse::<UciMove>().ok().and_then(|u| u.to_move(&position).ok()) {
+            Some(m) => m,
+            None => break,
+        };
+        sans.push(SanPlus::from_move_and_play_unchecked(&mut position, mv).to_string());
+    }
+    sans.join(" ")
+}
+
+/// The engine's best move when it recaptures on `square` with a different
+/// piece than the one the player took back with
+fn better_recapture(position: &Chess, played_move: &Move, best_move: &str, square: Square) -> Option<Move> {
+    let best = engine_move(position, best_move)?;
+    (best.is_capture() && best.to() == square && best.from() != played_move.from()).then_some(best)
+}
+
+/// The opponent piece the engine's best move takes for free, with its
+/// square, when the player left it: nothing can legally recapture there
+/// and the player didn't capture on that square either
+fn missed_free_capture(position: &Chess, played_move: &Move, best_move: &str) -> Option<(Square, Role)> {
+    let best = engine_move(position, best_move)?;
+    let role = best.capture().filter(|_| !best.is_en_passant())?;
+    let square = best.to();
+    if played_move.is_capture() && played_move.to() == square {
+        return None;
+    }
+    legal_attackers(position, square, !position.turn()).is_empty().then_some((square, role))
+}
+
+/// The engine's best move when it was a check the player passed up for a
+/// quiet move in a position that was already going wrong (`eval_before`
+/// at most `MISSED_DEFENSIVE_MAX_EVAL_CP` for the player): the active
+/// defense that was on offer
+fn missed_defensive_check(position: &Chess, played_move: &Move, best_move: &str, eval_before: i32) -> Option<Move> {
+    if eval_before > MISSED_DEFENSIVE_MAX_EVAL_CP || gives_check(position, played_move) {
+        return None;
+    }
+    let best = engine_move(position, best_move)?;
+    gives_check(position, &best).then_some(best)
+}
+
+/// The engine's best move, given in UCI, as a move in `position`; `None`
+/// when it is missing or doesn't fit the position
+fn engine_move(position: &Chess, best_move: &str) -> Option<Move> {
+    best_move.parse::<UciMove>().ok()?.to_move(position).ok()
+}
+
+fn gives_check(position: &Chess, mv: &Move) -> bool {
+    position.clone().play(*mv).is_ok_and(|after| after.is_check())
+}
+
+/// True if, before the endgame, the move took the player's last castling
+/// right without castling (a king move, or moving the only rook that could
+/// still castle), while the engine's move kept one
+fn lost_castling(position: &Chess, position_after: &Chess, best_move: &str) -> bool {
+    let mover = position.turn();
+    if game_phase(position) == GamePhase::Endgame
+        || !position.castles().has_color(mover)
+        || position_after.castles().has_color(mover)
+    {
+        return false;
+    }
+
+    let Some(best) = engine_move(position, best_move) else {
+        return false;
+    };
+    !best.is_castle() && position.clone().play(best).is_ok_and(|p| p.castles().has_color(mover))
+}
+
+/// True if, before the endgame, the move pushed a pawn in front of the
+/// player's own castled king for no tactical reason: not a capture or a
+/// check, the shield got weaker, the eval dropped by at least
+/// `STYLE_TIP_MIN_LOSS_CP`, and the engine wasn't pushing a
+/// pawn there itself
+fn weakening_pawn_storm(
+    position: &Chess,
+    position_after: &Chess,
+    played_move: &Move,
+    best_move: &str,
+    cp_loss: i32,
+) -> bool {
+    let mover = position.turn();
+    if cp_loss < STYLE_TIP_MIN_LOSS_CP
+        || game_phase(position) == GamePhase::Endgame
+        || played_move.is_capture()
+        || position_after.is_check()
+    {
+        return false;
+    }
+
+    let wing = match castled_wing(position, mover) {
+        Some(w) => w,
+        None => return false,
+    };
+    let in_front_of_king = |mv: &Move| mv.role() == Role::Pawn && wing.contains(mv.to());
+    if !in_front_of_king(played_move) {
+        return false;
+    }
+    if king_safety(position_after, mover).shield_score >= king_safety(position, mover).shield_score {
+        return false;
+    }
+
+    let best = engine_move(position, best_move);
+    !best.is_some_and(|b| in_front_of_king(&b))
+}
+
+/// True if, in the middlegame, the move pushed a pawn to
+/// `OVEREXTENDED_PAWN_MIN_RANK` or beyond where it has more attackers than
+/// defenders, the eval dropped by at least `STYLE_TIP_MIN_LOSS_CP`,
+/// and the engine wasn't pushing a pawn that far itself. Captures, checks
+/// and promotions are left to the tactical checks.
+fn overextended_pawn(
+    position: &Chess,
+    position_after: &Chess,
+    played_move: &Move,
+    best_move: &str,
+    cp_loss: i32,
+) -> bool {
+    let mover = position.turn();
+    if cp_loss < STYLE_TIP_MIN_LOSS_CP
+        || game_phase(position) != GamePhase::Middlegame
+        || played_move.is_capture()
+        || played_move.is_promotion()
+        || position_after.is_check()
+    {
+        return false;
+    }
+
+    let advanced = |mv: &Move| {
+        mv.role() == Role::Pawn && mover.relative_rank(mv.to().rank()) >= OVEREXTENDED_PAWN_MIN_RANK
+    };
+    if !advanced(played_move) {
+        return false;
+    }
+    let square = played_move.to();
+    if legal_attackers(position_after, square, !mover).len() <= legal_attackers(position_after, square, mover).len() {
+        return false;
+    }
+
+    let best = engine_move(position, best_move);
+    !best.is_some_and(|b| advanced(&b))
+}
+
+/// True if, in the opening, the move took a knight or bishop that had
+/// already moved (`moved_before` times) somewhere else while another minor
+/// piece was still at home, the eval dropped by at least
+/// `STYLE_TIP_MIN_LOSS_CP`, and the engine wanted to develop
+/// one of the pieces still at home. Captures and checks have a reason of
+/// their own.
+fn opening_piece_shuffle(
+    position: &Chess,
+    position_after: &Chess,
+    played_move: &Move,
+    moved_before: u8,
+    best_move: &str,
+    cp_loss: i32,
+) -> bool {
+    if cp_loss < STYLE_TIP_MIN_LOSS_CP
+        || moved_before == 0
+        || game_phase(position) != GamePhase::Opening
+        || !matches!(played_move.role(), Role::Knight | Role::Bishop)
+        || played_move.is_capture()
+        || position_after.is_check()
+    {
+        return false;
+    }
+
+    let at_home = undeveloped_minors(position, position.turn());
+    let best = engine_move(position, best_move);
+    best.and_then(|b| b.from()).is_some_and(|from| at_home.contains(from))
+}
+
+/// Knights and bishops of `color` still on their starting squares
+fn undeveloped_minors(position: &Chess, color: Color) -> Bitboard {
+    let board = position.board();
+    let home = |file| Square::from_coords(file, color.backrank());
+    let knights = Bitboard::from(home(File::B)) | Bitboard::from(home(File::G));
+    let bishops = Bitboard::from(home(File::C)) | Bitboard::from(home(File::F));
+    let ours = board.by_color(color);
+    (knights & board.knights() & ours) | (bishops & board.bishops() & ours)
+}
+
+/// Moves the count of the piece on the move's origin to its destination,
+/// one higher; whatever was captured there is forgotten. Castling moves
+/// neither a knight nor a bishop, so it isn't counted.
+fn track_piece_moves(counts: &mut [u8; 64], mv: &Move) {
+    if mv.is_castle() {
+        return;
+    }
+    if let Some(from) = mv.from() {
+        counts[usize::from(mv.to())] = counts[usize::from(from)].saturating_add(1);
+        counts[usize::from(from)] = 0;
+    }
+}
+
+/// The f, g and h files for a king castled short (on g1 or h1, or g8 or
+/// h8), the a, b and c files for one castled long (on a1-c1 or a8-c8)
+fn castled_wing(position: &Chess, color: Color) -> Option<Bitboard> {
+    let king = position.board().king_of(color)?;
+    if king.rank() != color.backrank() {
+        return None;
+    }
+    let files = match king.file() {
+        File::G | File::H => [File::F, File::G, File::H],
+        File::A | File::B | File::C => [File::A, File::B, File::C],
+        _ => return None,
+    };
+    Some(files.into_iter().map(Bitboard::from_file).fold(Bitboard::EMPTY, |acc, bb| acc | bb))
+}
+
+/// True if, in the middlegame, the engine wanted a luft move for a king
+/// sealed in on its back rank with an enemy rook or queen active, and the
+/// player played something else
+fn skipped_luft(position: &Chess, played_move: &Move, best_move: &str) -> bool {
+    let mover = position.turn();
+    if game_phase(position) != GamePhase::Middlegame
+        || !back_rank_sealed(position, mover)
+        || !has_active_major_piece(position, !mover)
+    {
+        return false;
+    }
+
+    let Some(best) = engine_move(position, best_move) else {
+        return false;
+    };
+    is_luft_move(position, &best) && !is_luft_move(position, played_move)
+}
+
+/// A push, by one square or two, of one of the pawns sealing the mover's
+/// back rank; either leaves the king a flight square
+fn is_luft_move(position: &Chess, mv: &Move) -> bool {
+    let shield = back_rank_shield(position, position.turn());
+    mv.role() == Role::Pawn
+        && mv.from().is_some_and(|from| shield.contains(from) && from.file() == mv.to().file())
+}
+
+/// True if `color` has a rook or queen on a file free of its own pawns
+fn has_active_major_piece(position: &Chess, color: Color) -> bool {
+    let board = position.board();
+    let own_pawns = board.pawns() & board.by_color(color);
+    (board.rooks_and_queens() & board.by_color(color))
+        .into_iter()
+        .any(|sq| (Bitboard::from_file(sq.file()) & own_pawns).is_empty())
+}
+
+/// True if a middlegame pawn move weakens the pawn shield in front of the
+/// mover's king. Only a king on a wing, castled or walked there, has a
+/// shield; in the centre its pawns are still the opening's to move.
+fn drops_pawn_shield(position: &Chess, played_move: &Move) -> bool {
+    if played_move.role() != Role::Pawn || game_phase(position) != GamePhase::Middlegame {
+        return false;
+    }
+
+    let mover = position.turn();
+    match position.board().king_of(mover) {
+        Some(king) if king.file() <= File::C || king.file() >= File::G => {}
+        _ => return false,
+    }
+    let after = match position.clone().play(*played_move) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+
+    king_safety(&after, mover).shield_score < king_safety(position, mover).shield_score
+}
+
+/// True if the move leaves the mover with more isolated or doubled pawns
+fn creates_pawn_weakness(position: &Chess, played_move: &Move) -> bool {
+    let mover = position.turn();
+    let after = match position.clone().play(*played_move) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+
+    let before = pawn_structure(position);
+    let after = pawn_structure(&after);
+    let (before, after) = (before.side(mover), after.side(mover));
+
+    after.isolated.len() > before.isolated.len() || after.doubled.len() > before.doubled.len()
+}
+
+/// True if castling walked the king into an attack: the eval drop confirms
+/// it was a mistake, and the opponent already has several pieces on the
+/// king's new zone, or, when the drop is large, at least one piece there
+/// or an open or half-open file next to the king
+fn castled_into_attack(position: &Chess, played_move: &Move, cp_loss: i32) -> bool {
+    if !played_move.is_castle() || cp_loss < CASTLED_INTO_ATTACK_MIN_LOSS_CP {
+        return false;
+    }
+
+    let mover = position.turn();
+    let after = match position.clone().play(*played_move) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    let safety = king_safety(&after, mover);
+    if cp_loss >= CASTLED_INTO_ATTACK_LOSS_CP {
+        return safety.zone_attackers > 0 || safety.open_files + safety.half_open_files > 0;
+    }
+    safety.zone_attackers >= CASTLED_INTO_ATTACK_ZONE_ATTACKERS
+}
+
+/// True if the player is past move `KING_IN_CENTER_AFTER_MOVE` with the
+/// king still on its starting square and able to castle right now, didn't
+/// castle, and paid for it: the queens are on, the d- or e-file has no
+/// pawns left to shelter the king, the eval dropped by at least
+/// `KING_IN_CENTER_MIN_LOSS_CP`, and after the move at least
+/// `KING_IN_CENTER_ZONE_ATTACKERS` enemy pieces bear on the king, so the
+/// loss is the opponent getting at it
+fn king_in_center(position: &Chess, played_move: &Move, cp_loss: i32) -> bool {
+    let mover = position.turn();
+    let board = position.board();
+    let home = Square::from_coords(File::E, mover.backrank());
+
+    if cp_loss < KING_IN_CENTER_MIN_LOSS_CP
+        || played_move.is_castle()
+        || position.fullmoves().get() <= KING_IN_CENTER_AFTER_MOVE
+        || game_phase(position) == GamePhase::Endgame
+        || board.king_of(mover) != Some(home)
+        || !position.castles().has_color(mover)
+        || (board.queens() & board.by_color(!mover)).is_empty()
+    {
+        return false;
+    }
+
+    if ![File::D, File::E].iter().any(|&file| (board.pawns() & Bitboard::from_file(file)).is_empty())
+        || !position.legal_moves().iter().any(|m| m.is_castle())
+    {
+        return false;
+    }
+
+    let after = match position.clone().play(*played_move) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    king_safety(&after, mover).zone_attackers >= KING_IN_CENTER_ZONE_ATTACKERS
+}
+
 fn classify_pattern(position: &Chess, played_move: &Move, cp_loss: i32) -> PatternType {
     let moved_piece = match played_move {
         Move::Normal { role, .. } => Some(*role),
@@ -156,10 +1359,16 @@ fn classify_pattern(position: &Chess, played_move: &Move, cp_loss: i32) -> Patte
         _ => None,
     };
 
-    if cp_loss >= 800 {
-        if moved_piece == Some(Role::Queen) {
-            return PatternType::QueenBlunder;
-        }
+    if castled_into_attack(position, played_move, cp_loss) {
+        return PatternType::CastledIntoAttack;
+    }
+
+    if cp_loss >= MOVED_INTO_PIN_MIN_LOSS_CP && moved_into_pin(position, played_move).is_some() {
+        return PatternType::MovedIntoPin;
+    }
+
+    if cp_loss >= 800 && moved_piece == Some(Role::Queen) {
+        return PatternType::QueenBlunder;
     }
 
     if cp_loss >= 400 {
@@ -171,14 +1380,683 @@ fn classify_pattern(position: &Chess, played_move: &Move, cp_loss: i32) -> Patte
         }
     }
 
-    let piece_count = position.board().occupied().count();
-    if piece_count >= 28 {
-        return PatternType::OpeningInaccuracy;
+    if king_in_center(position, played_move, cp_loss) {
+        return PatternType::KingInCenter;
     }
-    
-    if piece_count <= 10 {
+
+    // The pawn-ending rules explain an endgame loss better than the
+    // pawn-structure labels below would
+    if game_phase(position) == GamePhase::Endgame && pawn_ending_mistake(position, played_move).is_some() {
         return PatternType::EndgameError;
     }
 
+    if drops_pawn_shield(position, played_move) {
+        return PatternType::KingExposure;
+    }
+
+    if creates_pawn_weakness(position, played_move) {
+        return PatternType::WeakeningMove;
+    }
+
+    match game_phase(position) {
+        GamePhase::Opening => return PatternType::OpeningInaccuracy,
+        GamePhase::Endgame => return PatternType::EndgameError,
+        GamePhase::Middlegame => {}
+    }
+
     PatternType::TacticalMiss
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::CastlingMode;
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    fn san_move(position: &Chess, san: &str) -> Move {
+        san.parse::<San>().unwrap().to_move(position).unwrap()
+    }
+
+    fn play_san(position: &Chess, san: &str) -> Chess {
+        position.clone().play(san_move(position, san)).unwrap()
+    }
+
+    fn pattern(pattern_type: PatternType, severity: Severity, cp_loss: i32) -> DetectedPattern {
+        DetectedPattern {
+            move_number: 10,
+            ply: 18,
+            pattern_type,
+            severity,
+            cp_loss,
+            player_move: "Qh5".to_string(),
+            best_move: "Nf3".to_string(),
+            fen_before: String::new(),
+            fen_after: String::new(),
+            description: String::new(),
+            quiet: false,
+        }
+    }
+
+    #[test]
+    fn test_batch_report_over_two_games() {
+        let rough = GameReport::new(
+            vec![
+                pattern(PatternType::MissedFork, Severity::Mistake, 150),
+                pattern(PatternType::HangingPiece, Severity::Blunder, 400),
+                pattern(PatternType::HangingPiece, Severity::Blunder, 350),
+            ],
+            Vec::new(),
+            &[0, 150, 400, 350, 20],
+        );
+        let clean = GameReport::new(
+            vec![
+                pattern(PatternType::MissedFork, Severity::Inaccuracy, 60),
+                pattern(PatternType::WeakeningMove, Severity::Inaccuracy, 0),
+            ],
+            Vec::new(),
+            &[0, 60, 0],
+        );
+
+        let batch = PatternDetector::batch_report(&[rough, clean]);
+        assert_eq!(batch.games, 2);
+        assert_eq!((batch.blunders, batch.mistakes, batch.inaccuracies), (2, 1, 2));
+        // Two forks and two hanging pieces: the tie goes to the fork, seen first
+        assert_eq!(batch.most_common_pattern, Some(PatternType::MissedFork));
+        assert_eq!(batch.worst_game, Some(0));
+        assert_eq!(batch.best_game, Some(1));
+        // (184 + 20) / 2
+        assert!((batch.average_acpl.unwrap() - 102.0).abs() < 1e-9);
+
+        let empty = PatternDetector::batch_report(&[]);
+        assert_eq!(empty.games, 0);
+        assert_eq!((empty.average_acpl, empty.most_common_pattern, empty.worst_game), (None, None, None));
+    }
+
+    #[test]
+    fn test_careless_queen_move_allows_stalemate() {
+        // K+Q vs K: Qb6 leaves the black king on a8 with no legal moves
+        let before = position("k7/2K5/8/8/8/8/8/1Q6 w - - 0 1");
+        let after = play_san(&before, "Qb6");
+
+        assert!(after.is_stalemate());
+        assert!(allowed_stalemate(1500, &after));
+    }
+
+    #[test]
+    fn test_stalemate_not_flagged_when_not_winning() {
+        let before = position("k7/2K5/8/8/8/8/8/1Q6 w - - 0 1");
+        let after = play_san(&before, "Qb6");
+
+        assert!(!allowed_stalemate(0, &after));
+    }
+
+    fn eval(cp: i32) -> MoveEval {
+        MoveEval { eval: Some(cp), mate: None, best: None, variation: None, judgment: None }
+    }
+
+    #[test]
+    fn test_lichess_evals_detect_blunder_without_engine() {
+        let moves: Vec<String> = "e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7"
+            .split_whitespace().map(String::from).collect();
+        let mut evals = vec![eval(18), eval(25), eval(-10), eval(-5), eval(20)];
+        evals.push(MoveEval {
+            eval: None,
+            mate: Some(1),
+            best: Some("g7g6".to_string()),
+            variation: None,
+            judgment: None,
+        });
+        assert!(PatternDetector::evals_cover(&moves, &evals));
+
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "bob", "alice", &evals).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].player_move, "Nf6");
+        assert_eq!(patterns[0].best_move, "g7g6");
+        assert_eq!(patterns[0].severity, Severity::Blunder);
+
+        // White's moves never lost ground
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &evals).unwrap();
+        assert!(patterns.is_empty());
+
+        // Black lost 7, 5 and a mate (capped at 1000)
+        let report = PatternDetector::lichess_evals_report(&moves, "bob", "alice", &evals, &DetectorConfig::default()).unwrap();
+        assert_eq!(report.acpl, Some(1012.0 / 3.0));
+    }
+
+    #[test]
+    fn test_repeating_move_is_not_judged() {
+        let moves: Vec<String> = "Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1 Ng8 e4 e5 Qh5 Nc6"
+            .split_whitespace().map(String::from).collect();
+        // The second Ng8 repeats the start a third time, and looks like it
+        // throws 500 away; Nc6, after play went on, really does
+        let mut evals = vec![eval(0); 12];
+        evals[7] = eval(500);
+        evals[11] = eval(500);
+
+        let config = DetectorConfig { debug_evals: true, ..DetectorConfig::default() };
+        let report = PatternDetector::lichess_evals_report(&moves, "bob", "alice", &evals, &config).unwrap();
+        assert_eq!(report.repetition_claim, Some(7));
+        assert_eq!(report.eval_debug.iter().map(|r| r.ply).collect::<Vec<_>>(), [1, 3, 5, 9, 11]);
+        assert_eq!(report.patterns.len(), 1, "{:?}", report.patterns);
+        assert_eq!(report.patterns[0].player_move, "Nc6");
+        assert_eq!(report.patterns[0].ply, 11);
+
+        // The same drop on a move that doesn't repeat is a blunder
+        let mut moves = moves[..8].to_vec();
+        moves[7] = "Nc6".to_string();
+        let report = PatternDetector::lichess_evals_report(&moves, "bob", "alice", &evals[..8], &config).unwrap();
+        assert_eq!(report.patterns.len(), 1);
+        assert_eq!(report.patterns[0].player_move, "Nc6");
+        assert_eq!(report.repetition_claim, None);
+    }
+
+    #[test]
+    fn test_debug_evals_are_consistent() {
+        let moves: Vec<String> = "e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7"
+            .split_whitespace().map(String::from).collect();
+        let mut evals = vec![eval(18), eval(25), eval(-10), eval(-5), eval(20)];
+        evals.push(MoveEval { eval: None, mate: Some(1), best: None, variation: None, judgment: None });
+
+        let off = PatternDetector::lichess_evals_report(&moves, "bob", "alice", &evals, &DetectorConfig::default()).unwrap();
+        assert!(off.eval_debug.is_empty());
+        assert!(!serde_json::to_string(&off).unwrap().contains("eval_debug"));
+
+        let config = DetectorConfig { debug_evals: true, ..DetectorConfig::default() };
+        // Qxf7 ends the evals, so White's last move goes unjudged
+        for (username, side, plies) in [("bob", Color::Black, vec![1, 3, 5]), ("alice", Color::White, vec![2, 4])] {
+            let report = PatternDetector::lichess_evals_report(&moves, username, "alice", &evals, &config).unwrap();
+            assert_eq!(report.eval_debug.iter().map(|r| r.ply).collect::<Vec<_>>(), plies);
+            for record in &report.eval_debug {
+                assert_eq!(record.side, side);
+                assert_eq!(record.player_move, moves[record.ply as usize]);
+                assert_eq!(record.cp_loss, (record.eval_before - record.eval_after).max(0), "{:?}", record);
+            }
+            // The same losses the ACPL was averaged from
+            let losses: Vec<i32> = report.eval_debug.iter().map(|r| r.cp_loss).collect();
+            assert_eq!(report.acpl, GameReport::new(Vec::new(), Vec::new(), &losses).acpl);
+        }
+
+        // Evals are from the player's side: Black was 18 down and lost 7 more
+        let report = PatternDetector::lichess_evals_report(&moves, "bob", "alice", &evals, &config).unwrap();
+        let first = &report.eval_debug[0];
+        assert_eq!((first.eval_before, first.eval_after, first.cp_loss), (-18, -25, 7));
+    }
+
+    #[test]
+    fn test_sound_exchange_sacrifice_is_not_a_blunder() {
+        // Dragon: ...Rxc3 gives the exchange for the c3 knight and White's structure
+        let before = position("2r2rk1/pp1bppbp/3p1np1/q7/3NP3/2N1BP2/PPPQ2PP/2KR1B1R b - - 0 13");
+        let mv = "Rxc3".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(material_offered(&before, &mv), 100);
+
+        assert_eq!(judge_move(&before, &mv, -30, -10), MoveQuality::Sacrifice { material: 100 });
+        assert_eq!(judge_move(&before, &mv, -30, -70), MoveQuality::Sacrifice { material: 100 });
+        assert_eq!(judge_move(&before, &mv, -30, -400), MoveQuality::Error(Severity::Blunder));
+
+        // A quiet move that holds the eval is just sound
+        let quiet = "a6".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(judge_move(&before, &quiet, -30, -40), MoveQuality::Sound);
+    }
+
+    #[test]
+    fn test_gambit_pawn_is_reported_as_sacrifice() {
+        let moves: Vec<String> = "e4 e5 f4 exf4".split_whitespace().map(String::from).collect();
+        let evals = vec![eval(30), eval(25), eval(20), eval(35)];
+
+        let report = PatternDetector::lichess_evals_report(&moves, "alice", "alice", &evals, &DetectorConfig::default()).unwrap();
+        assert!(report.patterns.is_empty());
+        assert_eq!(report.sacrifices.len(), 1);
+        assert_eq!(report.sacrifices[0].player_move, "f4");
+        assert_eq!(report.sacrifices[0].material, 100);
+
+        // The same pawn dropped for nothing is an error, not a sacrifice
+        let evals = vec![eval(30), eval(25), eval(-150), eval(-140)];
+        let report = PatternDetector::lichess_evals_report(&moves, "alice", "alice", &evals, &DetectorConfig::default()).unwrap();
+        assert!(report.sacrifices.is_empty());
+        assert_eq!(report.patterns[0].severity, Severity::Mistake);
+    }
+
+    #[test]
+    fn test_pawn_capture_creating_doubled_pawns_is_weakening() {
+        // gxf3 doubles the f-pawns and isolates the h-pawn
+        let before = position("r2qkb1r/ppp2ppp/2n1pn2/3p4/3P4/2N2b2/PPP1PPPP/R1BQKB1R w KQkq - 0 6");
+        let mv = "gxf3".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(creates_pawn_weakness(&before, &mv));
+        assert_eq!(classify_pattern(&before, &mv, 120), PatternType::WeakeningMove);
+
+        let mv = "e3".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(!creates_pawn_weakness(&before, &mv));
+    }
+
+    #[test]
+    fn test_unprovoked_h6_exposes_king() {
+        let before = position("r1bq1rk1/p3bppp/2n1pn2/8/3P4/2NB1N2/P4PPP/R1BQ1RK1 b - - 0 10");
+        let mv = "h6".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(drops_pawn_shield(&before, &mv));
+        assert_eq!(classify_pattern(&before, &mv, 80), PatternType::KingExposure);
+
+        let mv = "a6".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(!drops_pawn_shield(&before, &mv));
+    }
+
+    #[test]
+    fn test_pawn_move_in_front_of_an_uncastled_king_keeps_no_shield() {
+        // The same position with Black's king still on e8: ...e5 thins the
+        // pawns in front of it, but there's no shield to speak of yet
+        let before = position("r1bqk2r/p3bppp/2n1pn2/8/3P4/2NB1N2/P4PPP/R1BQ1RK1 b kq - 0 10");
+        let mv = "e5".parse::<San>().unwrap().to_move(&before).unwrap();
+        let after = play_san(&before, "e5");
+        assert!(king_safety(&after, Color::Black).shield_score < king_safety(&before, Color::Black).shield_score);
+        assert!(!drops_pawn_shield(&before, &mv));
+        assert_ne!(classify_pattern(&before, &mv, 80), PatternType::KingExposure);
+    }
+
+    #[test]
+    fn test_castling_queenside_into_a_prepared_attack() {
+        // Queen, bishop and rook are all trained on c7 and d7 already
+        let before = position("r3kbnr/ppqn1ppp/8/8/Q4B2/2N5/PPP2PPP/3RKBNR b Kkq - 0 1");
+        let mv = "O-O-O".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(king_safety(&play_san(&before, "O-O-O"), Color::Black).zone_attackers, 3);
+        assert_eq!(classify_pattern(&before, &mv, 150), PatternType::CastledIntoAttack);
+
+        // A small drop doesn't overrule the general case for castling
+        assert_ne!(classify_pattern(&before, &mv, 60), PatternType::CastledIntoAttack);
+
+        // Kingside only the bishop is aimed at the king, so only a large drop counts
+        let before = position("rnbqk2r/pppp1ppp/5n2/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        let mv = "O-O".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(king_safety(&play_san(&before, "O-O"), Color::White).zone_attackers, 1);
+        assert_ne!(classify_pattern(&before, &mv, 150), PatternType::CastledIntoAttack);
+        assert_eq!(classify_pattern(&before, &mv, 250), PatternType::CastledIntoAttack);
+    }
+
+    #[test]
+    fn test_castling_to_a_quiet_wing_is_no_attack() {
+        // Nothing bears on g1 and the kingside files are closed, so the
+        // drop is down to something else
+        let before = position("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/5N2/PPPPBPPP/RNBQK2R w KQkq - 4 4");
+        let mv = "O-O".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(!castled_into_attack(&before, &mv, 400));
+        assert_ne!(classify_pattern(&before, &mv, 400), PatternType::CastledIntoAttack);
+    }
+
+    #[test]
+    fn test_delayed_castling_punished_in_an_open_centre() {
+        // Move 12, both centre files open, and White still hasn't castled
+        let before = position("r1bq1rk1/ppp2ppp/2n2n2/2b5/8/2N2N2/PPP1BPPP/R1BQK2R w KQ - 0 12");
+        let mv = "a3".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(classify_pattern(&before, &mv, 150), PatternType::KingInCenter);
+        // The eval has to show it
+        assert_ne!(classify_pattern(&before, &mv, 60), PatternType::KingInCenter);
+
+        // Castling is the cure, not the problem
+        let castle = "O-O".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(!king_in_center(&before, &castle, 150));
+
+        // Too early to expect castling yet
+        let early = position("r1bq1rk1/ppp2ppp/2n2n2/2b5/8/2N2N2/PPP1BPPP/R1BQK2R w KQ - 0 8");
+        assert!(!king_in_center(&early, &mv, 150));
+
+        // A closed centre keeps the king safe enough where it is
+        let closed = position("r1bq1rk1/ppp2ppp/2np1n2/2b1p3/4P3/2NP1N2/PPP1BPPP/R1BQK2R w KQ - 0 12");
+        let mv = "a3".parse::<San>().unwrap().to_move(&closed).unwrap();
+        assert!(!king_in_center(&closed, &mv, 150));
+
+        // Nor is there much to fear once the queens are off
+        let queenless = position("r1b2rk1/ppp2ppp/2n2n2/2b5/8/2N2N2/PPP1BPPP/R1B1K2R w KQ - 0 12");
+        let mv = "a3".parse::<San>().unwrap().to_move(&queenless).unwrap();
+        assert!(!king_in_center(&queenless, &mv, 150));
+
+        // With only the queen aimed at the king the loss came from elsewhere
+        let quiet = position("r1bq1rk1/ppp1bppp/2n2n2/8/8/2N2N2/PPP1BPPP/R1BQK2R w KQ - 0 12");
+        let mv = san_move(&quiet, "a3");
+        assert!(!king_in_center(&quiet, &mv, 150));
+
+        // Nh3 covers g1, so castling wasn't on offer this move
+        let covered = position("r1bq1rk1/ppp2ppp/2n5/2b5/8/2N2N1n/PPP1BPPP/R1BQK2R w KQ - 0 12");
+        let mv = san_move(&covered, "a3");
+        assert!(!king_in_center(&covered, &mv, 150));
+    }
+
+    #[test]
+    fn test_pawn_ending_rules_label_endgame_errors() {
+        // 1...Kf5 leaves the square of the a5 pawn
+        let before = position("8/8/8/P3k3/8/8/8/K7 b - - 0 1");
+        let mv = "Kf5".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(classify_pattern(&before, &mv, 900), PatternType::EndgameError);
+
+        // 1...Kd7 instead of taking the opposition with 1...Ke7
+        let before = position("4k3/8/8/8/8/4K3/4P3/8 b - - 0 1");
+        let mv = "Kd7".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(pawn_ending_mistake(&before, &mv), Some(PawnEndingMistake::LostOpposition));
+        assert_eq!(classify_pattern(&before, &mv, 300), PatternType::EndgameError);
+
+        // A pawn move that breaks no rule keeps its pawn-structure label
+        let before = position("7k/8/8/8/8/2P5/1P6/K7 w - - 0 1");
+        let mv = "b4".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(pawn_ending_mistake(&before, &mv), None);
+    }
+
+    #[test]
+    fn test_blocking_check_into_a_losing_pin() {
+        let before = position("6k1/5ppp/8/8/1b6/4p3/PPP3PP/1N2K2R w K - 0 1");
+        let mv = "Nd2".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(classify_pattern(&before, &mv, 300), PatternType::MovedIntoPin);
+
+        // Without an eval drop to back it up the pin is not reported
+        assert_ne!(classify_pattern(&before, &mv, 60), PatternType::MovedIntoPin);
+    }
+
+    #[test]
+    fn test_stepping_into_a_pin_against_the_queen() {
+        let before = position("3r2k1/5ppp/8/4p3/8/5N2/5PPP/3Q2K1 w - - 0 1");
+        let mv = "Nd4".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_eq!(classify_pattern(&before, &mv, 300), PatternType::MovedIntoPin);
+
+        let mv = "Nxe5".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert_ne!(classify_pattern(&before, &mv, 300), PatternType::MovedIntoPin);
+    }
+
+    #[test]
+    fn test_recapturing_with_the_wrong_piece() {
+        // Philidor: after dxe5 Black can take back with the knight or the pawn
+        let moves: Vec<String> = "e4 e5 Nf3 d6 d4 Nd7 dxe5 Nxe5"
+            .split_whitespace().map(String::from).collect();
+        let mut evals = vec![eval(30), eval(35), eval(30), eval(40), eval(45), eval(60), eval(55)];
+        evals.push(MoveEval {
+            eval: Some(190),
+            mate: None,
+            best: Some("d6e5".to_string()),
+            variation: None,
+            judgment: None,
+        });
+
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "bob", "alice", &evals).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern_type, PatternType::WrongRecapture);
+        assert_eq!(patterns[0].severity, Severity::Mistake);
+        assert!(patterns[0].description.ends_with("; recapturing with dxe5 was better"));
+
+        // An engine preference for something other than a recapture is a
+        // different mistake
+        evals[7].best = Some("d7c5".to_string());
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "bob", "alice", &evals).unwrap();
+        assert_ne!(patterns[0].pattern_type, PatternType::WrongRecapture);
+
+        // Only a different piece taking on the same square counts
+        let before = position("r1bqkbnr/pppn1ppp/3p4/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R w KQkq - 1 4");
+        let mv = "Nxe5".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(better_recapture(&before, &mv, "d4e5", Square::E5).is_some());
+        assert!(better_recapture(&before, &mv, "f3e5", Square::E5).is_none());
+    }
+
+    #[test]
+    fn test_leaving_a_free_queen_on_the_board() {
+        // 2.Qh5?? puts the queen where the f6 knight takes it for nothing
+        let moves: Vec<String> = "e4 Nf6 Qh5 Nc6".split_whitespace().map(String::from).collect();
+        let evals = vec![
+            eval(30),
+            eval(40),
+            eval(-850),
+            MoveEval { eval: Some(60), mate: None, best: Some("f6h5".to_string()), variation: None, judgment: None },
+        ];
+
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "bob", "alice", &evals).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern_type, PatternType::MissedFreeCapture);
+        assert_eq!(patterns[0].severity, Severity::Blunder);
+        assert!(patterns[0].description.ends_with("; the queen on h5 was free to take"));
+    }
+
+    #[test]
+    fn test_free_capture_needs_an_undefended_piece() {
+        let mv = |position: &Chess, uci: &str| uci.parse::<UciMove>().unwrap().to_move(position).unwrap();
+
+        // Nothing covers the rook on d5
+        let before = position("4k3/8/8/3R4/8/8/8/4K2b b - - 0 1");
+        assert_eq!(missed_free_capture(&before, &mv(&before, "e8f7"), "h1d5"), Some((Square::D5, Role::Rook)));
+
+        // With the c4 pawn guarding it, Bxd5 is only a trade
+        let before = position("4k3/8/8/3R4/2P5/8/8/4K2b b - - 0 1");
+        assert_eq!(missed_free_capture(&before, &mv(&before, "e8f7"), "h1d5"), None);
+    }
+
+    #[test]
+    fn test_non_mating_capture_misses_mate_in_one() {
+        // After ...Nf6?? Qxf7# is on; Bxf7+ wins a pawn but lets the king out
+        let moves: Vec<String> = "e4 e5 Bc4 Nc6 Qh5 Nf6 Bxf7+"
+            .split_whitespace().map(String::from).collect();
+        let mut evals = vec![eval(30), eval(30), eval(20), eval(25), eval(30)];
+        evals.push(MoveEval { eval: None, mate: Some(1), best: None, variation: None, judgment: None });
+        evals.push(MoveEval {
+            eval: Some(280),
+            mate: None,
+            best: Some("h5f7".to_string()),
+            variation: Some("Qxf7#".to_string()),
+            judgment: None,
+        });
+
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &evals).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern_type, PatternType::MissedMate);
+        assert_eq!(patterns[0].severity, Severity::Blunder);
+        assert_eq!(patterns[0].player_move, "Bxf7+");
+        assert!(patterns[0].description.contains("mate in 1 (Qxf7#)"));
+
+        // Playing the mate is not a miss
+        let mut moves = moves;
+        moves[6] = "Qxf7#".to_string();
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &evals[..6]).unwrap();
+        assert!(patterns.is_empty());
+    }
+
+    #[test]
+    fn test_slower_mate_counts_as_missed() {
+        let before = Chess::default();
+        let evals = |mate_after| MoveEvals {
+            best_move: String::new(),
+            best_line: String::new(),
+            before: MATE_SCORE_CP,
+            after: Some(MATE_SCORE_CP),
+            mate_before: Some(2),
+            mate_after,
+            recovered: None,
+        };
+
+        assert_eq!(missed_mate(&evals(Some(1)), &before), None);
+        assert_eq!(missed_mate(&evals(Some(3)), &before), Some(2));
+        assert_eq!(missed_mate(&evals(None), &before), Some(2));
+    }
+
+    #[test]
+    fn test_uci_line_renders_as_san() {
+        let line: Vec<String> = ["e2e4", "e7e5", "d1h5", "b8c6", "f1c4", "g8f6", "h5f7"]
+            .iter().map(|s| s.to_string()).collect();
+        assert_eq!(uci_line_to_san(&Chess::default(), &line), "e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7#");
+    }
+
+    #[test]
+    fn test_skipping_luft_under_back_rank_pressure() {
+        // Black's rook owns the open c-file and White's king has no escape square
+        let before = position("2r3k1/pp3ppp/2n1b3/8/8/2N1B3/PP3PPP/3R2K1 w - - 0 20");
+        let a3 = "a3".parse::<San>().unwrap().to_move(&before).unwrap();
+        let g3 = "g3".parse::<San>().unwrap().to_move(&before).unwrap();
+
+        assert!(skipped_luft(&before, &a3, "h2h3"));
+        // A different luft is fine, and so is skipping one the engine didn't want
+        assert!(!skipped_luft(&before, &g3, "h2h3"));
+        assert!(!skipped_luft(&before, &a3, "b2b3"));
+
+        // No active rook or queen: the rook is stuck behind its own c-pawn
+        let quiet = position("2r3k1/ppp2ppp/2n1b3/8/8/2N1B3/PP3PPP/3R2K1 w - - 0 20");
+        assert!(!skipped_luft(&quiet, &a3, "h2h3"));
+    }
+
+    #[test]
+    fn test_early_king_walk_loses_castling() {
+        let moves: Vec<String> = "e4 e5 Ke2".split_whitespace().map(String::from).collect();
+        let mut evals = vec![eval(30), eval(35), eval(10)];
+        evals[2].best = Some("g1f3".to_string());
+
+        let config = DetectorConfig { style_tips: true, ..DetectorConfig::default() };
+        let report = PatternDetector::lichess_evals_report(&moves, "alice", "alice", &evals, &config).unwrap();
+        assert_eq!(report.patterns.len(), 1);
+        assert_eq!(report.patterns[0].pattern_type, PatternType::LostCastling);
+        assert_eq!(report.patterns[0].severity, Severity::Inaccuracy);
+        assert!(PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &evals).unwrap().is_empty());
+
+        // If the engine would have moved the king too, nothing was given away
+        let before = position("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        let after = play_san(&before, "Ke2");
+        assert!(lost_castling(&before, &after, "g1f3"));
+        assert!(!lost_castling(&before, &after, "e1e2"));
+
+        // One rook move still leaves the other side to castle on
+        let before = position("r3k2r/pppq1ppp/2n1bn2/3pp3/3PP3/2N1BN2/PPPQ1PPP/R3K2R w KQkq - 0 8");
+        assert!(!lost_castling(&before, &play_san(&before, "Rb1"), "a2a3"));
+    }
+
+    #[test]
+    fn test_unprovoked_g4_in_front_of_castled_king() {
+        let moves: Vec<String> = "e4 e5 Nf3 Nc6 Bc4 Bc5 O-O Nge7 d3 h6 g4"
+            .split_whitespace().map(String::from).collect();
+        // Nothing attacks g4, so the pawn isn't offered; it's just loose
+        let mut evals: Vec<MoveEval> = [30, 30, 30, 30, 30, 30, 30, 30, 30, 30, -5].into_iter().map(eval).collect();
+        evals[10].best = Some("c2c3".to_string());
+
+        let config = DetectorConfig { style_tips: true, ..DetectorConfig::default() };
+        let report = PatternDetector::lichess_evals_report(&moves, "alice", "alice", &evals, &config).unwrap();
+        assert_eq!(report.patterns.len(), 1);
+        assert_eq!(report.patterns[0].pattern_type, PatternType::WeakeningPawnStorm);
+        assert_eq!(report.patterns[0].player_move, "g4");
+        assert!(PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &evals).unwrap().is_empty());
+
+        let before = position("r1bqk2r/ppppnpp1/2n4p/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQ1RK1 w kq - 0 6");
+        let g4 = san_move(&before, "g4");
+        let after = play_san(&before, "g4");
+        assert!(weakening_pawn_storm(&before, &after, &g4, "c2c3", 35));
+        // Not when the eval holds, or when the engine storms as well
+        assert!(!weakening_pawn_storm(&before, &after, &g4, "c2c3", 10));
+        assert!(!weakening_pawn_storm(&before, &after, &g4, "h2h3", 35));
+        // A queenside push doesn't touch the king's shield
+        let a3 = san_move(&before, "a3");
+        assert!(!weakening_pawn_storm(&before, &play_san(&before, "a3"), &a3, "c2c3", 35));
+    }
+
+    #[test]
+    fn test_overextended_pawn_against_a_sound_break() {
+        // d6 runs into the bishop and queen with only the queen behind it
+        let before = position("3q1rk1/pp2bppp/5n2/3Pp3/4P3/2N5/PP1Q1PPP/5RK1 w - - 0 20");
+        let d6 = san_move(&before, "d6");
+        let after = play_san(&before, "d6");
+        assert!(overextended_pawn(&before, &after, &d6, "f2f3", 40));
+        // The engine liked the push, or it cost nothing
+        assert!(!overextended_pawn(&before, &after, &d6, "d5d6", 40));
+        assert!(!overextended_pawn(&before, &after, &d6, "f2f3", 10));
+
+        // With c5 backing it up the same push is a sound break
+        let before = position("3q1rk1/pp2bppp/5n2/2PPp3/4P3/2N5/PP1Q1PPP/5RK1 w - - 0 20");
+        let d6 = san_move(&before, "d6");
+        let after = play_san(&before, "d6");
+        assert!(!overextended_pawn(&before, &after, &d6, "f2f3", 40));
+
+        // A pawn still in its own half isn't overextended
+        let before = position("3q1rk1/pp2bppp/5n2/4p3/3PP3/2N5/PP1Q1PPP/5RK1 w - - 0 20");
+        let d5 = san_move(&before, "d5");
+        assert!(!overextended_pawn(&before, &play_san(&before, "d5"), &d5, "f2f3", 40));
+    }
+
+    #[test]
+    fn test_knight_shuffle_in_the_opening() {
+        // Nf3-g5-f3 while the bishops and the other knight sit at home
+        let moves: Vec<String> = "e4 e5 Nf3 Nf6 Ng5 Nc6 Nf3".split_whitespace().map(String::from).collect();
+        let mut evals: Vec<MoveEval> = [30, 30, 30, 30, 0, 0, -30].into_iter().map(eval).collect();
+        evals[4].best = Some("f1c4".to_string());
+        evals[6].best = Some("b1c3".to_string());
+
+        let config = DetectorConfig { style_tips: true, ..DetectorConfig::default() };
+        let report = PatternDetector::lichess_evals_report(&moves, "alice", "alice", &evals, &config).unwrap();
+        let flagged: Vec<(&str, &PatternType)> = report.patterns.iter().map(|p| (p.player_move.as_str(), &p.pattern_type)).collect();
+        assert_eq!(flagged, [
+            ("Ng5", &PatternType::OpeningPieceShuffle),
+            ("Nf3", &PatternType::OpeningPieceShuffle),
+        ]);
+        assert!(report.patterns[1].description.ends_with("b1c3 would have developed a new piece"));
+        assert!(PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &evals).unwrap().is_empty());
+
+        let before = position("r1bqkb1r/pppp1ppp/2n2n2/4p1N1/4P3/8/PPPP1PPP/RNBQKB1R w KQkq - 4 4");
+        let nf3 = san_move(&before, "Nf3");
+        let after = play_san(&before, "Nf3");
+        assert!(opening_piece_shuffle(&before, &after, &nf3, 2, "b1c3", 30));
+        // Not when the engine wanted the same piece, or a pawn move, or it cost nothing
+        assert!(!opening_piece_shuffle(&before, &after, &nf3, 2, "g5f3", 30));
+        assert!(!opening_piece_shuffle(&before, &after, &nf3, 2, "d2d4", 30));
+        assert!(!opening_piece_shuffle(&before, &after, &nf3, 2, "b1c3", 10));
+
+        // A knight's first move is development
+        let start = position("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        let first = san_move(&start, "Nf3");
+        assert!(!opening_piece_shuffle(&start, &play_san(&start, "Nf3"), &first, 0, "b1c3", 30));
+    }
+
+    #[test]
+    fn test_quiet_move_instead_of_a_defensive_check() {
+        let moves: Vec<String> = "e4 e5 Nf3 d6 d3".split_whitespace().map(String::from).collect();
+        // White is worse after 2...d6 and Bb5+ is the resource
+        let mut evals: Vec<MoveEval> = [30, 30, 30, -150, -300].into_iter().map(eval).collect();
+        evals[4].best = Some("f1b5".to_string());
+
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &evals).unwrap();
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern_type, PatternType::MissedDefensiveResource);
+        assert!(patterns[0].description.ends_with("the check Bb5+ was the way to fight back"), "{}", patterns[0].description);
+
+        // Only while the player is already worse
+        let mut level = evals.clone();
+        level[3] = eval(100);
+        let patterns = PatternDetector::analyze_with_lichess_evals(&moves, "alice", "alice", &level).unwrap();
+        assert_ne!(patterns[0].pattern_type, PatternType::MissedDefensiveResource);
+
+        let before = position("rnbqkbnr/ppp2ppp/3p4/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3");
+        let d3 = "d3".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(missed_defensive_check(&before, &d3, "f1b5", -150).is_some());
+        assert!(missed_defensive_check(&before, &d3, "f1b5", 0).is_none());
+        // A few centipawns down isn't a position going wrong yet
+        assert!(missed_defensive_check(&before, &d3, "f1b5", -1).is_none());
+        // The best move has to be the check
+        assert!(missed_defensive_check(&before, &d3, "b1c3", -150).is_none());
+        // A check of the player's own isn't passive
+        let bb5 = "Bb5+".parse::<San>().unwrap().to_move(&before).unwrap();
+        assert!(missed_defensive_check(&before, &bb5, "f1b5", -150).is_none());
+    }
+
+    #[test]
+    fn test_endgame_king_move_keeps_castling_quiet() {
+        let before = position("r3k3/pp6/8/8/8/8/PP6/4K2R w Kq - 0 30");
+        let after = play_san(&before, "Kd2");
+        assert!(!lost_castling(&before, &after, "a2a3"));
+    }
+
+    #[test]
+    fn test_fortress_check_only_drops_inaccuracies() {
+        let fortress = position("8/5k2/4b3/5p2/2P5/1P4K1/P7/2B5 w - - 0 50");
+        let config = DetectorConfig { fortress_check: true, ..DetectorConfig::default() };
+
+        assert!(config.suppresses(Severity::Inaccuracy, &fortress));
+        assert!(!config.suppresses(Severity::Mistake, &fortress));
+        assert!(!DetectorConfig::default().suppresses(Severity::Inaccuracy, &fortress));
+        assert!(!config.suppresses(Severity::Inaccuracy, &Chess::default()));
+    }
+
+    #[test]
+    fn test_mate_is_not_stalemate() {
+        let before = position("k7/2K5/8/8/8/8/8/1Q6 w - - 0 1");
+        let after = play_san(&before, "Qb7");
+
+        assert!(!allowed_stalemate(1500, &after));
+    }
+}
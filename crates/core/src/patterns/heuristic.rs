@@ -0,0 +1,189 @@
+//! Engine-free detection of obvious blunders
+//!
+//! For users without Stockfish. Only mistakes visible from move generation
+//! and material counting are reported: hanging a piece, allowing mate in
+//! one, and stalemating while well ahead. Anything subtler needs an engine.
+
+use shakmaty::{fen::Fen, san::{San, SanPlus}, Chess, Color, EnPassantMode, Position};
+
+use super::tactics::{material_offered, piece_value};
+use super::types::*;
+use crate::parser::{move_label, PgnGame};
+use crate::util::player_color;
+
+/// Least material a move must leave en prise to count as hanging a piece,
+/// so pawn gambits aren't reported
+const HANGING_MIN_MATERIAL_CP: i32 = 300;
+
+/// Material lead above which stalemating the opponent throws away a win
+const STALEMATE_MIN_LEAD_CP: i32 = 500;
+
+/// Finds obvious blunders without an engine.
+///
+/// Patterns carry the material at stake as their `cp_loss` (a mate counts
+/// as `ACPL_CAP_CP`) and no best move. The checks are one move deep, so a
+/// sound sacrifice is reported as a hung piece.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicDetector;
+
+impl HeuristicDetector {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Like `PatternDetector::analyze_game`, but engine-free
+    pub fn analyze_game(&self, moves: &[String], username: &str, white_player: &str) -> Vec<DetectedPattern> {
+        self.analyze_game_report(moves, username, white_player).patterns
+    }
+
+    /// Like `PatternDetector::analyze_game_report`. Without evals there is
+    /// no ACPL and no sacrifice detection.
+    pub fn analyze_game_report(&self, moves: &[String], username: &str, white_player: &str) -> GameReport {
+        let mut patterns = Vec::new();
+        let mut position = Chess::default();
+        let player = player_color(username, white_player);
+
+        for (ply, move_str) in moves.iter().enumerate() {
+            let move_number = PgnGame::ply_to_move_number(ply);
+            let mv = match move_str.parse::<San>().ok().and_then(|san| san.to_move(&position).ok()) {
+                Some(m) => m,
+                None => {
+                    eprintln!("Warning: Invalid move '{}' at ply {}", move_str, ply);
+                    break;
+                }
+            };
+
+            if position.turn() != player {
+                position = match position.play(mv) {
+                    Ok(p) => p,
+                    Err(_) => break,
+                };
+                continue;
+            }
+
+            let position_before = position.clone();
+            position = match position.play(mv) {
+                Ok(p) => p,
+                Err(_) => break,
+            };
+            if position.is_checkmate() {
+                continue;
+            }
+
+            let lead = material(&position_before, player) - material(&position_before, !player);
+            let (pattern_type, cp_loss, description) = if position.is_stalemate() && lead >= STALEMATE_MIN_LEAD_CP {
+                (
+                    PatternType::AllowedStalemate,
+                    lead,
+                    format!("{} {} stalemates the opponent while {} cp of material up", move_label(ply), move_str, lead),
+                )
+            } else if let Some(mate) = mate_in_one(&position) {
+                (
+                    PatternType::AllowedMate,
+                    ACPL_CAP_CP,
+                    format!("{} {} allows mate in one ({})", move_label(ply), move_str, mate),
+                )
+            } else {
+                let material = material_offered(&position_before, &mv);
+                if material < HANGING_MIN_MATERIAL_CP {
+                    continue;
+                }
+                (
+                    PatternType::HangingPiece,
+                    material,
+                    format!("{} {} leaves {} cp of material en prise", move_label(ply), move_str, material),
+                )
+            };
+
+            patterns.push(DetectedPattern {
+                move_number,
+                ply: ply as u16,
+                pattern_type,
+                severity: Severity::from_cp_loss(cp_loss).unwrap_or(Severity::Blunder),
+                cp_loss,
+                player_move: move_str.clone(),
+                best_move: String::new(),
+                fen_before: Fen::from_position(&position_before, EnPassantMode::Legal).to_string(),
+                fen_after: Fen::from_position(&position, EnPassantMode::Legal).to_string(),
+                description,
+                quiet: false,
+            });
+        }
+
+        GameReport {
+            heuristic: true,
+            ..GameReport::new(patterns, Vec::new(), &[])
+        }
+    }
+}
+
+/// Total value of `color`'s pieces, kings excluded
+fn material(position: &Chess, color: Color) -> i32 {
+    let board = position.board();
+    board
+        .by_color(color)
+        .into_iter()
+        .filter_map(|sq| board.role_at(sq))
+        .map(piece_value)
+        .sum()
+}
+
+/// A mating reply for the side to move, in SAN
+fn mate_in_one(position: &Chess) -> Option<String> {
+    position.legal_moves().into_iter().find_map(|mv| {
+        let after = position.clone().play(mv).ok()?;
+        after
+            .is_checkmate()
+            .then(|| SanPlus::from_move(position.clone(), mv).to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(moves: &str) -> Vec<String> {
+        moves.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_hung_queen_is_a_hanging_piece() {
+        // Qxe5+ grabs a pawn, but the c6 knight takes the queen
+        let moves = line("e4 e5 Qh5 Nc6 Qxe5+ Nxe5");
+        let patterns = HeuristicDetector::new().analyze_game(&moves, "alice", "alice");
+
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern_type, PatternType::HangingPiece);
+        assert_eq!(patterns[0].player_move, "Qxe5+");
+        assert_eq!(patterns[0].severity, Severity::Blunder);
+        assert_eq!(patterns[0].cp_loss, 800);
+
+        // Black made no obvious blunder
+        assert!(HeuristicDetector::new().analyze_game(&moves, "bob", "alice").is_empty());
+    }
+
+    #[test]
+    fn test_allowing_fools_mate() {
+        let moves = line("f3 e5 g4 Qh4#");
+        let patterns = HeuristicDetector::new().analyze_game(&moves, "alice", "alice");
+
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern_type, PatternType::AllowedMate);
+        assert_eq!(patterns[0].player_move, "g4");
+        assert!(patterns[0].description.contains("Qh4#"));
+    }
+
+    #[test]
+    fn test_stalemating_while_ahead() {
+        // Sam Loyd's ten-move stalemate
+        let moves = line("e3 a5 Qh5 Ra6 Qxa5 h5 h4 Rah6 Qxc7 f6 Qxd7+ Kf7 Qxb7 Qd3 Qxb8 Qh7 Qxc8 Kg6 Qe6");
+        let report = HeuristicDetector::new().analyze_game_report(&moves, "alice", "alice");
+
+        let last = report.patterns.last().unwrap();
+        assert_eq!(last.pattern_type, PatternType::AllowedStalemate);
+        assert_eq!(last.player_move, "Qe6");
+        assert_eq!(last.cp_loss, 1000);
+        assert_eq!(report.acpl, None);
+        assert!(report.heuristic);
+    }
+}
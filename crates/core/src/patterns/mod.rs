@@ -1,7 +1,16 @@
 //! Pattern detection for chess games
 
 mod types;
+mod conversion;
 mod detector;
+mod heuristic;
+mod tactics;
+mod traps;
 
 pub use types::*;
-pub use detector::PatternDetector;
+pub use conversion::{endgame_conversion, TABLEBASE_MAX_PIECES};
+pub use detector::{forced_move_ratio, position_sharpness, tag_quiet_blunders, DetectorConfig, FastMode, PatternDetector, RecoveryWindow};
+pub use heuristic::HeuristicDetector;
+pub use tactics::{legal_attackers, pins, Pin, PinKind};
+pub(crate) use tactics::piece_value;
+pub use traps::{identify_trap, identify_trap_in, Trap, TrapInfo, TRAPS};
@@ -0,0 +1,263 @@
+//! Board primitives shared by the tactical pattern detectors
+
+use shakmaty::{attacks, Bitboard, Chess, Color, Move, Position, Role, Square};
+
+/// Pieces of `by` that can legally capture on (or move to) `square`.
+///
+/// Unlike `Board::attacks_to`, this drops pieces pinned to their king along a
+/// different line and a king that would walk into an attacked square. When
+/// `by` is the side to move and in check, only moves that resolve the check
+/// are counted. Pieces defending a friendly piece on `square` are treated as
+/// if they could recapture there.
+pub fn legal_attackers(position: &Chess, square: Square, by: Color) -> Vec<Square> {
+    let board = position.board();
+    let occupied = board.occupied();
+    let raw = board.attacks_to(square, by, occupied);
+
+    let king = board.king_of(by);
+    let pinned = match king {
+        Some(k) => pinned_pieces(position, by, k),
+        None => Bitboard::EMPTY,
+    };
+
+    let checkers = if position.turn() == by {
+        position.checkers()
+    } else {
+        Bitboard::EMPTY
+    };
+
+    raw.into_iter()
+        .filter(|&from| {
+            if board.role_at(from) == Some(Role::King) {
+                let without_king = occupied.without(from);
+                return board.attacks_to(square, !by, without_king).is_empty();
+            }
+
+            let king = match king {
+                Some(k) => k,
+                None => return true,
+            };
+
+            if pinned.contains(from) && !attacks::aligned(king, from, square) {
+                return false;
+            }
+
+            match checkers.count() {
+                0 => true,
+                1 => {
+                    let checker = checkers.first().unwrap();
+                    square == checker || attacks::between(king, checker).contains(square)
+                }
+                _ => false,
+            }
+        })
+        .collect()
+}
+
+/// Conventional material value in centipawns
+pub fn piece_value(role: Role) -> i32 {
+    match role {
+        Role::Pawn => 100,
+        Role::Knight | Role::Bishop => 300,
+        Role::Rook => 500,
+        Role::Queen => 900,
+        Role::King => 0,
+    }
+}
+
+/// Material the mover puts up for grabs with `played_move`, net of anything
+/// it captures. One level deep: the opponent's cheapest legal capture on
+/// the destination square, and our recapture if the square is defended.
+pub(crate) fn material_offered(position: &Chess, played_move: &Move) -> i32 {
+    let mover = position.turn();
+    let after = match position.clone().play(*played_move) {
+        Ok(p) => p,
+        Err(_) => return 0,
+    };
+    let to = played_move.to();
+    let moved = piece_value(played_move.promotion().unwrap_or(played_move.role()));
+    let captured = played_move.capture().map(piece_value).unwrap_or(0);
+
+    let cheapest_attacker = legal_attackers(&after, to, !mover)
+        .into_iter()
+        .filter_map(|sq| after.board().role_at(sq))
+        .map(piece_value)
+        .min();
+
+    let lost = match cheapest_attacker {
+        None => 0,
+        Some(attacker) if !legal_attackers(&after, to, mover).is_empty() => (moved - attacker).max(0),
+        Some(_) => moved,
+    };
+    lost - captured
+}
+
+/// What a pinned piece is shielding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinKind {
+    /// Pinned to the king; the piece cannot legally leave the line
+    Absolute,
+    /// Pinned to the queen; moving off the line loses the queen
+    Relative,
+}
+
+/// A piece standing alone between an enemy slider and its king or queen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pin {
+    pub pinned: Square,
+    pub pinner: Square,
+    pub kind: PinKind,
+}
+
+/// Every pin against `color`'s pieces, to the king or to a queen.
+///
+/// Only rooks and bishops count as pinning a piece to the queen: a queen
+/// lined up with a queen can simply be traded.
+pub fn pins(position: &Chess, color: Color) -> Vec<Pin> {
+    let board = position.board();
+    let mut pins = Vec::new();
+
+    if let Some(king) = board.king_of(color) {
+        for (pinned, pinner) in pins_to(position, color, king, board.by_color(!color)) {
+            pins.push(Pin { pinned, pinner, kind: PinKind::Absolute });
+        }
+    }
+
+    let pinners = board.by_color(!color) & !board.queens();
+    for queen in board.queens() & board.by_color(color) {
+        for (pinned, pinner) in pins_to(position, color, queen, pinners) {
+            if board.role_at(pinned) != Some(Role::King) {
+                pins.push(Pin { pinned, pinner, kind: PinKind::Relative });
+            }
+        }
+    }
+    pins
+}
+
+/// The pin the mover walks into with `played_move`, if it costs material.
+///
+/// A pin counts when it did not exist before the move and the pinned piece
+/// can be won: it is attacked and either undefended or attacked by
+/// something cheaper.
+pub(crate) fn moved_into_pin(position: &Chess, played_move: &Move) -> Option<Pin> {
+    let mover = position.turn();
+    let after = position.clone().play(*played_move).ok()?;
+    let existing: Vec<(Square, Square)> = pins(position, mover)
+        .into_iter()
+        .map(|pin| (pin.pinned, pin.pinner))
+        .collect();
+
+    pins(&after, mover)
+        .into_iter()
+        .filter(|pin| !existing.contains(&(pin.pinned, pin.pinner)))
+        .find(|pin| can_be_won(&after, pin.pinned, mover))
+}
+
+/// Whether the opponent of `owner` can win the piece on `square`
+fn can_be_won(position: &Chess, square: Square, owner: Color) -> bool {
+    let value = match position.board().role_at(square) {
+        Some(role) => piece_value(role),
+        None => return false,
+    };
+    let cheapest_attacker = legal_attackers(position, square, !owner)
+        .into_iter()
+        .filter_map(|sq| position.board().role_at(sq))
+        .map(piece_value)
+        .min();
+
+    match cheapest_attacker {
+        None => false,
+        Some(attacker) => attacker < value || legal_attackers(position, square, owner).is_empty(),
+    }
+}
+
+/// Pieces of `color` pinned to their own king
+fn pinned_pieces(position: &Chess, color: Color, king: Square) -> Bitboard {
+    pins_to(position, color, king, position.board().by_color(!color))
+        .into_iter()
+        .map(|(pinned, _)| pinned)
+        .collect()
+}
+
+/// (pinned, pinner) pairs where a lone piece of `color` stands between
+/// `target` and one of the sliders in `pinners`
+fn pins_to(position: &Chess, color: Color, target: Square, pinners: Bitboard) -> Vec<(Square, Square)> {
+    let board = position.board();
+    let snipers = ((attacks::rook_attacks(target, Bitboard::EMPTY) & board.rooks_and_queens())
+        | (attacks::bishop_attacks(target, Bitboard::EMPTY) & board.bishops_and_queens()))
+        & board.by_color(!color)
+        & pinners;
+
+    let mut pins = Vec::new();
+    for sniper in snipers {
+        let blockers = attacks::between(target, sniper) & board.occupied();
+        if blockers.count() == 1 && blockers.is_subset(board.by_color(color)) {
+            pins.push((blockers.first().unwrap(), sniper));
+        }
+    }
+    pins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, CastlingMode};
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    #[test]
+    fn test_pinned_knight_is_not_an_attacker() {
+        // Nd2 is pinned to the king by Bb4, so only the pawn attacks e4
+        let pos = position("4k3/8/8/8/1b2p3/3P4/3N4/4K3 w - - 0 1");
+        assert_eq!(pos.board().attacks_to(Square::E4, Color::White, pos.board().occupied()).count(), 2);
+        assert_eq!(legal_attackers(&pos, Square::E4, Color::White), vec![Square::D3]);
+    }
+
+    #[test]
+    fn test_pinned_piece_can_move_along_the_pin() {
+        let pos = position("4r1k1/8/8/8/4R3/8/8/4K3 w - - 0 1");
+        assert_eq!(legal_attackers(&pos, Square::E8, Color::White), vec![Square::E4]);
+        assert!(legal_attackers(&pos, Square::A4, Color::White).is_empty());
+    }
+
+    #[test]
+    fn test_king_cannot_capture_defended_piece() {
+        let defended = position("4k3/8/8/8/8/1n6/3r4/4K3 w - - 0 1");
+        assert!(legal_attackers(&defended, Square::D2, Color::White).is_empty());
+
+        let undefended = position("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1");
+        assert_eq!(legal_attackers(&undefended, Square::D2, Color::White), vec![Square::E1]);
+    }
+
+    #[test]
+    fn test_knight_blocking_into_absolute_pin() {
+        // Bb4 checks; Nd2 blocks onto a square the e3 pawn attacks
+        let pos = position("6k1/5ppp/8/8/1b6/4p3/PPP3PP/1N2K2R w K - 0 1");
+
+        let nd2 = Move::Normal { role: Role::Knight, from: Square::B1, capture: None, to: Square::D2, promotion: None };
+        let pin = moved_into_pin(&pos, &nd2).unwrap();
+        assert_eq!(pin, Pin { pinned: Square::D2, pinner: Square::B4, kind: PinKind::Absolute });
+
+        // Nc3 is pinned too, but only the bishop attacks it and b2 defends
+        let nc3 = Move::Normal { role: Role::Knight, from: Square::B1, capture: None, to: Square::C3, promotion: None };
+        assert_eq!(moved_into_pin(&pos, &nc3), None);
+    }
+
+    #[test]
+    fn test_knight_stepping_into_relative_pin() {
+        // Rd8 lines up with Qd1; Nd4 cannot leave the file and e5 takes it
+        let pos = position("3r2k1/5ppp/8/4p3/8/5N2/5PPP/3Q2K1 w - - 0 1");
+
+        let nd4 = Move::Normal { role: Role::Knight, from: Square::F3, capture: None, to: Square::D4, promotion: None };
+        let pin = moved_into_pin(&pos, &nd4).unwrap();
+        assert_eq!(pin, Pin { pinned: Square::D4, pinner: Square::D8, kind: PinKind::Relative });
+
+        // On d2 the knight is pinned but defended, and the rook is worth more
+        let nd2 = Move::Normal { role: Role::Knight, from: Square::F3, capture: None, to: Square::D2, promotion: None };
+        assert_eq!(pins(&pos.clone().play(nd2).unwrap(), Color::White).len(), 1);
+        assert_eq!(moved_into_pin(&pos, &nd2), None);
+    }
+}
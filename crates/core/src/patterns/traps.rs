@@ -0,0 +1,160 @@
+//! Named opening traps
+//!
+//! A centipawn loss says a move was bad; naming the trap says which
+//! well-known idea the player walked into, and how it's punished.
+//! Traps are matched by position rather than move order, so a game that
+//! transposes into one is still recognised.
+
+use serde::Serialize;
+use shakmaty::{fen::Epd, san::San, Chess, Color, EnPassantMode, Position};
+
+/// One trap: the line up to and including the losing move, and the
+/// punishment that follows it
+#[derive(Debug, Clone, Copy)]
+pub struct Trap {
+    pub name: &'static str,
+    /// SAN moves from the starting position, the last being the mistake
+    pub line: &'static str,
+    /// SAN moves that punish the mistake
+    pub refutation: &'static str,
+}
+
+/// The traps `identify_trap` knows about
+pub const TRAPS: &[Trap] = &[
+    Trap {
+        name: "Légal's Mate",
+        line: "e4 e5 Nf3 d6 Bc4 Bg4 Nc3 g6 Nxe5 Bxd1",
+        refutation: "Bxf7+ Ke7 Nd5#",
+    },
+    Trap {
+        name: "Fried Liver Attack",
+        line: "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5 d5 exd5 Nxd5",
+        refutation: "Nxf7 Kxf7 Qf3+",
+    },
+    Trap {
+        name: "Fishing Pole",
+        line: "e4 e5 Nf3 Nc6 Bb5 Nf6 O-O Ng4 h3 h5 hxg4",
+        refutation: "hxg4 Ne1 Qh4 f3 g3",
+    },
+    Trap {
+        name: "Elephant Trap",
+        line: "d4 d5 c4 e6 Nc3 Nf6 Bg5 Nbd7 cxd5 exd5 Nxd5",
+        refutation: "Nxd5 Bxd8 Bb4+ Qd2 Bxd2+ Kxd2 Kxd8",
+    },
+    Trap {
+        name: "Noah's Ark Trap",
+        line: "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 d6 d4 b5 Bb3 Nxd4 Nxd4 exd4 Qxd4",
+        refutation: "c5 Qd5 Be6 Qc6+ Bd7 Qd5 c4",
+    },
+];
+
+/// A trap found in a game
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrapInfo {
+    pub name: &'static str,
+    /// The side that fell for it
+    #[serde(serialize_with = "crate::util::color_serde::serialize")]
+    pub victim: Color,
+    /// Ply of the losing move, counting from 0
+    pub ply: usize,
+    /// The losing move as played in the game
+    pub mistake: String,
+    pub refutation: &'static str,
+    /// Whether the opponent went on to play the refutation's first move
+    pub punished: bool,
+}
+
+/// The first of `TRAPS` the game walked into
+pub fn identify_trap(moves: &[String]) -> Option<TrapInfo> {
+    identify_trap_in(moves, TRAPS)
+}
+
+/// Like `identify_trap`, with a trap table of the caller's own. A trap
+/// whose line doesn't parse never matches.
+pub fn identify_trap_in(moves: &[String], traps: &[Trap]) -> Option<TrapInfo> {
+    let positions = positions_after(moves.iter().map(String::as_str));
+
+    traps.iter().find_map(|trap| {
+        let line = positions_after(trap.line.split_whitespace());
+        let plies = line.len();
+        if plies == 0 || plies != trap.line.split_whitespace().count() {
+            return None;
+        }
+        if positions.get(plies - 1) != line.last() {
+            return None;
+        }
+
+        let ply = plies - 1;
+        let punished = match (moves.get(plies), trap.refutation.split_whitespace().next()) {
+            (Some(played), Some(first)) => played.trim_end_matches(['+', '#']) == first.trim_end_matches(['+', '#']),
+            _ => false,
+        };
+        Some(TrapInfo {
+            name: trap.name,
+            victim: if ply % 2 == 0 { Color::White } else { Color::Black },
+            ply,
+            mistake: moves[ply].clone(),
+            refutation: trap.refutation,
+            punished,
+        })
+    })
+}
+
+/// The position after each move, as EPD, stopping at the first move that
+/// doesn't parse
+fn positions_after<'a>(moves: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut position = Chess::default();
+    let mut positions = Vec::new();
+    for san in moves {
+        let mv = match san.parse::<San>().ok().and_then(|s| s.to_move(&position).ok()) {
+            Some(m) => m,
+            None => break,
+        };
+        position.play_unchecked(mv);
+        positions.push(Epd::from_position(&position, EnPassantMode::Legal).to_string());
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(moves: &str) -> Vec<String> {
+        moves.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_every_trap_line_is_legal() {
+        for trap in TRAPS {
+            let full = format!("{} {}", trap.line, trap.refutation);
+            assert_eq!(positions_after(full.split_whitespace()).len(), full.split_whitespace().count(), "{}", trap.name);
+        }
+    }
+
+    #[test]
+    fn test_legals_mate() {
+        let trap = identify_trap(&line("e4 e5 Nf3 d6 Bc4 Bg4 Nc3 g6 Nxe5 Bxd1 Bxf7+ Ke7 Nd5#")).unwrap();
+        assert_eq!(trap.name, "Légal's Mate");
+        assert_eq!(trap.victim, Color::Black);
+        assert_eq!(trap.ply, 9);
+        assert_eq!(trap.mistake, "Bxd1");
+        assert!(trap.punished);
+
+        // Declining the queen sidesteps it
+        assert_eq!(identify_trap(&line("e4 e5 Nf3 d6 Bc4 Bg4 Nc3 g6 Nxe5 dxe5")), None);
+    }
+
+    #[test]
+    fn test_elephant_trap_by_transposition() {
+        // Nf6 before e6, and the opponent misses the punishment
+        let moves = line("d4 d5 c4 Nf6 Nc3 e6 Bg5 Nbd7 cxd5 exd5 Nxd5 Be7");
+        let trap = identify_trap(&moves).unwrap();
+        assert_eq!(trap.name, "Elephant Trap");
+        assert_eq!(trap.victim, Color::White);
+        assert_eq!(trap.mistake, "Nxd5");
+        assert!(!trap.punished);
+
+        assert_eq!(identify_trap(&line("d4 d5 c4 e6")), None);
+    }
+}
@@ -1,6 +1,7 @@
 //! Pattern types for chess mistake detection
 
 use serde::{Deserialize, Serialize};
+use shakmaty::Color;
 
 /// Severity of a mistake
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -47,6 +48,13 @@ pub enum PatternType {
     AllowedFork,
     AllowedPin,
     AllowedBackRank,
+    AllowedStalemate,
+    AllowedMate,
+    MissedMate,
+    MovedIntoPin,
+    WrongRecapture,
+    MissedFreeCapture,
+    MissedDefensiveResource,
     
     // Material
     QueenBlunder,
@@ -56,9 +64,17 @@ pub enum PatternType {
     // Positional
     BadTrade,
     WeakeningMove,
+    KingExposure,
+    CastledIntoAttack,
+    KingInCenter,
+    NoLuft,
+    LostCastling,
+    WeakeningPawnStorm,
+    OverextendedPawn,
     
     // Phase-specific
     OpeningInaccuracy,
+    OpeningPieceShuffle,
     EndgameError,
     
     // Generic (when we can't classify further)
@@ -78,12 +94,27 @@ impl PatternType {
             PatternType::AllowedFork => "allowed_fork",
             PatternType::AllowedPin => "allowed_pin",
             PatternType::AllowedBackRank => "allowed_back_rank",
+            PatternType::AllowedStalemate => "allowed_stalemate",
+            PatternType::AllowedMate => "allowed_mate",
+            PatternType::MissedMate => "missed_mate",
+            PatternType::MovedIntoPin => "moved_into_pin",
+            PatternType::WrongRecapture => "wrong_recapture",
+            PatternType::MissedFreeCapture => "missed_free_capture",
+            PatternType::MissedDefensiveResource => "missed_defensive_resource",
             PatternType::QueenBlunder => "queen_blunder",
             PatternType::RookBlunder => "rook_blunder",
             PatternType::MinorPieceBlunder => "minor_piece_blunder",
             PatternType::BadTrade => "bad_trade",
             PatternType::WeakeningMove => "weakening_move",
+            PatternType::KingExposure => "king_exposure",
+            PatternType::CastledIntoAttack => "castled_into_attack",
+            PatternType::KingInCenter => "king_in_center",
+            PatternType::NoLuft => "no_luft",
+            PatternType::LostCastling => "lost_castling",
+            PatternType::WeakeningPawnStorm => "weakening_pawn_storm",
+            PatternType::OverextendedPawn => "overextended_pawn",
             PatternType::OpeningInaccuracy => "opening_inaccuracy",
+            PatternType::OpeningPieceShuffle => "opening_piece_shuffle",
             PatternType::EndgameError => "endgame_error",
             PatternType::TacticalMiss => "tactical_miss",
             PatternType::Unknown => "unknown",
@@ -101,17 +132,38 @@ impl PatternType {
             PatternType::AllowedFork => "Allowed Fork",
             PatternType::AllowedPin => "Allowed Pin",
             PatternType::AllowedBackRank => "Allowed Back Rank",
+            PatternType::AllowedStalemate => "Allowed Stalemate",
+            PatternType::AllowedMate => "Allowed Mate",
+            PatternType::MissedMate => "Missed Mate",
+            PatternType::MovedIntoPin => "Moved Into Pin",
+            PatternType::WrongRecapture => "Wrong Recapture",
+            PatternType::MissedFreeCapture => "Missed Free Capture",
+            PatternType::MissedDefensiveResource => "Missed Defensive Resource",
             PatternType::QueenBlunder => "Queen Blunder",
             PatternType::RookBlunder => "Rook Blunder",
             PatternType::MinorPieceBlunder => "Minor Piece Blunder",
             PatternType::BadTrade => "Bad Trade",
             PatternType::WeakeningMove => "Weakening Move",
+            PatternType::KingExposure => "King Exposure",
+            PatternType::CastledIntoAttack => "Castled Into Attack",
+            PatternType::KingInCenter => "King In Center",
+            PatternType::NoLuft => "No Luft",
+            PatternType::LostCastling => "Lost Castling",
+            PatternType::WeakeningPawnStorm => "Weakening Pawn Storm",
+            PatternType::OverextendedPawn => "Overextended Pawn",
             PatternType::OpeningInaccuracy => "Opening Inaccuracy",
+            PatternType::OpeningPieceShuffle => "Opening Piece Shuffle",
             PatternType::EndgameError => "Endgame Error",
             PatternType::TacticalMiss => "Tactical Miss",
             PatternType::Unknown => "Unknown",
         }
     }
+
+    /// Inverse of `as_str`, for values read back from the database
+    pub fn parse(s: &str) -> Option<Self> {
+        use serde::de::value::{Error, StrDeserializer};
+        Self::deserialize(StrDeserializer::<Error>::new(s)).ok()
+    }
 }
 
 /// A detected pattern in a game
@@ -127,6 +179,198 @@ pub struct DetectedPattern {
     pub fen_before: String,
     pub fen_after: String,
     pub description: String,
+    /// A blunder in a calm, level position with time to spare; see
+    /// `tag_quiet_blunders`
+    #[serde(default)]
+    pub quiet: bool,
+}
+
+impl DetectedPattern {
+    /// Lichess-style label for colouring the move: "best", "good",
+    /// "inaccuracy", "mistake" or "blunder".
+    ///
+    /// Usually just the severity. Inaccuracy-level tips such as `NoLuft`
+    /// can fire on a move the eval didn't punish, and those show as "best"
+    /// (no loss at all) or "good" (a loss below the inaccuracy threshold).
+    pub fn quality(&self) -> &'static str {
+        match self.severity {
+            Severity::Inaccuracy if self.cp_loss <= 0 => "best",
+            Severity::Inaccuracy if Severity::from_cp_loss(self.cp_loss).is_none() => "good",
+            severity => severity.as_str(),
+        }
+    }
+}
+
+/// Losses above this count as this much when averaging, so one missed mate
+/// doesn't swamp a game's ACPL
+pub const ACPL_CAP_CP: i32 = 1000;
+
+/// Verdict on a single move once material and eval are both considered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoveQuality {
+    /// Nothing worth reporting
+    Sound,
+    /// Gave up `material` centipawns of material but the eval held or improved
+    Sacrifice { material: i32 },
+    /// Lost ground on the eval
+    Error(Severity),
+}
+
+/// A move that gave up material without giving up the evaluation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sacrifice {
+    pub move_number: u16,
+    pub ply: u16,
+    pub player_move: String,
+    /// Net material offered, in centipawns
+    pub material: i32,
+    pub eval_before: i32,
+    pub eval_after: i32,
+    pub fen_before: String,
+}
+
+/// The numbers behind one judged move, from the player's point of view,
+/// recorded when `DetectorConfig::debug_evals` is on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvalDebugRecord {
+    pub ply: u16,
+    pub player_move: String,
+    /// Side to move before the move, i.e. the player
+    #[serde(with = "crate::util::color_serde")]
+    pub side: Color,
+    pub eval_before: i32,
+    /// The eval the move was judged on; the position before's when the
+    /// engine's own move was played
+    pub eval_after: i32,
+    pub cp_loss: i32,
+}
+
+/// Version of the `GameReport` JSON shape, bumped whenever a change would
+/// break existing clients. Reports stored before the field was added read
+/// back as version 1.
+///
+/// Changelog:
+/// - 1: `patterns`, `sacrifices`, `acpl` and `endgame_conversion`
+pub const GAME_REPORT_SCHEMA_VERSION: u32 = 1;
+
+fn schema_version_1() -> u32 {
+    1
+}
+
+/// Everything one analysis pass produces for a game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameReport {
+    /// Shape of this report; see `GAME_REPORT_SCHEMA_VERSION`
+    #[serde(default = "schema_version_1")]
+    pub schema_version: u32,
+    pub patterns: Vec<DetectedPattern>,
+    pub sacrifices: Vec<Sacrifice>,
+    /// Average centipawn loss over the player's judged moves
+    pub acpl: Option<f64>,
+    /// Share of the player's tablebase-range moves that kept the
+    /// theoretical result (see `endgame_conversion`); only filled in when
+    /// tablebase probing is on and the game got that far
+    #[serde(default)]
+    pub endgame_conversion: Option<f32>,
+    /// First ply after which either side could claim a draw by threefold
+    /// repetition (see `detect_repetition_draws`). The moves that make a
+    /// repetition claimable aren't judged.
+    #[serde(default)]
+    pub repetition_claim: Option<u16>,
+    /// Found by `HeuristicDetector`, without an engine: only the obvious
+    /// blunders, to be redone once an engine is available
+    #[serde(default)]
+    pub heuristic: bool,
+    /// One record per judged move when `DetectorConfig::debug_evals` is on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub eval_debug: Vec<EvalDebugRecord>,
+}
+
+impl GameReport {
+    pub fn new(patterns: Vec<DetectedPattern>, sacrifices: Vec<Sacrifice>, cp_losses: &[i32]) -> Self {
+        let acpl = if cp_losses.is_empty() {
+            None
+        } else {
+            let total: i64 = cp_losses.iter().map(|&l| l.clamp(0, ACPL_CAP_CP) as i64).sum();
+            Some(total as f64 / cp_losses.len() as f64)
+        };
+        Self {
+            schema_version: GAME_REPORT_SCHEMA_VERSION,
+            patterns,
+            sacrifices,
+            acpl,
+            endgame_conversion: None,
+            repetition_claim: None,
+            heuristic: false,
+            eval_debug: Vec::new(),
+        }
+    }
+}
+
+impl Default for GameReport {
+    fn default() -> Self {
+        Self::new(Vec::new(), Vec::new(), &[])
+    }
+}
+
+/// The takeaway from a batch of analyzed games; see
+/// `PatternDetector::batch_report`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BatchReport {
+    pub games: usize,
+    pub blunders: u32,
+    pub mistakes: u32,
+    pub inaccuracies: u32,
+    /// Mean of the games' ACPLs, over the games that have one
+    pub average_acpl: Option<f64>,
+    /// Ties go to the type seen first
+    pub most_common_pattern: Option<PatternType>,
+    /// Index of the report with the highest ACPL; ties go to the earlier
+    pub worst_game: Option<usize>,
+    /// Index of the report with the lowest ACPL; ties go to the earlier
+    pub best_game: Option<usize>,
+}
+
+impl BatchReport {
+    pub fn new(reports: &[GameReport]) -> Self {
+        let patterns = || reports.iter().flat_map(|r| &r.patterns);
+        let count = |severity: Severity| patterns().filter(|p| p.severity == severity).count() as u32;
+
+        let mut type_counts: Vec<(&PatternType, usize)> = Vec::new();
+        for pattern in patterns() {
+            match type_counts.iter_mut().find(|(t, _)| **t == pattern.pattern_type) {
+                Some((_, n)) => *n += 1,
+                None => type_counts.push((&pattern.pattern_type, 1)),
+            }
+        }
+        // `max_by_key` keeps the last of equals, so reverse to favour the first
+        let most_common_pattern = type_counts.iter().rev()
+            .max_by_key(|(_, n)| *n)
+            .map(|(t, _)| (*t).clone());
+
+        let acpls: Vec<(usize, f64)> = reports.iter().enumerate()
+            .filter_map(|(i, r)| r.acpl.map(|acpl| (i, acpl)))
+            .collect();
+        let average_acpl = (!acpls.is_empty())
+            .then(|| acpls.iter().map(|(_, acpl)| acpl).sum::<f64>() / acpls.len() as f64);
+        let pick = |worse: fn(f64, f64) -> bool| {
+            acpls.iter().copied()
+                .reduce(|kept, next| if worse(next.1, kept.1) { next } else { kept })
+                .map(|(i, _)| i)
+        };
+
+        Self {
+            games: reports.len(),
+            blunders: count(Severity::Blunder),
+            mistakes: count(Severity::Mistake),
+            inaccuracies: count(Severity::Inaccuracy),
+            average_acpl,
+            most_common_pattern,
+            worst_game: pick(|a, b| a > b),
+            best_game: pick(|a, b| a < b),
+        }
+    }
 }
 
 /// Summary of patterns for a player
@@ -146,3 +390,19 @@ pub struct PatternCount {
     pub count: u32,
     pub total_cp_loss: i32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_without_schema_version_is_version_1() {
+        let json = r#"{"patterns":[],"sacrifices":[],"acpl":12.5}"#;
+        let report: GameReport = serde_json::from_str(json).unwrap();
+        assert_eq!(report.schema_version, 1);
+        assert_eq!(report.acpl, Some(12.5));
+
+        let json = serde_json::to_string(&GameReport::default()).unwrap();
+        assert!(json.contains(&format!(r#""schema_version":{}"#, GAME_REPORT_SCHEMA_VERSION)));
+    }
+}
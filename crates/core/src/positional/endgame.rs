@@ -0,0 +1,154 @@
+//! King and pawn ending rules of thumb
+//!
+//! Pawn endings turn on a single tempo, which a shallow search can miss
+//! and a player can be taught. The opposition and the rule of the square
+//! answer most of them by counting squares.
+
+use shakmaty::{attacks, Chess, Color, Move, Position, Rank, Role, Square};
+
+/// Why a move in a pawn ending went wrong, by the classic rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PawnEndingMistake {
+    /// A king move that took the opposition was there and wasn't played
+    LostOpposition,
+    /// The enemy pawn on this square can now run to promotion
+    PawnOutOfSquare(Square),
+}
+
+/// Only kings and pawns on the board
+pub fn is_pawn_ending(position: &Chess) -> bool {
+    let board = position.board();
+    board.occupied() == board.kings() | board.pawns()
+}
+
+/// Whether `color` has the opposition: the kings face each other on a file
+/// or rank with an odd number of squares between them, and it's the other
+/// side to move
+pub fn has_opposition(position: &Chess, color: Color) -> bool {
+    let board = position.board();
+    let (own, theirs) = match (board.king_of(color), board.king_of(!color)) {
+        (Some(own), Some(theirs)) => (own, theirs),
+        _ => return false,
+    };
+    if position.turn() == color {
+        return false;
+    }
+    let files = own.file().distance(theirs.file());
+    let ranks = own.rank().distance(theirs.rank());
+    match (files, ranks) {
+        (0, d) | (d, 0) => d > 0 && d % 2 == 0,
+        _ => false,
+    }
+}
+
+/// Whether a pawn of `color` promotes by simply running: its path is clear
+/// and the defending king is outside its square, counting who is to move.
+/// Only meaningful in pawn endings.
+pub fn is_square_rule_winning(position: &Chess, color: Color) -> bool {
+    runaway_pawn(position, color).is_some()
+}
+
+/// The first pawn of `color` that `is_square_rule_winning` finds
+fn runaway_pawn(position: &Chess, color: Color) -> Option<Square> {
+    let board = position.board();
+    let king = board.king_of(!color)?;
+    let promotion_rank = (!color).backrank();
+    let start_rank = match color {
+        Color::White => Rank::Second,
+        Color::Black => Rank::Seventh,
+    };
+
+    (board.pawns() & board.by_color(color)).into_iter().find(|&pawn| {
+        let promotion = Square::from_coords(pawn.file(), promotion_rank);
+        let path = attacks::between(pawn, promotion).with(promotion);
+        if path.intersects(board.occupied()) {
+            return false;
+        }
+        // The double step saves a move from the starting rank
+        let moves = pawn.rank().distance(promotion_rank) - u32::from(pawn.rank() == start_rank);
+        let tempo = u32::from(position.turn() != color);
+        king.distance(promotion) > moves + tempo
+    })
+}
+
+/// What the rules say `mv` got wrong, for the side to move in a pawn
+/// ending; `None` when they have nothing to add (or it isn't one)
+pub fn pawn_ending_mistake(position: &Chess, mv: &Move) -> Option<PawnEndingMistake> {
+    if !is_pawn_ending(position) {
+        return None;
+    }
+    let mover = position.turn();
+    let mut after = position.clone();
+    after.play_unchecked(*mv);
+
+    if runaway_pawn(position, !mover).is_none() {
+        if let Some(pawn) = runaway_pawn(&after, !mover) {
+            return Some(PawnEndingMistake::PawnOutOfSquare(pawn));
+        }
+    }
+
+    let took_opposition = |m: &Move| {
+        let mut next = position.clone();
+        next.play_unchecked(*m);
+        has_opposition(&next, mover)
+    };
+    if !has_opposition(&after, mover) && position.legal_moves().iter().any(|m| m.role() == Role::King && took_opposition(m)) {
+        return Some(PawnEndingMistake::LostOpposition);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, san::San, CastlingMode};
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    fn mistake(fen: &str, san: &str) -> Option<PawnEndingMistake> {
+        let pos = position(fen);
+        let mv = san.parse::<San>().unwrap().to_move(&pos).unwrap();
+        pawn_ending_mistake(&pos, &mv)
+    }
+
+    #[test]
+    fn test_opposition() {
+        // Kings two squares apart on the e-file, White to move: Black has it
+        let pos = position("8/8/4k3/8/4K3/8/8/8 w - - 0 1");
+        assert!(has_opposition(&pos, Color::Black));
+        assert!(!has_opposition(&pos, Color::White));
+
+        // Distant opposition on a rank counts too; an even gap doesn't
+        assert!(has_opposition(&position("8/8/8/k5K1/8/8/8/8 b - - 0 1"), Color::White));
+        assert!(!has_opposition(&position("8/8/8/k4K2/8/8/8/8 b - - 0 1"), Color::White));
+
+        // Defending against e2: Ke7 takes the opposition, Kd7 gives it away
+        let defence = "4k3/8/8/8/8/4K3/4P3/8 b - - 0 1";
+        assert_eq!(mistake(defence, "Kd7"), Some(PawnEndingMistake::LostOpposition));
+        assert_eq!(mistake(defence, "Ke7"), None);
+    }
+
+    #[test]
+    fn test_rule_of_the_square() {
+        // The a5 pawn needs three moves; the king on e5 needs four
+        assert!(is_square_rule_winning(&position("8/8/8/P3k3/8/8/8/K7 w - - 0 1"), Color::White));
+        // With the move, the king steps into the square in time
+        assert!(!is_square_rule_winning(&position("8/8/8/P3k3/8/8/8/K7 b - - 0 1"), Color::White));
+        // From the second rank the double step counts as one move
+        assert!(!is_square_rule_winning(&position("8/8/8/8/5k2/8/P7/K7 w - - 0 1"), Color::White));
+        assert!(is_square_rule_winning(&position("8/8/8/8/6k1/8/P7/K7 w - - 0 1"), Color::White));
+        // A blocked pawn runs nowhere
+        assert!(!is_square_rule_winning(&position("8/p7/8/P3k3/8/8/8/K7 w - - 0 1"), Color::White));
+
+        // Stepping out of the square lets the pawn go; staying in doesn't
+        let inside = "8/8/8/P3k3/8/8/8/K7 b - - 0 1";
+        assert_eq!(mistake(inside, "Kf5"), Some(PawnEndingMistake::PawnOutOfSquare(Square::A5)));
+        assert_eq!(mistake(inside, "Kd5"), None);
+
+        // Not a pawn ending: the rules don't apply
+        assert_eq!(mistake("8/8/8/P3k3/8/8/8/KN6 b - - 0 1", "Kf5"), None);
+    }
+}
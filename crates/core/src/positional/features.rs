@@ -0,0 +1,130 @@
+//! All positional features of a position in one place
+//!
+//! Meant for heuristics that want several measurements at once, and for
+//! dumping a feature row per ply when building a dataset.
+
+use serde::Serialize;
+use shakmaty::{Bitboard, Chess, Color, Position};
+
+use super::{game_phase, king_safety, pawn_structure, GamePhase, KingSafety, PawnStructure};
+use crate::patterns::piece_value;
+
+/// Features of one side of a position
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SideFeatures {
+    /// Total value of the side's pieces in centipawns, king excluded
+    pub material: i32,
+    pub king_safety: KingSafety,
+    /// Legal moves the side has or would have on its turn; `None` for the
+    /// side not to move while the side to move is in check, since it can't
+    /// then be given the move
+    pub mobility: Option<u32>,
+    /// Attacks on d4, e4, d5 and e5, counting every attacker of each square
+    pub center_control: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PositionFeatures {
+    /// White's material minus Black's, in centipawns
+    pub material_balance: i32,
+    pub phase: GamePhase,
+    pub white: SideFeatures,
+    pub black: SideFeatures,
+    pub pawns: PawnStructure,
+}
+
+impl PositionFeatures {
+    pub fn side(&self, color: Color) -> &SideFeatures {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+}
+
+/// Measures everything at once; engine-free, board lookups and one legal
+/// move generation per side
+pub fn position_features(position: &Chess) -> PositionFeatures {
+    let white = side_features(position, Color::White);
+    let black = side_features(position, Color::Black);
+    PositionFeatures {
+        material_balance: white.material - black.material,
+        phase: game_phase(position),
+        white,
+        black,
+        pawns: pawn_structure(position),
+    }
+}
+
+fn side_features(position: &Chess, color: Color) -> SideFeatures {
+    let board = position.board();
+    let material = board
+        .by_color(color)
+        .into_iter()
+        .filter_map(|sq| board.role_at(sq))
+        .map(piece_value)
+        .sum();
+
+    let mobility = if position.turn() == color {
+        Some(position.legal_moves().len() as u32)
+    } else {
+        position.clone().swap_turn().ok().map(|p| p.legal_moves().len() as u32)
+    };
+
+    let center_control = Bitboard::CENTER
+        .into_iter()
+        .map(|sq| board.attacks_to(sq, color, board.occupied()).count() as u32)
+        .sum();
+
+    SideFeatures { material, king_safety: king_safety(position, color), mobility, center_control }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, CastlingMode};
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    #[test]
+    fn test_starting_position() {
+        let features = position_features(&Chess::default());
+
+        assert_eq!(features.material_balance, 0);
+        assert_eq!(features.phase, GamePhase::Opening);
+        assert_eq!(features.white, features.black);
+        assert_eq!(features.white.material, 3900);
+        assert_eq!(features.white.mobility, Some(20));
+        assert_eq!(features.white.center_control, 0);
+        assert_eq!(features.white.king_safety.shield_score, 6);
+        assert!(features.pawns.white.isolated.is_empty());
+    }
+
+    #[test]
+    fn test_sharp_middlegame() {
+        // Open Sicilian, English Attack: opposite castling, with pawns
+        // about to storm both kings
+        let pos = position("r1b2rk1/1q2bppp/p1nppn2/1p6/3NP1P1/2N1BP2/PPPQ3P/2KR1B1R w - - 1 12");
+        let features = position_features(&pos);
+
+        // Only two pawns are gone, so by piece count it's still the opening
+        assert_eq!(features.phase, GamePhase::Opening);
+        assert_eq!(features.material_balance, 0);
+        assert_eq!(features.white.mobility, Some(pos.legal_moves().len() as u32));
+        assert!(features.black.mobility.is_some());
+        // Knights, pawns and White's queen and bishop: six attacks each
+        assert_eq!(features.white.center_control, 6);
+        assert_eq!(features.black.center_control, 6);
+        assert!(features.pawns.side(Color::White).isolated.is_empty());
+
+        // The side not to move has no mobility while the other is in check
+        let pos = position("r1bqkb1r/pppp1Qpp/2n2n2/4p3/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 0 4");
+        let features = position_features(&pos);
+        assert_eq!(features.black.mobility, Some(1));
+        assert_eq!(features.white.mobility, None);
+        assert_eq!(features.material_balance, 100);
+    }
+}
@@ -0,0 +1,69 @@
+//! Drawn-despite-material detection
+
+use shakmaty::{Bitboard, Chess, Position};
+
+/// Largest pawn lead still treated as a likely draw with opposite-coloured bishops
+const OPPOSITE_BISHOPS_MAX_PAWN_LEAD: u32 = 2;
+
+/// Heuristic guess that the position is a dead draw whatever the material
+/// count says.
+///
+/// For now it only recognises pure opposite-coloured bishop endings: kings,
+/// one bishop each on different square colours, and pawns, with neither
+/// side more than two pawns up. It is deliberately rough. Some of these
+/// endings are still won (widely split passers, say), so a caller that
+/// suppresses verdicts on the strength of it will miss the odd real
+/// inaccuracy; keep it to small verdicts and behind an opt-in.
+pub fn is_likely_fortress(position: &Chess) -> bool {
+    let board = position.board();
+    if !(board.knights() | board.rooks() | board.queens()).is_empty() {
+        return false;
+    }
+
+    let white_bishops = board.bishops() & board.white();
+    let black_bishops = board.bishops() & board.black();
+    if white_bishops.count() != 1 || black_bishops.count() != 1 {
+        return false;
+    }
+    let light = Bitboard::LIGHT_SQUARES;
+    if white_bishops.intersects(light) == black_bishops.intersects(light) {
+        return false;
+    }
+
+    let white_pawns = (board.pawns() & board.white()).count() as u32;
+    let black_pawns = (board.pawns() & board.black()).count() as u32;
+    white_pawns.abs_diff(black_pawns) <= OPPOSITE_BISHOPS_MAX_PAWN_LEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, CastlingMode};
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    #[test]
+    fn test_opposite_bishops_two_pawns_up_is_drawish() {
+        // White's c1 bishop is dark-squared, Black's e6 bishop is light-squared
+        let pos = position("8/5k2/4b3/5p2/2P5/1P4K1/P7/2B5 w - - 0 50");
+        assert!(is_likely_fortress(&pos));
+
+        // Three pawns up is enough to try for a win
+        let pos = position("8/5k2/4b3/8/2P5/1P4K1/P7/2B5 w - - 0 50");
+        assert!(!is_likely_fortress(&pos));
+    }
+
+    #[test]
+    fn test_same_coloured_bishops_or_extra_pieces_are_not_fortresses() {
+        // Both bishops on light squares
+        let pos = position("8/5k2/4b3/5p2/2P5/1P4K1/P7/3B4 w - - 0 50");
+        assert!(!is_likely_fortress(&pos));
+
+        // Opposite bishops, but with rooks still on the board
+        let pos = position("r7/5k2/4b3/5p2/2P5/1P4K1/P7/2B4R w - - 0 50");
+        assert!(!is_likely_fortress(&pos));
+    }
+}
@@ -0,0 +1,145 @@
+//! King safety heuristics
+
+use serde::Serialize;
+use shakmaty::{attacks, Bitboard, Chess, Color, Position, Rank, Role};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct KingSafety {
+    /// Pawn shield score: 2 per friendly pawn directly in front of the king
+    /// (on its own or an adjacent file), 1 if it is a rank further up
+    pub shield_score: u8,
+    /// Best possible shield score (4 on an edge file, 6 otherwise)
+    pub shield_max: u8,
+    /// Files on or next to the king with no pawns at all
+    pub open_files: u8,
+    /// Files on or next to the king with only enemy pawns
+    pub half_open_files: u8,
+    /// Enemy pieces (not pawns or king) attacking the king or a square next to it
+    pub zone_attackers: u8,
+}
+
+impl KingSafety {
+    /// Shield score as a fraction of the maximum, 0.0 to 1.0
+    pub fn shield_integrity(&self) -> f32 {
+        if self.shield_max == 0 {
+            return 0.0;
+        }
+        self.shield_score as f32 / self.shield_max as f32
+    }
+}
+
+pub fn king_safety(position: &Chess, color: Color) -> KingSafety {
+    let board = position.board();
+    let king = match board.king_of(color) {
+        Some(k) => k,
+        None => return KingSafety::default(),
+    };
+
+    let our_pawns = board.pawns() & board.by_color(color);
+    let their_pawns = board.pawns() & board.by_color(!color);
+    let forward = if color == Color::White { 8 } else { -8 };
+
+    let mut safety = KingSafety::default();
+
+    for file in [king.file().offset(-1), Some(king.file()), king.file().offset(1)].into_iter().flatten() {
+        safety.shield_max += 2;
+
+        let file_bb = Bitboard::from_file(file);
+        let (ours, theirs) = ((our_pawns & file_bb).any(), (their_pawns & file_bb).any());
+        match (ours, theirs) {
+            (false, false) => safety.open_files += 1,
+            (false, true) => safety.half_open_files += 1,
+            _ => {}
+        }
+
+        let base = shakmaty::Square::from_coords(file, king.rank());
+        if base.offset(forward).is_some_and(|sq| our_pawns.contains(sq)) {
+            safety.shield_score += 2;
+        } else if base.offset(2 * forward).is_some_and(|sq| our_pawns.contains(sq)) {
+            safety.shield_score += 1;
+        }
+    }
+
+    let zone = attacks::king_attacks(king).with(king);
+    let attackers_bb = board.by_color(!color)
+        & !board.pawns()
+        & !board.by_role(Role::King);
+    let mut attackers = Bitboard::EMPTY;
+    for sq in zone {
+        attackers |= board.attacks_to(sq, !color, board.occupied()) & attackers_bb;
+    }
+    safety.zone_attackers = attackers.count() as u8;
+
+    safety
+}
+
+/// Squares directly in front of a king still on its back rank; empty once
+/// the king has left it
+pub fn back_rank_shield(position: &Chess, color: Color) -> Bitboard {
+    let king = match position.board().king_of(color) {
+        Some(k) => k,
+        None => return Bitboard::EMPTY,
+    };
+    if king.rank() != color.relative_rank(Rank::First) {
+        return Bitboard::EMPTY;
+    }
+    attacks::king_attacks(king) & Bitboard::from_rank(color.relative_rank(Rank::Second))
+}
+
+/// True if the king is boxed in on its back rank by unmoved pawns, so a
+/// rook or queen landing on that rank would mate
+pub fn back_rank_sealed(position: &Chess, color: Color) -> bool {
+    let board = position.board();
+    let shield = back_rank_shield(position, color);
+    let our_pawns = board.pawns() & board.by_color(color);
+    shield.any() && (shield & !our_pawns).is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, CastlingMode};
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    #[test]
+    fn test_intact_fianchetto() {
+        // Ng8-f6, g6, Bg7, O-O: only the g-pawn has stepped forward
+        let pos = position("rnbq1rk1/ppppppbp/5np1/8/8/5NP1/PPPPPPBP/RNBQ1RK1 w - - 4 5");
+        let safety = king_safety(&pos, Color::Black);
+
+        assert_eq!(safety.shield_score, 5);
+        assert_eq!(safety.shield_max, 6);
+        assert_eq!(safety.open_files, 0);
+        assert_eq!(safety.zone_attackers, 0);
+    }
+
+    #[test]
+    fn test_shattered_kingside() {
+        // Black's g- and h-pawns are gone, the f-pawn has advanced, and
+        // White's queen and rook bear down on the king
+        let pos = position("r4rk1/ppp5/3p1p2/8/8/6Q1/PPP3PP/5RK1 b - - 0 20");
+        let safety = king_safety(&pos, Color::Black);
+
+        assert_eq!(safety.shield_score, 1);
+        assert_eq!(safety.half_open_files, 2);
+        assert!(safety.zone_attackers >= 1);
+        assert!(safety.shield_integrity() < king_safety(&pos, Color::White).shield_integrity());
+    }
+
+    #[test]
+    fn test_back_rank_sealed_needs_every_pawn_at_home() {
+        let pos = position("2r3k1/pp3ppp/2n1b3/8/8/2N1B3/PP3PPP/3R2K1 w - - 0 20");
+        assert!(back_rank_sealed(&pos, Color::White));
+        assert!(back_rank_sealed(&pos, Color::Black));
+        assert_eq!(back_rank_shield(&pos, Color::White).count(), 3);
+
+        // White's f2 has gone, and Black's f-pawn has advanced
+        let pos = position("r4rk1/ppp5/3p1p2/8/8/6Q1/PPP3PP/5RK1 b - - 0 20");
+        assert!(!back_rank_sealed(&pos, Color::White));
+        assert!(!back_rank_sealed(&pos, Color::Black));
+    }
+}
@@ -0,0 +1,26 @@
+//! Engine-free positional features
+//!
+//! Cheap, bitboard-based measurements used as context for pattern
+//! detection and for display in game reviews.
+
+mod endgame;
+mod features;
+mod fortress;
+mod king_safety;
+mod pawns;
+mod phase;
+
+pub use endgame::{has_opposition, is_pawn_ending, is_square_rule_winning, pawn_ending_mistake, PawnEndingMistake};
+pub use features::{position_features, PositionFeatures, SideFeatures};
+pub use fortress::is_likely_fortress;
+pub use king_safety::{back_rank_sealed, back_rank_shield, king_safety, KingSafety};
+pub use pawns::{pawn_structure, PawnFlags, PawnStructure};
+pub use phase::{game_phase, GamePhase};
+
+use serde::Serializer;
+use shakmaty::Square;
+
+/// Serializes squares by name ("e4") since shakmaty types aren't `Serialize`
+pub(crate) fn serialize_squares<S: Serializer>(squares: &[Square], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(squares.iter().map(|sq| sq.to_string()))
+}
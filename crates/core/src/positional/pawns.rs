@@ -0,0 +1,157 @@
+//! Pawn structure analysis
+
+use serde::Serialize;
+use shakmaty::{attacks, Bitboard, Chess, Color, File, Position, Square};
+
+use super::serialize_squares;
+
+/// Structural pawn weaknesses and strengths for one side
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PawnFlags {
+    /// No friendly pawn on an adjacent file
+    #[serde(serialize_with = "serialize_squares")]
+    pub isolated: Vec<Square>,
+    /// Shares its file with another friendly pawn (every pawn on the file is listed)
+    #[serde(serialize_with = "serialize_squares")]
+    pub doubled: Vec<Square>,
+    /// Can't be supported by a neighbouring pawn and its stop square is
+    /// controlled by an enemy pawn
+    #[serde(serialize_with = "serialize_squares")]
+    pub backward: Vec<Square>,
+    /// No enemy pawn ahead of it on its own or an adjacent file
+    #[serde(serialize_with = "serialize_squares")]
+    pub passed: Vec<Square>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PawnStructure {
+    pub white: PawnFlags,
+    pub black: PawnFlags,
+}
+
+impl PawnStructure {
+    pub fn side(&self, color: Color) -> &PawnFlags {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+}
+
+/// Classifies every pawn on the board
+pub fn pawn_structure(position: &Chess) -> PawnStructure {
+    PawnStructure {
+        white: pawn_flags(position, Color::White),
+        black: pawn_flags(position, Color::Black),
+    }
+}
+
+fn pawn_flags(position: &Chess, color: Color) -> PawnFlags {
+    let board = position.board();
+    let ours = board.pawns() & board.by_color(color);
+    let theirs = board.pawns() & board.by_color(!color);
+
+    let mut flags = PawnFlags::default();
+
+    for pawn in ours {
+        let file = pawn.file();
+        let neighbours = ours & adjacent_files(file);
+
+        let isolated = neighbours.is_empty();
+        if isolated {
+            flags.isolated.push(pawn);
+        }
+
+        if (ours & Bitboard::from_file(file)).more_than_one() {
+            flags.doubled.push(pawn);
+        }
+
+        let blockers = theirs & (adjacent_files(file) | Bitboard::from_file(file));
+        if !blockers.into_iter().any(|sq| is_ahead(color, pawn, sq)) {
+            flags.passed.push(pawn);
+        }
+
+        if !isolated {
+            let can_be_supported = neighbours.into_iter().any(|sq| !is_ahead(color, pawn, sq));
+            let stop = pawn.offset(if color == Color::White { 8 } else { -8 });
+            let stop_controlled = stop
+                .is_some_and(|stop| (attacks::pawn_attacks(color, stop) & theirs).any());
+            if !can_be_supported && stop_controlled {
+                flags.backward.push(pawn);
+            }
+        }
+    }
+
+    flags
+}
+
+fn adjacent_files(file: File) -> Bitboard {
+    [file.offset(-1), file.offset(1)]
+        .into_iter()
+        .flatten()
+        .fold(Bitboard::EMPTY, |bb, f| bb | Bitboard::from_file(f))
+}
+
+/// True if `other` is strictly further up the board than `pawn` from `color`'s side
+fn is_ahead(color: Color, pawn: Square, other: Square) -> bool {
+    match color {
+        Color::White => other.rank() > pawn.rank(),
+        Color::Black => other.rank() < pawn.rank(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{fen::Fen, CastlingMode};
+
+    fn position(fen: &str) -> Chess {
+        let fen: Fen = fen.parse().unwrap();
+        fen.into_position(CastlingMode::Standard).unwrap()
+    }
+
+    #[test]
+    fn test_starting_position_has_no_weaknesses() {
+        let structure = pawn_structure(&Chess::default());
+        for color in [Color::White, Color::Black] {
+            let side = structure.side(color);
+            assert!(side.isolated.is_empty());
+            assert!(side.doubled.is_empty());
+            assert!(side.backward.is_empty());
+            assert!(side.passed.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_isolated_queen_pawn() {
+        // Typical IQP structure after an exchange on d4/e6
+        let pos = position("r1bq1rk1/pp2bppp/2n1pn2/8/3P4/2NB1N2/PP3PPP/R1BQ1RK1 w - - 0 10");
+        let structure = pawn_structure(&pos);
+
+        assert_eq!(structure.white.isolated, vec![Square::D4]);
+        assert!(structure.white.passed.is_empty());
+        assert!(structure.black.isolated.is_empty());
+    }
+
+    #[test]
+    fn test_doubled_f_pawns() {
+        // After ...Bxf3 gxf3 White has doubled f-pawns
+        let pos = position("r2qkb1r/ppp2ppp/2n1pn2/3p4/3P4/2N2P2/PPP1PP1P/R1BQKB1R w KQkq - 0 6");
+        let structure = pawn_structure(&pos);
+
+        assert_eq!(structure.white.doubled, vec![Square::F2, Square::F3]);
+        assert_eq!(structure.white.isolated, vec![Square::H2]);
+        assert!(structure.black.doubled.is_empty());
+    }
+
+    #[test]
+    fn test_backward_and_passed_pawns() {
+        // d3 can't be supported by c4/e4 and d4 is hit by c5; a5 is passed
+        let pos = position("4k3/8/8/P1p5/2P1P3/3P4/8/4K3 w - - 0 1");
+        let structure = pawn_structure(&pos);
+
+        assert_eq!(structure.white.backward, vec![Square::D3]);
+        assert_eq!(structure.white.passed, vec![Square::E4, Square::A5]);
+        assert!(structure.black.passed.is_empty());
+    }
+}
@@ -0,0 +1,31 @@
+//! Game phase estimation
+
+use serde::Serialize;
+use shakmaty::{Chess, Position};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GamePhase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl GamePhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GamePhase::Opening => "opening",
+            GamePhase::Middlegame => "middlegame",
+            GamePhase::Endgame => "endgame",
+        }
+    }
+}
+
+/// Rough phase from the number of pieces left on the board
+pub fn game_phase(position: &Chess) -> GamePhase {
+    match position.board().occupied().count() {
+        n if n >= 28 => GamePhase::Opening,
+        n if n <= 10 => GamePhase::Endgame,
+        _ => GamePhase::Middlegame,
+    }
+}
@@ -1,13 +1,19 @@
 //! Database operations
 
-use rusqlite::{Connection, params, Row};
+use rusqlite::{Connection, OptionalExtension, params, Row};
+use std::collections::HashMap;
+use std::io::Write;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
+use shakmaty::Color;
 
 use super::models::*;
 use crate::error::Result;
 use crate::lichess::LichessGame;
-use crate::patterns::DetectedPattern;
+use crate::parser::{replay_san_line, result_contradicts, san_line_to_uci, PgnGame};
+use crate::patterns::{DetectedPattern, GameReport};
+use crate::training::OpeningLine;
+use crate::util::{color_key, expected_score, parse_color};
 
 pub struct Database {
     conn: Connection,
@@ -83,17 +89,109 @@ impl Database {
                 created_at INTEGER NOT NULL
             );
 
+            CREATE TABLE IF NOT EXISTS puzzle_attempts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                pattern_id INTEGER NOT NULL,
+                correct INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (pattern_id) REFERENCES patterns(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS opening_lines (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                eco TEXT NOT NULL,
+                name TEXT NOT NULL,
+                moves TEXT NOT NULL,
+                for_color TEXT NOT NULL,
+                times_drilled INTEGER NOT NULL DEFAULT 0,
+                times_correct INTEGER NOT NULL DEFAULT 0,
+                last_drilled INTEGER
+            );
+
+            CREATE TABLE IF NOT EXISTS daily_puzzles (
+                seed_date TEXT PRIMARY KEY,
+                pattern_id INTEGER NOT NULL,
+                FOREIGN KEY (pattern_id) REFERENCES patterns(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS opening_drills (
+                moves TEXT NOT NULL,
+                for_color TEXT NOT NULL,
+                times_drilled INTEGER NOT NULL DEFAULT 0,
+                times_correct INTEGER NOT NULL DEFAULT 0,
+                last_drilled INTEGER,
+                PRIMARY KEY (moves, for_color)
+            );
+
+            CREATE TABLE IF NOT EXISTS analysis_jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                game_id INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                report TEXT,
+                error TEXT,
+                created_at INTEGER NOT NULL,
+                started_at INTEGER,
+                finished_at INTEGER,
+                FOREIGN KEY (game_id) REFERENCES games(id) ON DELETE CASCADE
+            );
+
             CREATE INDEX IF NOT EXISTS idx_games_lichess_id ON games(lichess_id);
             CREATE INDEX IF NOT EXISTS idx_games_played_at ON games(played_at);
             CREATE INDEX IF NOT EXISTS idx_patterns_game_id ON patterns(game_id);
             CREATE INDEX IF NOT EXISTS idx_patterns_type ON patterns(pattern_type);
             CREATE INDEX IF NOT EXISTS idx_training_date ON training_sessions(date);
             CREATE INDEX IF NOT EXISTS idx_training_type ON training_sessions(training_type);
+            CREATE INDEX IF NOT EXISTS idx_puzzle_attempts_pattern ON puzzle_attempts(pattern_id);
+            "#,
+        )?;
+        self.migrate()?;
+        Ok(())
+    }
+
+    /// Brings databases created by older versions up to the current schema
+    fn migrate(&self) -> Result<()> {
+        self.add_column_if_missing("games", "content_hash", "INTEGER")?;
+        self.add_column_if_missing("games", "lichess_analysis", "TEXT")?;
+        self.add_column_if_missing("games", "acpl", "REAL")?;
+        self.add_column_if_missing("games", "moves_uci", "TEXT")?;
+        self.add_column_if_missing("user_settings", "board_perspective", "TEXT")?;
+        self.add_column_if_missing("patterns", "quality", "TEXT")?;
+        self.add_column_if_missing("games", "result_suspect", "INTEGER NOT NULL DEFAULT 0")?;
+        self.add_column_if_missing("user_settings", "sync_started_at", "INTEGER")?;
+        self.add_column_if_missing("user_settings", "sync_cursor", "INTEGER")?;
+        self.add_column_if_missing("user_settings", "analyze_opponent", "INTEGER NOT NULL DEFAULT 0")?;
+        // Every pattern stored before this column existed was the user's own
+        self.add_column_if_missing("patterns", "side", "TEXT NOT NULL DEFAULT 'player'")?;
+        self.add_column_if_missing("patterns", "player_move", "TEXT")?;
+        self.add_column_if_missing("patterns", "best_move", "TEXT")?;
+        self.add_column_if_missing("games", "clocks", "TEXT")?;
+        self.add_column_if_missing("games", "endgame_conversion", "REAL")?;
+        self.add_column_if_missing("patterns", "quiet", "INTEGER NOT NULL DEFAULT 0")?;
+        self.add_column_if_missing("games", "time_control", "TEXT")?;
+        self.add_column_if_missing("games", "heuristic_only", "INTEGER NOT NULL DEFAULT 0")?;
+        self.conn.execute_batch(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_games_content_hash ON games(content_hash);
+            UPDATE patterns SET quality = severity WHERE quality IS NULL;
             "#,
         )?;
         Ok(())
     }
 
+    fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let exists = self.conn
+            .prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table))?
+            .exists(params![column])?;
+
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )?;
+        }
+        Ok(())
+    }
+
     fn now() -> u64 {
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -112,17 +210,23 @@ impl Database {
     // ========================================================================
 
     pub fn insert_game(&self, game: &LichessGame) -> Result<i64> {
-        let moves = game.moves.as_deref().unwrap_or("");
+        let moves = game.move_list().join(" ");
         let (eco, opening) = game.opening.as_ref()
             .map(|o| (Some(o.eco.clone()), Some(o.name.clone())))
             .unwrap_or((None, None));
+        let analysis = game.analysis.as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let (moves_uci, final_position) = replay_san_line(moves.split_whitespace());
+        let result_suspect = result_contradicts(game.result(), &final_position);
 
         self.conn.execute(
             r#"
             INSERT OR IGNORE INTO games 
             (lichess_id, white_username, black_username, white_rating, black_rating,
-             result, speed, rated, opening_eco, opening_name, moves, pgn, played_at, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+             result, speed, rated, opening_eco, opening_name, moves, pgn, played_at, created_at,
+             lichess_analysis, moves_uci, result_suspect, clocks, time_control)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)
             "#,
             params![
                 game.id,
@@ -139,6 +243,11 @@ impl Database {
                 game.pgn,
                 game.last_move_at / 1000,
                 Self::now(),
+                analysis,
+                moves_uci.join(" "),
+                result_suspect,
+                game.clocks.as_deref().map(join_clocks),
+                game.clock.as_ref().map(|c| format!("{}+{}", c.initial, c.increment)),
             ],
         )?;
 
@@ -155,28 +264,162 @@ impl Database {
         Ok(count)
     }
 
-    pub fn insert_pattern(&self, game_id: i64, pattern: &DetectedPattern) -> Result<i64> {
+    /// Imports a game parsed from a PGN file.
+    ///
+    /// PGN games have no Lichess id, so they are deduplicated by
+    /// `PgnGame::content_hash`. Returns `None` if the game is already stored.
+    pub fn insert_pgn_game(&self, game: &PgnGame) -> Result<Option<i64>> {
+        // SQLite integers are signed; store the hash bit-for-bit
+        let hash = game.content_hash() as i64;
+
+        let exists = self.conn
+            .prepare("SELECT 1 FROM games WHERE content_hash = ?1")?
+            .exists(params![hash])?;
+        if exists {
+            return Ok(None);
+        }
+
         self.conn.execute(
             r#"
-            INSERT INTO patterns 
-            (game_id, move_number, pattern_type, severity, centipawn_loss, position_fen, description, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            INSERT INTO games 
+            (lichess_id, white_username, black_username, white_rating, black_rating,
+             result, speed, rated, moves, played_at, created_at, content_hash, moves_uci, result_suspect, clocks,
+             time_control)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
             "#,
             params![
-                game_id,
-                pattern.move_number,
-                pattern.pattern_type.as_str(),
-                pattern.severity.as_str(),
-                pattern.cp_loss,
-                pattern.fen_before,
-                pattern.description,
+                format!("pgn:{:016x}", game.content_hash()),
+                game.white.as_deref().unwrap_or("Unknown"),
+                game.black.as_deref().unwrap_or("Unknown"),
+                game.white_elo,
+                game.black_elo,
+                game.result.as_deref().unwrap_or("*"),
+                "unknown",
+                false,
+                game.moves.join(" "),
+                game.played_at().unwrap_or(0),
                 Self::now(),
+                hash,
+                san_line_to_uci(&game.moves).join(" "),
+                game.result_suspect(),
+                game.clocks.as_deref().map(join_clocks),
+                game.time_control,
             ],
         )?;
 
+        Ok(Some(self.conn.last_insert_rowid()))
+    }
+
+    /// Imports PGN games, skipping duplicates. Returns the number inserted.
+    pub fn insert_pgn_games(&self, games: &[PgnGame]) -> Result<u32> {
+        let mut count = 0;
+        for game in games {
+            if self.insert_pgn_game(game)?.is_some() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn insert_pattern(&self, game_id: i64, pattern: &DetectedPattern) -> Result<i64> {
+        Self::insert_pattern_into(&self.conn, game_id, pattern, SIDE_PLAYER)?;
         Ok(self.conn.last_insert_rowid())
     }
 
+    /// Stores a game's patterns and marks it analyzed in one transaction,
+    /// so a failure never leaves a half-stored analysis behind. Returns the
+    /// number of patterns stored.
+    pub fn insert_patterns(&self, game_id: i64, patterns: &[DetectedPattern]) -> Result<u32> {
+        self.insert_patterns_with_opponent(game_id, patterns, &[])
+    }
+
+    /// Like `insert_patterns`, also storing the opponent's mistakes from
+    /// the same game, tagged as such. Returns the number of both stored.
+    pub fn insert_patterns_with_opponent(
+        &self,
+        game_id: i64,
+        patterns: &[DetectedPattern],
+        opponent_patterns: &[DetectedPattern],
+    ) -> Result<u32> {
+        self.store_patterns(game_id, patterns, opponent_patterns, false)
+    }
+
+    /// Like `insert_patterns_with_opponent`, for patterns found without an
+    /// engine. The game counts as analyzed, but `requeue_heuristic_games`
+    /// puts it back in line for the engine.
+    pub fn insert_heuristic_patterns(
+        &self,
+        game_id: i64,
+        patterns: &[DetectedPattern],
+        opponent_patterns: &[DetectedPattern],
+    ) -> Result<u32> {
+        self.store_patterns(game_id, patterns, opponent_patterns, true)
+    }
+
+    /// Patterns left from an earlier heuristic pass are replaced, not added to
+    fn store_patterns(
+        &self,
+        game_id: i64,
+        patterns: &[DetectedPattern],
+        opponent_patterns: &[DetectedPattern],
+        heuristic_only: bool,
+    ) -> Result<u32> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM patterns WHERE game_id = ?1 AND (SELECT heuristic_only FROM games WHERE id = ?1)",
+            params![game_id],
+        )?;
+        for pattern in patterns {
+            Self::insert_pattern_into(&tx, game_id, pattern, SIDE_PLAYER)?;
+        }
+        for pattern in opponent_patterns {
+            Self::insert_pattern_into(&tx, game_id, pattern, SIDE_OPPONENT)?;
+        }
+        tx.execute(
+            "UPDATE games SET analyzed = 1, heuristic_only = ?2 WHERE id = ?1",
+            params![game_id, heuristic_only],
+        )?;
+        tx.commit()?;
+        Ok((patterns.len() + opponent_patterns.len()) as u32)
+    }
+
+    /// Marks the games only heuristics have looked at unanalyzed again, for
+    /// when an engine is available. Their patterns stay until the engine's
+    /// replace them. Returns the number of games put back.
+    pub fn requeue_heuristic_games(&self) -> Result<usize> {
+        let count = self.conn.execute(
+            "UPDATE games SET analyzed = 0 WHERE analyzed = 1 AND heuristic_only = 1",
+            [],
+        )?;
+        Ok(count)
+    }
+
+    fn insert_pattern_into(conn: &Connection, game_id: i64, pattern: &DetectedPattern, side: &str) -> Result<()> {
+        conn.prepare_cached(
+            r#"
+            INSERT INTO patterns 
+            (game_id, move_number, pattern_type, severity, centipawn_loss, position_fen, description, created_at,
+             quality, side, player_move, best_move, quiet)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+            "#,
+        )?.execute(params![
+            game_id,
+            pattern.move_number,
+            pattern.pattern_type.as_str(),
+            pattern.severity.as_str(),
+            pattern.cp_loss,
+            pattern.fen_before,
+            pattern.description,
+            Self::now(),
+            pattern.quality(),
+            side,
+            pattern.player_move,
+            pattern.best_move,
+            pattern.quiet,
+        ])?;
+        Ok(())
+    }
+
     pub fn mark_game_analyzed(&self, game_id: i64) -> Result<()> {
         self.conn.execute(
             "UPDATE games SET analyzed = 1 WHERE id = ?1",
@@ -185,6 +428,174 @@ impl Database {
         Ok(())
     }
 
+    pub fn set_game_acpl(&self, game_id: i64, acpl: f64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE games SET acpl = ?1 WHERE id = ?2",
+            params![acpl, game_id],
+        )?;
+        Ok(())
+    }
+
+    /// See `GameReport::endgame_conversion`
+    pub fn set_game_endgame_conversion(&self, game_id: i64, score: f32) -> Result<()> {
+        self.conn.execute(
+            "UPDATE games SET endgame_conversion = ?1 WHERE id = ?2",
+            params![score, game_id],
+        )?;
+        Ok(())
+    }
+
+    /// Average ACPL of the user's analyzed games, grouped into buckets of
+    /// `bucket_days` days and oldest first. `perf_type` matches the game
+    /// speed (`blitz`, `rapid`, ...); `None` includes every speed.
+    pub fn acpl_trend(
+        &self,
+        username: &str,
+        perf_type: Option<&str>,
+        bucket_days: u32,
+    ) -> Result<Vec<AcplBucket>> {
+        let bucket_secs = bucket_days.max(1) as i64 * 86400;
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT (played_at / ?3) * ?3 AS period, AVG(acpl), COUNT(*)
+            FROM games
+            WHERE analyzed = 1 AND acpl IS NOT NULL
+              AND (white_username = ?1 COLLATE NOCASE OR black_username = ?1 COLLATE NOCASE)
+              AND (?2 IS NULL OR speed = ?2)
+            GROUP BY period
+            ORDER BY period
+            "#,
+        )?;
+        let buckets = stmt.query_map(params![username, perf_type, bucket_secs], |row| {
+            Ok(AcplBucket {
+                period_start: row.get(0)?,
+                avg_acpl: row.get(1)?,
+                games: row.get(2)?,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(buckets)
+    }
+
+    /// Average ACPL per opening (by ECO) over the user's analyzed games in
+    /// `earlier` and in `later`, both `[from, to)` in Unix seconds. Only
+    /// openings played in both periods are compared. Most improved first,
+    /// so the last entry is the biggest regression.
+    pub fn most_improved_opening(
+        &self,
+        username: &str,
+        earlier: (u64, u64),
+        later: (u64, u64),
+    ) -> Result<Vec<OpeningChange>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT opening_eco, MAX(opening_name),
+                   AVG(CASE WHEN in_earlier THEN acpl END), SUM(in_earlier),
+                   AVG(CASE WHEN in_later THEN acpl END), SUM(in_later)
+            FROM (
+                SELECT opening_eco, opening_name, acpl,
+                       played_at >= ?2 AND played_at < ?3 AS in_earlier,
+                       played_at >= ?4 AND played_at < ?5 AS in_later
+                FROM games
+                WHERE analyzed = 1 AND acpl IS NOT NULL AND opening_eco IS NOT NULL
+                  AND (white_username = ?1 COLLATE NOCASE OR black_username = ?1 COLLATE NOCASE)
+            )
+            GROUP BY opening_eco
+            HAVING SUM(in_earlier) > 0 AND SUM(in_later) > 0
+            ORDER BY AVG(CASE WHEN in_earlier THEN acpl END) - AVG(CASE WHEN in_later THEN acpl END) DESC,
+                     opening_eco
+            "#,
+        )?;
+        let changes = stmt.query_map(
+            params![username, earlier.0 as i64, earlier.1 as i64, later.0 as i64, later.1 as i64],
+            |row| {
+                Ok(OpeningChange {
+                    eco: row.get(0)?,
+                    name: row.get(1)?,
+                    earlier_acpl: row.get(2)?,
+                    earlier_games: row.get(3)?,
+                    later_acpl: row.get(4)?,
+                    later_games: row.get(5)?,
+                })
+            },
+        )?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(changes)
+    }
+
+    /// Every opponent `username` has played, with the user's wins, losses
+    /// and draws against them, most games first
+    pub fn head_to_head(&self, username: &str) -> Result<Vec<OpponentRecord>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT opponent, COUNT(*),
+                   COALESCE(SUM(user_result = '1-0'), 0),
+                   COALESCE(SUM(user_result = '0-1'), 0),
+                   COALESCE(SUM(user_result = '1/2-1/2'), 0)
+            FROM (
+                SELECT
+                    CASE WHEN white_username = ?1 COLLATE NOCASE
+                         THEN black_username ELSE white_username END AS opponent,
+                    -- The result from the user's side: '1-0' is always a win
+                    CASE WHEN white_username = ?1 COLLATE NOCASE THEN result
+                         WHEN result = '1-0' THEN '0-1'
+                         WHEN result = '0-1' THEN '1-0'
+                         ELSE result END AS user_result
+                FROM games
+                WHERE white_username = ?1 COLLATE NOCASE OR black_username = ?1 COLLATE NOCASE
+            )
+            GROUP BY opponent COLLATE NOCASE
+            ORDER BY COUNT(*) DESC, opponent COLLATE NOCASE
+            "#,
+        )?;
+        let records = stmt.query_map(params![username], |row| {
+            Ok(OpponentRecord {
+                opponent: row.get(0)?,
+                games: row.get(1)?,
+                wins: row.get(2)?,
+                losses: row.get(3)?,
+                draws: row.get(4)?,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// The user's score against the Elo expectation over their finished
+    /// games where both ratings are known. `perf_type` matches the game
+    /// speed (`blitz`, `rapid`, ...); `None` includes every speed. `None`
+    /// when there are no such games.
+    pub fn performance_vs_expectation(&self, username: &str, perf_type: Option<&str>) -> Result<Option<RatingPerformance>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT white_username = ?1 COLLATE NOCASE, white_rating, black_rating, result
+            FROM games
+            WHERE (white_username = ?1 COLLATE NOCASE OR black_username = ?1 COLLATE NOCASE)
+              AND white_rating IS NOT NULL AND black_rating IS NOT NULL
+              AND result IN ('1-0', '0-1', '1/2-1/2')
+              AND (?2 IS NULL OR speed = ?2)
+            "#,
+        )?;
+        let rows = stmt.query_map(params![username, perf_type], |row| {
+            Ok((row.get::<_, bool>(0)?, row.get::<_, u16>(1)?, row.get::<_, u16>(2)?, row.get::<_, String>(3)?))
+        })?;
+
+        let mut performance = RatingPerformance { games: 0, score: 0.0, expected: 0.0 };
+        for row in rows {
+            let (as_white, white_rating, black_rating, result) = row?;
+            let (own, opponent) = if as_white { (white_rating, black_rating) } else { (black_rating, white_rating) };
+            let white_score = match result.as_str() {
+                "1-0" => 1.0,
+                "0-1" => 0.0,
+                _ => 0.5,
+            };
+            performance.games += 1;
+            performance.score += if as_white { white_score } else { 1.0 - white_score };
+            performance.expected += expected_score(own, opponent);
+        }
+        Ok((performance.games > 0).then_some(performance))
+    }
+
     fn row_to_game(row: &Row) -> rusqlite::Result<StoredGame> {
         Ok(StoredGame {
             id: row.get(0)?,
@@ -199,13 +610,47 @@ impl Database {
             opening_eco: row.get(9)?,
             opening_name: row.get(10)?,
             moves: row.get(11)?,
+            moves_uci: row.get("moves_uci")?,
             pgn: row.get(12)?,
             analyzed: row.get(13)?,
             played_at: row.get(14)?,
             created_at: row.get(15)?,
+            lichess_analysis: row.get::<_, Option<String>>("lichess_analysis")?
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            acpl: row.get("acpl")?,
+            endgame_conversion: row.get("endgame_conversion")?,
+            result_suspect: row.get("result_suspect")?,
+            time_control: row.get("time_control")?,
+            clocks: row.get::<_, Option<String>>("clocks")?
+                .and_then(|text| text.split_whitespace().map(str::parse).collect::<std::result::Result<_, _>>().ok()),
         })
     }
 
+    /// A game's moves in UCI, ready to send to an engine. Rows stored before
+    /// the column existed are converted from SAN once and written back.
+    pub fn moves_uci(&self, game_id: i64) -> Result<Option<Vec<String>>> {
+        let row: Option<(String, Option<String>)> = self.conn.query_row(
+            "SELECT moves, moves_uci FROM games WHERE id = ?1",
+            params![game_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?;
+
+        let (moves, stored) = match row {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        if let Some(stored) = stored {
+            return Ok(Some(stored.split_whitespace().map(String::from).collect()));
+        }
+
+        let uci = san_line_to_uci(moves.split_whitespace());
+        self.conn.execute(
+            "UPDATE games SET moves_uci = ?1 WHERE id = ?2",
+            params![uci.join(" "), game_id],
+        )?;
+        Ok(Some(uci))
+    }
+
     pub fn get_game(&self, id: i64) -> Result<Option<StoredGame>> {
         let mut stmt = self.conn.prepare("SELECT * FROM games WHERE id = ?1")?;
         let game = stmt.query_row(params![id], Self::row_to_game).ok();
@@ -219,6 +664,90 @@ impl Database {
         Ok(games)
     }
 
+    /// Every game between `username` and `opponent`, with either colour,
+    /// newest first
+    pub fn get_games_vs(&self, username: &str, opponent: &str) -> Result<Vec<StoredGame>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT * FROM games
+            WHERE (white_username = ?1 COLLATE NOCASE AND black_username = ?2 COLLATE NOCASE)
+               OR (white_username = ?2 COLLATE NOCASE AND black_username = ?1 COLLATE NOCASE)
+            ORDER BY played_at DESC
+            "#,
+        )?;
+        let games = stmt.query_map(params![username, opponent], Self::row_to_game)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(games)
+    }
+
+    /// Openings, results and mistakes of `opponent` in their games against
+    /// `username`, for preparing a rematch
+    pub fn scout_report(&self, username: &str, opponent: &str) -> Result<ScoutReport> {
+        let games = self.get_games_vs(username, opponent)?;
+        let mut report = ScoutReport {
+            opponent: opponent.to_string(),
+            games: games.len() as u32,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            openings_as_white: Vec::new(),
+            openings_as_black: Vec::new(),
+            mistakes: Vec::new(),
+        };
+
+        for game in &games {
+            let as_white = game.white_username.eq_ignore_ascii_case(opponent);
+            match (game.result.as_str(), as_white) {
+                ("1-0", true) | ("0-1", false) => report.wins += 1,
+                ("1-0", false) | ("0-1", true) => report.losses += 1,
+                ("1/2-1/2", _) => report.draws += 1,
+                _ => {}
+            }
+
+            let Some(eco) = &game.opening_eco else { continue };
+            let openings = if as_white { &mut report.openings_as_white } else { &mut report.openings_as_black };
+            match openings.iter_mut().find(|o| &o.eco == eco) {
+                Some(opening) => opening.games += 1,
+                None => openings.push(OpeningCount { eco: eco.clone(), name: game.opening_name.clone(), games: 1 }),
+            }
+        }
+        for openings in [&mut report.openings_as_white, &mut report.openings_as_black] {
+            openings.sort_by(|a, b| b.games.cmp(&a.games).then_with(|| a.eco.cmp(&b.eco)));
+        }
+
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT p.pattern_type, COUNT(*)
+            FROM patterns p
+            JOIN games g ON g.id = p.game_id
+            WHERE p.side = ?3
+              AND ((g.white_username = ?1 COLLATE NOCASE AND g.black_username = ?2 COLLATE NOCASE)
+                OR (g.white_username = ?2 COLLATE NOCASE AND g.black_username = ?1 COLLATE NOCASE))
+            GROUP BY p.pattern_type
+            ORDER BY COUNT(*) DESC, p.pattern_type
+            "#,
+        )?;
+        report.mistakes = stmt.query_map(params![username, opponent, SIDE_OPPONENT], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(report)
+    }
+
+    /// The account most recently synced, for tools that need a user but
+    /// weren't given one
+    pub fn last_synced_username(&self) -> Result<Option<String>> {
+        let username = self.conn.query_row(
+            r#"
+            SELECT lichess_username FROM user_settings
+            WHERE games_synced_at IS NOT NULL
+            ORDER BY games_synced_at DESC
+            LIMIT 1
+            "#,
+            [],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(username)
+    }
+
     pub fn get_recent_games(&self, limit: u32) -> Result<Vec<StoredGame>> {
         let mut stmt = self.conn.prepare("SELECT * FROM games ORDER BY played_at DESC LIMIT ?1")?;
         let games = stmt.query_map(params![limit], Self::row_to_game)?
@@ -235,6 +764,41 @@ impl Database {
         Ok(games)
     }
 
+    /// Unanalyzed games played in `[from, to)` (Unix seconds), newest first
+    pub fn get_unanalyzed_games_between(&self, from: u64, to: u64, limit: u32) -> Result<Vec<StoredGame>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT * FROM games
+            WHERE analyzed = 0 AND played_at >= ?1 AND played_at < ?2
+            ORDER BY played_at DESC LIMIT ?3
+            "#,
+        )?;
+        let games = stmt.query_map(params![from, to, limit], Self::row_to_game)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(games)
+    }
+
+    /// Games whose stored result contradicts their final position
+    pub fn get_suspect_result_games(&self) -> Result<Vec<StoredGame>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM games WHERE result_suspect = 1 ORDER BY played_at DESC"
+        )?;
+        let games = stmt.query_map([], Self::row_to_game)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(games)
+    }
+
+    /// Whether no games have been stored yet, as on first run; cheaper
+    /// than counting them
+    pub fn is_empty(&self) -> Result<bool> {
+        let empty: bool = self.conn.query_row(
+            "SELECT NOT EXISTS (SELECT 1 FROM games)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(empty)
+    }
+
     pub fn count_games(&self) -> Result<u32> {
         let count: u32 = self.conn.query_row(
             "SELECT COUNT(*) FROM games",
@@ -244,6 +808,24 @@ impl Database {
         Ok(count)
     }
 
+    pub fn count_analyzed_games(&self) -> Result<u32> {
+        let count: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM games WHERE analyzed = 1",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    pub fn count_unanalyzed_games(&self) -> Result<u32> {
+        let count: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM games WHERE analyzed = 0",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
     // ========================================================================
     // PATTERNS
     // ========================================================================
@@ -257,84 +839,524 @@ impl Database {
         Ok(count)
     }
 
+    /// All dashboard numbers from a single aggregate query. `perf_type`
+    /// matches the game speed (`blitz`, `rapid`, ...); `None` includes
+    /// every speed. Only the user's own patterns are counted.
+    pub fn dashboard_summary(&self, perf_type: Option<&str>) -> Result<DashboardSummary> {
+        let summary = self.conn.query_row(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM games WHERE ?1 IS NULL OR speed = ?1),
+                COUNT(*),
+                COALESCE(SUM(p.severity = 'blunder'), 0),
+                COALESCE(SUM(p.severity = 'mistake'), 0),
+                COALESCE(SUM(p.severity = 'inaccuracy'), 0),
+                (SELECT p.pattern_type FROM patterns p
+                 JOIN games g ON g.id = p.game_id
+                 WHERE (?1 IS NULL OR g.speed = ?1) AND p.side = 'player'
+                 GROUP BY p.pattern_type
+                 ORDER BY COUNT(*) DESC, p.pattern_type
+                 LIMIT 1),
+                (SELECT AVG(acpl) FROM games WHERE acpl IS NOT NULL AND (?1 IS NULL OR speed = ?1))
+            FROM patterns p
+            JOIN games g ON g.id = p.game_id
+            WHERE (?1 IS NULL OR g.speed = ?1) AND p.side = 'player'
+            "#,
+            params![perf_type],
+            |row| Ok(DashboardSummary {
+                total_games: row.get(0)?,
+                total_patterns: row.get(1)?,
+                blunders: row.get(2)?,
+                mistakes: row.get(3)?,
+                inaccuracies: row.get(4)?,
+                most_common_pattern: row.get(5)?,
+                avg_acpl: row.get(6)?,
+            }),
+        )?;
+        Ok(summary)
+    }
+
+    fn row_to_pattern(row: &Row) -> rusqlite::Result<StoredPattern> {
+        Ok(StoredPattern {
+            id: row.get(0)?,
+            game_id: row.get(1)?,
+            move_number: row.get(2)?,
+            pattern_type: row.get(3)?,
+            subtype: row.get(4)?,
+            severity: row.get(5)?,
+            centipawn_loss: row.get(6)?,
+            position_fen: row.get(7)?,
+            description: row.get(8)?,
+            created_at: row.get(9)?,
+            quality: row.get("quality")?,
+            side: row.get("side")?,
+            player_move: row.get("player_move")?,
+            best_move: row.get("best_move")?,
+            quiet: row.get("quiet")?,
+        })
+    }
+
     pub fn get_all_patterns(&self) -> Result<Vec<StoredPattern>> {
         let mut stmt = self.conn.prepare("SELECT * FROM patterns ORDER BY id DESC")?;
-        let patterns = stmt.query_map([], |row| {
-            Ok(StoredPattern {
-                id: row.get(0)?,
-                game_id: row.get(1)?,
-                move_number: row.get(2)?,
-                pattern_type: row.get(3)?,
-                subtype: row.get(4)?,
-                severity: row.get(5)?,
-                centipawn_loss: row.get(6)?,
-                position_fen: row.get(7)?,
-                description: row.get(8)?,
-                created_at: row.get(9)?,
-            })
-        })?.collect::<std::result::Result<Vec<_>, _>>()?;
+        let patterns = stmt.query_map([], Self::row_to_pattern)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
         Ok(patterns)
     }
 
-    // ========================================================================
-    // USER SETTINGS
-    // ========================================================================
-
-    pub fn get_last_sync_time(&self, username: &str) -> Result<Option<u64>> {
-        let time: Option<u64> = self.conn.query_row(
-            "SELECT games_synced_at FROM user_settings WHERE lichess_username = ?1",
-            params![username],
-            |row| row.get(0),
-        ).ok().flatten();
-        Ok(time)
+    /// Patterns found in one game, in move order
+    pub fn get_game_patterns(&self, game_id: i64) -> Result<Vec<StoredPattern>> {
+        let mut stmt = self.conn.prepare("SELECT * FROM patterns WHERE game_id = ?1 ORDER BY move_number, id")?;
+        let patterns = stmt.query_map(params![game_id], Self::row_to_pattern)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(patterns)
     }
 
-    pub fn set_last_sync_time(&self, username: &str) -> Result<()> {
-        self.conn.execute(
+    /// One page of patterns, from the most recently played games first.
+    /// Within a game they are in move order. `perf_type` filters by game
+    /// speed as in `dashboard_summary`; the opponent's mistakes are left
+    /// out unless `include_opponent` is set.
+    pub fn recent_patterns(
+        &self,
+        limit: u32,
+        offset: u32,
+        perf_type: Option<&str>,
+        include_opponent: bool,
+    ) -> Result<Vec<StoredPattern>> {
+        let mut stmt = self.conn.prepare(
             r#"
-            INSERT INTO user_settings (lichess_username, games_synced_at, created_at)
-            VALUES (?1, ?2, ?3)
-            ON CONFLICT(lichess_username) DO UPDATE SET games_synced_at = ?2
+            SELECT p.* FROM patterns p
+            JOIN games g ON g.id = p.game_id
+            WHERE (?3 IS NULL OR g.speed = ?3) AND (?4 OR p.side = 'player')
+            ORDER BY g.played_at DESC, g.id DESC, p.move_number, p.id
+            LIMIT ?1 OFFSET ?2
             "#,
-            params![username, Self::now(), Self::now()],
         )?;
-        Ok(())
+        let patterns = stmt.query_map(params![limit, offset, perf_type, include_opponent], Self::row_to_pattern)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(patterns)
     }
 
-    // ========================================================================
-    // TRAINING
-    // ========================================================================
-
-    pub fn save_training_session(
-        &self,
-        training_type: &str,
-        attempts: u32,
-        correct: u32,
-        total_time_ms: u64,
-        best_time_ms: Option<u64>,
-    ) -> Result<i64> {
-        self.conn.execute(
+    /// Blunders the user made in games of the given opening, worst first.
+    /// Backs the "drill the mistakes you've made in this line" flow.
+    pub fn puzzles_for_opening(&self, eco: &str, username: &str) -> Result<Vec<StoredPattern>> {
+        let mut stmt = self.conn.prepare(
             r#"
-            INSERT INTO training_sessions 
-            (training_type, attempts, correct, total_time_ms, best_time_ms, date, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            SELECT p.* FROM patterns p
+            JOIN games g ON g.id = p.game_id
+            WHERE g.opening_eco = ?1
+              AND (g.white_username = ?2 COLLATE NOCASE OR g.black_username = ?2 COLLATE NOCASE)
+              AND p.severity = 'blunder' AND p.side = 'player'
+            ORDER BY p.centipawn_loss DESC, p.id DESC
             "#,
-            params![
-                training_type,
-                attempts,
-                correct,
-                total_time_ms,
-                best_time_ms,
-                Self::today(),
-                Self::now(),
-            ],
         )?;
-        Ok(self.conn.last_insert_rowid())
+        let patterns = stmt.query_map(params![eco, username], Self::row_to_pattern)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(patterns)
     }
 
-    pub fn get_training_stats(&self, training_type: &str) -> Result<TrainingStats> {
-        let today = Self::today();
-
+    /// How many blunders `puzzles_for_opening` would return for each ECO
+    /// code, fetched in one pass for the openings overview.
+    pub fn blunder_counts_by_opening(&self, username: &str) -> Result<HashMap<String, usize>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT g.opening_eco, COUNT(*) FROM patterns p
+            JOIN games g ON g.id = p.game_id
+            WHERE g.opening_eco IS NOT NULL
+              AND (g.white_username = ?1 COLLATE NOCASE OR g.black_username = ?1 COLLATE NOCASE)
+              AND p.severity = 'blunder' AND p.side = 'player'
+            GROUP BY g.opening_eco
+            "#,
+        )?;
+        let counts = stmt.query_map(params![username], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+        })?
+            .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+        Ok(counts)
+    }
+
+    /// Writes the user's patterns as CSV, one row per pattern in game and
+    /// move order, under a header of the column names. Patterns stored
+    /// before moves were kept have empty `best_move` and `player_move`.
+    pub fn export_patterns_csv(&self, mut writer: impl Write) -> Result<()> {
+        writeln!(writer, "game_id,move_number,pattern_type,severity,cp_loss,fen,best_move,player_move,description")?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM patterns WHERE side = 'player' ORDER BY game_id, move_number, id",
+        )?;
+        for pattern in stmt.query_map([], Self::row_to_pattern)? {
+            let p = pattern?;
+            let fields = [
+                p.game_id.to_string(),
+                p.move_number.to_string(),
+                p.pattern_type,
+                p.severity,
+                p.centipawn_loss.map(|cp| cp.to_string()).unwrap_or_default(),
+                p.position_fen,
+                p.best_move.unwrap_or_default(),
+                p.player_move.unwrap_or_default(),
+                p.description,
+            ];
+            let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // INTEGRITY
+    // ========================================================================
+
+    /// Everything `doctor` reports, in one pass
+    pub fn check_integrity(&self) -> Result<IntegrityReport> {
+        Ok(IntegrityReport {
+            games_missing_moves: self.games_missing_moves()?,
+            orphan_patterns: self.orphan_patterns()?,
+            suspect_results: self.get_suspect_result_games()?.into_iter().map(|g| g.id).collect(),
+        })
+    }
+
+    /// Games stored without any moves (aborted at the start, or cut short
+    /// by a PGN parse error), which analysis silently skips
+    pub fn games_missing_moves(&self) -> Result<Vec<i64>> {
+        self.ids("SELECT id FROM games WHERE TRIM(moves) = '' ORDER BY id")
+    }
+
+    /// Patterns pointing at a game that isn't stored, left behind when a
+    /// game was deleted without foreign keys enforced (by an older build or
+    /// another SQLite client)
+    pub fn orphan_patterns(&self) -> Result<Vec<i64>> {
+        self.ids("SELECT id FROM patterns WHERE game_id NOT IN (SELECT id FROM games) ORDER BY id")
+    }
+
+    /// Deletes the patterns `orphan_patterns` finds; returns how many
+    pub fn delete_orphan_patterns(&self) -> Result<u32> {
+        let deleted = self.conn.execute(
+            "DELETE FROM patterns WHERE game_id NOT IN (SELECT id FROM games)",
+            [],
+        )?;
+        Ok(deleted as u32)
+    }
+
+    /// Deletes patterns that lost fewer than `min_cp_loss` centipawns, too
+    /// small to be worth drilling as puzzles; returns how many. Patterns
+    /// without a loss are kept.
+    pub fn prune_low_value_patterns(&self, min_cp_loss: i32) -> Result<u32> {
+        let deleted = self.conn.execute(
+            "DELETE FROM patterns WHERE centipawn_loss IS NOT NULL AND centipawn_loss < ?1",
+            params![min_cp_loss],
+        )?;
+        Ok(deleted as u32)
+    }
+
+    /// Collapses patterns with the same position, best move and side, which
+    /// make the same puzzle, to the one that lost the most (the oldest on a tie);
+    /// returns how many were deleted. Patterns stored before moves were kept
+    /// have no best move and are left alone.
+    pub fn dedupe_patterns(&self) -> Result<u32> {
+        let deleted = self.conn.execute(
+            r#"
+            DELETE FROM patterns
+            WHERE COALESCE(best_move, '') != ''
+              AND EXISTS (
+                SELECT 1 FROM patterns q
+                WHERE q.position_fen = patterns.position_fen
+                  AND q.best_move = patterns.best_move
+                  AND q.side = patterns.side
+                  AND (COALESCE(q.centipawn_loss, 0) > COALESCE(patterns.centipawn_loss, 0)
+                       OR (COALESCE(q.centipawn_loss, 0) = COALESCE(patterns.centipawn_loss, 0) AND q.id < patterns.id))
+              )
+            "#,
+            [],
+        )?;
+        Ok(deleted as u32)
+    }
+
+    fn ids(&self, sql: &str) -> Result<Vec<i64>> {
+        let ids = self.conn.prepare(sql)?
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<i64>, _>>()?;
+        Ok(ids)
+    }
+
+    // ========================================================================
+    // MERGING
+    // ========================================================================
+
+    /// Merges games and patterns from another database file into this one.
+    ///
+    /// Games already stored here (same `lichess_id`, or same `content_hash`
+    /// for PGN imports) are skipped, except that an unanalyzed local copy
+    /// picks up the other side's patterns. New games get fresh ids and their
+    /// patterns are remapped to them. The whole merge is one transaction.
+    ///
+    /// The other file is migrated to the current schema before merging.
+    pub fn import_from(&self, other_path: &Path) -> Result<ImportSummary> {
+        if !other_path.is_file() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} does not exist", other_path.display()),
+            ).into());
+        }
+        drop(Database::open(other_path)?);
+
+        self.conn.execute(
+            "ATTACH DATABASE ?1 AS other",
+            params![other_path.to_string_lossy()],
+        )?;
+        let summary = self.merge_attached();
+        self.conn.execute_batch("DETACH DATABASE other")?;
+        summary
+    }
+
+    fn merge_attached(&self) -> Result<ImportSummary> {
+        let game_columns = self.column_list("games", &["id"])?;
+        let pattern_columns = self.column_list("patterns", &["id", "game_id"])?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        let mut summary = ImportSummary::default();
+
+        let incoming: Vec<(i64, String, Option<i64>, bool)> = tx
+            .prepare("SELECT id, lichess_id, content_hash, analyzed FROM other.games ORDER BY id")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        for (other_id, lichess_id, content_hash, analyzed) in incoming {
+            let existing: Option<(i64, bool)> = tx.query_row(
+                r#"
+                SELECT id, analyzed FROM main.games
+                WHERE lichess_id = ?1 OR (?2 IS NOT NULL AND content_hash = ?2)
+                "#,
+                params![lichess_id, content_hash],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).optional()?;
+
+            let game_id = match existing {
+                Some((local_id, local_analyzed)) => {
+                    summary.games_skipped += 1;
+                    if local_analyzed || !analyzed {
+                        continue;
+                    }
+                    tx.execute("UPDATE main.games SET analyzed = 1 WHERE id = ?1", params![local_id])?;
+                    local_id
+                }
+                None => {
+                    tx.execute(
+                        &format!(
+                            "INSERT INTO main.games ({cols}) SELECT {cols} FROM other.games WHERE id = ?1",
+                            cols = game_columns
+                        ),
+                        params![other_id],
+                    )?;
+                    summary.games_merged += 1;
+                    tx.last_insert_rowid()
+                }
+            };
+
+            summary.patterns_merged += tx.execute(
+                &format!(
+                    "INSERT INTO main.patterns (game_id, {cols}) \
+                     SELECT ?1, {cols} FROM other.patterns WHERE game_id = ?2 ORDER BY id",
+                    cols = pattern_columns
+                ),
+                params![game_id, other_id],
+            )? as u32;
+        }
+
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    /// Comma-separated column names of a main-schema table, minus `exclude`
+    fn column_list(&self, table: &str, exclude: &[&str]) -> Result<String> {
+        let columns: Vec<String> = self.conn
+            .prepare("SELECT name FROM pragma_table_info(?1, 'main') ORDER BY cid")?
+            .query_map(params![table], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(columns
+            .into_iter()
+            .filter(|c| !exclude.contains(&c.as_str()))
+            .collect::<Vec<_>>()
+            .join(", "))
+    }
+
+    // ========================================================================
+    // USER SETTINGS
+    // ========================================================================
+
+    pub fn get_last_sync_time(&self, username: &str) -> Result<Option<u64>> {
+        let time: Option<u64> = self.conn.query_row(
+            "SELECT games_synced_at FROM user_settings WHERE lichess_username = ?1",
+            params![username],
+            |row| row.get(0),
+        ).ok().flatten();
+        Ok(time)
+    }
+
+    pub fn set_last_sync_time(&self, username: &str) -> Result<()> {
+        self.conn.execute(
+            r#"
+            INSERT INTO user_settings (lichess_username, games_synced_at, created_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(lichess_username) DO UPDATE SET games_synced_at = ?2
+            "#,
+            params![username, Self::now(), Self::now()],
+        )?;
+        Ok(())
+    }
+
+    /// Resumes the user's unfinished windowed sync, or starts a new one
+    pub fn begin_sync(&self, username: &str) -> Result<SyncCursor> {
+        self.conn.execute(
+            r#"
+            INSERT INTO user_settings (lichess_username, sync_started_at, created_at)
+            VALUES (?1, ?2, ?2)
+            ON CONFLICT(lichess_username) DO UPDATE SET sync_started_at = COALESCE(sync_started_at, ?2)
+            "#,
+            params![username, Self::now()],
+        )?;
+        let cursor = self.conn.query_row(
+            "SELECT sync_started_at, sync_cursor, games_synced_at FROM user_settings WHERE lichess_username = ?1",
+            params![username],
+            |row| Ok(SyncCursor {
+                started_at: row.get(0)?,
+                until: row.get(1)?,
+                since: row.get::<_, Option<u64>>(2)?.map(|secs| secs * 1000),
+            }),
+        )?;
+        Ok(cursor)
+    }
+
+    /// Saves how far back the current sync has got, in Unix milliseconds
+    pub fn advance_sync_cursor(&self, username: &str, until: u64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE user_settings SET sync_cursor = ?2 WHERE lichess_username = ?1",
+            params![username, until],
+        )?;
+        Ok(())
+    }
+
+    /// Marks the current sync done. The last sync time becomes the moment it
+    /// started, so games played while it ran are picked up next time.
+    pub fn finish_sync(&self, username: &str) -> Result<()> {
+        self.conn.execute(
+            r#"
+            UPDATE user_settings
+            SET games_synced_at = COALESCE(sync_started_at, games_synced_at),
+                sync_started_at = NULL,
+                sync_cursor = NULL
+            WHERE lichess_username = ?1
+            "#,
+            params![username],
+        )?;
+        Ok(())
+    }
+
+    /// Side the user last chose to view training boards from
+    pub fn get_board_perspective(&self, username: &str) -> Result<Option<Color>> {
+        let perspective: Option<String> = self.conn.query_row(
+            "SELECT board_perspective FROM user_settings WHERE lichess_username = ?1",
+            params![username],
+            |row| row.get(0),
+        ).optional()?.flatten();
+        Ok(perspective.as_deref().and_then(parse_color))
+    }
+
+    pub fn set_board_perspective(&self, username: &str, perspective: Color) -> Result<()> {
+        self.conn.execute(
+            r#"
+            INSERT INTO user_settings (lichess_username, board_perspective, created_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(lichess_username) DO UPDATE SET board_perspective = ?2
+            "#,
+            params![username, color_key(perspective), Self::now()],
+        )?;
+        Ok(())
+    }
+
+    /// Whether analysis should also report the opponent's mistakes
+    pub fn get_analyze_opponent(&self, username: &str) -> Result<bool> {
+        let analyze: Option<bool> = self.conn.query_row(
+            "SELECT analyze_opponent FROM user_settings WHERE lichess_username = ?1",
+            params![username],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(analyze.unwrap_or(false))
+    }
+
+    pub fn set_analyze_opponent(&self, username: &str, analyze: bool) -> Result<()> {
+        self.conn.execute(
+            r#"
+            INSERT INTO user_settings (lichess_username, analyze_opponent, created_at)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT(lichess_username) DO UPDATE SET analyze_opponent = ?2
+            "#,
+            params![username, analyze, Self::now()],
+        )?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // TRAINING
+    // ========================================================================
+
+    pub fn save_training_session(
+        &self,
+        training_type: &str,
+        attempts: u32,
+        correct: u32,
+        total_time_ms: u64,
+        best_time_ms: Option<u64>,
+    ) -> Result<i64> {
+        self.conn.execute(
+            r#"
+            INSERT INTO training_sessions 
+            (training_type, attempts, correct, total_time_ms, best_time_ms, date, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+            params![
+                training_type,
+                attempts,
+                correct,
+                total_time_ms,
+                best_time_ms,
+                Self::today(),
+                Self::now(),
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// The latest training sessions, newest first, optionally of one type only
+    pub fn recent_training_sessions(&self, training_type: Option<&str>, limit: u32) -> Result<Vec<TrainingSessionRow>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT id, training_type, attempts, correct, total_time_ms, best_time_ms, date, created_at
+            FROM training_sessions
+            WHERE ?1 IS NULL OR training_type = ?1
+            ORDER BY created_at DESC, id DESC
+            LIMIT ?2
+            "#,
+        )?;
+        let sessions = stmt.query_map(params![training_type, limit], |row| {
+            Ok(TrainingSessionRow {
+                id: row.get(0)?,
+                training_type: row.get(1)?,
+                attempts: row.get(2)?,
+                correct: row.get(3)?,
+                total_time_ms: row.get(4)?,
+                best_time_ms: row.get(5)?,
+                date: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(sessions)
+    }
+
+    pub fn get_training_stats(&self, training_type: &str) -> Result<TrainingStats> {
+        let today = Self::today();
+
         let (today_attempts, today_correct): (u32, u32) = self.conn.query_row(
             r#"
             SELECT COALESCE(SUM(attempts), 0), COALESCE(SUM(correct), 0)
@@ -405,6 +1427,309 @@ impl Database {
         Ok(streak)
     }
 
+    /// Accuracy and speed of one training type, grouped into buckets of
+    /// `bucket_days` days and oldest first. Attempts are pooled within a
+    /// bucket, so a long session counts for more than a short one.
+    pub fn training_progress(&self, training_type: &str, bucket_days: u32) -> Result<Vec<TrainingProgress>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT (CAST(date AS INTEGER) / ?2) * ?2 AS period,
+                   SUM(correct), SUM(attempts), SUM(total_time_ms), COUNT(*)
+            FROM training_sessions
+            WHERE training_type = ?1
+            GROUP BY period
+            ORDER BY period
+            "#,
+        )?;
+        let buckets = stmt.query_map(params![training_type, bucket_days.max(1)], |row| {
+            let correct: u64 = row.get(1)?;
+            let attempts: u64 = row.get(2)?;
+            let time_ms: u64 = row.get(3)?;
+            let (accuracy, avg_time_ms) = if attempts == 0 {
+                (0.0, 0.0)
+            } else {
+                (correct as f64 * 100.0 / attempts as f64, time_ms as f64 / attempts as f64)
+            };
+            Ok(TrainingProgress {
+                period_start: row.get(0)?,
+                accuracy,
+                avg_time_ms,
+                sessions: row.get(4)?,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(buckets)
+    }
+
+    /// Logs one try at the puzzle made from pattern `pattern_id`. `None`
+    /// when there's no such pattern.
+    pub fn record_puzzle_attempt(&self, pattern_id: i64, correct: bool) -> Result<Option<i64>> {
+        let inserted = self.conn.execute(
+            r#"
+            INSERT INTO puzzle_attempts (pattern_id, correct, created_at)
+            SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM patterns WHERE id = ?1)
+            "#,
+            params![pattern_id, correct, Self::now()],
+        )?;
+        Ok((inserted > 0).then(|| self.conn.last_insert_rowid()))
+    }
+
+    /// The puzzle of the day for `seed_date` (e.g. `"2024-03-01"`): one of
+    /// the user's own mistakes and blunders that hadn't been solved when
+    /// the date was first asked for. `None` when no unsolved puzzle is left.
+    ///
+    /// The first pick for a date is saved and returned for the rest of it,
+    /// so importing games or solving the puzzle doesn't swap it. The date is
+    /// hashed with FNV-1a, like `PgnGame::content_hash`, so the pick doesn't
+    /// change between Rust releases.
+    pub fn daily_puzzle(&self, seed_date: &str) -> Result<Option<StoredPattern>> {
+        let picked = self.conn.query_row(
+            "SELECT p.* FROM daily_puzzles d JOIN patterns p ON p.id = d.pattern_id WHERE d.seed_date = ?1",
+            params![seed_date],
+            Self::row_to_pattern,
+        ).optional()?;
+        if picked.is_some() {
+            return Ok(picked);
+        }
+
+        let unsolved = r#"
+            FROM patterns p
+            WHERE p.side = ?1 AND p.severity IN ('mistake', 'blunder')
+              AND NOT EXISTS (SELECT 1 FROM puzzle_attempts a WHERE a.pattern_id = p.id AND a.correct)
+        "#;
+        let count: u64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) {}", unsolved),
+            params![SIDE_PLAYER],
+            |row| row.get(0),
+        )?;
+        if count == 0 {
+            return Ok(None);
+        }
+
+        let seed = seed_date.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        let puzzle = self.conn.query_row(
+            &format!("SELECT p.* {} ORDER BY p.id LIMIT 1 OFFSET ?2", unsolved),
+            params![SIDE_PLAYER, (seed % count) as i64],
+            Self::row_to_pattern,
+        ).optional()?;
+        if let Some(puzzle) = &puzzle {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO daily_puzzles (seed_date, pattern_id) VALUES (?1, ?2)",
+                params![seed_date, puzzle.id],
+            )?;
+        }
+        Ok(puzzle)
+    }
+
+    // ========================================================================
+    // ANALYSIS JOBS
+    // ========================================================================
+
+    /// Records a pending analysis of one game, for a client to poll.
+    /// Returns the job id.
+    pub fn create_analysis_job(&self, game_id: i64) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO analysis_jobs (game_id, status, created_at) VALUES (?1, ?2, ?3)",
+            params![game_id, JOB_PENDING, Self::now()],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn start_analysis_job(&self, id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE analysis_jobs SET status = ?2, started_at = ?3 WHERE id = ?1",
+            params![id, JOB_RUNNING, Self::now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn finish_analysis_job(&self, id: i64, report: &GameReport) -> Result<()> {
+        self.conn.execute(
+            "UPDATE analysis_jobs SET status = ?2, report = ?3, finished_at = ?4 WHERE id = ?1",
+            params![id, JOB_DONE, serde_json::to_string(report)?, Self::now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn fail_analysis_job(&self, id: i64, error: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE analysis_jobs SET status = ?2, error = ?3, finished_at = ?4 WHERE id = ?1",
+            params![id, JOB_FAILED, error, Self::now()],
+        )?;
+        Ok(())
+    }
+
+    /// Fails every job still pending or running. The queue lives in
+    /// memory, so after a restart nothing will ever finish them. Returns
+    /// how many were failed.
+    pub fn fail_unfinished_analysis_jobs(&self) -> Result<usize> {
+        let failed = self.conn.execute(
+            "UPDATE analysis_jobs SET status = ?3, error = ?4, finished_at = ?5 WHERE status IN (?1, ?2)",
+            params![JOB_PENDING, JOB_RUNNING, JOB_FAILED, "interrupted by a restart", Self::now()],
+        )?;
+        Ok(failed)
+    }
+
+    pub fn get_analysis_job(&self, id: i64) -> Result<Option<AnalysisJobStatus>> {
+        let job = self.conn.query_row(
+            r#"
+            SELECT id, game_id, status, report, error, created_at, started_at, finished_at
+            FROM analysis_jobs WHERE id = ?1
+            "#,
+            params![id],
+            |row| {
+                let report: Option<String> = row.get(3)?;
+                Ok(AnalysisJobStatus {
+                    id: row.get(0)?,
+                    game_id: row.get(1)?,
+                    status: row.get(2)?,
+                    // A report that no longer parses is left out rather than failing the lookup
+                    report: report.and_then(|json| serde_json::from_str(&json).ok()),
+                    error: row.get(4)?,
+                    created_at: row.get(5)?,
+                    started_at: row.get(6)?,
+                    finished_at: row.get(7)?,
+                })
+            },
+        ).optional()?;
+        Ok(job)
+    }
+
+    /// The imported opening repertoire, with the stats it was imported
+    /// with, in saved order
+    pub fn get_opening_lines(&self) -> Result<Vec<OpeningLine>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT eco, name, moves, for_color, times_drilled, times_correct, last_drilled
+            FROM opening_lines
+            ORDER BY id
+            "#,
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, u32>(4)?,
+                row.get::<_, u32>(5)?,
+                row.get::<_, Option<i64>>(6)?,
+            ))
+        })?;
+
+        let mut lines = Vec::new();
+        for row in rows {
+            let (eco, name, moves, color, times_drilled, times_correct, last_drilled) = row?;
+            // A line with a colour we can't read can't be drilled either
+            let Some(for_color) = parse_color(&color) else { continue };
+            lines.push(OpeningLine {
+                eco,
+                name,
+                moves: moves.split_whitespace().map(String::from).collect(),
+                for_color,
+                times_drilled,
+                times_correct,
+                last_drilled: last_drilled.map(|t| t as u64),
+            });
+        }
+        Ok(lines)
+    }
+
+    /// Replaces the imported opening repertoire with `lines`
+    pub fn save_opening_lines(&self, lines: &[OpeningLine]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM opening_lines", [])?;
+        for line in lines {
+            tx.execute(
+                r#"
+                INSERT INTO opening_lines (eco, name, moves, for_color, times_drilled, times_correct, last_drilled)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#,
+                params![
+                    line.eco,
+                    line.name,
+                    line.moves.join(" "),
+                    line.color_name(),
+                    line.times_drilled,
+                    line.times_correct,
+                    line.last_drilled.map(|t| t as i64),
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Saves `line`'s drill stats under its moves and colour, so they
+    /// follow the line whichever repertoire it turns up in
+    pub fn save_opening_drill(&self, line: &OpeningLine) -> Result<()> {
+        self.conn.execute(
+            r#"
+            INSERT INTO opening_drills (moves, for_color, times_drilled, times_correct, last_drilled)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (moves, for_color) DO UPDATE SET
+                times_drilled = excluded.times_drilled,
+                times_correct = excluded.times_correct,
+                last_drilled = excluded.last_drilled
+            "#,
+            params![
+                line.moves.join(" "),
+                line.color_name(),
+                line.times_drilled,
+                line.times_correct,
+                line.last_drilled.map(|t| t as i64),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the stats of each of `lines` that has been drilled with the
+    /// saved ones; lines never drilled keep theirs
+    pub fn load_opening_drills(&self, lines: &mut [OpeningLine]) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT times_drilled, times_correct, last_drilled FROM opening_drills WHERE moves = ?1 AND for_color = ?2",
+        )?;
+        for line in lines {
+            let stats = stmt.query_row(params![line.moves.join(" "), line.color_name()], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?, row.get::<_, Option<i64>>(2)?))
+            }).optional()?;
+            if let Some((times_drilled, times_correct, last_drilled)) = stats {
+                line.times_drilled = times_drilled;
+                line.times_correct = times_correct;
+                line.last_drilled = last_drilled.map(|t| t as u64);
+            }
+        }
+        Ok(())
+    }
+
+    /// Share of puzzle attempts solved for each pattern type that has been
+    /// tried, weakest first, so the theme most worth studying leads
+    pub fn puzzle_solve_rates(&self) -> Result<Vec<PuzzleSolveRate>> {
+        let mut stmt = self.conn.prepare(
+            r#"
+            SELECT p.pattern_type, COUNT(*), SUM(a.correct)
+            FROM puzzle_attempts a
+            JOIN patterns p ON p.id = a.pattern_id
+            GROUP BY p.pattern_type
+            ORDER BY SUM(a.correct) * 1.0 / COUNT(*), p.pattern_type
+            "#,
+        )?;
+        let rates = stmt.query_map([], |row| {
+            let attempts: u32 = row.get(1)?;
+            let solved: u32 = row.get(2)?;
+            Ok(PuzzleSolveRate {
+                pattern_type: row.get(0)?,
+                attempts,
+                solved,
+                rate: solved as f64 * 100.0 / attempts as f64,
+            })
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(rates)
+    }
+
     pub fn get_all_training_stats(&self) -> Result<AllTrainingStats> {
         let coords = self.get_training_stats("coordinates")?;
         let viz = self.get_training_stats("visualization")?;
@@ -432,3 +1757,1028 @@ impl Database {
         })
     }
 }
+
+/// Clocks as stored in `games.clocks`: centiseconds, space-separated
+fn join_clocks(clocks: &[u32]) -> String {
+    clocks.iter().map(u32::to_string).collect::<Vec<_>>().join(" ")
+}
+
+/// `field` quoted for CSV when it holds a comma, quote or line break, with
+/// its quotes doubled
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::pgn::parse_pgn_string;
+    use crate::patterns::{PatternType, Severity};
+
+    fn lichess_game(id: &str, white: &str, black: &str, eco: &str, played_at_secs: u64) -> LichessGame {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "rated": true,
+            "variant": "standard",
+            "speed": "blitz",
+            "perf": "blitz",
+            "createdAt": played_at_secs * 1000,
+            "lastMoveAt": played_at_secs * 1000,
+            "status": "mate",
+            "players": {
+                "white": { "user": { "name": white }, "rating": 1500 },
+                "black": { "user": { "name": black }, "rating": 1500 }
+            },
+            "winner": "white",
+            "moves": "e4 e5 Nf3 Nc6 Bb5",
+            "opening": { "eco": eco, "name": "Test Opening", "ply": 5 }
+        })).unwrap()
+    }
+
+    fn pattern(severity: Severity, cp_loss: i32) -> DetectedPattern {
+        DetectedPattern {
+            move_number: 3,
+            ply: 4,
+            pattern_type: PatternType::TacticalMiss,
+            severity,
+            cp_loss,
+            player_move: "Bb5".to_string(),
+            best_move: "d2d4".to_string(),
+            fen_before: "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".to_string(),
+            fen_after: String::new(),
+            description: "test".to_string(),
+            quiet: false,
+        }
+    }
+
+    const SAMPLE_PGN: &str = r#"[Event "Club night"]
+[Date "2024.03.01"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0
+"#;
+
+    #[test]
+    fn test_duplicate_pgn_import_is_skipped() {
+        let db = Database::open_in_memory().unwrap();
+        let games = parse_pgn_string(SAMPLE_PGN).unwrap();
+
+        assert!(db.insert_pgn_game(&games[0]).unwrap().is_some());
+        assert!(db.insert_pgn_game(&games[0]).unwrap().is_none());
+
+        assert_eq!(db.count_games().unwrap(), 1);
+        let stored = &db.get_all_games().unwrap()[0];
+        assert_eq!(stored.moves, "e4 e5 Nf3 Nc6 Bb5");
+        assert_eq!(stored.played_at, 1709251200);
+    }
+
+    #[test]
+    fn test_dashboard_summary() {
+        let db = Database::open_in_memory().unwrap();
+        assert_eq!(db.dashboard_summary(None).unwrap().most_common_pattern, None);
+
+        let g1 = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let g2 = db.insert_game(&lichess_game("g2", "Alice", "Carol", "B20", 200)).unwrap();
+        db.insert_game(&lichess_game("g3", "Alice", "Dave", "A00", 300)).unwrap();
+        db.set_game_acpl(g1, 30.0).unwrap();
+        db.set_game_acpl(g2, 50.0).unwrap();
+
+        db.insert_pattern(g1, &pattern(Severity::Blunder, 400)).unwrap();
+        db.insert_pattern(g1, &pattern(Severity::Mistake, 150)).unwrap();
+        let mut hanging = pattern(Severity::Blunder, 500);
+        hanging.pattern_type = PatternType::HangingPiece;
+        db.insert_pattern(g2, &hanging).unwrap();
+        db.insert_pattern(g2, &pattern(Severity::Inaccuracy, 60)).unwrap();
+
+        let summary = db.dashboard_summary(None).unwrap();
+        assert_eq!(summary.total_games, 3);
+        assert_eq!(summary.total_patterns, 4);
+        assert_eq!((summary.blunders, summary.mistakes, summary.inaccuracies), (2, 1, 1));
+        assert_eq!(summary.most_common_pattern.as_deref(), Some("tactical_miss"));
+        assert_eq!(PatternType::parse("tactical_miss"), Some(PatternType::TacticalMiss));
+        assert_eq!(summary.avg_acpl, Some(40.0));
+    }
+
+    #[test]
+    fn test_pattern_stats_by_speed() {
+        let db = Database::open_in_memory().unwrap();
+        let blitz = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let mut game = lichess_game("g2", "Alice", "Carol", "B20", 200);
+        game.speed = "bullet".to_string();
+        let bullet = db.insert_game(&game).unwrap();
+        db.set_game_acpl(blitz, 35.0).unwrap();
+        db.set_game_acpl(bullet, 90.0).unwrap();
+
+        db.insert_pattern(blitz, &pattern(Severity::Mistake, 150)).unwrap();
+        let mut hanging = pattern(Severity::Blunder, 500);
+        hanging.pattern_type = PatternType::HangingPiece;
+        db.insert_pattern(bullet, &hanging).unwrap();
+        db.insert_pattern(bullet, &hanging).unwrap();
+
+        let summary = db.dashboard_summary(Some("bullet")).unwrap();
+        assert_eq!((summary.total_games, summary.total_patterns), (1, 2));
+        assert_eq!((summary.blunders, summary.mistakes), (2, 0));
+        assert_eq!(summary.most_common_pattern.as_deref(), Some("hanging_piece"));
+        assert_eq!(summary.avg_acpl, Some(90.0));
+
+        let summary = db.dashboard_summary(Some("blitz")).unwrap();
+        assert_eq!((summary.total_patterns, summary.avg_acpl), (1, Some(35.0)));
+        assert_eq!(db.dashboard_summary(Some("rapid")).unwrap().total_patterns, 0);
+        assert_eq!(db.dashboard_summary(None).unwrap().total_patterns, 3);
+
+        let games: Vec<i64> = db.recent_patterns(10, 0, Some("blitz"), false).unwrap()
+            .iter().map(|p| p.game_id).collect();
+        assert_eq!(games, vec![blitz]);
+        assert_eq!(db.recent_patterns(10, 0, Some("bullet"), false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_head_to_head() {
+        let db = Database::open_in_memory().unwrap();
+        let result = |mut game: LichessGame, winner: Option<&str>| {
+            game.winner = winner.map(String::from);
+            if winner.is_none() {
+                game.status = "draw".to_string();
+            }
+            game
+        };
+
+        db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        db.insert_game(&result(lichess_game("g2", "bob", "alice", "C60", 200), Some("white"))).unwrap();
+        db.insert_game(&result(lichess_game("g3", "Bob", "Alice", "C60", 300), None)).unwrap();
+        db.insert_game(&result(lichess_game("g4", "Bob", "Alice", "C60", 400), Some("white"))).unwrap();
+        db.insert_game(&result(lichess_game("g5", "Carol", "Alice", "B20", 500), Some("black"))).unwrap();
+        db.insert_game(&lichess_game("g6", "Bob", "Carol", "A00", 600)).unwrap();
+
+        let records = db.head_to_head("alice").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].opponent.to_lowercase(), "bob");
+        assert_eq!((records[0].games, records[0].wins, records[0].losses, records[0].draws), (4, 1, 2, 1));
+        assert_eq!(records[1], OpponentRecord {
+            opponent: "Carol".to_string(),
+            games: 1,
+            wins: 1,
+            losses: 0,
+            draws: 0,
+        });
+        assert!(db.head_to_head("nobody").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_performance_vs_expectation() {
+        let db = Database::open_in_memory().unwrap();
+        assert_eq!(db.performance_vs_expectation("alice", None).unwrap(), None);
+
+        let rated = |id: &str, white: &str, black: &str, ratings: (u16, u16), winner: Option<&str>| {
+            let mut game = lichess_game(id, white, black, "C60", 100);
+            game.players.white.rating = Some(ratings.0);
+            game.players.black.rating = Some(ratings.1);
+            game.winner = winner.map(String::from);
+            if winner.is_none() {
+                game.status = "draw".to_string();
+            }
+            game
+        };
+        // Equal ratings, won as White; a 400-point underdog drawing as Black
+        db.insert_game(&rated("g1", "Alice", "Bob", (1500, 1500), Some("white"))).unwrap();
+        db.insert_game(&rated("g2", "Carol", "alice", (1900, 1500), None)).unwrap();
+        // Unknown rating: not counted
+        let mut unrated = rated("g3", "Alice", "Dave", (1500, 1500), Some("black"));
+        unrated.players.black.rating = None;
+        db.insert_game(&unrated).unwrap();
+
+        let performance = db.performance_vs_expectation("alice", None).unwrap().unwrap();
+        assert_eq!(performance.games, 2);
+        assert_eq!(performance.score, 1.5);
+        assert!((performance.expected - (0.5 + expected_score(1500, 1900))).abs() < 1e-9);
+        // 1.5 scored where about 0.59 was expected
+        assert!((performance.over_performance() - 0.454).abs() < 0.001);
+
+        assert_eq!(db.performance_vs_expectation("alice", Some("rapid")).unwrap(), None);
+        assert_eq!(db.performance_vs_expectation("alice", Some("blitz")).unwrap().unwrap().games, 2);
+    }
+
+    #[test]
+    fn test_games_vs_one_opponent_either_colour() {
+        let db = Database::open_in_memory().unwrap();
+        let result = |mut game: LichessGame, winner: Option<&str>| {
+            game.winner = winner.map(String::from);
+            if winner.is_none() {
+                game.status = "draw".to_string();
+            }
+            game
+        };
+        db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        db.insert_game(&result(lichess_game("g2", "bob", "alice", "B20", 200), Some("white"))).unwrap();
+        db.insert_game(&result(lichess_game("g3", "Bob", "Alice", "B20", 300), None)).unwrap();
+        db.insert_game(&lichess_game("g4", "Alice", "Carol", "C60", 400)).unwrap();
+        // Bob against someone else isn't a game against the user
+        db.insert_game(&lichess_game("g5", "Bob", "Carol", "A00", 500)).unwrap();
+
+        let games = db.get_games_vs("alice", "BOB").unwrap();
+        let ids: Vec<&str> = games.iter().map(|g| g.lichess_id.as_str()).collect();
+        assert_eq!(ids, ["g3", "g2", "g1"]);
+        assert!(db.get_games_vs("alice", "dave").unwrap().is_empty());
+
+        let g2 = games[1].id;
+        db.insert_patterns_with_opponent(g2, &[], &[
+            pattern(Severity::Blunder, 400),
+            pattern(Severity::Mistake, 150),
+        ]).unwrap();
+        db.insert_patterns(g2, &[pattern(Severity::Blunder, 350)]).unwrap();
+
+        let report = db.scout_report("alice", "bob").unwrap();
+        assert_eq!(report.games, 3);
+        // From Bob's side: lost g1, won g2, drew g3
+        assert_eq!((report.wins, report.losses, report.draws), (1, 1, 1));
+        assert_eq!(report.openings_as_white, [OpeningCount { eco: "B20".to_string(), name: Some("Test Opening".to_string()), games: 2 }]);
+        assert_eq!(report.openings_as_black.len(), 1);
+        // Only Bob's own mistakes, not Alice's
+        assert_eq!(report.mistakes, [("tactical_miss".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_daily_puzzle_is_stable_for_a_date() {
+        let db = Database::open_in_memory().unwrap();
+        assert_eq!(db.daily_puzzle("2024-03-01").unwrap().map(|p| p.id), None);
+
+        let game_id = db.insert_game(&lichess_game("g1", "alice", "bob", "C60", 100)).unwrap();
+        db.insert_patterns_with_opponent(game_id, &[
+            pattern(Severity::Blunder, 400),
+            pattern(Severity::Mistake, 200),
+            pattern(Severity::Blunder, 300),
+            pattern(Severity::Inaccuracy, 60),
+        ], &[pattern(Severity::Blunder, 500)]).unwrap();
+
+        let today = db.daily_puzzle("2024-03-01").unwrap().unwrap();
+        assert_eq!(db.daily_puzzle("2024-03-01").unwrap().unwrap().id, today.id);
+        assert_eq!((today.side.as_str(), today.severity == "inaccuracy"), (SIDE_PLAYER, false));
+
+        // It moves on over the days, and always among the three candidates
+        let week: std::collections::HashSet<i64> = (1..=7)
+            .map(|day| db.daily_puzzle(&format!("2024-03-0{}", day)).unwrap().unwrap().id)
+            .collect();
+        assert!(week.len() > 1 && week.len() <= 3);
+
+        // Solving it or adding puzzles during the day doesn't swap it
+        db.record_puzzle_attempt(today.id, true).unwrap();
+        db.insert_pattern(game_id, &pattern(Severity::Blunder, 600)).unwrap();
+        assert_eq!(db.daily_puzzle("2024-03-01").unwrap().unwrap().id, today.id);
+
+        // Later days skip it now it's solved
+        assert!((2..=7).all(|day| db.daily_puzzle(&format!("2024-04-0{}", day)).unwrap().unwrap().id != today.id));
+    }
+
+    #[test]
+    fn test_analysis_job_lifecycle() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "alice", "bob", "C60", 100)).unwrap();
+        assert!(db.get_analysis_job(1).unwrap().is_none());
+
+        let id = db.create_analysis_job(game_id).unwrap();
+        let job = db.get_analysis_job(id).unwrap().unwrap();
+        assert_eq!((job.game_id, job.status.as_str()), (game_id, JOB_PENDING));
+        assert!(job.started_at.is_none() && job.report.is_none());
+
+        db.start_analysis_job(id).unwrap();
+        db.fail_analysis_job(id, "engine crashed").unwrap();
+        let job = db.get_analysis_job(id).unwrap().unwrap();
+        assert_eq!((job.status.as_str(), job.error.as_deref()), (JOB_FAILED, Some("engine crashed")));
+        assert!(job.started_at.is_some() && job.finished_at.is_some());
+    }
+
+    #[test]
+    fn test_unfinished_analysis_jobs_fail_on_restart() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "alice", "bob", "C60", 100)).unwrap();
+        let pending = db.create_analysis_job(game_id).unwrap();
+        let running = db.create_analysis_job(game_id).unwrap();
+        db.start_analysis_job(running).unwrap();
+        let done = db.create_analysis_job(game_id).unwrap();
+        db.finish_analysis_job(done, &GameReport::default()).unwrap();
+
+        assert_eq!(db.fail_unfinished_analysis_jobs().unwrap(), 2);
+        for id in [pending, running] {
+            let job = db.get_analysis_job(id).unwrap().unwrap();
+            assert_eq!((job.status.as_str(), job.error.as_deref()), (JOB_FAILED, Some("interrupted by a restart")));
+        }
+        assert_eq!(db.get_analysis_job(done).unwrap().unwrap().status, JOB_DONE);
+    }
+
+    #[test]
+    fn test_count_analyzed_games() {
+        let db = Database::open_in_memory().unwrap();
+        assert_eq!((db.count_analyzed_games().unwrap(), db.count_unanalyzed_games().unwrap()), (0, 0));
+
+        let g1 = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        db.insert_game(&lichess_game("g2", "Alice", "Carol", "B20", 200)).unwrap();
+        db.insert_game(&lichess_game("g3", "Alice", "Dave", "A00", 300)).unwrap();
+        db.mark_game_analyzed(g1).unwrap();
+
+        assert_eq!(db.count_analyzed_games().unwrap(), 1);
+        assert_eq!(db.count_unanalyzed_games().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_is_empty_until_a_game_is_stored() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(db.is_empty().unwrap());
+        // Settings and training alone don't count as data to show
+        db.set_board_perspective("alice", Color::Black).unwrap();
+        db.save_training_session("coordinates", 10, 8, 20_000, None).unwrap();
+        assert!(db.is_empty().unwrap());
+
+        db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        assert!(!db.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_acpl_trend_buckets() {
+        let db = Database::open_in_memory().unwrap();
+        let day = 86400;
+        let games = [
+            ("g1", 1, Some(60.0)),
+            ("g2", 10, Some(40.0)),
+            ("g3", 35, Some(20.0)),
+            ("g4", 36, None),
+        ];
+        for (id, played_day, acpl) in games {
+            let game_id = db.insert_game(&lichess_game(id, "Alice", "Bob", "C60", played_day * day)).unwrap();
+            if let Some(acpl) = acpl {
+                db.set_game_acpl(game_id, acpl).unwrap();
+                db.mark_game_analyzed(game_id).unwrap();
+            }
+        }
+
+        let trend = db.acpl_trend("alice", Some("blitz"), 30).unwrap();
+        assert_eq!(trend.len(), 2);
+        assert_eq!(trend[0].period_start, 0);
+        assert_eq!((trend[0].avg_acpl, trend[0].games), (50.0, 2));
+        assert_eq!(trend[1].period_start, 30 * day);
+        assert_eq!((trend[1].avg_acpl, trend[1].games), (20.0, 1));
+
+        assert!(db.acpl_trend("alice", Some("rapid"), 30).unwrap().is_empty());
+        assert_eq!(db.acpl_trend("alice", None, 7).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_most_improved_opening() {
+        let db = Database::open_in_memory().unwrap();
+        let day = 86400;
+        // (id, eco, day played, acpl)
+        let games = [
+            // Ruy Lopez: rough at first, much better later
+            ("r1", "C60", 1, 90.0),
+            ("r2", "C60", 5, 70.0),
+            ("r3", "C60", 40, 30.0),
+            // Sicilian: slightly worse later
+            ("s1", "B20", 2, 40.0),
+            ("s2", "B20", 45, 55.0),
+            // French: only played early, so nothing to compare
+            ("f1", "C00", 3, 100.0),
+        ];
+        for (id, eco, played_day, acpl) in games {
+            let game_id = db.insert_game(&lichess_game(id, "Alice", "Bob", eco, played_day * day)).unwrap();
+            db.set_game_acpl(game_id, acpl).unwrap();
+            db.mark_game_analyzed(game_id).unwrap();
+        }
+
+        let changes = db.most_improved_opening("alice", (0, 30 * day), (30 * day, 60 * day)).unwrap();
+        assert_eq!(changes.iter().map(|c| c.eco.as_str()).collect::<Vec<_>>(), ["C60", "B20"]);
+        assert_eq!((changes[0].earlier_acpl, changes[0].earlier_games), (80.0, 2));
+        assert_eq!((changes[0].later_acpl, changes[0].later_games), (30.0, 1));
+        assert_eq!(changes[0].improvement(), 50.0);
+        assert_eq!(changes[1].improvement(), -15.0);
+        assert_eq!(changes[0].name.as_deref(), Some("Test Opening"));
+
+        assert!(db.most_improved_opening("bob", (0, 30 * day), (60 * day, 90 * day)).unwrap().is_empty());
+        assert!(db.most_improved_opening("carol", (0, 30 * day), (30 * day, 60 * day)).unwrap().is_empty());
+    }
+
+    fn temp_db_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("chess-analyzer-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_import_from_merges_and_remaps() {
+        let other_path = temp_db_path("merge-other");
+        {
+            let other = Database::open(&other_path).unwrap();
+            let shared = other.insert_game(&lichess_game("shared", "Alice", "Bob", "C60", 100)).unwrap();
+            other.insert_pattern(shared, &pattern(Severity::Blunder, 400)).unwrap();
+            other.mark_game_analyzed(shared).unwrap();
+
+            let laptop = other.insert_game(&lichess_game("laptop", "Alice", "Carol", "B20", 200)).unwrap();
+            other.insert_pattern(laptop, &pattern(Severity::Mistake, 150)).unwrap();
+            other.insert_pattern(laptop, &pattern(Severity::Blunder, 600)).unwrap();
+            other.mark_game_analyzed(laptop).unwrap();
+        }
+
+        let db = Database::open_in_memory().unwrap();
+        let desktop = db.insert_game(&lichess_game("desktop", "Alice", "Dave", "A00", 50)).unwrap();
+        db.insert_pattern(desktop, &pattern(Severity::Blunder, 300)).unwrap();
+        let shared = db.insert_game(&lichess_game("shared", "Alice", "Bob", "C60", 100)).unwrap();
+        db.insert_pattern(shared, &pattern(Severity::Blunder, 400)).unwrap();
+        db.mark_game_analyzed(shared).unwrap();
+
+        let summary = db.import_from(&other_path).unwrap();
+        assert_eq!(summary.games_merged, 1);
+        assert_eq!(summary.games_skipped, 1);
+        assert_eq!(summary.patterns_merged, 2);
+
+        assert_eq!(db.count_games().unwrap(), 3);
+        assert_eq!(db.count_patterns().unwrap(), 4);
+        let laptop = db.get_all_games().unwrap()
+            .into_iter()
+            .find(|g| g.lichess_id == "laptop")
+            .unwrap();
+        assert!(laptop.analyzed);
+        let remapped = db.get_all_patterns().unwrap()
+            .into_iter()
+            .filter(|p| p.game_id == laptop.id)
+            .count();
+        assert_eq!(remapped, 2);
+
+        // Importing again is a no-op
+        let again = db.import_from(&other_path).unwrap();
+        assert_eq!((again.games_merged, again.patterns_merged), (0, 0));
+
+        let _ = std::fs::remove_file(&other_path);
+    }
+
+    #[test]
+    fn test_recent_patterns_follow_game_date() {
+        let db = Database::open_in_memory().unwrap();
+        let old = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let new = db.insert_game(&lichess_game("g2", "Alice", "Carol", "B20", 300)).unwrap();
+        let middle = db.insert_game(&lichess_game("g3", "Alice", "Dave", "A00", 200)).unwrap();
+
+        // Inserted newest game first, as after re-analyzing an old game
+        db.insert_pattern(new, &pattern(Severity::Mistake, 150)).unwrap();
+        db.insert_pattern(old, &pattern(Severity::Blunder, 400)).unwrap();
+        let mut late = pattern(Severity::Blunder, 500);
+        late.move_number = 30;
+        db.insert_pattern(middle, &late).unwrap();
+        db.insert_pattern(middle, &pattern(Severity::Inaccuracy, 60)).unwrap();
+
+        let order: Vec<(i64, u16)> = db.recent_patterns(10, 0, None, false).unwrap()
+            .iter().map(|p| (p.game_id, p.move_number)).collect();
+        assert_eq!(order, vec![(new, 3), (middle, 3), (middle, 30), (old, 3)]);
+
+        let page: Vec<i64> = db.recent_patterns(2, 2, None, false).unwrap().iter().map(|p| p.game_id).collect();
+        assert_eq!(page, vec![middle, old]);
+        assert!(db.recent_patterns(2, 4, None, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_puzzles_for_opening() {
+        let db = Database::open_in_memory().unwrap();
+        let ruy = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let other = db.insert_game(&lichess_game("g2", "Alice", "Carol", "B20", 200)).unwrap();
+        let not_mine = db.insert_game(&lichess_game("g3", "Dave", "Erin", "C60", 300)).unwrap();
+
+        db.insert_pattern(ruy, &pattern(Severity::Blunder, 400)).unwrap();
+        db.insert_pattern(ruy, &pattern(Severity::Blunder, 900)).unwrap();
+        db.insert_pattern(ruy, &pattern(Severity::Mistake, 150)).unwrap();
+        db.insert_pattern(other, &pattern(Severity::Blunder, 500)).unwrap();
+        db.insert_pattern(not_mine, &pattern(Severity::Blunder, 500)).unwrap();
+
+        let puzzles = db.puzzles_for_opening("C60", "alice").unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert!(puzzles.iter().all(|p| p.game_id == ruy && p.severity == "blunder"));
+        assert_eq!(puzzles[0].centipawn_loss, Some(900));
+
+        let counts = db.blunder_counts_by_opening("alice").unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["C60"], 2);
+        assert_eq!(counts["B20"], 1);
+    }
+
+    #[test]
+    fn test_board_perspective_round_trip() {
+        let db = Database::open_in_memory().unwrap();
+        assert_eq!(db.get_board_perspective("alice").unwrap(), None);
+
+        db.set_last_sync_time("alice").unwrap();
+        db.set_board_perspective("alice", Color::Black).unwrap();
+        assert_eq!(db.get_board_perspective("alice").unwrap(), Some(Color::Black));
+        assert!(db.get_last_sync_time("alice").unwrap().is_some());
+
+        db.set_board_perspective("alice", Color::White).unwrap();
+        assert_eq!(db.get_board_perspective("alice").unwrap(), Some(Color::White));
+    }
+
+    #[test]
+    fn test_stored_uci_replays_to_same_position() {
+        use shakmaty::{uci::UciMove, Position};
+
+        let db = Database::open_in_memory().unwrap();
+        let game = parse_pgn_string(SAMPLE_PGN).unwrap().remove(0);
+        let id = db.insert_pgn_game(&game).unwrap().unwrap();
+
+        let stored = db.get_game(id).unwrap().unwrap();
+        assert_eq!(stored.moves, game.moves.join(" "));
+        let uci = db.moves_uci(id).unwrap().unwrap();
+        assert_eq!(stored.moves_uci, Some(uci.join(" ")));
+        assert_eq!(uci.len(), game.moves.len());
+
+        let replayed = uci.iter().fold(shakmaty::Chess::default(), |position, mv| {
+            let mv = mv.parse::<UciMove>().unwrap().to_move(&position).unwrap();
+            position.play(mv).unwrap()
+        });
+        assert_eq!(replayed, game.final_position);
+
+        // Rows from before the column existed are filled in on first use
+        db.conn.execute("UPDATE games SET moves_uci = NULL", []).unwrap();
+        assert_eq!(db.moves_uci(id).unwrap().unwrap(), uci);
+        assert!(db.get_game(id).unwrap().unwrap().moves_uci.is_some());
+        assert_eq!(db.moves_uci(id + 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_clocks_are_stored_with_the_game() {
+        let db = Database::open_in_memory().unwrap();
+        let mut timed = lichess_game("g1", "Alice", "Bob", "C60", 100);
+        timed.clocks = Some(vec![18000, 17950, 17800, 17700, 17500]);
+        let id = db.insert_game(&timed).unwrap();
+        assert_eq!(db.get_game(id).unwrap().unwrap().clocks, timed.clocks);
+
+        let untimed = db.insert_game(&lichess_game("g2", "Alice", "Bob", "C60", 200)).unwrap();
+        assert_eq!(db.get_game(untimed).unwrap().unwrap().clocks, None);
+
+        let pgn = "[White \"Alice\"]\n[Black \"Bob\"]\n\n1. e4 { [%clk 0:03:00] } e5 { [%clk 0:02:58] } *\n";
+        let game = parse_pgn_string(pgn).unwrap().remove(0);
+        let id = db.insert_pgn_game(&game).unwrap().unwrap();
+        assert_eq!(db.get_game(id).unwrap().unwrap().clocks, Some(vec![18000, 17800]));
+    }
+
+    #[test]
+    fn test_endgame_conversion_is_stored_with_the_game() {
+        let db = Database::open_in_memory().unwrap();
+        let id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        assert_eq!(db.get_game(id).unwrap().unwrap().endgame_conversion, None);
+
+        db.set_game_endgame_conversion(id, 0.5).unwrap();
+        assert_eq!(db.get_game(id).unwrap().unwrap().endgame_conversion, Some(0.5));
+    }
+
+    #[test]
+    fn test_quiet_flag_is_stored_with_the_pattern() {
+        let db = Database::open_in_memory().unwrap();
+        let id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        db.insert_pattern(id, &pattern(Severity::Mistake, 150)).unwrap();
+        db.insert_pattern(id, &DetectedPattern { quiet: true, ..pattern(Severity::Blunder, 400) }).unwrap();
+
+        let stored = db.get_all_patterns().unwrap();
+        // Newest first; the quiet blunder keeps its type
+        assert!(stored[0].quiet);
+        assert_eq!(stored[0].pattern_type, "tactical_miss");
+        assert!(!stored[1].quiet);
+    }
+
+    #[test]
+    fn test_recent_training_sessions() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(db.recent_training_sessions(None, 10).unwrap().is_empty());
+
+        let first = db.save_training_session("coordinates", 20, 15, 60_000, Some(900)).unwrap();
+        let second = db.save_training_session("visualization", 10, 10, 45_000, None).unwrap();
+        let third = db.save_training_session("coordinates", 8, 2, 30_000, Some(1200)).unwrap();
+
+        // Saved within the same second, so the id breaks the tie
+        let all = db.recent_training_sessions(None, 10).unwrap();
+        assert_eq!(all.iter().map(|s| s.id).collect::<Vec<_>>(), vec![third, second, first]);
+        assert_eq!(all[1].best_time_ms, None);
+
+        let coords = db.recent_training_sessions(Some("coordinates"), 10).unwrap();
+        assert_eq!(coords.len(), 2);
+        assert_eq!((coords[1].attempts, coords[1].correct), (20, 15));
+        assert_eq!(coords[1].total_time_ms, 60_000);
+        assert_eq!(coords[1].best_time_ms, Some(900));
+        assert_eq!(coords[1].accuracy(), 75);
+        assert!(!coords[1].date.is_empty());
+
+        let latest = db.recent_training_sessions(None, 1).unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].id, third);
+    }
+
+    #[test]
+    fn test_training_progress_buckets() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(db.training_progress("coordinates", 7).unwrap().is_empty());
+
+        // (day, attempts, correct, total_time_ms)
+        let sessions = [
+            (20_000, 10, 5, 20_000),
+            (20_002, 30, 21, 45_000),
+            (20_008, 20, 18, 20_000),
+            (20_011, 10, 10, 8_000),
+        ];
+        for (day, attempts, correct, time_ms) in sessions {
+            let id = db.save_training_session("coordinates", attempts, correct, time_ms, None).unwrap();
+            db.conn.execute("UPDATE training_sessions SET date = ?1 WHERE id = ?2", params![day.to_string(), id]).unwrap();
+        }
+        db.save_training_session("visualization", 10, 0, 99_000, None).unwrap();
+
+        let progress = db.training_progress("coordinates", 7).unwrap();
+        assert_eq!(progress.len(), 2);
+        // Buckets start on multiples of the bucket size
+        assert_eq!(progress[0].period_start, 19_999);
+        assert_eq!(progress[0].sessions, 2);
+        assert_eq!(progress[0].accuracy, 65.0);
+        assert_eq!(progress[0].avg_time_ms, 1_625.0);
+        assert_eq!(progress[1].period_start, 20_006);
+        assert_eq!((progress[1].accuracy, progress[1].avg_time_ms), (28.0 * 100.0 / 30.0, 28_000.0 / 30.0));
+
+        let daily = db.training_progress("coordinates", 1).unwrap();
+        assert_eq!(daily.iter().map(|p| p.period_start).collect::<Vec<_>>(), [20_000, 20_002, 20_008, 20_011]);
+        assert_eq!(daily[3].accuracy, 100.0);
+    }
+
+    #[test]
+    fn test_opening_lines_round_trip() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(db.get_opening_lines().unwrap().is_empty());
+
+        let line = |moves: &str, for_color: Color| OpeningLine {
+            eco: "C60".to_string(),
+            name: "Ruy Lopez".to_string(),
+            moves: moves.split_whitespace().map(String::from).collect(),
+            for_color,
+            times_drilled: 4,
+            times_correct: 3,
+            last_drilled: Some(1_700_000_000),
+        };
+        db.save_opening_lines(&[line("e4 e5 Nf3 Nc6 Bb5", Color::White), line("e4 e5", Color::Black)]).unwrap();
+
+        let mut drilled = db.get_opening_lines().unwrap();
+        assert_eq!(drilled.len(), 2);
+        assert_eq!(drilled[0].moves.len(), 5);
+        assert_eq!(drilled[1].for_color, Color::Black);
+        assert_eq!(drilled[0].last_drilled, Some(1_700_000_000));
+
+        // Saving again replaces rather than appends
+        drilled[0].record_drill(true, 1_700_100_000);
+        db.save_opening_lines(&drilled[..1]).unwrap();
+        let saved = db.get_opening_lines().unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!((saved[0].times_drilled, saved[0].times_correct), (5, 4));
+        assert_eq!(saved[0].last_drilled, Some(1_700_100_000));
+    }
+
+    #[test]
+    fn test_opening_drills_follow_the_moves() {
+        let db = Database::open_in_memory().unwrap();
+        let line = |moves: &str, for_color: Color| OpeningLine {
+            eco: "C60".to_string(),
+            name: "Ruy Lopez".to_string(),
+            moves: moves.split_whitespace().map(String::from).collect(),
+            for_color,
+            times_drilled: 0,
+            times_correct: 0,
+            last_drilled: None,
+        };
+        let mut drilled = line("e4 e5 Nf3", Color::White);
+        drilled.record_drill(true, 1_700_000_000);
+        db.save_opening_drill(&drilled).unwrap();
+        drilled.record_drill(false, 1_700_100_000);
+        db.save_opening_drill(&drilled).unwrap();
+
+        // Found again at another position in the list, or for the other colour
+        let mut lines = vec![line("d4 d5", Color::White), line("e4 e5 Nf3", Color::Black), line("e4 e5 Nf3", Color::White)];
+        db.load_opening_drills(&mut lines).unwrap();
+        assert_eq!(lines[0].times_drilled, 0);
+        assert_eq!(lines[1].times_drilled, 0);
+        assert_eq!((lines[2].times_drilled, lines[2].times_correct), (2, 1));
+        assert_eq!(lines[2].last_drilled, Some(1_700_100_000));
+        // Drills aren't a repertoire
+        assert!(db.get_opening_lines().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_puzzle_solve_rates_by_type() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(db.puzzle_solve_rates().unwrap().is_empty());
+
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let typed = |pattern_type| DetectedPattern { pattern_type, ..pattern(Severity::Blunder, 400) };
+        let fork = db.insert_pattern(game_id, &typed(PatternType::MissedFork)).unwrap();
+        let other_fork = db.insert_pattern(game_id, &typed(PatternType::MissedFork)).unwrap();
+        let back_rank = db.insert_pattern(game_id, &typed(PatternType::AllowedBackRank)).unwrap();
+        db.insert_pattern(game_id, &typed(PatternType::HangingPiece)).unwrap();
+
+        for correct in [true, true, false, true] {
+            db.record_puzzle_attempt(fork, correct).unwrap().unwrap();
+        }
+        db.record_puzzle_attempt(other_fork, true).unwrap().unwrap();
+        for correct in [false, true, false, false, true] {
+            db.record_puzzle_attempt(back_rank, correct).unwrap().unwrap();
+        }
+        assert_eq!(db.record_puzzle_attempt(9_999, true).unwrap(), None);
+
+        // Weakest first; untried types are left out
+        let rates = db.puzzle_solve_rates().unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].pattern_type, "allowed_back_rank");
+        assert_eq!((rates[0].attempts, rates[0].solved, rates[0].rate), (5, 2, 40.0));
+        assert_eq!(rates[1].pattern_type, "missed_fork");
+        assert_eq!((rates[1].attempts, rates[1].solved, rates[1].rate), (5, 4, 80.0));
+    }
+
+    #[test]
+    fn test_pattern_quality_is_stored() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+
+        db.insert_pattern(game_id, &pattern(Severity::Blunder, 400)).unwrap();
+        db.insert_pattern(game_id, &pattern(Severity::Inaccuracy, 60)).unwrap();
+        // Style tips are reported at inaccuracy level even when the eval held
+        db.insert_pattern(game_id, &pattern(Severity::Inaccuracy, 20)).unwrap();
+        db.insert_pattern(game_id, &pattern(Severity::Inaccuracy, 0)).unwrap();
+
+        let quality: Vec<String> = db.get_all_patterns().unwrap().into_iter().rev().map(|p| p.quality).collect();
+        assert_eq!(quality, ["blunder", "inaccuracy", "good", "best"]);
+        assert_eq!(db.get_all_patterns().unwrap()[0].move_label(), "3.");
+
+        // Rows from before the column existed fall back to their severity
+        db.conn.execute("UPDATE patterns SET quality = NULL", []).unwrap();
+        db.migrate().unwrap();
+        assert!(db.get_all_patterns().unwrap().iter().all(|p| p.quality == p.severity));
+    }
+
+    #[test]
+    fn test_insert_patterns_is_atomic() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let patterns = [pattern(Severity::Blunder, 400), pattern(Severity::Mistake, 150), pattern(Severity::Inaccuracy, 60)];
+
+        assert_eq!(db.insert_patterns(game_id, &patterns).unwrap(), 3);
+        assert_eq!(db.count_patterns().unwrap(), 3);
+        assert!(db.get_game(game_id).unwrap().unwrap().analyzed);
+
+        // A failure on the second pattern takes the first one with it
+        let other = db.insert_game(&lichess_game("g2", "Alice", "Bob", "C60", 200)).unwrap();
+        db.conn.execute_batch(
+            "CREATE TRIGGER fail_mistakes BEFORE INSERT ON patterns WHEN NEW.severity = 'mistake'
+             BEGIN SELECT RAISE(ABORT, 'forced failure'); END;",
+        ).unwrap();
+        assert!(db.insert_patterns(other, &patterns).is_err());
+        assert_eq!(db.count_patterns().unwrap(), 3);
+        assert!(!db.get_game(other).unwrap().unwrap().analyzed);
+    }
+
+    #[test]
+    fn test_heuristic_games_are_requeued_for_the_engine() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let engine_game = db.insert_game(&lichess_game("g2", "Alice", "Bob", "C60", 200)).unwrap();
+        db.insert_heuristic_patterns(game_id, &[pattern(Severity::Blunder, 900)], &[]).unwrap();
+        db.insert_patterns(engine_game, &[pattern(Severity::Blunder, 400)]).unwrap();
+        assert_eq!(db.count_unanalyzed_games().unwrap(), 0);
+
+        // Only the heuristic game goes back, keeping its patterns until then
+        assert_eq!(db.requeue_heuristic_games().unwrap(), 1);
+        let unanalyzed = db.get_unanalyzed_games(10).unwrap();
+        assert_eq!(unanalyzed.iter().map(|g| g.id).collect::<Vec<_>>(), [game_id]);
+        assert_eq!(db.count_patterns().unwrap(), 2);
+
+        // The engine's patterns replace the heuristic ones, and that's final
+        let engine_patterns = [pattern(Severity::Mistake, 150), pattern(Severity::Inaccuracy, 60)];
+        db.insert_patterns(game_id, &engine_patterns).unwrap();
+        assert_eq!(db.count_patterns().unwrap(), 3);
+        assert_eq!(db.requeue_heuristic_games().unwrap(), 0);
+        assert_eq!(db.count_unanalyzed_games().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_opponent_patterns_are_kept_apart() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let ours = [pattern(Severity::Blunder, 400)];
+        let theirs = [pattern(Severity::Mistake, 150), pattern(Severity::Mistake, 120)];
+
+        assert_eq!(db.insert_patterns_with_opponent(game_id, &ours, &theirs).unwrap(), 3);
+        let mine = db.recent_patterns(10, 0, None, false).unwrap();
+        assert_eq!(mine.len(), 1);
+        assert_eq!(mine[0].side, SIDE_PLAYER);
+        let all = db.recent_patterns(10, 0, None, true).unwrap();
+        assert_eq!(all.iter().filter(|p| p.side == SIDE_OPPONENT).count(), 2);
+
+        // The dashboard is about the user's own play
+        let summary = db.dashboard_summary(None).unwrap();
+        assert_eq!((summary.total_patterns, summary.blunders, summary.mistakes), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_side_column_added_to_old_databases() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        db.conn.execute_batch("ALTER TABLE patterns DROP COLUMN side").unwrap();
+        db.conn.execute(
+            "INSERT INTO patterns (game_id, move_number, pattern_type, severity, position_fen, description, created_at)
+             VALUES (?1, 12, 'hanging_piece', 'blunder', 'fen', 'old', 0)",
+            params![game_id],
+        ).unwrap();
+
+        db.migrate().unwrap();
+        assert_eq!(db.get_all_patterns().unwrap()[0].side, SIDE_PLAYER);
+    }
+
+    #[test]
+    fn test_analyze_opponent_setting() {
+        let db = Database::open_in_memory().unwrap();
+        assert!(!db.get_analyze_opponent("alice").unwrap());
+
+        db.set_analyze_opponent("alice", true).unwrap();
+        assert!(db.get_analyze_opponent("alice").unwrap());
+        db.set_board_perspective("alice", Color::Black).unwrap();
+        assert!(db.get_analyze_opponent("alice").unwrap());
+
+        db.set_analyze_opponent("alice", false).unwrap();
+        assert!(!db.get_analyze_opponent("alice").unwrap());
+    }
+
+    /// Splits CSV text into rows of fields, undoing `csv_field`'s quoting
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let (mut rows, mut row, mut field) = (Vec::new(), Vec::new(), String::new());
+        let mut chars = text.chars().peekable();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => { chars.next(); field.push('"'); }
+                ('"', _) => quoted = !quoted,
+                (',', false) => row.push(std::mem::take(&mut field)),
+                ('\n', false) => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (c, _) => field.push(c),
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn test_export_patterns_csv() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let mut tricky = pattern(Severity::Blunder, 400);
+        tricky.description = "3. Bb5, the \"Spanish\"\nbishop, hangs".to_string();
+        let mine = [pattern(Severity::Mistake, 150), tricky.clone()];
+        db.insert_patterns_with_opponent(game_id, &mine, &[pattern(Severity::Blunder, 900)]).unwrap();
+
+        let mut out = Vec::new();
+        db.export_patterns_csv(&mut out).unwrap();
+        let rows = parse_csv(&String::from_utf8(out).unwrap());
+
+        assert_eq!(rows[0].join(","), "game_id,move_number,pattern_type,severity,cp_loss,fen,best_move,player_move,description");
+        // The opponent's blunder isn't exported
+        assert_eq!(rows.len(), 3);
+        let game = game_id.to_string();
+        assert_eq!(rows[2], [
+            game.as_str(), "3", "tactical_miss", "blunder", "400", tricky.fen_before.as_str(),
+            "d2d4", "Bb5", tricky.description.as_str(),
+        ]);
+        assert_eq!(rows[1][3], "mistake");
+        assert_eq!(rows[1][8], "test");
+    }
+
+    #[test]
+    fn test_integrity_of_a_clean_database() {
+        let db = Database::open_in_memory().unwrap();
+        let game_id = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        db.insert_pattern(game_id, &pattern(Severity::Blunder, 400)).unwrap();
+        assert!(db.check_integrity().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_games_missing_moves() {
+        let db = Database::open_in_memory().unwrap();
+        db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let empty = db.insert_game(&lichess_game("g2", "Alice", "Bob", "C60", 200)).unwrap();
+        db.conn.execute("UPDATE games SET moves = ' ' WHERE id = ?1", params![empty]).unwrap();
+
+        assert_eq!(db.games_missing_moves().unwrap(), [empty]);
+        assert_eq!(db.check_integrity().unwrap().games_missing_moves, [empty]);
+    }
+
+    #[test]
+    fn test_orphan_patterns_are_found_and_deleted() {
+        let db = Database::open_in_memory().unwrap();
+        let kept = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let gone = db.insert_game(&lichess_game("g2", "Alice", "Bob", "C60", 200)).unwrap();
+        let kept_pattern = db.insert_pattern(kept, &pattern(Severity::Blunder, 400)).unwrap();
+        let orphan = db.insert_pattern(gone, &pattern(Severity::Mistake, 150)).unwrap();
+        db.conn.execute_batch("PRAGMA foreign_keys = OFF").unwrap();
+        db.conn.execute("DELETE FROM games WHERE id = ?1", params![gone]).unwrap();
+
+        assert_eq!(db.orphan_patterns().unwrap(), [orphan]);
+        assert_eq!(db.delete_orphan_patterns().unwrap(), 1);
+        assert!(db.orphan_patterns().unwrap().is_empty());
+        assert_eq!(db.get_all_patterns().unwrap()[0].id, kept_pattern);
+    }
+
+    #[test]
+    fn test_prune_and_dedupe_patterns() {
+        let db = Database::open_in_memory().unwrap();
+        let game = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        db.insert_pattern(game, &pattern(Severity::Inaccuracy, 60)).unwrap();
+        db.insert_pattern(game, &pattern(Severity::Mistake, 150)).unwrap();
+        let worst = db.insert_pattern(game, &pattern(Severity::Blunder, 400)).unwrap();
+        db.insert_pattern(game, &pattern(Severity::Blunder, 400)).unwrap();
+        let other = DetectedPattern { best_move: "f3e5".to_string(), ..pattern(Severity::Mistake, 120) };
+        let other = db.insert_pattern(game, &other).unwrap();
+
+        assert_eq!(db.prune_low_value_patterns(100).unwrap(), 1);
+        assert_eq!(db.count_patterns().unwrap(), 4);
+
+        // Same position and best move: only the first 400 cp loss stays
+        assert_eq!(db.dedupe_patterns().unwrap(), 2);
+        let mut kept: Vec<i64> = db.get_all_patterns().unwrap().iter().map(|p| p.id).collect();
+        kept.sort();
+        assert_eq!(kept, [worst, other]);
+        assert_eq!(db.dedupe_patterns().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_dedupe_keeps_player_and_opponent_apart() {
+        let db = Database::open_in_memory().unwrap();
+        let game = db.insert_game(&lichess_game("g1", "Alice", "Bob", "C60", 100)).unwrap();
+        let other = db.insert_game(&lichess_game("g2", "Carol", "Alice", "C60", 200)).unwrap();
+        db.insert_patterns(game, &[pattern(Severity::Mistake, 150)]).unwrap();
+        // The opponent's worse miss in the same position mustn't take the
+        // player's puzzle with it
+        db.insert_patterns_with_opponent(other, &[], &[pattern(Severity::Blunder, 500)]).unwrap();
+
+        assert_eq!(db.dedupe_patterns().unwrap(), 0);
+        let mut sides: Vec<String> = db.get_all_patterns().unwrap().into_iter().map(|p| p.side).collect();
+        sides.sort();
+        assert_eq!(sides, [SIDE_OPPONENT, SIDE_PLAYER]);
+    }
+
+    #[test]
+    fn test_integrity_lists_suspect_results() {
+        let db = Database::open_in_memory().unwrap();
+        let pgn = SAMPLE_PGN.to_string() + r#"
+[White "Carol"]
+[Black "Dave"]
+[Result "1-0"]
+
+1. f3 e5 2. g4 Qh4# 1-0
+"#;
+        db.insert_pgn_games(&parse_pgn_string(&pgn).unwrap()).unwrap();
+
+        let report = db.check_integrity().unwrap();
+        assert_eq!(report.suspect_results.len(), 1);
+        assert!(report.games_missing_moves.is_empty());
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn test_unanalyzed_games_between_dates() {
+        let db = Database::open_in_memory().unwrap();
+        let day = 86_400;
+        db.insert_game(&lichess_game("mon", "Alice", "Bob", "C60", 10 * day)).unwrap();
+        let sat = db.insert_game(&lichess_game("sat", "Alice", "Bob", "C60", 15 * day)).unwrap();
+        db.insert_game(&lichess_game("sun", "Alice", "Bob", "C60", 16 * day + 3600)).unwrap();
+        db.insert_game(&lichess_game("next-mon", "Alice", "Bob", "C60", 17 * day)).unwrap();
+
+        let weekend = db.get_unanalyzed_games_between(15 * day, 17 * day, 10).unwrap();
+        assert_eq!(weekend.iter().map(|g| g.lichess_id.as_str()).collect::<Vec<_>>(), ["sun", "sat"]);
+
+        db.mark_game_analyzed(sat).unwrap();
+        let weekend = db.get_unanalyzed_games_between(15 * day, 17 * day, 10).unwrap();
+        assert_eq!(weekend.len(), 1);
+        assert_eq!(db.get_unanalyzed_games_between(0, 17 * day, 1).unwrap()[0].lichess_id, "sun");
+    }
+
+    #[test]
+    fn test_draw_claimed_for_checkmate_is_flagged() {
+        let db = Database::open_in_memory().unwrap();
+        let pgn = SAMPLE_PGN.to_string() + r#"
+[White "Carol"]
+[Black "Dave"]
+[Result "1/2-1/2"]
+
+1. f3 e5 2. g4 Qh4# 1/2-1/2
+"#;
+        db.insert_pgn_games(&parse_pgn_string(&pgn).unwrap()).unwrap();
+
+        let suspect = db.get_suspect_result_games().unwrap();
+        assert_eq!(suspect.len(), 1);
+        assert_eq!(suspect[0].white_username, "Carol");
+        assert!(suspect[0].result_suspect);
+        // A resignation can't be inferred, so it is never suspect
+        assert!(db.get_all_games().unwrap().iter().any(|g| g.white_username == "Alice" && !g.result_suspect));
+    }
+}
@@ -2,6 +2,9 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::lichess::MoveEval;
+use crate::patterns::GameReport;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoredGame {
     pub id: i64,
@@ -16,10 +19,28 @@ pub struct StoredGame {
     pub opening_eco: Option<String>,
     pub opening_name: Option<String>,
     pub moves: String,
+    /// The same moves in UCI, space-joined; `None` for rows stored before
+    /// the column existed (see `Database::moves_uci`)
+    pub moves_uci: Option<String>,
     pub pgn: Option<String>,
     pub analyzed: bool,
     pub played_at: u64,
     pub created_at: u64,
+    /// Lichess server analysis, if the game was analysed there
+    pub lichess_analysis: Option<Vec<MoveEval>>,
+    /// Average centipawn loss of the analyzed player, once analyzed
+    pub acpl: Option<f64>,
+    /// Share of the player's tablebase-range moves that kept the
+    /// theoretical result, when analysis probed the tablebase
+    pub endgame_conversion: Option<f32>,
+    /// The stored result contradicts the final position (e.g. a draw
+    /// recorded for a checkmate); flagged at import for review
+    pub result_suspect: bool,
+    /// `TimeControl` tag form, e.g. `"180+2"`; `None` when unknown
+    pub time_control: Option<String>,
+    /// Time left after each ply in centiseconds, when the game was stored
+    /// with clock data
+    pub clocks: Option<Vec<u32>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +55,60 @@ pub struct StoredPattern {
     pub position_fen: String,
     pub description: String,
     pub created_at: u64,
+    /// `DetectedPattern::quality` at insert time; the severity for older rows
+    pub quality: String,
+    /// Whose move it was: `SIDE_PLAYER` for the analyzed user, or
+    /// `SIDE_OPPONENT` when the opponent's mistakes were analyzed too
+    pub side: String,
+    /// SAN of the move played and UCI of the engine's choice; `None` for
+    /// rows stored before they were kept
+    pub player_move: Option<String>,
+    pub best_move: Option<String>,
+    /// See `DetectedPattern::quiet`
+    pub quiet: bool,
+}
+
+impl StoredPattern {
+    /// `"15."` or `"15..."`, as `parser::move_label` numbers the move;
+    /// which side played it is read from `position_fen`
+    pub fn move_label(&self) -> String {
+        let dots = match self.position_fen.split_whitespace().nth(1) {
+            Some("b") => "...",
+            _ => ".",
+        };
+        format!("{}{}", self.move_number, dots)
+    }
+
+    /// Ply of the move, counting from 0 for White's first
+    pub fn ply(&self) -> usize {
+        let black = self.position_fen.split_whitespace().nth(1) == Some("b");
+        (self.move_number.max(1) as usize - 1) * 2 + black as usize
+    }
+}
+
+pub const SIDE_PLAYER: &str = "player";
+pub const SIDE_OPPONENT: &str = "opponent";
+
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_DONE: &str = "done";
+pub const JOB_FAILED: &str = "failed";
+
+/// One game analysis a client asked for and polls, from `analysis_jobs`.
+/// Timestamps are Unix seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisJobStatus {
+    pub id: i64,
+    pub game_id: i64,
+    /// `JOB_PENDING`, `JOB_RUNNING`, `JOB_DONE` or `JOB_FAILED`
+    pub status: String,
+    /// The user's report, once done
+    pub report: Option<GameReport>,
+    /// Why the job failed
+    pub error: Option<String>,
+    pub created_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +120,154 @@ pub struct UserSettings {
     pub created_at: u64,
 }
 
+/// Headline numbers for the dashboard
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DashboardSummary {
+    pub total_games: u32,
+    pub total_patterns: u32,
+    pub blunders: u32,
+    pub mistakes: u32,
+    pub inaccuracies: u32,
+    /// `PatternType::as_str` of the most frequent pattern, if any
+    pub most_common_pattern: Option<String>,
+    /// Average of the per-game ACPL over analyzed games
+    pub avg_acpl: Option<f64>,
+}
+
+/// One time bucket of `Database::acpl_trend`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcplBucket {
+    /// Unix seconds at the start of the bucket
+    pub period_start: u64,
+    pub avg_acpl: f64,
+    pub games: u32,
+}
+
+/// One time bucket of `Database::training_progress`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainingProgress {
+    /// Day number (days since the Unix epoch) at the start of the bucket
+    pub period_start: i64,
+    /// Percentage of the bucket's attempts answered correctly
+    pub accuracy: f64,
+    /// Average time per attempt in milliseconds
+    pub avg_time_ms: f64,
+    pub sessions: u32,
+}
+
+/// How often puzzles of one pattern type were solved, from
+/// `Database::puzzle_solve_rates`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PuzzleSolveRate {
+    /// `PatternType::as_str` of the puzzles' pattern
+    pub pattern_type: String,
+    pub attempts: u32,
+    pub solved: u32,
+    /// Percentage of attempts solved
+    pub rate: f64,
+}
+
+/// The user's record against one opponent, from `Database::head_to_head`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpponentRecord {
+    pub opponent: String,
+    /// All games against them, including unfinished ones (result `*`)
+    pub games: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+/// What the user's games against one opponent say about them, from
+/// `Database::scout_report`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoutReport {
+    pub opponent: String,
+    pub games: u32,
+    /// Results from the opponent's side; unfinished games count in none
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    /// Openings reached with the opponent as White, most played first
+    pub openings_as_white: Vec<OpeningCount>,
+    pub openings_as_black: Vec<OpeningCount>,
+    /// The opponent's own mistakes per pattern type, most common first.
+    /// Empty unless some of the games were analyzed with the opponent's
+    /// mistakes included.
+    pub mistakes: Vec<(String, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpeningCount {
+    pub eco: String,
+    pub name: Option<String>,
+    pub games: u32,
+}
+
+/// The user's score against what their ratings predicted, from
+/// `Database::performance_vs_expectation`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatingPerformance {
+    pub games: u32,
+    /// Points scored, a draw being half
+    pub score: f64,
+    /// Points the Elo model expected from the ratings at the time
+    pub expected: f64,
+}
+
+impl RatingPerformance {
+    /// Points per game above expectation, e.g. 0.05 for scoring 5% more
+    /// than the ratings predicted; negative for an underperformance
+    pub fn over_performance(&self) -> f64 {
+        (self.score - self.expected) / self.games as f64
+    }
+}
+
+/// How the user's ACPL in one opening moved between two periods, from
+/// `Database::most_improved_opening`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpeningChange {
+    pub eco: String,
+    pub name: Option<String>,
+    pub earlier_acpl: f64,
+    pub earlier_games: u32,
+    pub later_acpl: f64,
+    pub later_games: u32,
+}
+
+impl OpeningChange {
+    /// Centipawns per move no longer lost; negative for a regression
+    pub fn improvement(&self) -> f64 {
+        self.earlier_acpl - self.later_acpl
+    }
+}
+
+/// Result of `Database::import_from`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportSummary {
+    pub games_merged: u32,
+    /// Games that were already present locally
+    pub games_skipped: u32,
+    pub patterns_merged: u32,
+}
+
+/// Result of `Database::check_integrity`; each list holds row ids
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Games that can't be analyzed because no moves were stored
+    pub games_missing_moves: Vec<i64>,
+    /// Patterns whose game no longer exists
+    pub orphan_patterns: Vec<i64>,
+    /// Games whose result contradicts the final position
+    pub suspect_results: Vec<i64>,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.games_missing_moves.is_empty() && self.orphan_patterns.is_empty() && self.suspect_results.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrainingStats {
     pub today_attempts: u32,
@@ -66,6 +289,30 @@ impl TrainingStats {
     }
 }
 
+/// One row of `training_sessions`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainingSessionRow {
+    pub id: i64,
+    pub training_type: String,
+    pub attempts: u32,
+    pub correct: u32,
+    pub total_time_ms: u64,
+    pub best_time_ms: Option<u64>,
+    /// Days since the Unix epoch, as written by `save_training_session`
+    pub date: String,
+    pub created_at: u64,
+}
+
+impl TrainingSessionRow {
+    pub fn accuracy(&self) -> u32 {
+        if self.attempts == 0 {
+            0
+        } else {
+            ((self.correct as f64 / self.attempts as f64) * 100.0) as u32
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AllTrainingStats {
     pub coordinates: TrainingStats,
@@ -76,3 +323,17 @@ pub struct AllTrainingStats {
     pub overall_accuracy: u32,
     pub max_streak: u32,
 }
+
+/// Where a user's windowed Lichess sync stands (see `LichessClient::sync_all`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncCursor {
+    /// When this sync began, in Unix seconds; becomes the last sync time
+    /// once it finishes
+    pub started_at: u64,
+    /// Oldest point reached so far, in Unix milliseconds; the next window
+    /// fetches games before it. `None` until the first window is stored.
+    pub until: Option<u64>,
+    /// Start of the previous finished sync, in Unix milliseconds; older
+    /// games are already stored
+    pub since: Option<u64>,
+}
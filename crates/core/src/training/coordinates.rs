@@ -3,10 +3,19 @@
 use shakmaty::{Square, Color};
 use rand::seq::IndexedRandom;
 
+use super::grading::DrillGrading;
+
+/// Answers a targeted square needs before it can graduate, so one lucky
+/// click doesn't count as mastery
+pub const TARGET_MIN_ATTEMPTS: u32 = 3;
+
 pub struct CoordinateTrainer {
     mode: CoordinateMode,
     perspective: Color,
     history: Vec<CoordinateAttempt>,
+    grading: DrillGrading,
+    /// Squares of the targeted session, if one is running
+    targets: Vec<SquareMastery>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -16,6 +25,28 @@ pub enum CoordinateMode {
     SquareColor,
 }
 
+/// How one square of a targeted session is going
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SquareMastery {
+    pub square: Square,
+    pub attempts: u32,
+    pub correct: u32,
+}
+
+impl SquareMastery {
+    /// Accuracy this session, 0.0 to 1.0
+    pub fn accuracy(&self) -> f32 {
+        if self.attempts == 0 {
+            return 0.0;
+        }
+        self.correct as f32 / self.attempts as f32
+    }
+
+    pub fn is_mastered(&self, grading: &DrillGrading) -> bool {
+        self.attempts >= TARGET_MIN_ATTEMPTS && grading.is_accurate_enough(self.accuracy())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CoordinateAttempt {
     pub square: Square,
@@ -29,6 +60,8 @@ impl CoordinateTrainer {
             mode,
             perspective,
             history: Vec::new(),
+            grading: DrillGrading::default(),
+            targets: Vec::new(),
         }
     }
 
@@ -48,8 +81,41 @@ impl CoordinateTrainer {
         self.perspective = perspective;
     }
 
+    /// Thresholds a targeted square has to reach to graduate
+    pub fn set_grading(&mut self, grading: DrillGrading) {
+        self.grading = grading;
+    }
+
+    /// Starts deliberate practice on `squares`, usually the weak squares
+    /// from earlier sessions: `next_square` only draws from them, and each
+    /// graduates out once answered accurately enough. When all have
+    /// graduated the drill goes back to the whole board.
+    pub fn targeted_session(&mut self, squares: &[Square]) {
+        self.targets.clear();
+        for &square in squares {
+            if !self.targets.iter().any(|t| t.square == square) {
+                self.targets.push(SquareMastery { square, attempts: 0, correct: 0 });
+            }
+        }
+    }
+
+    /// Per-square progress of the targeted session, in the order given
+    pub fn mastery(&self) -> &[SquareMastery] {
+        &self.targets
+    }
+
+    /// Targeted squares that haven't graduated yet
+    pub fn remaining_targets(&self) -> Vec<Square> {
+        self.targets
+            .iter()
+            .filter(|t| !t.is_mastered(&self.grading))
+            .map(|t| t.square)
+            .collect()
+    }
+
     pub fn next_square(&self) -> Square {
-        let squares: Vec<Square> = Square::ALL.to_vec();
+        let remaining = self.remaining_targets();
+        let squares: Vec<Square> = if remaining.is_empty() { Square::ALL.to_vec() } else { remaining };
         let mut rng = rand::rng();
         *squares.choose(&mut rng).unwrap()
     }
@@ -81,6 +147,10 @@ impl CoordinateTrainer {
             correct,
             response_ms,
         });
+        if let Some(target) = self.targets.iter_mut().find(|t| t.square == square) {
+            target.attempts += 1;
+            target.correct += correct as u32;
+        }
     }
 
     pub fn attempts(&self) -> usize {
@@ -116,9 +186,12 @@ impl CoordinateTrainer {
 
     pub fn reset(&mut self) {
         self.history.clear();
+        self.targets.clear();
     }
 
-    pub fn weak_squares(&self) -> Vec<(Square, f32, u64)> {
+    /// Squares below `grading`'s accuracy or slower than it allows, as
+    /// `(square, accuracy %, average ms)`, least accurate first
+    pub fn weak_squares(&self, grading: &DrillGrading) -> Vec<(Square, f32, u64)> {
         use std::collections::HashMap;
 
         let mut square_stats: HashMap<Square, (u32, u32, u64)> = HashMap::new();
@@ -139,7 +212,7 @@ impl CoordinateTrainer {
             .filter_map(|(sq, (total, correct, time))| {
                 let accuracy = *correct as f32 / *total as f32;
                 let avg_square_time = time / *total as u64;
-                if accuracy < 0.8 || avg_square_time > avg_time * 2 {
+                if !grading.is_accurate_enough(accuracy) || grading.is_slow(avg_square_time, avg_time) {
                     Some((*sq, accuracy * 100.0, avg_square_time))
                 } else {
                     None
@@ -151,3 +224,94 @@ impl CoordinateTrainer {
         weak
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trainer(attempts: &[(Square, bool, u64)]) -> CoordinateTrainer {
+        let mut trainer = CoordinateTrainer::new(CoordinateMode::NameToSquare, Color::White);
+        for &(square, correct, ms) in attempts {
+            trainer.record(square, correct, ms);
+        }
+        trainer
+    }
+
+    fn answers(square: Square, correct: usize, wrong: usize) -> Vec<(Square, bool, u64)> {
+        let mut answers = vec![(square, true, 1000); correct];
+        answers.extend(vec![(square, false, 1000); wrong]);
+        answers
+    }
+
+    #[test]
+    fn test_accuracy_threshold_for_weak_squares() {
+        // e4 sits exactly on the 80% default, d4 just under it
+        let mut attempts = answers(Square::E4, 4, 1);
+        attempts.extend(answers(Square::D4, 3, 1));
+        let trainer = trainer(&attempts);
+
+        let weak = trainer.weak_squares(&DrillGrading::default());
+        assert_eq!(weak.iter().map(|w| w.0).collect::<Vec<_>>(), vec![Square::D4]);
+        assert_eq!(weak[0].1, 75.0);
+
+        let strict = DrillGrading { min_accuracy: 0.81, ..DrillGrading::default() };
+        assert_eq!(trainer.weak_squares(&strict).len(), 2);
+
+        let lenient = DrillGrading { min_accuracy: 0.75, ..DrillGrading::default() };
+        assert!(trainer.weak_squares(&lenient).is_empty());
+    }
+
+    #[test]
+    fn test_slow_squares_are_weak() {
+        // Session average is 1500ms; a5 averages 3000ms
+        let trainer = trainer(&[
+            (Square::E4, true, 1000),
+            (Square::E4, true, 1000),
+            (Square::E4, true, 1000),
+            (Square::A5, true, 3000),
+        ]);
+
+        assert!(trainer.weak_squares(&DrillGrading::default()).is_empty());
+        let impatient = DrillGrading { slow_multiplier: 1.9, ..DrillGrading::default() };
+        assert_eq!(trainer.weak_squares(&impatient)[0].0, Square::A5);
+    }
+
+    #[test]
+    fn test_mastered_squares_stop_appearing() {
+        let mut trainer = trainer(&[]);
+        trainer.targeted_session(&[Square::E4, Square::D4, Square::E4]);
+        assert_eq!(trainer.mastery().len(), 2);
+
+        for _ in 0..3 {
+            trainer.record(Square::E4, true, 1000);
+        }
+        trainer.record(Square::D4, true, 1000);
+        trainer.record(Square::D4, false, 1000);
+        trainer.record(Square::D4, true, 1000);
+        // Off-target answers don't count towards anything
+        trainer.record(Square::A1, true, 1000);
+
+        assert_eq!(trainer.remaining_targets(), vec![Square::D4]);
+        for _ in 0..50 {
+            assert_eq!(trainer.next_square(), Square::D4);
+        }
+
+        // Two more right makes 4 of 5, exactly the 80% default
+        trainer.record(Square::D4, true, 1000);
+        trainer.record(Square::D4, true, 1000);
+        assert!(trainer.remaining_targets().is_empty());
+        assert_eq!(trainer.mastery()[1], SquareMastery { square: Square::D4, attempts: 5, correct: 4 });
+
+        // A stricter grading sends d4 back into the draw
+        trainer.set_grading(DrillGrading { min_accuracy: 0.9, ..DrillGrading::default() });
+        assert_eq!(trainer.remaining_targets(), vec![Square::D4]);
+    }
+
+    #[test]
+    fn test_eval_guess_tolerance() {
+        let grading = DrillGrading::default();
+        assert!(grading.eval_guess_correct(120, 80));
+        assert!(grading.eval_guess_correct(-30, 20));
+        assert!(!grading.eval_guess_correct(150, 80));
+    }
+}
@@ -0,0 +1,37 @@
+//! Thresholds for grading drill answers
+
+/// How strictly drills are graded. The defaults are the long-standing
+/// built-in thresholds; coaches can tighten or relax them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrillGrading {
+    /// A square answered correctly less often than this (0.0 to 1.0) is weak
+    pub min_accuracy: f32,
+    /// A square answered this many times slower than the session average is weak
+    pub slow_multiplier: f32,
+    /// An eval guess within this many centipawns of the engine counts as right
+    pub eval_tolerance_cp: i32,
+}
+
+impl Default for DrillGrading {
+    fn default() -> Self {
+        Self {
+            min_accuracy: 0.8,
+            slow_multiplier: 2.0,
+            eval_tolerance_cp: 50,
+        }
+    }
+}
+
+impl DrillGrading {
+    pub fn is_accurate_enough(&self, accuracy: f32) -> bool {
+        accuracy >= self.min_accuracy
+    }
+
+    pub fn is_slow(&self, response_ms: u64, average_ms: u64) -> bool {
+        response_ms as f32 > average_ms as f32 * self.slow_multiplier
+    }
+
+    pub fn eval_guess_correct(&self, guess_cp: i32, actual_cp: i32) -> bool {
+        (guess_cp - actual_cp).abs() <= self.eval_tolerance_cp
+    }
+}
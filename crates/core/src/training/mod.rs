@@ -2,9 +2,11 @@
 //! Training modules for chess improvement
 
 pub mod coordinates;
+pub mod grading;
 pub mod openings;
 pub mod visualization;
 
 pub use coordinates::CoordinateTrainer;
-pub use openings::{OpeningTrainer, OpeningLine, DrillResult};
+pub use grading::DrillGrading;
+pub use openings::{OpeningTrainer, OpeningLine, CoverageReport, Deviation, DrillMode, DrillResult};
 pub use visualization::VisualizationDrill;
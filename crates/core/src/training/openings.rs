@@ -1,13 +1,19 @@
 //! Opening repertoire trainer
 
-use shakmaty::{Chess, san::San, Color, Position, EnPassantMode, fen::Fen};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use shakmaty::{Chess, Color, Position, EnPassantMode, fen::Fen};
+use std::collections::{HashMap, VecDeque};
 
-#[derive(Debug, Clone)]
+use crate::error::Result;
+use crate::parser::{parse_user_move, PgnGame};
+use crate::util::{color_name, player_color};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpeningLine {
     pub eco: String,
     pub name: String,
     pub moves: Vec<String>,
+    #[serde(with = "crate::util::color_serde")]
     pub for_color: Color,
     pub times_drilled: u32,
     pub times_correct: u32,
@@ -17,7 +23,7 @@ pub struct OpeningLine {
 impl OpeningLine {
     /// Returns the color as a string ("White" or "Black")
     pub fn color_name(&self) -> &'static str {
-        if self.for_color == Color::White { "White" } else { "Black" }
+        color_name(self.for_color)
     }
 
     pub fn accuracy(&self) -> f32 {
@@ -28,20 +34,31 @@ impl OpeningLine {
     }
 
     pub fn needs_review(&self, now: u64) -> bool {
-        match self.last_drilled {
-            None => true,
-            Some(last) => {
-                let days_since = (now - last) / 86400;
-                let interval = if self.accuracy() > 90.0 {
-                    7
-                } else if self.accuracy() > 70.0 {
-                    3
-                } else {
-                    1
-                };
-                days_since >= interval
-            }
+        self.due_at().is_none_or(|due| due <= now)
+    }
+
+    /// When the line is next due for review, in Unix seconds; `None` if it
+    /// has never been drilled, which makes it due straight away. The
+    /// better it's known, the longer the gap: a week above 90% accuracy,
+    /// three days above 70%, otherwise a day.
+    pub fn due_at(&self) -> Option<u64> {
+        let interval_days = if self.accuracy() > 90.0 {
+            7
+        } else if self.accuracy() > 70.0 {
+            3
+        } else {
+            1
+        };
+        self.last_drilled.map(|last| last + interval_days * 86400)
+    }
+
+    /// Counts one drill of the line at `now`
+    pub fn record_drill(&mut self, correct: bool, now: u64) {
+        self.times_drilled += 1;
+        if correct {
+            self.times_correct += 1;
         }
+        self.last_drilled = Some(now);
     }
 }
 
@@ -54,11 +71,27 @@ pub struct DrillResult {
     pub correct: bool,
 }
 
+/// What the trainer does after a missed move
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DrillMode {
+    /// Stay on the missed move until it is played, then carry on
+    #[default]
+    Line,
+    /// As `Line`, but once the line is finished jump back to each position
+    /// where a move was missed and replay the line from there
+    Mistakes,
+}
+
 pub struct OpeningTrainer {
     repertoire: Vec<OpeningLine>,
     current_position: Chess,
     current_line_idx: Option<usize>,
     current_move_idx: usize,
+    mode: DrillMode,
+    /// Misses per (line index, move index)
+    failures: HashMap<(usize, usize), u32>,
+    /// Move indices of the current line still to be replayed in `Mistakes` mode
+    retry_queue: VecDeque<usize>,
 }
 
 impl OpeningTrainer {
@@ -68,13 +101,52 @@ impl OpeningTrainer {
             current_position: Chess::default(),
             current_line_idx: None,
             current_move_idx: 0,
+            mode: DrillMode::default(),
+            failures: HashMap::new(),
+            retry_queue: VecDeque::new(),
         }
     }
 
+    pub fn mode(&self) -> DrillMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: DrillMode) {
+        self.mode = mode;
+    }
+
+    /// Moves missed at least once as `(line_idx, move_idx, fail_count)`,
+    /// most missed first
+    pub fn failure_hotspots(&self) -> Vec<(usize, usize, u32)> {
+        let mut hotspots: Vec<(usize, usize, u32)> = self.failures
+            .iter()
+            .map(|(&(line, mv), &count)| (line, mv, count))
+            .collect();
+        hotspots.sort_by(|a, b| b.2.cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+        hotspots
+    }
+
     pub fn add_line(&mut self, line: OpeningLine) {
         self.repertoire.push(line);
     }
 
+    pub fn lines(&self) -> &[OpeningLine] {
+        &self.repertoire
+    }
+
+    /// The repertoire, drill stats included, as a JSON array of lines
+    pub fn export_json(&self) -> String {
+        serde_json::to_string_pretty(&self.repertoire)
+            .expect("opening lines always serialize")
+    }
+
+    /// Builds a trainer from `export_json` output
+    pub fn import_json(s: &str) -> Result<OpeningTrainer> {
+        let mut trainer = OpeningTrainer::new();
+        trainer.repertoire = serde_json::from_str(s)?;
+        Ok(trainer)
+    }
+
     pub fn extract_from_games(
         games: &[crate::storage::StoredGame],
         username: &str,
@@ -90,11 +162,7 @@ impl OpeningTrainer {
                 continue;
             }
 
-            let color = if game.white_username.eq_ignore_ascii_case(username) {
-                Color::White
-            } else {
-                Color::Black
-            };
+            let color = player_color(username, &game.white_username);
 
             let moves: Vec<String> = game.moves
                 .split_whitespace()
@@ -139,6 +207,7 @@ impl OpeningTrainer {
         self.current_position = Chess::default();
         self.current_line_idx = Some(line_idx);
         self.current_move_idx = 0;
+        self.retry_queue.clear();
 
         let line = &self.repertoire[line_idx];
 
@@ -173,11 +242,17 @@ impl OpeningTrainer {
         }
 
         let expected = &line.moves[self.current_move_idx];
-        let correct = player_move.trim() == expected.trim();
+        let expected_move = parse_user_move(&self.current_position, expected);
+
+        // Compare the moves themselves so "e8N", "e8=N" and "e8=N+" all match
+        let correct = match (&expected_move, parse_user_move(&self.current_position, player_move)) {
+            (Some(e), Some(p)) => *e == p,
+            _ => player_move.trim() == expected.trim(),
+        };
 
         let result = DrillResult {
             line_name: line.name.clone(),
-            move_number: (self.current_move_idx / 2 + 1) as u16,
+            move_number: PgnGame::ply_to_move_number(self.current_move_idx),
             expected: expected.clone(),
             played: player_move.to_string(),
             correct,
@@ -187,15 +262,27 @@ impl OpeningTrainer {
         if correct {
             line.times_correct += 1;
         }
+        let line_len = line.moves.len();
+
+        if !correct {
+            *self.failures.entry((line_idx, self.current_move_idx)).or_insert(0) += 1;
+            if self.mode == DrillMode::Mistakes && !self.retry_queue.contains(&self.current_move_idx) {
+                self.retry_queue.push_back(self.current_move_idx);
+            }
+        }
 
         if correct {
-            if let Ok(san) = expected.parse::<San>() {
-                if let Ok(mv) = san.to_move(&self.current_position) {
-                    if let Ok(new_pos) = self.current_position.clone().play(mv) {
-                        self.current_position = new_pos;
-                        self.current_move_idx += 1;
-                        self.play_opponent_move();
-                    }
+            if let Some(mv) = expected_move {
+                if let Ok(new_pos) = self.current_position.clone().play(mv) {
+                    self.current_position = new_pos;
+                    self.current_move_idx += 1;
+                    self.play_opponent_move();
+                }
+            }
+
+            if self.current_move_idx >= line_len {
+                if let Some(move_idx) = self.retry_queue.pop_front() {
+                    self.rewind_to(move_idx);
                 }
             }
         }
@@ -203,6 +290,24 @@ impl OpeningTrainer {
         Some(result)
     }
 
+    /// Replays the current line from the start up to (not including) `move_idx`
+    fn rewind_to(&mut self, move_idx: usize) {
+        let line_idx = match self.current_line_idx {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let mut position = Chess::default();
+        for move_str in &self.repertoire[line_idx].moves[..move_idx] {
+            match parse_user_move(&position, move_str).and_then(|mv| position.clone().play(mv).ok()) {
+                Some(p) => position = p,
+                None => return,
+            }
+        }
+        self.current_position = position;
+        self.current_move_idx = move_idx;
+    }
+
     fn play_opponent_move(&mut self) {
         let line_idx = match self.current_line_idx {
             Some(idx) => idx,
@@ -210,27 +315,89 @@ impl OpeningTrainer {
         };
 
         let line = &self.repertoire[line_idx];
-        let is_our_turn = (self.current_move_idx % 2 == 0) == (line.for_color == Color::White);
+        let is_our_turn = self.current_move_idx.is_multiple_of(2) == (line.for_color == Color::White);
 
         if !is_our_turn && self.current_move_idx < line.moves.len() {
             let move_str = &line.moves[self.current_move_idx];
-            if let Ok(san) = move_str.parse::<San>() {
-                if let Ok(mv) = san.to_move(&self.current_position) {
-                    if let Ok(new_pos) = self.current_position.clone().play(mv) {
-                        self.current_position = new_pos;
-                        self.current_move_idx += 1;
-                    }
+            if let Some(mv) = parse_user_move(&self.current_position, move_str) {
+                if let Ok(new_pos) = self.current_position.clone().play(mv) {
+                    self.current_position = new_pos;
+                    self.current_move_idx += 1;
                 }
             }
         }
     }
 
+    /// Lines due for review at `now`, most overdue first; lines never
+    /// drilled come before all the others
     pub fn lines_to_review(&self, now: u64) -> Vec<(usize, &OpeningLine)> {
-        self.repertoire
+        let mut due: Vec<(usize, &OpeningLine)> = self.repertoire
             .iter()
             .enumerate()
             .filter(|(_, line)| line.needs_review(now))
-            .collect()
+            .collect();
+        due.sort_by_key(|(_, line)| line.due_at());
+        due
+    }
+
+    /// How closely `username`'s games followed the repertoire: where each
+    /// one left it, and whether the player or the opponent stepped off.
+    ///
+    /// A game is checked against the lines for the colour the player had
+    /// in it, all at once, so a repertoire with several replies to 1.e4
+    /// counts any of them. A game that plays on past the end of every line
+    /// it matched, or ends inside one, followed the repertoire. Move marks
+    /// (`+`, `#`, `!`, `?`) don't count as differences.
+    pub fn coverage(&self, games: &[crate::storage::StoredGame], username: &str) -> CoverageReport {
+        let mut report = CoverageReport::default();
+
+        for game in games {
+            let color = player_color(username, &game.white_username);
+            let mut candidates: Vec<&OpeningLine> = self.repertoire.iter().filter(|l| l.for_color == color).collect();
+            if candidates.is_empty() {
+                report.uncovered += 1;
+                continue;
+            }
+
+            let moves: Vec<&str> = game.moves.split_whitespace().collect();
+            let mut deviation = None;
+            for (ply, played) in moves.iter().enumerate() {
+                // The game followed one of the lines to its end
+                if candidates.iter().any(|l| l.moves.len() == ply) {
+                    break;
+                }
+                let still_in_book: Vec<&OpeningLine> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|l| same_move(&l.moves[ply], played))
+                    .collect();
+                if still_in_book.is_empty() {
+                    let mut expected: Vec<String> = Vec::new();
+                    for line in &candidates {
+                        if !expected.contains(&line.moves[ply]) {
+                            expected.push(line.moves[ply].clone());
+                        }
+                    }
+                    deviation = Some(Deviation {
+                        game_id: game.id,
+                        ply,
+                        move_number: PgnGame::ply_to_move_number(ply),
+                        by_player: (ply % 2 == 0) == (color == Color::White),
+                        played: played.to_string(),
+                        expected,
+                        line_name: candidates[0].name.clone(),
+                    });
+                    break;
+                }
+                candidates = still_in_book;
+            }
+
+            match deviation {
+                Some(d) => report.deviations.push(d),
+                None => report.followed += 1,
+            }
+        }
+        report
     }
 
     pub fn summary(&self) -> RepertoireSummary {
@@ -249,6 +416,53 @@ impl OpeningTrainer {
     }
 }
 
+impl Default for OpeningTrainer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether two SAN moves are the same, check and annotation marks aside
+fn same_move(a: &str, b: &str) -> bool {
+    let bare = |s: &str| s.trim_end_matches(['+', '#', '!', '?']).to_string();
+    bare(a) == bare(b)
+}
+
+/// Where one game left the repertoire
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deviation {
+    pub game_id: i64,
+    /// Ply of the first move not in the repertoire, counting from 0
+    pub ply: usize,
+    pub move_number: u16,
+    /// The player's own move left the book; otherwise the opponent's did
+    pub by_player: bool,
+    pub played: String,
+    /// The repertoire's moves at that point; more than one when lines branch
+    pub expected: Vec<String>,
+    /// One of the lines the game was following
+    pub line_name: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    /// Games that stayed in the repertoire for as long as it went
+    pub followed: u32,
+    /// Games that left it, in the order they were given
+    pub deviations: Vec<Deviation>,
+    /// Games played with a colour the repertoire has no lines for
+    pub uncovered: u32,
+}
+
+impl CoverageReport {
+    /// Deviations by the player, the ones worth practising, earliest first
+    pub fn player_deviations(&self) -> Vec<&Deviation> {
+        let mut mine: Vec<&Deviation> = self.deviations.iter().filter(|d| d.by_player).collect();
+        mine.sort_by_key(|d| d.ply);
+        mine
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RepertoireSummary {
     pub total_lines: u32,
@@ -257,3 +471,252 @@ pub struct RepertoireSummary {
     pub struggling: u32,
     pub not_started: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shakmaty::{Piece, Role, Square};
+
+    fn line(moves: &str, for_color: Color) -> OpeningLine {
+        OpeningLine {
+            eco: "D08".to_string(),
+            name: "Albin Countergambit: Lasker Trap".to_string(),
+            moves: moves.split_whitespace().map(String::from).collect(),
+            for_color,
+            times_drilled: 0,
+            times_correct: 0,
+            last_drilled: None,
+        }
+    }
+
+    const LASKER_TRAP: &str = "d4 d5 c4 e5 dxe5 d4 e3 Bb4+ Bd2 dxe3 Bxb4 exf2+ Ke2 fxg1=N+";
+
+    #[test]
+    fn test_underpromotion_is_accepted_and_played() {
+        let mut trainer = OpeningTrainer::new();
+        trainer.add_line(line(LASKER_TRAP, Color::Black));
+        trainer.start_line(0);
+
+        for mv in ["d5", "e5", "d4", "Bb4+", "dxe3", "exf2+"] {
+            assert!(trainer.check_move(mv).unwrap().correct, "{} rejected", mv);
+        }
+        assert_eq!(trainer.current_move_idx, 13);
+
+        // Queening is legal here but isn't the line
+        let wrong = trainer.check_move("fxg1=Q+").unwrap();
+        assert!(!wrong.correct);
+        assert_eq!(trainer.current_move_idx, 13);
+
+        // Written without "=", still the same move
+        let result = trainer.check_move("fxg1N+").unwrap();
+        assert!(result.correct);
+        assert_eq!(result.expected, "fxg1=N+");
+        assert_eq!(trainer.current_move_idx, 14);
+        assert!(trainer.get_prompt().is_none());
+
+        let board = trainer.current_position.board();
+        assert_eq!(board.piece_at(Square::G1), Some(Piece { color: Color::Black, role: Role::Knight }));
+        assert!(trainer.current_position.is_check());
+    }
+
+    #[test]
+    fn test_opponent_promotion_is_replayed() {
+        // Drilled from White's side, the underpromotion is the opponent's reply
+        let mut trainer = OpeningTrainer::new();
+        trainer.add_line(line(LASKER_TRAP, Color::White));
+        trainer.start_line(0);
+
+        for mv in ["d4", "c4", "dxe5", "e3", "Bd2", "Bxb4", "Ke2"] {
+            assert!(trainer.check_move(mv).unwrap().correct, "{} rejected", mv);
+        }
+        assert_eq!(trainer.current_move_idx, 14);
+        assert_eq!(
+            trainer.current_position.board().role_at(Square::G1),
+            Some(Role::Knight)
+        );
+    }
+
+    #[test]
+    fn test_mistake_drill_replays_from_failure_point() {
+        let mut trainer = OpeningTrainer::new();
+        trainer.set_mode(DrillMode::Mistakes);
+        trainer.add_line(line(LASKER_TRAP, Color::White));
+        trainer.start_line(0);
+
+        assert!(trainer.check_move("d4").unwrap().correct);
+        let after_d5 = trainer.current_fen();
+        assert!(!trainer.check_move("Nf3").unwrap().correct);
+        assert!(!trainer.check_move("e4").unwrap().correct);
+        // Still asked for the same move from the same position
+        assert_eq!(trainer.current_fen(), after_d5);
+
+        for mv in ["c4", "dxe5", "e3", "Bd2", "Bxb4", "Ke2"] {
+            assert!(trainer.check_move(mv).unwrap().correct, "{} rejected", mv);
+        }
+
+        // Finishing the line goes back to the miss instead of ending
+        assert!(trainer.get_prompt().is_some());
+        assert_eq!(trainer.current_move_idx, 2);
+        assert_eq!(trainer.current_fen(), after_d5);
+
+        for mv in ["c4", "dxe5", "e3", "Bd2", "Bxb4", "Ke2"] {
+            assert!(trainer.check_move(mv).unwrap().correct, "{} rejected", mv);
+        }
+        assert!(trainer.get_prompt().is_none());
+        assert_eq!(trainer.failure_hotspots(), vec![(0, 2, 2)]);
+    }
+
+    #[test]
+    fn test_line_mode_records_hotspots_without_replaying() {
+        let mut trainer = OpeningTrainer::new();
+        trainer.add_line(line("e4 e5 Nf3 Nc6 Bb5", Color::White));
+        trainer.add_line(line(LASKER_TRAP, Color::Black));
+
+        trainer.start_line(1);
+        assert!(!trainer.check_move("e6").unwrap().correct);
+
+        trainer.start_line(0);
+        for mv in ["e4", "Bc4", "Nf3", "Bc4", "Bc4", "Bb5"] {
+            trainer.check_move(mv);
+        }
+        assert!(trainer.get_prompt().is_none());
+        assert_eq!(trainer.failure_hotspots(), vec![(0, 4, 2), (0, 2, 1), (1, 1, 1)]);
+    }
+
+    fn game(id: i64, white: &str, black: &str, moves: &str) -> crate::storage::StoredGame {
+        crate::storage::StoredGame {
+            id,
+            lichess_id: format!("g{}", id),
+            white_username: white.to_string(),
+            black_username: black.to_string(),
+            white_rating: None,
+            black_rating: None,
+            result: "*".to_string(),
+            speed: "blitz".to_string(),
+            rated: true,
+            opening_eco: None,
+            opening_name: None,
+            moves: moves.to_string(),
+            moves_uci: None,
+            pgn: None,
+            analyzed: false,
+            played_at: 0,
+            created_at: 0,
+            lichess_analysis: None,
+            acpl: None,
+            endgame_conversion: None,
+            result_suspect: false,
+            time_control: None,
+            clocks: None,
+        }
+    }
+
+    #[test]
+    fn test_coverage_tells_my_deviations_from_the_opponents() {
+        let mut trainer = OpeningTrainer::new();
+        let mut ruy = line("e4 e5 Nf3 Nc6 Bb5", Color::White);
+        ruy.name = "Ruy Lopez".to_string();
+        trainer.add_line(ruy);
+        trainer.add_line(line("e4 c5 Nf3", Color::White));
+        trainer.add_line(line("d4 d5 c4 e6", Color::Black));
+
+        let games = [
+            game(1, "alice", "bob", "e4 e5 Nf3 Nc6 Bc4 Bc5"),
+            game(2, "alice", "bob", "e4 e6 d4 d5"),
+            game(3, "bob", "alice", "d4 d5 c4 e6 Nc3 Nf6"),
+            game(4, "Alice", "bob", "e4 e5"),
+            game(5, "alice", "bob", "e4 c5 Nf3+ d6"),
+        ];
+        let report = trainer.coverage(&games, "alice");
+
+        // Out of book after the line ends, inside it, or with a stray "+"
+        assert_eq!(report.followed, 3);
+        assert_eq!(report.uncovered, 0);
+        assert_eq!(report.deviations.len(), 2);
+
+        let mine = report.player_deviations();
+        assert_eq!(mine.len(), 1);
+        assert_eq!(*mine[0], Deviation {
+            game_id: 1,
+            ply: 4,
+            move_number: 3,
+            by_player: true,
+            played: "Bc4".to_string(),
+            expected: vec!["Bb5".to_string()],
+            line_name: "Ruy Lopez".to_string(),
+        });
+
+        let theirs = &report.deviations[1];
+        assert_eq!((theirs.game_id, theirs.ply, theirs.by_player), (2, 1, false));
+        assert_eq!(theirs.expected, ["e5", "c5"]);
+
+        // Completing the short line counts, even where a longer one from
+        // the same moves goes another way
+        let mut nested = OpeningTrainer::new();
+        nested.add_line(line("e4 e5", Color::White));
+        nested.add_line(line("e4 e5 Nf3 Nc6 Bb5", Color::White));
+        let report = nested.coverage(&games[..1], "alice");
+        assert_eq!((report.followed, report.deviations.len()), (1, 0));
+
+        // No Black lines at all: nothing to measure against
+        let mut white_only = OpeningTrainer::new();
+        white_only.add_line(line("e4 e5", Color::White));
+        assert_eq!(white_only.coverage(&games[2..3], "alice").uncovered, 1);
+    }
+
+    #[test]
+    fn test_lines_to_review_most_overdue_first() {
+        let now = 1_700_000_000;
+        let day = 86400;
+        let drilled = |days_ago: u64, correct: u32| {
+            let mut l = line("e4 e5 Nf3", Color::White);
+            l.times_drilled = 10;
+            l.times_correct = correct;
+            l.last_drilled = Some(now - days_ago * day);
+            l
+        };
+
+        let mut trainer = OpeningTrainer::new();
+        // Drilled today, and two days ago but known well enough to wait a week
+        trainer.add_line(drilled(0, 5));
+        trainer.add_line(drilled(2, 10));
+        // Overdue by a day, and by five
+        trainer.add_line(drilled(4, 8));
+        trainer.add_line(drilled(6, 5));
+        trainer.add_line(line("d4 d5", Color::Black));
+
+        let due: Vec<usize> = trainer.lines_to_review(now).iter().map(|(idx, _)| *idx).collect();
+        assert_eq!(due, [4, 3, 2]);
+
+        let mut today = line("e4 e5", Color::White);
+        today.record_drill(false, now);
+        assert!(!today.needs_review(now));
+        assert!(today.needs_review(now + day));
+        assert_eq!((today.times_drilled, today.times_correct), (1, 0));
+    }
+
+    #[test]
+    fn test_json_round_trip_keeps_stats_and_colors() {
+        let mut trainer = OpeningTrainer::new();
+        let mut drilled = line(LASKER_TRAP, Color::Black);
+        drilled.times_drilled = 12;
+        drilled.times_correct = 9;
+        drilled.last_drilled = Some(1_700_000_000);
+        trainer.add_line(drilled);
+        trainer.add_line(line("e4 e5 Nf3", Color::White));
+
+        let json = trainer.export_json();
+        assert!(json.contains("\"for_color\": \"Black\""));
+
+        let restored = OpeningTrainer::import_json(&json).unwrap();
+        let lines = restored.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].for_color, Color::Black);
+        assert_eq!((lines[0].times_drilled, lines[0].times_correct), (12, 9));
+        assert_eq!(lines[0].last_drilled, Some(1_700_000_000));
+        assert_eq!(lines[0].moves.last().map(String::as_str), Some("fxg1=N+"));
+        assert_eq!(lines[1].for_color, Color::White);
+
+        assert!(OpeningTrainer::import_json(&json.replace("White", "Green")).is_err());
+    }
+}
@@ -1,9 +1,11 @@
 //! Board visualization training
 
-use shakmaty::{Chess, Position, Square, Role, Color, CastlingMode, fen::Fen};
+use shakmaty::{Chess, Position, Square, Color, CastlingMode, fen::Fen};
 use rand::seq::IndexedRandom;
 use rand::Rng;
 
+use crate::util::{color_name, piece_name};
+
 pub struct VisualizationDrill {
     position: Chess,
     fen: String,
@@ -63,7 +65,7 @@ impl VisualizationDrill {
 
                 let correct_answer = match piece {
                     Some(p) => format!("{} {}",
-                        if p.color == Color::White { "White" } else { "Black" },
+                        color_name(p.color),
                         piece_name(p.role)
                     ),
                     None => "Empty".to_string(),
@@ -100,11 +102,9 @@ impl VisualizationDrill {
 
                 let square = *occupied.choose(&mut rng).unwrap();
                 let piece = self.position.board().piece_at(square).unwrap();
-                let color_name = if piece.color == Color::White { "White" } else { "Black" };
-
                 VisualizationQuestion {
                     fen: self.fen.clone(),
-                    question: format!("Where is the {} {}?", color_name, piece_name(piece.role)),
+                    question: format!("Where is the {} {}?", color_name(piece.color), piece_name(piece.role)),
                     correct_answer: square.to_string(),
                     options: None,
                     show_board_for_ms: self.show_duration(),
@@ -125,7 +125,7 @@ impl VisualizationDrill {
                     fen: self.fen.clone(),
                     question: format!("Is {} attacked by {}?",
                         square,
-                        if color == Color::White { "White" } else { "Black" }
+                        color_name(color)
                     ),
                     correct_answer: if is_attacked { "Yes" } else { "No" }.to_string(),
                     options: Some(vec!["Yes".to_string(), "No".to_string()]),
@@ -165,17 +165,6 @@ impl VisualizationDrill {
     }
 }
 
-fn piece_name(role: Role) -> &'static str {
-    match role {
-        Role::Pawn => "Pawn",
-        Role::Knight => "Knight",
-        Role::Bishop => "Bishop",
-        Role::Rook => "Rook",
-        Role::Queen => "Queen",
-        Role::King => "King",
-    }
-}
-
 pub fn training_positions(difficulty: Difficulty) -> Vec<&'static str> {
     match difficulty {
         Difficulty::Beginner => vec![
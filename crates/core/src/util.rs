@@ -0,0 +1,197 @@
+//! Small helpers shared across modules
+
+use shakmaty::fen::Fen;
+use shakmaty::zobrist::Zobrist64;
+use shakmaty::{CastlingMode, Chess, Color, EnPassantMode, Position, PositionError, Role};
+
+use crate::error::{Error, Result};
+
+/// Default values for the FEN fields after the board, in order
+const FEN_FIELD_DEFAULTS: [&str; 5] = ["w", "-", "-", "0", "1"];
+
+/// Display name of a color. Templates depend on these exact strings.
+pub fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "White",
+        Color::Black => "Black",
+    }
+}
+
+/// Display name of a piece type, e.g. "Knight"
+pub fn piece_name(role: Role) -> &'static str {
+    match role {
+        Role::Pawn => "Pawn",
+        Role::Knight => "Knight",
+        Role::Bishop => "Bishop",
+        Role::Rook => "Rook",
+        Role::Queen => "Queen",
+        Role::King => "King",
+    }
+}
+
+/// Lowercase name of a color, as stored in settings and sent in query
+/// strings and `data-` attributes
+pub fn color_key(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+/// Parses "white"/"black" or "w"/"b", ignoring case and surrounding whitespace
+pub fn parse_color(s: &str) -> Option<Color> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "white" | "w" => Some(Color::White),
+        "black" | "b" => Some(Color::Black),
+        _ => None,
+    }
+}
+
+/// Serde adapter storing a `Color` as its display name ("White"/"Black").
+/// Use with `#[serde(with = "crate::util::color_serde")]`.
+pub mod color_serde {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use shakmaty::Color;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(super::color_name(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Color, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        super::parse_color(&s)
+            .ok_or_else(|| de::Error::custom(format!("invalid color '{}'", s)))
+    }
+}
+
+/// The color `username` played, given the game's white player.
+/// Lichess usernames are case-insensitive.
+pub fn player_color(username: &str, white_username: &str) -> Color {
+    if username.eq_ignore_ascii_case(white_username) {
+        Color::White
+    } else {
+        Color::Black
+    }
+}
+
+/// The share of a point, 0.0 to 1.0, a player rated `player_rating` is
+/// expected to score against `opponent_rating` under the Elo model: 0.5
+/// between equals, about 0.91 for a 400-point favourite.
+pub fn expected_score(player_rating: u16, opponent_rating: u16) -> f64 {
+    let gap = opponent_rating as f64 - player_rating as f64;
+    1.0 / (1.0 + 10f64.powf(gap / 400.0))
+}
+
+/// Parses a FEN leniently and re-emits it in canonical form.
+///
+/// Whitespace is collapsed, anything after the sixth field is dropped, and
+/// missing trailing fields (side to move, castling, en passant, move
+/// counters) get their defaults. Castling rights and en passant squares
+/// that don't fit the position are dropped rather than rejected.
+pub fn normalize_fen(input: &str) -> Result<String> {
+    let position = parse_fen_lenient(input)?;
+    Ok(Fen::from_position(&position, EnPassantMode::Legal).to_string())
+}
+
+/// Builds a position from a FEN with the same leniency as `normalize_fen`
+pub fn parse_fen_lenient(input: &str) -> Result<Chess> {
+    let mut fields: Vec<&str> = input.split_whitespace().take(6).collect();
+    if fields.is_empty() {
+        return Err(Error::Fen("empty input".to_string()));
+    }
+    let present = fields.len() - 1;
+    fields.extend_from_slice(&FEN_FIELD_DEFAULTS[present..]);
+
+    let fen: Fen = fields.join(" ").parse().map_err(|e| Error::Fen(format!("{}", e)))?;
+    fen.into_position(CastlingMode::Standard)
+        .or_else(PositionError::ignore_invalid_castling_rights)
+        .or_else(PositionError::ignore_invalid_ep_square)
+        .map_err(|e| Error::Fen(format!("{}", e)))
+}
+
+/// Cache key for a position: a Zobrist hash of the board, side to move,
+/// castling rights and a capturable en passant square, so the same
+/// position reached in different games (or by different move orders)
+/// gets the same key.
+///
+/// The halfmove clock and move number are left out. That's fine for
+/// caching evals, except close to the fifty-move rule, where an engine
+/// scores a won position as the draw it is about to become.
+pub fn position_key(position: &Chess) -> u64 {
+    position.zobrist_hash::<Zobrist64>(EnPassantMode::Legal).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_score() {
+        assert_eq!(expected_score(1500, 1500), 0.5);
+        assert!((expected_score(1900, 1500) - 0.909).abs() < 0.001);
+        assert!((expected_score(1500, 1900) - 0.091).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_color_name_round_trips() {
+        for color in [Color::White, Color::Black] {
+            assert_eq!(parse_color(color_name(color)), Some(color));
+            assert_eq!(parse_color(color_key(color)), Some(color));
+        }
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color(" WHITE "), Some(Color::White));
+        assert_eq!(parse_color("b"), Some(Color::Black));
+        assert_eq!(parse_color("red"), None);
+        assert_eq!(parse_color(""), None);
+    }
+
+    #[test]
+    fn test_normalize_fen_adds_missing_counters() {
+        assert_eq!(
+            normalize_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -").unwrap(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(
+            normalize_fen("8/8/8/8/8/k7/8/K7").unwrap(),
+            "8/8/8/8/8/k7/8/K7 w - - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_normalize_fen_collapses_whitespace_and_junk() {
+        assert_eq!(
+            normalize_fen("  rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR   w  KQkq  -  0   1  extra\n").unwrap(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_normalize_fen_rejects_garbage() {
+        assert!(normalize_fen("").is_err());
+        assert!(normalize_fen("not a fen").is_err());
+        // No kings
+        assert!(normalize_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_player_color() {
+        assert_eq!(player_color("alice", "Alice"), Color::White);
+        assert_eq!(player_color("alice", "Bob"), Color::Black);
+    }
+
+    #[test]
+    fn test_position_key_ignores_move_counters() {
+        let early = parse_fen_lenient("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        let late = parse_fen_lenient("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 14 40").unwrap();
+        assert_eq!(position_key(&early), position_key(&late));
+
+        // Side to move and castling rights do count
+        let black = parse_fen_lenient("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 2 3").unwrap();
+        let no_castling = parse_fen_lenient("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w kq - 2 3").unwrap();
+        assert_ne!(position_key(&early), position_key(&black));
+        assert_ne!(position_key(&early), position_key(&no_castling));
+    }
+}
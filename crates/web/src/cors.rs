@@ -0,0 +1,81 @@
+//! Cross-origin access to the web routes
+//!
+//! `CORS_ALLOWED_ORIGINS` lists the origins whose browser front ends may
+//! call the server, comma-separated (e.g.
+//! `http://localhost:5173,https://chess.example.com`), or `*` for any
+//! origin. Unset or empty keeps the server same-origin only.
+//!
+//! Cookies aren't involved anywhere, so credentials are never allowed.
+
+use axum::http::{header, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+pub fn layer_from_env() -> CorsLayer {
+    let origins = std::env::var("CORS_ALLOWED_ORIGINS").unwrap_or_default();
+    if !origins.trim().is_empty() {
+        println!("CORS: allowing {}", origins.trim());
+    }
+    layer(&origins)
+}
+
+fn layer(origins: &str) -> CorsLayer {
+    let origins: Vec<&str> = origins.split(',').map(str::trim).filter(|o| !o.is_empty()).collect();
+
+    let allow_origin = if origins.contains(&"*") {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(origins.into_iter().filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                eprintln!("Ignoring CORS origin {:?}: not a valid header value", origin);
+                None
+            }
+        }))
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([header::ACCEPT, header::CONTENT_TYPE])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn preflight(origins: &str, origin: &str) -> axum::http::Response<Body> {
+        let app = Router::new().route("/games", get(|| async { "games" })).layer(layer(origins));
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/games")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "accept")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_preflight_headers() {
+        let origins = "http://localhost:5173, https://chess.example.com";
+
+        let response = preflight(origins, "https://chess.example.com").await;
+        assert!(response.status().is_success());
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://chess.example.com");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().contains("GET"));
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap().contains("accept"));
+
+        // Unlisted origins get no grant, and nothing is allowed by default
+        let response = preflight(origins, "https://evil.example.com").await;
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+        let response = preflight("", "http://localhost:5173").await;
+        assert!(!response.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        let response = preflight("*", "https://anywhere.example.com").await;
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+}
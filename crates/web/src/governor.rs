@@ -0,0 +1,115 @@
+//! Resource limits for background analysis
+//!
+//! Configured through environment variables:
+//!
+//! - `ANALYSIS_MAX_CONCURRENCY`: engine searches allowed at once, i.e. the
+//!   number of worker threads, each with its own engine (default 1)
+//! - `ANALYSIS_NICE`: Unix niceness (0-19) applied to every engine process
+//!   (default unset, normal priority)
+//! - `ANALYSIS_PAUSE_MS`: sleep between games on each worker (default 0)
+//!
+//! Niceness is applied with `setpriority(2)`, so it only works on Unix, and
+//! unprivileged users can lower priority but not raise it back. On other
+//! platforms the setting is logged and ignored. It limits CPU scheduling
+//! only; engine memory (hash size) is unaffected.
+
+use std::time::Duration;
+
+use chess_analyzer_core::PatternDetector;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Governor {
+    pub max_concurrency: usize,
+    pub nice: Option<i32>,
+    pub pause: Duration,
+}
+
+impl Default for Governor {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 1,
+            nice: None,
+            pause: Duration::ZERO,
+        }
+    }
+}
+
+impl Governor {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        let parse = |key: &str| -> Option<i64> {
+            let value = lookup(key)?;
+            match value.trim().parse() {
+                Ok(v) => Some(v),
+                Err(_) => {
+                    eprintln!("Ignoring {}={:?}: not a number", key, value);
+                    None
+                }
+            }
+        };
+
+        Self {
+            max_concurrency: parse("ANALYSIS_MAX_CONCURRENCY")
+                .map(|n| n.clamp(1, 64) as usize)
+                .unwrap_or(defaults.max_concurrency),
+            nice: parse("ANALYSIS_NICE").map(|n| n.clamp(0, 19) as i32),
+            pause: parse("ANALYSIS_PAUSE_MS")
+                .map(|ms| Duration::from_millis(ms.max(0) as u64))
+                .unwrap_or(defaults.pause),
+        }
+    }
+
+    /// Applies the priority setting to a freshly started engine
+    pub fn apply(&self, detector: &PatternDetector) {
+        if let Some(nice) = self.nice {
+            if let Err(e) = detector.set_engine_priority(nice) {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    /// Called by a worker after each game
+    pub fn pause(&self) {
+        if !self.pause.is_zero() {
+            std::thread::sleep(self.pause);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn governor(vars: &[(&str, &str)]) -> Governor {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Governor::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn test_defaults_without_env() {
+        assert_eq!(governor(&[]), Governor::default());
+    }
+
+    #[test]
+    fn test_reads_and_clamps_env() {
+        let g = governor(&[
+            ("ANALYSIS_MAX_CONCURRENCY", "0"),
+            ("ANALYSIS_NICE", "25"),
+            ("ANALYSIS_PAUSE_MS", "250"),
+        ]);
+        assert_eq!(g.max_concurrency, 1);
+        assert_eq!(g.nice, Some(19));
+        assert_eq!(g.pause, Duration::from_millis(250));
+
+        let g = governor(&[("ANALYSIS_MAX_CONCURRENCY", "lots")]);
+        assert_eq!(g.max_concurrency, 1);
+    }
+}
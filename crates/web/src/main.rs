@@ -3,15 +3,40 @@ use axum::{
     Router,
 };
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tower_http::services::ServeDir;
 
+use chess_analyzer_core::patterns::DetectorConfig;
 use chess_analyzer_core::Database;
 
+mod cors;
+mod governor;
 mod routes;
+mod shared_engine;
+mod worker;
+
+use shared_engine::{EngineSettings, SharedEngine};
+use worker::AnalysisQueue;
 
 pub struct AppState {
     pub db: Mutex<Database>,
     pub username: Mutex<Option<String>>,
+    pub analysis_queue: AnalysisQueue,
+    /// Engine for request handlers that need a quick search
+    pub engine: SharedEngine,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State for handler tests, with an analysis queue nothing reads
+    pub(crate) fn for_test(db: Database, username: Option<&str>, engine: SharedEngine) -> Self {
+        AppState {
+            db: Mutex::new(db),
+            username: Mutex::new(username.map(String::from)),
+            analysis_queue: AnalysisQueue::new().0,
+            engine,
+        }
+    }
 }
 
 #[tokio::main]
@@ -19,26 +44,97 @@ async fn main() {
     tracing_subscriber::fmt::init();
 
     let db = Database::open("chess_analyzer.db").expect("Failed to open database");
+    match db.fail_unfinished_analysis_jobs() {
+        Ok(0) => {}
+        Ok(n) => println!("Marked {} analysis job(s) left over from the last run as failed", n),
+        Err(e) => eprintln!("Failed to clear old analysis jobs: {}", e),
+    }
+
+    let (analysis_queue, receiver) = AnalysisQueue::new();
 
     let state = Arc::new(AppState {
         db: Mutex::new(db),
         username: Mutex::new(None),
+        analysis_queue,
+        engine: SharedEngine::from_env(),
     });
 
+    let engine_settings = EngineSettings::from_env();
+    if engine_settings.warm_up {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || match state.engine.warm_up() {
+            Ok(()) => println!("Engine warmed up"),
+            Err(e) => eprintln!("Engine warm-up failed: {}", e),
+        });
+    }
+    if let Some(timeout) = engine_settings.idle_timeout {
+        println!("Shutting the shared engine down after {:?} idle", timeout);
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(timeout.min(Duration::from_secs(30)));
+            loop {
+                ticks.tick().await;
+                state.engine.close_if_idle(timeout);
+            }
+        });
+    }
+
+    let governor = governor::Governor::from_env();
+    println!("Analysis: {} worker(s), nice {:?}, {:?} pause between games",
+        governor.max_concurrency, governor.nice, governor.pause);
+    let detector_config = DetectorConfig::from_env();
+    if detector_config.style_tips {
+        println!("Style tips enabled");
+    }
+    if detector_config.fortress_check {
+        println!("Skipping inaccuracies in likely fortresses");
+    }
+    if detector_config.tablebase {
+        println!("Scoring endgame conversion with the Lichess tablebase");
+    }
+    if let Some(fast) = detector_config.fast_mode {
+        println!("Fast mode: screening at depth {}, depth {} above {} cp",
+            fast.screen_depth, fast.deep_depth, fast.threshold_cp);
+    }
+    let analysis_worker = worker::spawn_worker(state.clone(), receiver, governor, detector_config);
+
     let app = Router::new()
         .route("/", get(routes::index))
         .route("/games", get(routes::games_list))
         .route("/patterns", get(routes::patterns_list))
+        .route("/api/patterns.csv", get(routes::patterns_csv))
+        .route("/stats/trend", get(routes::stats_trend))
+        .route("/stats/opponents", get(routes::stats_opponents))
+        .route("/stats/improvement", get(routes::stats_improvement))
         .route("/sync", post(routes::sync_games))
-        .route("/analyze", get(routes::analyze_games))
+        .route("/analyze", get(routes::analyze_games).post(routes::analyze_games_form))
+        .route("/api/analyze/queue", get(routes::analysis_queue))
+        .route("/api/analyze/cancel", post(routes::cancel_analysis))
+        .route("/api/games/:id/analyze", post(routes::analyze_game_job))
+        .route("/api/jobs/:id", get(routes::job_status))
+        .route("/api/analyze/line", post(routes::api::analyze_line))
+        .route("/api/games/:id/time-usage", get(routes::api::game_time_usage))
         .route("/health", get(routes::health))
         .route("/train", get(routes::training::training_hub))
         .route("/training/coordinates", get(routes::training::coordinates_drill))
         .route("/training/visualization", get(routes::training::visualization_drill))
         .route("/training/openings", get(routes::training::openings_trainer))
+        .route("/training/openings/review", get(routes::training::openings_review))
+        .route("/training/puzzles", get(routes::training::opening_puzzles))
+        .route("/training/history", get(routes::training::session_history))
+        .route("/training/:type/progress", get(routes::training::training_progress))
         .route("/api/training/save", post(routes::training::save_session))
+        .route("/api/training/puzzles/attempt", post(routes::training::save_puzzle_attempt))
+        .route("/api/training/perspective", post(routes::training::save_perspective))
+        .route("/api/training/openings/export", get(routes::training::export_repertoire))
+        .route("/api/training/openings/import", post(routes::training::import_repertoire))
+        .route("/api/training/openings/drill", post(routes::training::save_opening_drill))
+        .route("/api/fen/normalize", post(routes::api::normalize_fen))
+        .route("/api/position/king-safety", post(routes::api::king_safety_for_fen))
+        .route("/api/position/eval", post(routes::api::eval_position))
         .nest_service("/static", ServeDir::new("crates/web/static"))
-        .with_state(state);
+        .layer(cors::layer_from_env())
+        .with_state(state.clone());
 
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
         .await
@@ -46,5 +142,18 @@ async fn main() {
 
     println!("Server running at http://localhost:3000");
 
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+
+    println!("Shutting down, finishing queued analysis...");
+    state.analysis_queue.close();
+    analysis_worker.await.ok();
+}
+
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to install Ctrl+C handler");
 }
@@ -0,0 +1,394 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chess_analyzer_core::engine::Evaluation;
+use chess_analyzer_core::parser::clock::time_usage;
+use chess_analyzer_core::parser::{parse_user_move, ThinkCorrelation, TimeUsage};
+use chess_analyzer_core::storage::SIDE_PLAYER;
+use chess_analyzer_core::positional::{king_safety, KingSafety};
+use chess_analyzer_core::util::parse_fen_lenient;
+use serde::{Deserialize, Serialize};
+use shakmaty::{fen::Fen, san::SanPlus, uci::UciMove, Color, EnPassantMode, Position};
+
+use crate::AppState;
+
+/// Search depth for `/api/position/eval`; shallow enough to answer while
+/// the user waits
+const EVAL_DEPTH: u8 = 12;
+
+/// Longest line `/api/analyze/line` searches, one search per ply
+const MAX_LINE_PLIES: usize = 40;
+
+// ============================================================================
+// FEN
+// ============================================================================
+
+#[derive(Deserialize)]
+pub struct FenRequest {
+    pub fen: String,
+}
+
+#[derive(Serialize)]
+pub struct FenResponse {
+    pub fen: Option<String>,
+    pub error: Option<String>,
+}
+
+pub async fn normalize_fen(Json(req): Json<FenRequest>) -> (StatusCode, Json<FenResponse>) {
+    match chess_analyzer_core::util::normalize_fen(&req.fen) {
+        Ok(fen) => (StatusCode::OK, Json(FenResponse { fen: Some(fen), error: None })),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(FenResponse { fen: None, error: Some(e.to_string()) }),
+        ),
+    }
+}
+
+// ============================================================================
+// KING SAFETY
+// ============================================================================
+
+#[derive(Serialize)]
+pub struct KingSafetyResponse {
+    pub white: Option<KingSafety>,
+    pub black: Option<KingSafety>,
+    pub error: Option<String>,
+}
+
+pub async fn king_safety_for_fen(
+    Json(req): Json<FenRequest>,
+) -> (StatusCode, Json<KingSafetyResponse>) {
+    match parse_fen_lenient(&req.fen) {
+        Ok(position) => (
+            StatusCode::OK,
+            Json(KingSafetyResponse {
+                white: Some(king_safety(&position, Color::White)),
+                black: Some(king_safety(&position, Color::Black)),
+                error: None,
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(KingSafetyResponse { white: None, black: None, error: Some(e.to_string()) }),
+        ),
+    }
+}
+
+// ============================================================================
+// EVAL
+// ============================================================================
+
+#[derive(Serialize, Default)]
+pub struct EvalResponse {
+    pub best_move: Option<String>,
+    /// From White's point of view, e.g. "+0.31" or "M-3"
+    pub eval: Option<String>,
+    pub depth: Option<u8>,
+    pub error: Option<String>,
+}
+
+pub async fn eval_position(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<FenRequest>,
+) -> (StatusCode, Json<EvalResponse>) {
+    let position = match parse_fen_lenient(&req.fen) {
+        Ok(p) => p,
+        Err(e) => {
+            return (StatusCode::BAD_REQUEST, Json(EvalResponse { error: Some(e.to_string()), ..Default::default() }));
+        }
+    };
+    let fen = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+    let turn = position.turn();
+
+    let search = tokio::task::spawn_blocking(move || {
+        state.engine.with(|engine| {
+            engine.set_position(Some(&fen), None)?;
+            engine.analyze(EVAL_DEPTH)
+        })
+    })
+    .await;
+
+    match search {
+        Ok(Ok(analysis)) => {
+            (
+                StatusCode::OK,
+                Json(EvalResponse {
+                    best_move: Some(analysis.best_move),
+                    eval: Some(white_eval(analysis.evaluation, turn).to_string()),
+                    depth: Some(analysis.depth),
+                    error: None,
+                }),
+            )
+        }
+        Ok(Err(e)) => (StatusCode::SERVICE_UNAVAILABLE, Json(EvalResponse { error: Some(e.to_string()), ..Default::default() })),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(EvalResponse { error: Some(e.to_string()), ..Default::default() })),
+    }
+}
+
+/// A UCI score, which is for the side to move, from White's point of view
+fn white_eval(evaluation: Evaluation, turn: Color) -> Evaluation {
+    match (evaluation, turn) {
+        (evaluation, Color::White) => evaluation,
+        (Evaluation::Centipawns(cp), Color::Black) => Evaluation::Centipawns(-cp),
+        (Evaluation::Mate(m), Color::Black) => Evaluation::Mate(-m),
+    }
+}
+
+// ============================================================================
+// WHAT-IF LINES
+// ============================================================================
+
+#[derive(Deserialize)]
+pub struct LineRequest {
+    pub fen: String,
+    /// SAN, UCI or long algebraic, as `parse_user_move` reads them
+    pub moves: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LinePlyView {
+    pub uci: String,
+    pub san: String,
+    /// After the move, from White's point of view
+    pub eval: String,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct LineResponse {
+    pub plies: Vec<LinePlyView>,
+    /// The engine's choice at the end of the line, in UCI; `None` when the
+    /// game is over there
+    pub best_move: Option<String>,
+    /// How many moves were legal; short of the request's on an error
+    pub played: usize,
+    pub error: Option<String>,
+}
+
+/// Replays a line of the user's from `fen` and evaluates the position after
+/// every move. Every move is checked before the engine is asked anything;
+/// the first illegal one is reported with the number played before it.
+pub async fn analyze_line(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<LineRequest>,
+) -> (StatusCode, Json<LineResponse>) {
+    let bad_request = |played: usize, error: String| {
+        (StatusCode::BAD_REQUEST, Json(LineResponse { played, error: Some(error), ..Default::default() }))
+    };
+
+    let mut position = match parse_fen_lenient(&req.fen) {
+        Ok(p) => p,
+        Err(e) => return bad_request(0, e.to_string()),
+    };
+    if req.moves.len() > MAX_LINE_PLIES {
+        return bad_request(0, format!("lines are limited to {} moves", MAX_LINE_PLIES));
+    }
+    let start = Fen::from_position(&position, EnPassantMode::Legal).to_string();
+
+    // (uci, san, side to move after it)
+    let mut line = Vec::new();
+    for (i, input) in req.moves.iter().enumerate() {
+        let mv = match parse_user_move(&position, input) {
+            Some(m) => m,
+            None => return bad_request(i, format!("move {} ('{}') is not legal here", i + 1, input)),
+        };
+        let uci = UciMove::from_standard(mv).to_string();
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, mv).to_string();
+        line.push((uci, san, position.turn()));
+    }
+    let played = line.len();
+
+    let search = tokio::task::spawn_blocking(move || {
+        state.engine.with(|engine| {
+            let uci: Vec<String> = line.iter().map(|(uci, _, _)| uci.clone()).collect();
+            let mut plies = Vec::new();
+            let mut best_move = None;
+            // The start is only searched for a best move when there is no line
+            let ends = if uci.is_empty() { 0..=0 } else { 1..=uci.len() };
+            for end in ends {
+                engine.set_position(Some(&start), Some(&uci[..end]))?;
+                let analysis = engine.analyze(EVAL_DEPTH)?;
+                if end > 0 {
+                    let (uci, san, turn) = &line[end - 1];
+                    plies.push(LinePlyView {
+                        uci: uci.clone(),
+                        san: san.clone(),
+                        eval: white_eval(analysis.evaluation, *turn).to_string(),
+                    });
+                }
+                best_move = Some(analysis.best_move).filter(|m| !m.is_empty() && m != "(none)");
+            }
+            Ok((plies, best_move))
+        })
+    })
+    .await;
+
+    match search {
+        Ok(Ok((plies, best_move))) => (StatusCode::OK, Json(LineResponse { plies, best_move, played, error: None })),
+        Ok(Err(e)) => (StatusCode::SERVICE_UNAVAILABLE, Json(LineResponse { played, error: Some(e.to_string()), ..Default::default() })),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(LineResponse { played, error: Some(e.to_string()), ..Default::default() })),
+    }
+}
+
+// ============================================================================
+// TIME USAGE
+// ============================================================================
+
+#[derive(Serialize, Debug)]
+pub struct TimeUsageResponse {
+    #[serde(flatten)]
+    pub usage: TimeUsage,
+    /// How the user's mistakes and blunders split between their long thinks
+    /// and their other moves; `None` when the user didn't play the game
+    pub player: Option<ThinkCorrelation>,
+    pub long_thinks_helped: Option<bool>,
+}
+
+/// Where the clock went in one stored game, from the clocks stored with
+/// it. 404 for an unknown game or one without clock data.
+pub async fn game_time_usage(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<TimeUsageResponse>, StatusCode> {
+    let username = state.username.lock().unwrap().clone();
+    let db = state.db.lock().unwrap();
+    let game = db.get_game(id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+
+    let moves: Vec<String> = game.moves.split_whitespace().map(String::from).collect();
+    let usage = game.clocks.as_deref()
+        .and_then(|clocks| time_usage(&moves, clocks, game.time_control.as_deref()))
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let color = username.as_deref().and_then(|user| {
+        if game.white_username.eq_ignore_ascii_case(user) {
+            Some(Color::White)
+        } else if game.black_username.eq_ignore_ascii_case(user) {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    });
+    let player = match color {
+        Some(color) => {
+            let mistakes: Vec<usize> = db.get_game_patterns(id)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .iter()
+                .filter(|p| p.side == SIDE_PLAYER && p.severity != "inaccuracy")
+                .map(|p| p.ply())
+                .collect();
+            Some(usage.think_correlation(color, &mistakes))
+        }
+        None => None,
+    };
+
+    Ok(Json(TimeUsageResponse { long_thinks_helped: player.and_then(|p| p.long_thinks_helped()), usage, player }))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use chess_analyzer_core::Database;
+    use crate::shared_engine::{tests::mock_engine, SharedEngine};
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn state(engine: &std::path::Path) -> Arc<AppState> {
+        Arc::new(AppState::for_test(Database::open_in_memory().unwrap(), None, SharedEngine::new(engine.to_str().unwrap(), &[])))
+    }
+
+    fn line_request(moves: &[&str]) -> Json<LineRequest> {
+        Json(LineRequest { fen: START.to_string(), moves: moves.iter().map(|m| m.to_string()).collect() })
+    }
+
+    #[tokio::test]
+    async fn test_sequential_evals_share_one_engine() {
+        let (script, log) = mock_engine("eval-endpoint");
+        let state = state(&script);
+        let eval = |fen: &str| eval_position(State(state.clone()), Json(FenRequest { fen: fen.to_string() }));
+
+        let (status, Json(white)) = eval(START).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((white.best_move.as_deref(), white.eval.as_deref()), (Some("e2e4"), Some("+0.31")));
+        // The engine's +31 is Black's here
+        let (_, Json(black)) = eval("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").await;
+        assert_eq!(black.eval.as_deref(), Some("-0.31"));
+
+        let (status, Json(bad)) = eval("not a fen").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(bad.error.is_some());
+
+        let commands = fs::read_to_string(&log).unwrap();
+        assert_eq!(commands.lines().filter(|l| *l == "start").count(), 1);
+        assert_eq!(commands.lines().filter(|l| *l == "uci").count(), 1);
+
+        let _ = fs::remove_dir_all(script.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_legal_line() {
+        let (script, log) = mock_engine("what-if-legal");
+        let (status, Json(line)) = analyze_line(State(state(&script)), line_request(&["e4", "e7e5", "Ng1-f3"])).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(line.played, 3);
+        let plies: Vec<(&str, &str, &str)> = line.plies.iter().map(|p| (p.uci.as_str(), p.san.as_str(), p.eval.as_str())).collect();
+        assert_eq!(plies, [("e2e4", "e4", "-0.31"), ("e7e5", "e5", "+0.31"), ("g1f3", "Nf3", "-0.31")]);
+        assert_eq!(line.best_move.as_deref(), Some("e2e4"));
+
+        // Each search gets the line so far, so repetitions count
+        let commands = fs::read_to_string(&log).unwrap();
+        assert!(commands.lines().any(|l| l == format!("position fen {} moves e2e4 e7e5 g1f3", START)));
+        assert_eq!(commands.lines().filter(|l| l.starts_with("go depth")).count(), 3);
+
+        let _ = fs::remove_dir_all(script.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_illegal_move_mid_line() {
+        let (script, log) = mock_engine("what-if-illegal");
+        let (status, Json(line)) = analyze_line(State(state(&script)), line_request(&["e4", "e5", "Ke3", "Nf3"])).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(line.played, 2);
+        assert_eq!(line.error.as_deref(), Some("move 3 ('Ke3') is not legal here"));
+        assert!(line.plies.is_empty());
+        // Nothing was searched
+        assert!(!fs::read_to_string(&log).unwrap_or_default().contains("go depth"));
+
+        let _ = fs::remove_dir_all(script.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_time_usage_of_an_unknown_game() {
+        let state = state(std::path::Path::new("stockfish"));
+        let usage = game_time_usage(State(state), Path(42)).await;
+        assert_eq!(usage.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_time_usage_of_an_imported_game() {
+        let state = state(std::path::Path::new("stockfish"));
+        *state.username.lock().unwrap() = Some("alice".to_string());
+        let pgn = "[White \"Alice\"]\n[Black \"Bob\"]\n[TimeControl \"180+2\"]\n\n\
+            1. e4 { [%clk 0:03:00] } e5 { [%clk 0:02:58] } 2. Nf3 { [%clk 0:02:55] } Nc6 { [%clk 0:02:40] } *\n";
+        let (timed, untimed) = {
+            let db = state.db.lock().unwrap();
+            let game = chess_analyzer_core::parser::pgn::parse_pgn_string(pgn).unwrap().remove(0);
+            let untimed = chess_analyzer_core::parser::pgn::parse_pgn_string("1. e4 e5 *").unwrap().remove(0);
+            (db.insert_pgn_game(&game).unwrap().unwrap(), db.insert_pgn_game(&untimed).unwrap().unwrap())
+        };
+
+        // The increment is added back from each side's second move on
+        let Json(response) = game_time_usage(State(state.clone()), Path(timed)).await.unwrap();
+        assert_eq!(response.usage.spent, [0, 200, 700, 2000]);
+        assert!(response.player.is_some());
+
+        let usage = game_time_usage(State(state), Path(untimed)).await;
+        assert_eq!(usage.unwrap_err(), StatusCode::NOT_FOUND);
+    }
+}
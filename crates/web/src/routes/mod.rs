@@ -1,23 +1,37 @@
 use askama::Template;
 use axum::{
-    extract::State,
-    response::{Html, IntoResponse, Redirect},
-    Form,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form, Json,
 };
 use std::sync::Arc;
 
+use chess_analyzer_core::storage::{AnalysisJobStatus, OpeningChange, OpponentRecord, SIDE_OPPONENT};
+use chess_analyzer_core::{Database, PatternType};
+
+use crate::worker::AnalysisJob;
 use crate::AppState;
 
-#[derive(Template)]
+#[derive(Template, serde::Serialize)]
 #[template(path = "index.html")]
 pub struct IndexTemplate {
     pub title: String,
     pub games_count: u32,
     pub patterns_found: u32,
+    pub blunders: u32,
+    pub mistakes: u32,
+    pub inaccuracies: u32,
+    pub most_common_pattern: Option<String>,
+    pub avg_acpl: Option<String>,
     pub username: Option<String>,
+    pub progress: AnalysisProgress,
+    /// False on a fresh database, where the page shows onboarding instead
+    /// of an all-zero dashboard
+    pub has_games: bool,
 }
 
-#[derive(Template)]
+#[derive(Template, serde::Serialize)]
 #[template(path = "games.html")]
 pub struct GamesTemplate {
     pub title: String,
@@ -25,14 +39,72 @@ pub struct GamesTemplate {
     pub username: Option<String>,
 }
 
-#[derive(Template)]
+#[derive(Template, serde::Serialize)]
 #[template(path = "patterns.html")]
 pub struct PatternsTemplate {
     pub title: String,
     pub patterns: Vec<PatternRow>,
     pub summary: PatternSummaryView,
+    pub progress: AnalysisProgress,
+    /// 1-based
+    pub page: u32,
+    pub has_next: bool,
+    pub perf_options: Vec<PerfOption>,
+    /// `&perf=...` for the page links, empty when showing every speed
+    pub perf_query: String,
+    /// The opponent's mistakes are listed alongside the user's
+    pub show_opponent: bool,
+    /// Analysis reports the opponent's mistakes too
+    pub analyze_opponent: bool,
+    /// Any games synced; without them there's nothing to analyze yet
+    pub has_games: bool,
 }
 
+const PATTERNS_PER_PAGE: u32 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct PatternsQuery {
+    pub page: Option<u32>,
+    pub perf: Option<String>,
+    pub opponent: Option<bool>,
+}
+
+/// Most games a single date-range request queues
+const ANALYZE_RANGE_LIMIT: u32 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct AnalyzeQuery {
+    /// First day to include, `YYYY-MM-DD`
+    pub from: Option<String>,
+    /// Last day to include, `YYYY-MM-DD`
+    pub to: Option<String>,
+    /// "player" or "both"; the form's choice is remembered for later runs,
+    /// which leave it out
+    pub sides: Option<String>,
+}
+
+impl AnalyzeQuery {
+    /// `[from, to)` in Unix seconds, or `None` when no range was asked for.
+    /// A missing end leaves that side of the range open.
+    fn range(&self) -> Option<(u64, u64)> {
+        let from = self.from.as_deref().filter(|d| !d.is_empty());
+        let to = self.to.as_deref().filter(|d| !d.is_empty());
+        if from.is_none() && to.is_none() {
+            return None;
+        }
+        let start = from.and_then(day_start).unwrap_or(0);
+        let end = to.and_then(day_start).map_or(i64::MAX as u64, |d| d + 86_400);
+        Some((start, end))
+    }
+}
+
+/// Midnight UTC at the start of a `YYYY-MM-DD` date, in Unix seconds
+fn day_start(date: &str) -> Option<u64> {
+    let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    u64::try_from(date.and_hms_opt(0, 0, 0)?.and_utc().timestamp()).ok()
+}
+
+#[derive(serde::Serialize)]
 pub struct GameRow {
     pub id: i64,
     pub white: String,
@@ -41,17 +113,30 @@ pub struct GameRow {
     pub opening: String,
     pub speed: String,
     pub date: String,
+    /// Full moves; a trailing white move counts as a move
+    pub move_count: usize,
+    pub is_analyzed: bool,
+    /// `"75%"`, when analysis scored the endgame against the tablebase
+    pub endgame_conversion: Option<String>,
 }
 
+#[derive(serde::Serialize)]
 pub struct PatternRow {
     pub game_id: i64,
     pub move_number: u16,
+    /// `"15."` or `"15..."`
+    pub move_label: String,
     pub pattern_type: String,
     pub severity: String,
+    /// "best" to "blunder"; picks the colour of the dot
+    pub quality: String,
     pub cp_loss: i32,
     pub description: String,
+    /// Played by the opponent rather than the user
+    pub opponent: bool,
 }
 
+#[derive(serde::Serialize)]
 pub struct PatternSummaryView {
     pub total_games: u32,
     pub blunders: u32,
@@ -59,26 +144,89 @@ pub struct PatternSummaryView {
     pub inaccuracies: u32,
 }
 
+/// How much of the synced collection has been analyzed, for the progress bar
+#[derive(serde::Serialize)]
+pub struct AnalysisProgress {
+    pub analyzed: u32,
+    pub total: u32,
+    /// 0-100, rounded down so the bar only fills when everything is done
+    pub percent: u32,
+}
+
+impl AnalysisProgress {
+    pub fn new(analyzed: u32, unanalyzed: u32) -> Self {
+        let total = analyzed + unanalyzed;
+        let percent = (analyzed * 100).checked_div(total).unwrap_or(0);
+        Self { analyzed, total, percent }
+    }
+
+    fn load(db: &Database) -> Self {
+        Self::new(
+            db.count_analyzed_games().unwrap_or(0),
+            db.count_unanalyzed_games().unwrap_or(0),
+        )
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.total - self.analyzed
+    }
+}
+
+/// Whether the client asked for JSON instead of a page
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|media| media.split(';').next())
+        .any(|media| media.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Renders a page, or sends its template data as JSON when the client asks
+/// with `Accept: application/json`. Browsers never do, so the same route
+/// serves both the server-rendered page and a separate front end.
+pub(crate) fn render<T: Template + serde::Serialize>(headers: &HeaderMap, page: T) -> Response {
+    if wants_json(headers) {
+        Json(page).into_response()
+    } else {
+        Html(page.render().unwrap()).into_response()
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct SyncForm {
     pub username: String,
 }
 
-pub async fn index(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let db = state.db.lock().unwrap();
-    let games_count = db.count_games().unwrap_or(0);
-    let patterns_found = db.count_patterns().unwrap_or(0);
+pub async fn index(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let (summary, progress, has_games) = {
+        let db = state.db.lock().unwrap();
+        let has_games = !db.is_empty().unwrap_or(true);
+        (db.dashboard_summary(None).unwrap_or_default(), AnalysisProgress::load(&db), has_games)
+    };
 
     let template = IndexTemplate {
         title: "Chess Analyzer".to_string(),
-        games_count,
-        patterns_found,
+        games_count: summary.total_games,
+        patterns_found: summary.total_patterns,
+        blunders: summary.blunders,
+        mistakes: summary.mistakes,
+        inaccuracies: summary.inaccuracies,
+        most_common_pattern: summary.most_common_pattern.as_deref().map(|p| {
+            PatternType::parse(p)
+                .map(|t| t.display_name().to_string())
+                .unwrap_or_else(|| p.to_string())
+        }),
+        avg_acpl: summary.avg_acpl.map(|a| format!("{:.1}", a)),
         username: state.username.lock().unwrap().clone(),
+        progress,
+        has_games,
     };
-    Html(template.render().unwrap())
+    render(&headers, template)
 }
 
-pub async fn games_list(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+pub async fn games_list(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
     let db = state.db.lock().unwrap();
     let stored_games = db.get_recent_games(50).unwrap_or_default();
     
@@ -95,6 +243,9 @@ pub async fn games_list(State(state): State<Arc<AppState>>) -> impl IntoResponse
             opening: g.opening_name.clone().unwrap_or_else(|| "-".to_string()),
             speed: g.speed.clone(),
             date,
+            move_count: g.moves.split_whitespace().count().div_ceil(2),
+            is_analyzed: g.analyzed,
+            endgame_conversion: g.endgame_conversion.map(|score| format!("{:.0}%", score * 100.0)),
         }
     }).collect();
 
@@ -103,7 +254,7 @@ pub async fn games_list(State(state): State<Arc<AppState>>) -> impl IntoResponse
         games,
         username: state.username.lock().unwrap().clone(),
     };
-    Html(template.render().unwrap())
+    render(&headers, template)
 }
 
 pub async fn sync_games(
@@ -127,120 +278,621 @@ pub async fn sync_games(
         }
     };
 
-    let params = chess_analyzer_core::lichess::GameExportParams::new()
-        .max(500);
-
+    // Large accounts come down in windows; an interrupted sync resumes
     println!("Fetching games from Lichess...");
-    match client.get_user_games(&username, &params).await {
-        Ok(games) => {
-            println!("Fetched {} games from Lichess", games.len());
-            let db = state.db.lock().unwrap();
-            match db.insert_games(&games) {
-                Ok(count) => println!("Inserted {} games into database", count),
-                Err(e) => eprintln!("Failed to insert games: {}", e),
-            }
-        }
-        Err(e) => {
-            eprintln!("Failed to fetch games: {}", e);
-        }
+    let result = client.sync_all(&state.db, &username, |progress| {
+        println!("Window {}: {} games fetched so far", progress.windows, progress.fetched);
+    }).await;
+    match result {
+        Ok(progress) => println!("Inserted {} games into database", progress.inserted),
+        Err(e) => eprintln!("Failed to fetch games: {}", e),
     }
 
     Redirect::to("/games")
 }
 
-pub async fn analyze_games(State(state): State<Arc<AppState>>) -> Redirect {
+pub async fn analyze_games(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AnalyzeQuery>,
+) -> Redirect {
+    queue_analysis(&state, &query)
+}
+
+/// The analyze form: like `analyze_games`, and its `sides` choice is saved
+/// for later runs
+pub async fn analyze_games_form(
+    State(state): State<Arc<AppState>>,
+    Form(query): Form<AnalyzeQuery>,
+) -> Redirect {
+    let username = state.username.lock().unwrap().clone();
+    if let (Some(user), Some(both)) = (username, parse_sides(query.sides.as_deref())) {
+        if let Err(e) = state.db.lock().unwrap().set_analyze_opponent(&user, both) {
+            eprintln!("Failed to save analysis setting: {}", e);
+        }
+    }
+    queue_analysis(&state, &query)
+}
+
+fn queue_analysis(state: &AppState, query: &AnalyzeQuery) -> Redirect {
     let username = state.username.lock().unwrap().clone();
     let username = match username {
         Some(u) => u,
         None => return Redirect::to("/"),
     };
 
-    println!("Analyzing games for {}...", username);
-
-    // Run blocking Stockfish analysis in a separate thread
     let games = {
         let db = state.db.lock().unwrap();
-        db.get_unanalyzed_games(5).unwrap_or_default()
+        match query.range() {
+            Some((from, to)) => db.get_unanalyzed_games_between(from, to, ANALYZE_RANGE_LIMIT),
+            None => db.get_unanalyzed_games(5),
+        }
+        .unwrap_or_default()
     };
+    let analyze_opponent = analyze_opponent(state, &username, query.sides.as_deref());
 
     if games.is_empty() {
         println!("No unanalyzed games found");
         return Redirect::to("/patterns");
     }
 
-    // Spawn blocking task for Stockfish
-    let state_clone = state.clone();
-    tokio::task::spawn_blocking(move || {
-        let mut detector = match chess_analyzer_core::PatternDetector::new() {
-            Ok(d) => d,
-            Err(e) => {
-                eprintln!("Failed to create detector: {}", e);
-                return;
-            }
-        };
+    // The background worker does the Stockfish work; just queue the games
+    let queued = games
+        .into_iter()
+        .filter(|game| state.analysis_queue.enqueue(AnalysisJob {
+            game: game.clone(),
+            username: username.clone(),
+            analyze_opponent,
+            job_id: None,
+        }))
+        .count();
 
-        for game in &games {
-            let moves: Vec<String> = game.moves.split_whitespace().map(String::from).collect();
-            if moves.is_empty() {
-                continue;
-            }
+    println!("Queued {} games for analysis for {}", queued, username);
 
-            println!("Analyzing game {} ({} vs {}, {} moves)...", 
-                game.id, game.white_username, game.black_username, moves.len());
-
-            match detector.analyze_game(&moves, &username, &game.white_username) {
-                Ok(patterns) => {
-                    println!("Found {} patterns in game {}", patterns.len(), game.id);
-                    let db = state_clone.db.lock().unwrap();
-                    for pattern in &patterns {
-                        if let Err(e) = db.insert_pattern(game.id, pattern) {
-                            eprintln!("Failed to insert pattern: {}", e);
-                        }
-                    }
-                    let _ = db.mark_game_analyzed(game.id);
-                }
-                Err(e) => {
-                    eprintln!("Failed to analyze game {}: {}", game.id, e);
-                }
-            }
+    Redirect::to("/patterns")
+}
+
+/// Whether to analyze the opponent's moves too: an explicit choice for
+/// this run, otherwise the one saved for the user
+fn analyze_opponent(state: &AppState, username: &str, sides: Option<&str>) -> bool {
+    parse_sides(sides).unwrap_or_else(|| state.db.lock().unwrap().get_analyze_opponent(username).unwrap_or(false))
+}
+
+/// `Some(true)` for "both", `Some(false)` for "player"
+fn parse_sides(sides: Option<&str>) -> Option<bool> {
+    match sides {
+        Some("both") => Some(true),
+        Some("player") => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct QueueStatus {
+    pub depth: usize,
+}
+
+pub async fn analysis_queue(State(state): State<Arc<AppState>>) -> Json<QueueStatus> {
+    Json(QueueStatus {
+        depth: state.analysis_queue.depth(),
+    })
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct JobCreated {
+    pub job_id: i64,
+}
+
+/// Queues one game and answers at once with a job to poll at
+/// `/api/jobs/:id`, for clients that can't hold an SSE connection open.
+/// 404 for an unknown game, 409 if the game is already queued.
+pub async fn analyze_game_job(
+    State(state): State<Arc<AppState>>,
+    Path(game_id): Path<i64>,
+) -> Result<(StatusCode, Json<JobCreated>), StatusCode> {
+    let username = state.username.lock().unwrap().clone().ok_or(StatusCode::BAD_REQUEST)?;
+    let (game, job_id) = {
+        let db = state.db.lock().unwrap();
+        let game = db.get_game(game_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.ok_or(StatusCode::NOT_FOUND)?;
+        let job_id = db.create_analysis_job(game_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        (game, job_id)
+    };
+    let analyze_opponent = analyze_opponent(&state, &username, None);
+
+    let job = AnalysisJob { game, username, analyze_opponent, job_id: Some(job_id) };
+    if !state.analysis_queue.enqueue(job) {
+        if let Err(e) = state.db.lock().unwrap().fail_analysis_job(job_id, "the game is already queued") {
+            eprintln!("Failed to update analysis job {}: {}", job_id, e);
         }
-    }).await.ok();
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok((StatusCode::ACCEPTED, Json(JobCreated { job_id })))
+}
 
-    Redirect::to("/patterns")
+/// Where a job from `analyze_game_job` stands, with the report once done
+pub async fn job_status(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<Json<AnalysisJobStatus>, StatusCode> {
+    let db = state.db.lock().unwrap();
+    match db.get_analysis_job(id) {
+        Ok(Some(job)) => Ok(Json(job)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Stops the analysis in progress and drops everything queued
+pub async fn cancel_analysis(State(state): State<Arc<AppState>>) -> Json<QueueStatus> {
+    let cancelled = state.analysis_queue.cancel();
+    if cancelled > 0 {
+        println!("Cancelled analysis of {} games", cancelled);
+    }
+    Json(QueueStatus {
+        depth: state.analysis_queue.depth(),
+    })
 }
 
-pub async fn patterns_list(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+pub async fn patterns_list(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PatternsQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let page = query.page.unwrap_or(1).max(1);
+    let perf = perf_filter(query.perf);
+    let show_opponent = query.opponent.unwrap_or(false);
+    let username = state.username.lock().unwrap().clone();
     let db = state.db.lock().unwrap();
-    let stored_patterns = db.get_all_patterns().unwrap_or_default();
+    // One extra row tells us whether there is a next page
+    let mut stored_patterns = db
+        .recent_patterns(
+            PATTERNS_PER_PAGE + 1,
+            page.saturating_sub(1).saturating_mul(PATTERNS_PER_PAGE),
+            perf.as_deref(),
+            show_opponent,
+        )
+        .unwrap_or_default();
+    let has_next = stored_patterns.len() > PATTERNS_PER_PAGE as usize;
+    stored_patterns.truncate(PATTERNS_PER_PAGE as usize);
 
     let patterns: Vec<PatternRow> = stored_patterns.iter().map(|p| {
         PatternRow {
             game_id: p.game_id,
             move_number: p.move_number,
+            move_label: p.move_label(),
             pattern_type: p.pattern_type.clone(),
             severity: p.severity.clone(),
+            quality: p.quality.clone(),
             cp_loss: p.centipawn_loss.unwrap_or(0),
             description: p.description.clone(),
+            opponent: p.side == SIDE_OPPONENT,
         }
     }).collect();
 
+    let dashboard = db.dashboard_summary(perf.as_deref()).unwrap_or_default();
     let summary = PatternSummaryView {
-        total_games: db.count_games().unwrap_or(0),
-        blunders: stored_patterns.iter().filter(|p| p.severity == "blunder").count() as u32,
-        mistakes: stored_patterns.iter().filter(|p| p.severity == "mistake").count() as u32,
-        inaccuracies: stored_patterns.iter().filter(|p| p.severity == "inaccuracy").count() as u32,
+        total_games: dashboard.total_games,
+        blunders: dashboard.blunders,
+        mistakes: dashboard.mistakes,
+        inaccuracies: dashboard.inaccuracies,
     };
 
     let template = PatternsTemplate {
         title: "Patterns".to_string(),
         patterns,
         summary,
+        progress: AnalysisProgress::load(&db),
+        page,
+        has_next,
+        perf_options: perf_options(perf.as_deref()),
+        perf_query: perf.map(|p| format!("&perf={}", p)).unwrap_or_default(),
+        show_opponent,
+        analyze_opponent: username.is_some_and(|u| db.get_analyze_opponent(&u).unwrap_or(false)),
+        has_games: !db.is_empty().unwrap_or(true),
     };
-    Html(template.render().unwrap())
+    render(&headers, template)
+}
+
+/// The user's patterns as a CSV download, for spreadsheets
+pub async fn patterns_csv(State(state): State<Arc<AppState>>) -> Response {
+    let mut csv = Vec::new();
+    if let Err(e) = state.db.lock().unwrap().export_patterns_csv(&mut csv) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"patterns.csv\""),
+        ],
+        csv,
+    )
+        .into_response()
+}
+
+#[derive(Template, serde::Serialize)]
+#[template(path = "trend.html")]
+pub struct TrendTemplate {
+    pub title: String,
+    pub username: Option<String>,
+    pub perf_options: Vec<PerfOption>,
+    pub days: u32,
+    pub buckets: Vec<TrendRow>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PerfOption {
+    pub value: &'static str,
+    pub selected: bool,
+}
+
+const PERF_TYPES: [&str; 6] = ["all", "bullet", "blitz", "rapid", "classical", "correspondence"];
+
+/// The speed a stats page is filtered to; `None` for "all", nothing chosen
+/// or anything that isn't one of `PERF_TYPES`
+fn perf_filter(perf: Option<String>) -> Option<String> {
+    perf.filter(|p| p != "all" && PERF_TYPES.contains(&p.as_str()))
+}
+
+fn perf_options(perf: Option<&str>) -> Vec<PerfOption> {
+    PERF_TYPES.iter().map(|&value| PerfOption {
+        value,
+        selected: value == perf.unwrap_or("all"),
+    }).collect()
+}
+
+#[derive(serde::Serialize)]
+pub struct TrendRow {
+    pub period: String,
+    pub avg_acpl: String,
+    pub games: u32,
+    /// Change from the previous bucket; negative means fewer centipawns lost
+    pub change: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TrendQuery {
+    pub perf: Option<String>,
+    pub days: Option<u32>,
+}
+
+pub async fn stats_trend(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TrendQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let username = state.username.lock().unwrap().clone();
+    let perf = perf_filter(query.perf);
+    let days = query.days.unwrap_or(30).max(1);
+
+    let buckets = match &username {
+        Some(u) => {
+            let db = state.db.lock().unwrap();
+            db.acpl_trend(u, perf.as_deref(), days).unwrap_or_default()
+        }
+        None => Vec::new(),
+    };
+
+    let mut previous: Option<f64> = None;
+    let buckets = buckets.iter().map(|b| {
+        let change = previous.map(|p| format!("{:+.1}", b.avg_acpl - p));
+        previous = Some(b.avg_acpl);
+        TrendRow {
+            period: chrono::DateTime::from_timestamp(b.period_start as i64, 0)
+                .map(|d| d.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            avg_acpl: format!("{:.1}", b.avg_acpl),
+            games: b.games,
+            change,
+        }
+    }).collect();
+
+    let template = TrendTemplate {
+        title: "ACPL Trend".to_string(),
+        username,
+        perf_options: perf_options(perf.as_deref()),
+        days,
+        buckets,
+    };
+    render(&headers, template)
+}
+
+#[derive(Template, serde::Serialize)]
+#[template(path = "improvement.html")]
+pub struct ImprovementTemplate {
+    pub title: String,
+    pub username: Option<String>,
+    pub days: u32,
+    /// Openings played in both periods, most improved first
+    pub openings: Vec<ImprovementRow>,
+    pub most_improved: Option<ImprovementRow>,
+    pub biggest_regression: Option<ImprovementRow>,
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ImprovementRow {
+    pub eco: String,
+    pub name: String,
+    pub earlier_acpl: String,
+    pub earlier_games: u32,
+    pub later_acpl: String,
+    pub later_games: u32,
+    /// ACPL no longer lost, signed; positive is better
+    pub improvement: String,
+}
+
+impl From<&OpeningChange> for ImprovementRow {
+    fn from(change: &OpeningChange) -> Self {
+        Self {
+            eco: change.eco.clone(),
+            name: change.name.clone().unwrap_or_else(|| change.eco.clone()),
+            earlier_acpl: format!("{:.1}", change.earlier_acpl),
+            earlier_games: change.earlier_games,
+            later_acpl: format!("{:.1}", change.later_acpl),
+            later_games: change.later_games,
+            improvement: format!("{:+.1}", change.improvement()),
+        }
+    }
+}
+
+/// The two back-to-back periods `/stats/improvement` compares: the last
+/// `days` days up to `now`, and the `days` before them
+fn improvement_periods(now: u64, days: u32) -> ((u64, u64), (u64, u64)) {
+    let span = days as u64 * 86_400;
+    let split = now.saturating_sub(span);
+    ((split.saturating_sub(span), split), (split, i64::MAX as u64))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImprovementQuery {
+    pub days: Option<u32>,
+}
+
+pub async fn stats_improvement(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ImprovementQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let username = state.username.lock().unwrap().clone();
+    let days = query.days.unwrap_or(30).max(1);
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let (earlier, later) = improvement_periods(now, days);
+
+    let changes = match &username {
+        Some(u) => state.db.lock().unwrap().most_improved_opening(u, earlier, later).unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    let template = ImprovementTemplate {
+        title: "Opening Improvement".to_string(),
+        username,
+        days,
+        most_improved: changes.first().filter(|c| c.improvement() > 0.0).map(ImprovementRow::from),
+        biggest_regression: changes.last().filter(|c| c.improvement() < 0.0).map(ImprovementRow::from),
+        openings: changes.iter().map(ImprovementRow::from).collect(),
+    };
+    render(&headers, template)
+}
+
+#[derive(Template, serde::Serialize)]
+#[template(path = "opponents.html")]
+pub struct OpponentsTemplate {
+    pub title: String,
+    pub username: Option<String>,
+    pub opponents: Vec<OpponentRecord>,
+}
+
+pub async fn stats_opponents(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let username = state.username.lock().unwrap().clone();
+    let opponents = match &username {
+        Some(u) => state.db.lock().unwrap().head_to_head(u).unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    let template = OpponentsTemplate {
+        title: "Opponents".to_string(),
+        username,
+        opponents,
+    };
+    render(&headers, template)
 }
 
 pub async fn health() -> &'static str {
     "OK"
 }
 
+pub mod api;
 pub mod training;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::shared_engine::SharedEngine;
+    use crate::worker::AnalysisQueue;
+
+    #[test]
+    fn test_analysis_progress() {
+        let empty = AnalysisProgress::new(0, 0);
+        assert_eq!((empty.total, empty.percent, empty.remaining()), (0, 0, 0));
+
+        let progress = AnalysisProgress::new(120, 380);
+        assert_eq!((progress.total, progress.percent, progress.remaining()), (500, 24, 380));
+
+        // Only a finished collection shows a full bar
+        assert_eq!(AnalysisProgress::new(999, 1).percent, 99);
+    }
+
+    #[test]
+    fn test_analyze_query_range() {
+        let query = |from: &str, to: &str| AnalyzeQuery { from: Some(from.to_string()), to: Some(to.to_string()), sides: None };
+
+        assert_eq!(query("", "").range(), None);
+        // Saturday 2 March to Sunday 3 March 2024, Sunday included
+        assert_eq!(query("2024-03-02", "2024-03-03").range(), Some((1709337600, 1709510400)));
+        assert_eq!(query("2024-03-02", "").range(), Some((1709337600, i64::MAX as u64)));
+        assert_eq!(query("", "2024-03-03").range(), Some((0, 1709510400)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_job_runs_from_pending_to_done() {
+        use chess_analyzer_core::parser::pgn::parse_pgn_string;
+        use chess_analyzer_core::patterns::DetectorConfig;
+        use chess_analyzer_core::storage::{JOB_DONE, JOB_PENDING};
+        use crate::governor::Governor;
+        use crate::shared_engine::tests::mock_engine;
+        use crate::worker::spawn_worker;
+
+        let (script, _) = mock_engine("analysis-job");
+        let (analysis_queue, receiver) = AnalysisQueue::new();
+        let engine = SharedEngine::new(script.to_str().unwrap(), &[]);
+        let state = Arc::new(AppState {
+            analysis_queue,
+            ..AppState::for_test(Database::open_in_memory().unwrap(), Some("alice"), engine)
+        });
+        let game = parse_pgn_string("[White \"alice\"]\n[Black \"bob\"]\n\n1. e4 e5 2. Nf3 Nc6 *\n").unwrap().remove(0);
+        let game_id = state.db.lock().unwrap().insert_pgn_game(&game).unwrap().unwrap();
+
+        let (status, Json(created)) = analyze_game_job(State(state.clone()), Path(game_id)).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let Json(job) = job_status(State(state.clone()), Path(created.job_id)).await.unwrap();
+        assert_eq!((job.status.as_str(), job.started_at), (JOB_PENDING, None));
+        // Queued once is enough
+        assert_eq!(analyze_game_job(State(state.clone()), Path(game_id)).await.unwrap_err(), StatusCode::CONFLICT);
+
+        let worker = spawn_worker(state.clone(), receiver, Governor::default(), DetectorConfig::default());
+        state.analysis_queue.close();
+        worker.await.unwrap();
+
+        let Json(job) = job_status(State(state.clone()), Path(created.job_id)).await.unwrap();
+        assert_eq!(job.status, JOB_DONE);
+        // The report polled is the one stored for the game
+        let stored = state.db.lock().unwrap().count_patterns().unwrap();
+        assert_eq!(job.report.unwrap().patterns.len() as u32, stored);
+        assert!(job.created_at <= job.started_at.unwrap() && job.started_at <= job.finished_at);
+
+        assert_eq!(job_status(State(state.clone()), Path(999)).await.unwrap_err(), StatusCode::NOT_FOUND);
+        assert_eq!(analyze_game_job(State(state), Path(999)).await.unwrap_err(), StatusCode::NOT_FOUND);
+        let _ = std::fs::remove_dir_all(script.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_opponent_choice_is_remembered() {
+        let state = Arc::new(AppState::for_test(Database::open_in_memory().unwrap(), Some("alice"), SharedEngine::new("stockfish", &[])));
+        let query = |sides: &str| AnalyzeQuery { from: None, to: None, sides: Some(sides.to_string()) };
+
+        assert!(!analyze_opponent(&state, "alice", None));
+        // A link's choice applies to that run only
+        let _ = analyze_games(State(state.clone()), Query(query("both"))).await;
+        assert!(analyze_opponent(&state, "alice", Some("both")));
+        assert!(!analyze_opponent(&state, "alice", None));
+
+        // The form's is kept, and a plain "Analyze Games" link uses it
+        let _ = analyze_games_form(State(state.clone()), Form(query("both"))).await;
+        assert!(analyze_opponent(&state, "alice", None));
+        assert!(!analyze_opponent(&state, "alice", Some("player")));
+        let _ = analyze_games_form(State(state.clone()), Form(query("player"))).await;
+        assert!(!analyze_opponent(&state, "alice", None));
+    }
+
+    #[tokio::test]
+    async fn test_pages_serve_json_on_request() {
+        let state = Arc::new(AppState::for_test(Database::open_in_memory().unwrap(), Some("alice"), SharedEngine::new("stockfish", &[])));
+        let games_page = |accept: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, accept.parse().unwrap());
+            let state = state.clone();
+            async move {
+                let response = games_list(State(state), headers).await;
+                let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (content_type, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (content_type, body) = games_page("application/json").await;
+        assert_eq!(content_type, "application/json");
+        assert_eq!(body, r#"{"title":"Your Games","games":[],"username":"alice"}"#);
+
+        // What a browser sends for a page load
+        let (content_type, body) = games_page("text/html,application/xhtml+xml,*/*;q=0.8").await;
+        assert!(content_type.starts_with("text/html"));
+        assert!(body.contains("Your Games"));
+
+        let (content_type, _) = games_page("text/plain, Application/JSON; q=0.9").await;
+        assert_eq!(content_type, "application/json");
+
+        let pgn = "[White \"alice\"]\n[Black \"bob\"]\n[Result \"*\"]\n\n1. e4 e5 *";
+        let game = chess_analyzer_core::parser::pgn::parse_pgn_string(pgn).unwrap().remove(0);
+        {
+            let db = state.db.lock().unwrap();
+            let id = db.insert_pgn_game(&game).unwrap().unwrap();
+            db.set_game_endgame_conversion(id, 0.75).unwrap();
+        }
+        let (_, body) = games_page("application/json").await;
+        assert!(body.contains(r#""endgame_conversion":"75%""#), "{}", body);
+        let (_, body) = games_page("text/html").await;
+        assert!(body.contains("endgame 75% converted"));
+    }
+
+    #[tokio::test]
+    async fn test_patterns_csv_download() {
+        let state = Arc::new(AppState::for_test(Database::open_in_memory().unwrap(), None, SharedEngine::new("stockfish", &[])));
+
+        let response = patterns_csv(State(state)).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.starts_with(b"game_id,move_number,"));
+    }
+
+    #[test]
+    fn test_perf_filter_only_takes_known_speeds() {
+        assert_eq!(perf_filter(Some("blitz".to_string())).as_deref(), Some("blitz"));
+        assert_eq!(perf_filter(Some("all".to_string())), None);
+        assert_eq!(perf_filter(Some(String::new())), None);
+        assert_eq!(perf_filter(Some("blitz\"><script>".to_string())), None);
+        assert_eq!(perf_filter(None), None);
+    }
+
+    #[tokio::test]
+    async fn test_patterns_page_far_past_the_end() {
+        let state = Arc::new(AppState::for_test(Database::open_in_memory().unwrap(), None, SharedEngine::new("stockfish", &[])));
+        let query = PatternsQuery { page: Some(u32::MAX), perf: None, opponent: None };
+
+        let response = patterns_list(State(state), Query(query), HeaderMap::new()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_index_onboards_a_fresh_database() {
+        let state = Arc::new(AppState::for_test(Database::open_in_memory().unwrap(), None, SharedEngine::new("stockfish", &[])));
+        let page = |state: Arc<AppState>| async move {
+            let response = index(State(state), HeaderMap::new()).await;
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let body = page(state.clone()).await;
+        assert!(body.contains("Sync your Lichess account to begin"));
+        assert!(!body.contains("Games Synced"));
+
+        let pgn = "[White \"Alice\"]\n[Black \"Bob\"]\n[Result \"*\"]\n\n1. e4 e5 *";
+        let game = chess_analyzer_core::parser::pgn::parse_pgn_string(pgn).unwrap().remove(0);
+        state.db.lock().unwrap().insert_pgn_game(&game).unwrap();
+
+        let body = page(state).await;
+        assert!(body.contains("Games Synced"));
+        assert!(!body.contains("Sync your Lichess account to begin"));
+    }
+
+    #[test]
+    fn test_improvement_periods_are_back_to_back() {
+        let day = 86_400;
+        let (earlier, later) = improvement_periods(100 * day, 30);
+        assert_eq!(earlier, (40 * day, 70 * day));
+        assert_eq!(later.0, 70 * day);
+        assert!(later.1 > 100 * day);
+
+        // Early in the epoch the earlier period just starts at 0
+        assert_eq!(improvement_periods(10 * day, 30).0, (0, 0));
+    }
+}
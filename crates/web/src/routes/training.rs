@@ -1,41 +1,66 @@
 use askama::Template;
 use axum::{
-    extract::{Query, State},
-    response::{Html, IntoResponse},
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
     Json,
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use shakmaty::{san::SanPlus, uci::UciMove, Color};
 use std::sync::Arc;
 
-use chess_analyzer_core::storage::{TrainingStats, AllTrainingStats};
+use chess_analyzer_core::storage::{StoredPattern, TrainingProgress, TrainingSessionRow, TrainingStats, AllTrainingStats};
+use chess_analyzer_core::patterns::PatternType;
+use chess_analyzer_core::training::{OpeningLine, OpeningTrainer};
+use chess_analyzer_core::util::{color_key, parse_color, parse_fen_lenient};
+use super::render;
 use crate::AppState;
 
 // ============================================================================
 // TEMPLATES
 // ============================================================================
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "training/coordinates.html")]
 pub struct CoordinatesTemplate {
     pub title: String,
+    /// "white" or "black"; the side at the bottom of the board
+    pub perspective: &'static str,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "training/visualization.html")]
 pub struct VisualizationTemplate {
     pub title: String,
     pub difficulty: String,
+    pub perspective: &'static str,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "training/openings.html")]
 pub struct OpeningsTemplate {
     pub title: String,
     pub lines: Vec<OpeningLineView>,
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
+#[template(path = "training/review.html")]
+pub struct OpeningReviewTemplate {
+    pub title: String,
+    /// Lines due now, most overdue first
+    pub lines: Vec<ReviewLineView>,
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "training/puzzles.html")]
+pub struct OpeningPuzzlesTemplate {
+    pub title: String,
+    pub eco: String,
+    /// Worst blunder first
+    pub puzzles: Vec<PuzzleView>,
+}
+
+#[derive(Template, Serialize)]
 #[template(path = "training/index.html")]
 pub struct TrainingHubTemplate {
     pub streak: u32,
@@ -46,15 +71,73 @@ pub struct TrainingHubTemplate {
     pub viz_progress: u32,
     pub opening_progress: u32,
     pub opening_lines: u32,
+    /// Puzzle themes the user has tried, weakest first
+    pub solve_rates: Vec<SolveRateView>,
+}
+
+#[derive(Serialize)]
+pub struct SolveRateView {
+    pub name: String,
+    pub attempts: u32,
+    pub rate: u32,
 }
 
+#[derive(Serialize)]
 pub struct OpeningLineView {
-    pub idx: usize,
+    /// SAN, space-joined; with the colour, what a drill is recorded under
+    pub moves: String,
     pub name: String,
     pub eco: String,
     pub color: String,
     pub accuracy: f32,
     pub times_drilled: u32,
+    /// Blunders the user has made in games of this opening
+    pub blunders: usize,
+}
+
+#[derive(Serialize)]
+pub struct ReviewLineView {
+    /// See `OpeningLineView::moves`
+    pub moves: String,
+    pub name: String,
+    pub eco: String,
+    pub color: String,
+    pub accuracy: f32,
+    pub times_drilled: u32,
+    /// Whole days past due; `None` for a line never drilled
+    pub overdue_days: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct PuzzleView {
+    /// The pattern the puzzle came from; what an attempt is recorded under
+    pub pattern_id: i64,
+    pub move_label: String,
+    /// The position before the blunder, the user to move
+    pub fen: String,
+    /// SAN of the move the user played
+    pub played: String,
+    pub best_uci: String,
+    pub best_san: String,
+    pub cp_loss: i32,
+}
+
+impl PuzzleView {
+    /// `None` for a pattern stored before its best move was kept
+    fn from_pattern(p: &StoredPattern) -> Option<Self> {
+        let best_uci = p.best_move.clone()?;
+        let position = parse_fen_lenient(&p.position_fen).ok()?;
+        let mv = best_uci.parse::<UciMove>().ok()?.to_move(&position).ok()?;
+        Some(PuzzleView {
+            pattern_id: p.id,
+            move_label: p.move_label(),
+            fen: p.position_fen.clone(),
+            played: p.player_move.clone().unwrap_or_default(),
+            best_san: SanPlus::from_move(position, mv).to_string(),
+            best_uci,
+            cp_loss: p.centipawn_loss.unwrap_or(0),
+        })
+    }
 }
 
 // ============================================================================
@@ -64,6 +147,30 @@ pub struct OpeningLineView {
 #[derive(Deserialize)]
 pub struct DifficultyQuery {
     pub difficulty: Option<String>,
+    pub perspective: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PerspectiveQuery {
+    pub perspective: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PuzzleQuery {
+    pub eco: String,
+}
+
+/// An explicit `?perspective=` wins for this view only; otherwise the
+/// preference saved by `save_perspective`, otherwise White
+fn board_perspective(state: &AppState, requested: Option<&str>) -> Color {
+    if let Some(perspective) = requested.and_then(parse_color) {
+        return perspective;
+    }
+
+    let username = state.username.lock().unwrap().clone();
+    username
+        .and_then(|user| state.db.lock().unwrap().get_board_perspective(&user).ok().flatten())
+        .unwrap_or(Color::White)
 }
 
 // ============================================================================
@@ -72,7 +179,8 @@ pub struct DifficultyQuery {
 
 pub async fn training_hub(
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
+    headers: HeaderMap,
+) -> Response {
     let db = state.db.lock().unwrap();
     
     let default_stats = TrainingStats {
@@ -95,6 +203,12 @@ pub async fn training_hub(
         max_streak: 0,
     });
 
+    let solve_rates = db.puzzle_solve_rates().unwrap_or_default().into_iter().map(|r| SolveRateView {
+        name: PatternType::parse(&r.pattern_type).map_or(r.pattern_type.clone(), |t| t.display_name().to_string()),
+        attempts: r.attempts,
+        rate: r.rate.round() as u32,
+    }).collect();
+
     let template = TrainingHubTemplate {
         streak: stats.max_streak,
         today_drills: stats.today_total,
@@ -104,67 +218,229 @@ pub async fn training_hub(
         viz_progress: stats.visualization.accuracy(),
         opening_progress: stats.openings.accuracy(),
         opening_lines: 0,
+        solve_rates,
     };
-    Html(template.render().unwrap())
+    render(&headers, template)
 }
 
-pub async fn coordinates_drill() -> impl IntoResponse {
+pub async fn coordinates_drill(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PerspectiveQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let perspective = board_perspective(&state, params.perspective.as_deref());
+
     let template = CoordinatesTemplate {
         title: "Coordinate Training".to_string(),
+        perspective: color_key(perspective),
     };
-    Html(template.render().unwrap())
+    render(&headers, template)
 }
 
 pub async fn visualization_drill(
+    State(state): State<Arc<AppState>>,
     Query(params): Query<DifficultyQuery>,
-) -> impl IntoResponse {
+    headers: HeaderMap,
+) -> Response {
     let difficulty = params.difficulty.unwrap_or_else(|| "beginner".to_string());
+    let perspective = board_perspective(&state, params.perspective.as_deref());
 
     let template = VisualizationTemplate {
         title: "Board Visualization".to_string(),
         difficulty,
+        perspective: color_key(perspective),
     };
-    Html(template.render().unwrap())
+    render(&headers, template)
+}
+
+/// The imported repertoire if there is one, otherwise lines extracted
+/// from the user's games as they are now; with the stats of any line that
+/// has been drilled
+fn current_repertoire(state: &AppState) -> Vec<OpeningLine> {
+    let mut lines = state.db.lock().unwrap().get_opening_lines().unwrap_or_default();
+    if lines.is_empty() {
+        if let Some(user) = state.username.lock().unwrap().as_deref() {
+            let games = state.db.lock().unwrap().get_all_games().unwrap_or_default();
+            lines = OpeningTrainer::extract_from_games(&games, user, 3);
+        }
+    }
+    if let Err(e) = state.db.lock().unwrap().load_opening_drills(&mut lines) {
+        eprintln!("Failed to load opening drills: {}", e);
+    }
+    lines
 }
 
 pub async fn openings_trainer(
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
+    headers: HeaderMap,
+) -> Response {
     let username = state.username.lock().unwrap().clone();
+    let repertoire = current_repertoire(&state);
 
-    let lines: Vec<OpeningLineView> = if let Some(ref user) = username {
-        let db = state.db.lock().unwrap();
-        let games = db.get_all_games().unwrap_or_default();
-
-        let extracted = chess_analyzer_core::training::openings::OpeningTrainer::extract_from_games(
-            &games, user, 3
-        );
+    let lines: Vec<OpeningLineView> = {
+        let user = username.as_deref().unwrap_or("");
+        let blunders = state.db.lock().unwrap().blunder_counts_by_opening(user).unwrap_or_default();
 
-        extracted.iter().enumerate().map(|(idx, line)| {
+        repertoire.iter().map(|line| {
             OpeningLineView {
-                idx,
+                moves: line.moves.join(" "),
                 name: line.name.clone(),
                 eco: line.eco.clone(),
                 color: line.color_name().to_string(),
                 accuracy: line.accuracy(),
                 times_drilled: line.times_drilled,
+                blunders: blunders.get(&line.eco).copied().unwrap_or(0),
             }
         }).collect()
-    } else {
-        Vec::new()
     };
 
     let template = OpeningsTemplate {
         title: "Opening Trainer".to_string(),
         lines,
     };
-    Html(template.render().unwrap())
+    render(&headers, template)
+}
+
+/// The repertoire lines due for review, most overdue first, for drilling
+pub async fn openings_review(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let mut trainer = OpeningTrainer::new();
+    for line in current_repertoire(&state) {
+        trainer.add_line(line);
+    }
+
+    let lines = trainer.lines_to_review(now).into_iter().map(|(_, line)| ReviewLineView {
+        moves: line.moves.join(" "),
+        name: line.name.clone(),
+        eco: line.eco.clone(),
+        color: line.color_name().to_string(),
+        accuracy: line.accuracy(),
+        times_drilled: line.times_drilled,
+        overdue_days: line.due_at().map(|due| now.saturating_sub(due) / 86400),
+    }).collect();
+
+    let template = OpeningReviewTemplate {
+        title: "Opening Review".to_string(),
+        lines,
+    };
+    render(&headers, template)
+}
+
+/// The blunders the user made in one opening, set as find-the-move
+/// puzzles, worst first
+pub async fn opening_puzzles(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PuzzleQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let username = state.username.lock().unwrap().clone();
+    let patterns = state.db.lock().unwrap()
+        .puzzles_for_opening(&params.eco, username.as_deref().unwrap_or(""))
+        .unwrap_or_default();
+
+    let template = OpeningPuzzlesTemplate {
+        title: format!("{} Puzzles", params.eco),
+        eco: params.eco,
+        puzzles: patterns.iter().filter_map(PuzzleView::from_pattern).collect(),
+    };
+    render(&headers, template)
 }
 
 // ============================================================================
 // API
 // ============================================================================
 
+#[derive(Deserialize)]
+pub struct OpeningDrillRequest {
+    /// The line's moves in SAN, space-joined
+    pub moves: String,
+    /// "White" or "Black", the side the line is played for
+    pub color: String,
+    pub correct: bool,
+}
+
+/// Counts a drill of one repertoire line and saves its stats, so the
+/// line's next review date survives a restart; 404 for an unknown line
+pub async fn save_opening_drill(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<OpeningDrillRequest>,
+) -> StatusCode {
+    let color = parse_color(&req.color);
+    let mut lines = current_repertoire(&state);
+    let line = match lines.iter_mut().find(|l| Some(l.for_color) == color && l.moves.join(" ") == req.moves) {
+        Some(l) => l,
+        None => return StatusCode::NOT_FOUND,
+    };
+    line.record_drill(req.correct, chrono::Utc::now().timestamp().max(0) as u64);
+
+    match state.db.lock().unwrap().save_opening_drill(line) {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+pub async fn export_repertoire(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut trainer = OpeningTrainer::new();
+    for line in current_repertoire(&state) {
+        trainer.add_line(line);
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/json"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"repertoire.json\""),
+        ],
+        trainer.export_json(),
+    )
+}
+
+pub async fn import_repertoire(
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> (StatusCode, String) {
+    match OpeningTrainer::import_json(&body) {
+        Ok(trainer) => {
+            let count = trainer.lines().len();
+            match state.db.lock().unwrap().save_opening_lines(trainer.lines()) {
+                Ok(_) => (StatusCode::OK, format!("Imported {} lines", count)),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PerspectiveRequest {
+    pub perspective: String,
+}
+
+/// Saves the board perspective chosen on a training page
+pub async fn save_perspective(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<PerspectiveRequest>,
+) -> StatusCode {
+    let perspective = match parse_color(&req.perspective) {
+        Some(p) => p,
+        None => return StatusCode::BAD_REQUEST,
+    };
+
+    let username = state.username.lock().unwrap().clone();
+    let user = match username {
+        Some(u) => u,
+        // Nothing to attach it to until a user has synced
+        None => return StatusCode::NO_CONTENT,
+    };
+
+    match state.db.lock().unwrap().set_board_perspective(&user, perspective) {
+        Ok(_) => StatusCode::OK,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 #[derive(Deserialize)]
 pub struct SaveSessionRequest {
     pub training_type: String,
@@ -191,3 +467,347 @@ pub async fn save_session(
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
+
+#[derive(Deserialize)]
+pub struct PuzzleAttemptRequest {
+    pub pattern_id: i64,
+    pub correct: bool,
+}
+
+/// Logs a try at a puzzle; 404 when the pattern it came from is gone
+pub async fn save_puzzle_attempt(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<PuzzleAttemptRequest>,
+) -> StatusCode {
+    match state.db.lock().unwrap().record_puzzle_attempt(req.pattern_id, req.correct) {
+        Ok(Some(_)) => StatusCode::OK,
+        Ok(None) => StatusCode::NOT_FOUND,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Sessions returned by `/training/history` when no limit is given
+const HISTORY_DEFAULT_LIMIT: u32 = 50;
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub training_type: Option<String>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    #[serde(flatten)]
+    pub session: TrainingSessionRow,
+    pub accuracy: u32,
+}
+
+/// Log of past training sessions, newest first
+pub async fn session_history(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HistoryQuery>,
+) -> Result<Json<Vec<HistoryEntry>>, StatusCode> {
+    let limit = params.limit.unwrap_or(HISTORY_DEFAULT_LIMIT);
+    let sessions = state.db.lock().unwrap()
+        .recent_training_sessions(params.training_type.as_deref(), limit)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(sessions
+        .into_iter()
+        .map(|session| HistoryEntry { accuracy: session.accuracy(), session })
+        .collect()))
+}
+
+/// Days per bucket on `/training/:type/progress` when none is given
+const PROGRESS_DEFAULT_BUCKET_DAYS: u32 = 7;
+
+#[derive(Deserialize)]
+pub struct ProgressQuery {
+    pub bucket: Option<u32>,
+}
+
+/// Accuracy and time per attempt of one training type, bucketed by week
+/// unless `?bucket=` says otherwise
+pub async fn training_progress(
+    State(state): State<Arc<AppState>>,
+    Path(training_type): Path<String>,
+    Query(params): Query<ProgressQuery>,
+) -> Result<Json<Vec<TrainingProgress>>, StatusCode> {
+    let bucket = params.bucket.unwrap_or(PROGRESS_DEFAULT_BUCKET_DAYS);
+    state.db.lock().unwrap()
+        .training_progress(&training_type, bucket)
+        .map(Json)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chess_analyzer_core::lichess::{LichessGame, Opening, Player, Players, User};
+    use chess_analyzer_core::patterns::{DetectedPattern, Severity};
+    use chess_analyzer_core::Database;
+    use crate::shared_engine::SharedEngine;
+
+    fn state(username: Option<&str>) -> Arc<AppState> {
+        Arc::new(AppState::for_test(Database::open_in_memory().unwrap(), username, SharedEngine::new("stockfish", &[])))
+    }
+
+    async fn coordinates_page(state: &Arc<AppState>, perspective: Option<&str>) -> String {
+        let query = PerspectiveQuery { perspective: perspective.map(String::from) };
+        let response = coordinates_drill(State(state.clone()), Query(query), HeaderMap::new()).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_coordinates_route_passes_perspective_through() {
+        let state = state(Some("alice"));
+
+        let page = coordinates_page(&state, None).await;
+        assert!(page.contains(r#"data-perspective-default="white""#));
+
+        let page = coordinates_page(&state, Some("black")).await;
+        assert!(page.contains(r#"data-perspective-default="black""#));
+
+        // A link's choice isn't saved; only the POST is
+        let page = coordinates_page(&state, None).await;
+        assert!(page.contains(r#"data-perspective-default="white""#));
+        assert_eq!(state.db.lock().unwrap().get_board_perspective("alice").unwrap(), None);
+
+        let save = PerspectiveRequest { perspective: "black".to_string() };
+        assert_eq!(save_perspective(State(state.clone()), Json(save)).await, StatusCode::OK);
+        let page = coordinates_page(&state, None).await;
+        assert!(page.contains(r#"data-perspective-default="black""#));
+        let page = coordinates_page(&state, Some("white")).await;
+        assert!(page.contains(r#"data-perspective-default="white""#));
+    }
+
+    #[tokio::test]
+    async fn test_review_shows_only_due_lines() {
+        let state = state(Some("alice"));
+        let now = chrono::Utc::now().timestamp() as u64;
+        let line = |name: &str, moves: &str, last_drilled: u64| OpeningLine {
+            eco: "C60".to_string(),
+            name: name.to_string(),
+            moves: moves.split_whitespace().map(String::from).collect(),
+            for_color: Color::White,
+            times_drilled: 4,
+            times_correct: 2,
+            last_drilled: Some(last_drilled),
+        };
+        state.db.lock().unwrap().save_opening_lines(&[
+            line("Drilled Today", "e4 e5", now - 60),
+            line("Stale Line", "e4 c5", now - 10 * 86400),
+        ]).unwrap();
+
+        let review = |state: Arc<AppState>| async move {
+            let response = openings_review(State(state), HeaderMap::new()).await;
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+        let page = review(state.clone()).await;
+        assert!(page.contains("Stale Line"));
+        assert!(!page.contains("Drilled Today"));
+
+        // Drilling the stale line saves it as drilled, so it's off the list
+        let drill = |moves: &str, color: &str| OpeningDrillRequest { moves: moves.to_string(), color: color.to_string(), correct: true };
+        assert_eq!(save_opening_drill(State(state.clone()), Json(drill("e4 c5", "White"))).await, StatusCode::OK);
+        assert!(!review(state.clone()).await.contains("Stale Line"));
+        let lines = current_repertoire(&state);
+        assert_eq!((lines[1].times_drilled, lines[1].times_correct), (5, 3));
+        assert!(lines[1].last_drilled.unwrap() >= now);
+        // The imported stats are left as they came
+        assert_eq!(state.db.lock().unwrap().get_opening_lines().unwrap()[1].times_drilled, 4);
+
+        assert_eq!(save_opening_drill(State(state.clone()), Json(drill("e4 c5", "Black"))).await, StatusCode::NOT_FOUND);
+        assert_eq!(save_opening_drill(State(state), Json(drill("d4", "White"))).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_imported_repertoire_is_saved() {
+        let state = state(None);
+        let mut trainer = OpeningTrainer::new();
+        trainer.add_line(OpeningLine {
+            eco: "B20".to_string(),
+            name: "Sicilian Defence".to_string(),
+            moves: vec!["e4".to_string(), "c5".to_string()],
+            for_color: Color::Black,
+            times_drilled: 0,
+            times_correct: 0,
+            last_drilled: None,
+        });
+
+        let (status, message) = import_repertoire(State(state.clone()), trainer.export_json()).await;
+        assert_eq!((status, message.as_str()), (StatusCode::OK, "Imported 1 lines"));
+        let saved = state.db.lock().unwrap().get_opening_lines().unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].name, "Sicilian Defence");
+
+        let (status, _) = import_repertoire(State(state), "not json".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_opening_puzzles_page() {
+        let state = state(Some("alice"));
+        {
+            let db = state.db.lock().unwrap();
+            let player = |name: &str| Player {
+                user: Some(User { name: name.to_string(), id: name.to_lowercase() }),
+                rating: Some(1500),
+                rating_diff: None,
+            };
+            let game = LichessGame {
+                id: "g1".to_string(),
+                rated: true,
+                variant: "standard".to_string(),
+                speed: "blitz".to_string(),
+                perf: "blitz".to_string(),
+                created_at: 0,
+                last_move_at: 0,
+                status: "mate".to_string(),
+                players: Players { white: player("Alice"), black: player("Bob") },
+                winner: None,
+                moves: Some("e4 e5".to_string()),
+                pgn: None,
+                opening: Some(Opening { eco: "C20".to_string(), name: "King's Pawn Game".to_string(), ply: 2 }),
+                clock: None,
+                clocks: None,
+                analysis: None,
+            };
+            let game_id = db.insert_game(&game).unwrap();
+            db.insert_pattern(game_id, &DetectedPattern {
+                move_number: 1,
+                ply: 0,
+                pattern_type: PatternType::HangingPiece,
+                severity: Severity::Blunder,
+                cp_loss: 300,
+                player_move: "e4".to_string(),
+                best_move: "g1f3".to_string(),
+                fen_before: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+                fen_after: String::new(),
+                description: "test".to_string(),
+                quiet: false,
+            }).unwrap();
+        }
+
+        let page = |eco: &str| {
+            let state = state.clone();
+            let query = PuzzleQuery { eco: eco.to_string() };
+            async move {
+                let response = opening_puzzles(State(state), Query(query), HeaderMap::new()).await;
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+        let c20 = page("C20").await;
+        assert!(c20.contains(r#"data-best-uci="g1f3" data-best-san="Nf3""#), "{}", c20);
+        // Answers are logged against the pattern, like any other attempt
+        assert!(c20.contains(r#"data-pattern-id="1""#));
+        assert!(c20.contains("/api/training/puzzles/attempt"));
+        assert!(!page("B20").await.contains("data-best-uci"));
+
+        state.db.lock().unwrap().save_opening_lines(&[OpeningLine {
+            eco: "C20".to_string(),
+            name: "King's Pawn Game".to_string(),
+            moves: vec!["e4".to_string(), "e5".to_string()],
+            for_color: Color::White,
+            times_drilled: 0,
+            times_correct: 0,
+            last_drilled: None,
+        }]).unwrap();
+        let response = openings_trainer(State(state), HeaderMap::new()).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8(body.to_vec()).unwrap().contains(r#"href="/training/puzzles?eco=C20""#));
+    }
+
+    #[tokio::test]
+    async fn test_session_history_filters_by_type() {
+        let state = state(None);
+        {
+            let db = state.db.lock().unwrap();
+            db.save_training_session("coordinates", 20, 15, 60_000, Some(900)).unwrap();
+            db.save_training_session("visualization", 10, 10, 45_000, None).unwrap();
+        }
+
+        let query = HistoryQuery { training_type: Some("coordinates".to_string()), limit: None };
+        let Json(history) = session_history(State(state.clone()), Query(query)).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].accuracy, 75);
+
+        // The row's columns sit next to the accuracy, not nested under it
+        let response = Json(history).into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json = String::from_utf8(body.to_vec()).unwrap();
+        assert!(json.contains(r#""training_type":"coordinates""#));
+        assert!(json.contains(r#""best_time_ms":900"#));
+        assert!(json.contains(r#""accuracy":75"#));
+
+        let query = HistoryQuery { training_type: None, limit: Some(5) };
+        let Json(history) = session_history(State(state), Query(query)).await.unwrap();
+        assert_eq!(history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_training_progress_route() {
+        let state = state(None);
+        {
+            let db = state.db.lock().unwrap();
+            db.save_training_session("coordinates", 20, 15, 60_000, Some(900)).unwrap();
+            db.save_training_session("coordinates", 20, 17, 40_000, Some(800)).unwrap();
+        }
+
+        let query = ProgressQuery { bucket: None };
+        let Json(progress) = training_progress(State(state.clone()), Path("coordinates".to_string()), Query(query))
+            .await
+            .unwrap();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].sessions, 2);
+        assert_eq!(progress[0].accuracy, 80.0);
+        assert_eq!(progress[0].avg_time_ms, 2_500.0);
+
+        let query = ProgressQuery { bucket: Some(1) };
+        let Json(progress) = training_progress(State(state), Path("visualization".to_string()), Query(query))
+            .await
+            .unwrap();
+        assert!(progress.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_training_hub_shows_solve_rates() {
+        let state = state(None);
+        let pattern_id = {
+            let db = state.db.lock().unwrap();
+            let pgn = "[White \"Alice\"]\n[Black \"Bob\"]\n[Result \"*\"]\n\n1. e4 e5 *";
+            let game = chess_analyzer_core::parser::pgn::parse_pgn_string(pgn).unwrap().remove(0);
+            let game_id = db.insert_pgn_game(&game).unwrap().unwrap();
+            db.insert_pattern(game_id, &DetectedPattern {
+                move_number: 1,
+                ply: 0,
+                pattern_type: PatternType::MissedFork,
+                severity: Severity::Blunder,
+                cp_loss: 400,
+                player_move: "e4".to_string(),
+                best_move: "d2d4".to_string(),
+                fen_before: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+                fen_after: String::new(),
+                description: "test".to_string(),
+                quiet: false,
+            }).unwrap()
+        };
+
+        let attempt = |correct| PuzzleAttemptRequest { pattern_id, correct };
+        assert_eq!(save_puzzle_attempt(State(state.clone()), Json(attempt(true))).await, StatusCode::OK);
+        assert_eq!(save_puzzle_attempt(State(state.clone()), Json(attempt(false))).await, StatusCode::OK);
+        let missing = PuzzleAttemptRequest { pattern_id: pattern_id + 1, correct: true };
+        assert_eq!(save_puzzle_attempt(State(state.clone()), Json(missing)).await, StatusCode::NOT_FOUND);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, "application/json".parse().unwrap());
+        let response = training_hub(State(state), headers).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json = String::from_utf8(body.to_vec()).unwrap();
+        assert!(json.contains(r#""solve_rates":[{"name":"Missed Fork","attempts":2,"rate":50}]"#), "{}", json);
+    }
+}
@@ -0,0 +1,221 @@
+//! One engine process shared by the request handlers
+//!
+//! Starting Stockfish and completing the UCI handshake takes a few hundred
+//! milliseconds, too long to pay on every request. Handlers borrow this
+//! engine instead: it is started on first use, kept running between
+//! requests, given `ucinewgame` before each one and started again if it
+//! has died. Background analysis keeps its own engines; see `worker`.
+//!
+//! Configured through environment variables:
+//!
+//! - `ENGINE_WARMUP`: `1` starts the engine with the server rather than on
+//!   the first request (default off)
+//! - `ENGINE_IDLE_SECS`: shuts the engine down after this many seconds
+//!   without a request, to give its memory back (default unset, kept
+//!   running)
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use chess_analyzer_core::engine::{engine_args, engine_path, EngineError, StockfishEngine};
+use chess_analyzer_core::PatternDetector;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EngineSettings {
+    pub warm_up: bool,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl EngineSettings {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let idle_timeout = lookup("ENGINE_IDLE_SECS").and_then(|value| match value.trim().parse::<u64>() {
+            Ok(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+            Ok(_) => None,
+            Err(_) => {
+                eprintln!("Ignoring ENGINE_IDLE_SECS={:?}: not a number", value);
+                None
+            }
+        });
+        Self {
+            warm_up: lookup("ENGINE_WARMUP").is_some_and(|v| v.trim() == "1"),
+            idle_timeout,
+        }
+    }
+}
+
+struct Running {
+    engine: StockfishEngine,
+    last_used: Instant,
+}
+
+pub struct SharedEngine {
+    path: String,
+    args: Vec<String>,
+    running: Mutex<Option<Running>>,
+}
+
+impl SharedEngine {
+    /// An engine started from `path` with `args` when first needed
+    pub fn new(path: &str, args: &[String]) -> Self {
+        Self { path: path.to_string(), args: args.to_vec(), running: Mutex::new(None) }
+    }
+
+    /// The engine `ENGINE_PATH` and `ENGINE_ARGS` name
+    pub fn from_env() -> Self {
+        Self::new(&engine_path(), &engine_args())
+    }
+
+    /// Runs `f` on the shared engine, starting it first if it isn't running.
+    ///
+    /// Blocks while another request has the engine, so call it off the
+    /// async runtime. An I/O or protocol error from `f` means the engine
+    /// can't be trusted any more; it is dropped and the next call starts a
+    /// fresh one.
+    pub fn with<T>(&self, f: impl FnOnce(&mut StockfishEngine) -> Result<T, EngineError>) -> Result<T, EngineError> {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let reusable = match running.as_mut() {
+            Some(r) => r.engine.is_running() && r.engine.new_game().is_ok(),
+            None => false,
+        };
+        if !reusable {
+            // The old process, if any, is shut down before the new one starts
+            *running = None;
+            *running = Some(Running { engine: self.start()?, last_used: Instant::now() });
+        }
+
+        let current = running.as_mut().expect("engine was just started");
+        current.last_used = Instant::now();
+        let result = f(&mut current.engine);
+        if matches!(result, Err(EngineError::IoError(_) | EngineError::ProtocolError(_))) {
+            *running = None;
+        }
+        result
+    }
+
+    /// Starts the engine now rather than on the first request
+    pub fn warm_up(&self) -> Result<(), EngineError> {
+        self.with(|_| Ok(()))
+    }
+
+    /// Shuts the engine down if it has gone unused for `timeout`. Leaves it
+    /// alone while a request is using it.
+    pub fn close_if_idle(&self, timeout: Duration) -> bool {
+        let mut running = match self.running.try_lock() {
+            Ok(r) => r,
+            Err(_) => return false,
+        };
+        if running.as_ref().is_some_and(|r| r.last_used.elapsed() >= timeout) {
+            *running = None;
+            return true;
+        }
+        false
+    }
+
+    /// A detector with its own process of the same engine, for background
+    /// analysis that shouldn't hold up the handlers
+    pub fn start_detector(&self) -> chess_analyzer_core::Result<PatternDetector> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        PatternDetector::with_engine_args(&self.path, &args)
+    }
+
+    fn start(&self) -> Result<StockfishEngine, EngineError> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        StockfishEngine::new_with_args(&self.path, &args)
+    }
+}
+
+#[cfg(all(test, unix))]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    /// A shell script speaking just enough UCI, scoring every position
+    /// +31 and logging `start` each time it is launched, then every command
+    pub(crate) fn mock_engine(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("mock-engine.sh");
+        let log = dir.join("commands.log");
+        let body = format!(
+            r#"#!/bin/sh
+echo "start" >> "{log}"
+while read -r cmd; do
+    echo "$cmd" >> "{log}"
+    case "$cmd" in
+        uci) echo "id name MockFish"; echo "uciok" ;;
+        isready) echo "readyok" ;;
+        go*) echo "info depth 12 score cp 31 nodes 1000 time 5 pv e2e4 e7e5"; echo "bestmove e2e4" ;;
+        *die) exit 1 ;;
+        quit) exit 0 ;;
+    esac
+done
+"#,
+            log = log.display()
+        );
+        fs::write(&script, body).unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        (script, log)
+    }
+
+    fn starts(log: &PathBuf) -> usize {
+        fs::read_to_string(log).unwrap_or_default().lines().filter(|l| *l == "start").count()
+    }
+
+    #[test]
+    fn test_engine_is_reused_and_restarted() {
+        let (script, log) = mock_engine("shared-engine");
+        let shared = SharedEngine::new(script.to_str().unwrap(), &[]);
+        assert_eq!(starts(&log), 0);
+
+        for _ in 0..2 {
+            let analysis = shared.with(|e| {
+                e.set_position(None, None)?;
+                e.analyze(12)
+            }).unwrap();
+            assert_eq!(analysis.best_move, "e2e4");
+        }
+        assert_eq!(starts(&log), 1);
+        let commands = fs::read_to_string(&log).unwrap();
+        assert_eq!(commands.lines().filter(|l| *l == "ucinewgame").count(), 1);
+
+        // The engine dies mid-request; the next one gets a fresh process
+        shared.with(|e| {
+            e.set_position(None, Some(&["die".to_string()]))?;
+            Ok(())
+        }).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(shared.with(|e| e.analyze(12)).is_ok());
+        assert_eq!(starts(&log), 2);
+
+        assert!(!shared.close_if_idle(Duration::from_secs(60)));
+        assert!(shared.close_if_idle(Duration::ZERO));
+        shared.warm_up().unwrap();
+        assert_eq!(starts(&log), 3);
+
+        let _ = fs::remove_dir_all(script.parent().unwrap());
+    }
+
+    #[test]
+    fn test_settings_from_env() {
+        let settings = |vars: &[(&str, &str)]| {
+            let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            EngineSettings::from_lookup(|key| vars.get(key).cloned())
+        };
+
+        assert_eq!(settings(&[]), EngineSettings::default());
+        let configured = settings(&[("ENGINE_WARMUP", "1"), ("ENGINE_IDLE_SECS", "300")]);
+        assert!(configured.warm_up);
+        assert_eq!(configured.idle_timeout, Some(Duration::from_secs(300)));
+        assert_eq!(settings(&[("ENGINE_IDLE_SECS", "soon")]).idle_timeout, None);
+        assert_eq!(settings(&[("ENGINE_IDLE_SECS", "0")]).idle_timeout, None);
+    }
+}
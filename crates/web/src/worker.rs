@@ -0,0 +1,369 @@
+//! Background analysis worker
+//!
+//! `/analyze` only enqueues games. Worker threads drain the queue, each with
+//! a long-lived Stockfish instance, so requests return immediately and the
+//! engine handshake is paid once rather than per batch. The `Governor`
+//! decides how many workers run and how gently they use the CPU.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use chess_analyzer_core::engine::AbortFlag;
+use chess_analyzer_core::storage::StoredGame;
+use chess_analyzer_core::patterns::{DetectedPattern, GameReport};
+use chess_analyzer_core::patterns::{endgame_conversion, DetectorConfig, HeuristicDetector};
+use chess_analyzer_core::util::player_color;
+use chess_analyzer_core::{Error, LichessClient, PatternDetector, Result};
+use shakmaty::{fen::Fen, Chess, Color, EnPassantMode};
+
+use crate::governor::Governor;
+use crate::AppState;
+
+pub struct AnalysisJob {
+    pub game: StoredGame,
+    pub username: String,
+    /// Also find the opponent's mistakes, stored apart from the user's
+    pub analyze_opponent: bool,
+    /// The `analysis_jobs` row to keep up to date, for games queued through
+    /// `POST /api/games/:id/analyze`
+    pub job_id: Option<i64>,
+}
+
+pub struct AnalysisQueue {
+    sender: Mutex<Option<mpsc::UnboundedSender<AnalysisJob>>>,
+    /// Game ids queued or currently being analyzed
+    pending: Mutex<HashSet<i64>>,
+    /// Set by `cancel` until everything pending has been dropped
+    abort: AbortFlag,
+}
+
+impl AnalysisQueue {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<AnalysisJob>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = Self {
+            sender: Mutex::new(Some(sender)),
+            pending: Mutex::new(HashSet::new()),
+            abort: AbortFlag::new(),
+        };
+        (queue, receiver)
+    }
+
+    /// Queues a game for analysis. Returns false if it is already queued
+    /// or the queue has been closed.
+    pub fn enqueue(&self, job: AnalysisJob) -> bool {
+        let sender = self.sender.lock().unwrap();
+        let sender = match sender.as_ref() {
+            Some(s) => s,
+            None => return false,
+        };
+
+        let game_id = job.game.id;
+        if !self.pending.lock().unwrap().insert(game_id) {
+            return false;
+        }
+
+        if sender.send(job).is_err() {
+            self.pending.lock().unwrap().remove(&game_id);
+            return false;
+        }
+        true
+    }
+
+    /// Number of games waiting for or undergoing analysis
+    pub fn depth(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Stops accepting jobs. The worker finishes everything already queued
+    /// and then exits.
+    pub fn close(&self) {
+        self.sender.lock().unwrap().take();
+    }
+
+    /// Drops every pending game: searches in progress are stopped and
+    /// queued games are skipped, including any queued before the queue
+    /// has emptied. Returns how many games were pending.
+    pub fn cancel(&self) -> usize {
+        let pending = self.pending.lock().unwrap();
+        if !pending.is_empty() {
+            self.abort.abort();
+        }
+        pending.len()
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.abort.is_aborted()
+    }
+
+    fn finish(&self, game_id: i64) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.remove(&game_id);
+        if pending.is_empty() {
+            self.abort.reset();
+        }
+    }
+}
+
+/// Starts `governor.max_concurrency` workers, each analyzing with `config`.
+/// The returned handle completes once all of them have drained the queue
+/// and stopped.
+pub fn spawn_worker(
+    state: Arc<AppState>,
+    receiver: mpsc::UnboundedReceiver<AnalysisJob>,
+    governor: Governor,
+    config: DetectorConfig,
+) -> JoinHandle<()> {
+    let receiver = Arc::new(Mutex::new(receiver));
+    let governor = Arc::new(governor);
+
+    let workers: Vec<JoinHandle<()>> = (0..governor.max_concurrency)
+        .map(|n| {
+            let (state, receiver, governor) = (state.clone(), receiver.clone(), governor.clone());
+            tokio::task::spawn_blocking(move || run(n, state, receiver, governor, config))
+        })
+        .collect();
+
+    tokio::spawn(async move {
+        for worker in workers {
+            let _ = worker.await;
+        }
+    })
+}
+
+fn run(
+    n: usize,
+    state: Arc<AppState>,
+    receiver: Arc<Mutex<mpsc::UnboundedReceiver<AnalysisJob>>>,
+    governor: Arc<Governor>,
+    config: DetectorConfig,
+) {
+    let mut detector: Option<PatternDetector> = None;
+
+    loop {
+        // Hold the lock only while waiting, so idle workers queue up behind it
+        let job = receiver.lock().unwrap().blocking_recv();
+        let job = match job {
+            Some(j) => j,
+            None => break,
+        };
+        if !state.analysis_queue.is_cancelled() {
+            analyze_job(&state, &governor, config, &mut detector, &job);
+        } else {
+            record_job(&state, job.job_id, Err("cancelled".to_string()));
+        }
+        state.analysis_queue.finish(job.game.id);
+        governor.pause();
+    }
+
+    println!("Analysis worker {} stopped", n);
+}
+
+fn analyze_job(
+    state: &AppState,
+    governor: &Governor,
+    config: DetectorConfig,
+    detector: &mut Option<PatternDetector>,
+    job: &AnalysisJob,
+) {
+    let game = &job.game;
+    let moves: Vec<String> = game.moves.split_whitespace().map(String::from).collect();
+    if moves.is_empty() {
+        record_job(state, job.job_id, Err("the game has no moves".to_string()));
+        return;
+    }
+    if let Some(id) = job.job_id {
+        if let Err(e) = state.db.lock().unwrap().start_analysis_job(id) {
+            eprintln!("Failed to update analysis job {}: {}", id, e);
+        }
+    }
+    // Rows stored before the column existed get it filled in here, once
+    let moves_uci: Vec<String> = match &game.moves_uci {
+        Some(uci) => uci.split_whitespace().map(String::from).collect(),
+        None => state.db.lock().unwrap().moves_uci(game.id).ok().flatten().unwrap_or_default(),
+    };
+    let line = GameLine { san: &moves, uci: &moves_uci };
+
+    let mut report = game_report(state, governor, config, detector, job, &job.username, line);
+    if config.tablebase {
+        if let Ok(report) = &mut report {
+            add_endgame_conversion(report, job, &moves);
+        }
+    }
+
+    // A second pass from the other side; only its patterns are kept
+    let mut opponent_patterns = Vec::new();
+    if job.analyze_opponent && report.is_ok() {
+        let opponent = match player_color(&job.username, &game.white_username) {
+            Color::White => &game.black_username,
+            Color::Black => &game.white_username,
+        };
+        match game_report(state, governor, config, detector, job, opponent, line) {
+            Ok(r) => opponent_patterns = r.patterns,
+            Err(e) => report = Err(e),
+        }
+    }
+    store_report(state, job, report, &opponent_patterns);
+}
+
+/// A game's moves, in SAN for display and in UCI for the engine
+#[derive(Clone, Copy)]
+struct GameLine<'a> {
+    san: &'a [String],
+    uci: &'a [String],
+}
+
+/// `username`'s report: Lichess server analysis when it covers the game,
+/// else the engine, else engine-free heuristics. Only the engine looks
+/// for quiet blunders, using the game's clocks when they were stored.
+fn game_report(
+    state: &AppState,
+    governor: &Governor,
+    config: DetectorConfig,
+    detector: &mut Option<PatternDetector>,
+    job: &AnalysisJob,
+    username: &str,
+    line: GameLine,
+) -> Result<GameReport> {
+    let game = &job.game;
+    let moves = line.san;
+
+    if let Some(evals) = game.lichess_analysis.as_deref() {
+        if PatternDetector::evals_cover(moves, evals) {
+            return PatternDetector::lichess_evals_report(
+                moves, username, &game.white_username, evals, &config,
+            );
+        }
+    }
+
+    if detector.is_none() {
+        match state.engine.start_detector() {
+            Ok(mut d) => {
+                governor.apply(&d);
+                d.set_config(config);
+                d.set_abort_flag(state.analysis_queue.abort.clone());
+                *detector = Some(d);
+                // Games analyzed while there was no engine get another look
+                match state.db.lock().unwrap().requeue_heuristic_games() {
+                    Ok(0) => {}
+                    Ok(n) => println!("Re-queued {} game(s) analyzed without an engine", n),
+                    Err(e) => eprintln!("Failed to re-queue heuristic games: {}", e),
+                }
+            }
+            Err(e) => {
+                // Without an engine only obvious blunders can be found
+                eprintln!("Failed to create detector: {}; using heuristics", e);
+                return Ok(HeuristicDetector::new()
+                    .analyze_game_report(moves, username, &game.white_username));
+            }
+        }
+    }
+    let engine = detector.as_mut().unwrap();
+
+    println!("Analyzing game {} ({} vs {}, {} moves)...",
+        game.id, game.white_username, game.black_username, moves.len());
+
+    let mut report = engine.analyze_stored_game_report(moves, line.uci, username, &game.white_username);
+    match &mut report {
+        // Calm, level blunders with time on the clock are calculation errors
+        Ok(report) => {
+            if let Err(e) = engine.tag_quiet_blunders(&mut report.patterns, game.clocks.as_deref()) {
+                eprintln!("Failed to look for quiet blunders in game {}: {}", game.id, e);
+            }
+        }
+        // The engine may have died; start a fresh one for the next job
+        Err(e) if !matches!(e, Error::Aborted) => *detector = None,
+        Err(_) => {}
+    }
+    report
+}
+
+/// Scores the player's endgame against the Lichess tablebase. Worker
+/// threads are blocking tasks, so the async client is driven with
+/// `block_on`. Without the network the score is left out and the rest of
+/// the report is stored as usual.
+fn add_endgame_conversion(report: &mut GameReport, job: &AnalysisJob, moves: &[String]) {
+    let client = match LichessClient::new() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Failed to create client: {}; skipping endgame conversion", e);
+            return;
+        }
+    };
+    let runtime = tokio::runtime::Handle::current();
+    let color = player_color(&job.username, &job.game.white_username);
+
+    let probe = |position: &Chess| {
+        let fen = Fen::from_position(position, EnPassantMode::Legal).to_string();
+        runtime.block_on(client.tablebase(&fen)).map(|entry| entry.category.wdl())
+    };
+    match endgame_conversion(moves, color, probe) {
+        Ok(Some(score)) => {
+            println!("Endgame conversion in game {}: {:.0}%", job.game.id, score * 100.0);
+            report.endgame_conversion = Some(score);
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Tablebase unavailable for game {}: {}; skipping endgame conversion", job.game.id, e),
+    }
+}
+
+/// Stores the report's patterns and stats, then brings the job up to date:
+/// it's only done once the patterns are in
+fn store_report(state: &AppState, job: &AnalysisJob, report: Result<GameReport>, opponent_patterns: &[DetectedPattern]) {
+    let game_id = job.game.id;
+    let outcome = match report {
+        Ok(report) => {
+            println!("Found {} patterns in game {}", report.patterns.len() + opponent_patterns.len(), game_id);
+            let db = state.db.lock().unwrap();
+            if let Some(acpl) = report.acpl {
+                if let Err(e) = db.set_game_acpl(game_id, acpl) {
+                    eprintln!("Failed to store ACPL: {}", e);
+                }
+            }
+            if let Some(score) = report.endgame_conversion {
+                if let Err(e) = db.set_game_endgame_conversion(game_id, score) {
+                    eprintln!("Failed to store endgame conversion: {}", e);
+                }
+            }
+            // Nothing is stored on failure, so the game stays unanalyzed
+            let stored = if report.heuristic {
+                db.insert_heuristic_patterns(game_id, &report.patterns, opponent_patterns)
+            } else {
+                db.insert_patterns_with_opponent(game_id, &report.patterns, opponent_patterns)
+            };
+            match stored {
+                Ok(_) => Ok(report),
+                Err(e) => {
+                    eprintln!("Failed to store patterns for game {}: {}", game_id, e);
+                    Err(format!("failed to store patterns: {}", e))
+                }
+            }
+        }
+        Err(Error::Aborted) => {
+            println!("Analysis of game {} cancelled", game_id);
+            Err(Error::Aborted.to_string())
+        }
+        Err(e) => {
+            eprintln!("Failed to analyze game {}: {}", game_id, e);
+            Err(e.to_string())
+        }
+    };
+    record_job(state, job.job_id, outcome.as_ref().map_err(String::clone));
+}
+
+/// Brings a polled job's row up to date with how its analysis ended
+fn record_job(state: &AppState, job_id: Option<i64>, outcome: std::result::Result<&GameReport, String>) {
+    let id = match job_id {
+        Some(id) => id,
+        None => return,
+    };
+    let db = state.db.lock().unwrap();
+    let recorded = match outcome {
+        Ok(report) => db.finish_analysis_job(id, report),
+        Err(error) => db.fail_analysis_job(id, &error),
+    };
+    if let Err(e) = recorded {
+        eprintln!("Failed to update analysis job {}: {}", id, e);
+    }
+}
@@ -430,6 +430,7 @@
         state.perspective = perspective;
         updatePerspectiveButtons();
         renderBoard();
+        savePerspective();
     }
     
     function setTimer(seconds) {
@@ -505,6 +506,12 @@
     // ==========================================================================
     
     function init() {
+        // Start from the perspective the server remembered
+        const board = document.getElementById('chess-board');
+        if (board && board.dataset.perspectiveDefault) {
+            state.perspective = board.dataset.perspectiveDefault;
+        }
+        
         // Bind mode buttons
         document.querySelectorAll('[data-mode]').forEach(btn => {
             btn.addEventListener('click', () => setMode(btn.dataset.mode));
@@ -573,6 +580,14 @@
         }).catch(err => console.error('Failed to save session:', err));
     }
 
+    function savePerspective() {
+        fetch('/api/training/perspective', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ perspective: state.perspective }),
+        }).catch(err => console.error('Failed to save perspective:', err));
+    }
+
     // Save when leaving page
     window.addEventListener('beforeunload', saveSession);
 
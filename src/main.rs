@@ -1,16 +1,66 @@
-use chess_analyzer::analyze_position;
-use chess_analyzer::engine::StockfishEngine;
-use chess_analyzer::parser::parse_pgn_file;
-use shakmaty::{fen::Fen, san::San, uci::Uci, Chess, Position};
+use chess_analyzer::{analyze_position, Database};
+use chess_analyzer::engine::{engine_args, engine_path, EngineError, PositionAnalysis, StockfishEngine};
+use chess_analyzer::parser::{parse_pgn_file, san_line_to_uci, PgnGame};
+use chess_analyzer::patterns::{forced_move_ratio, BatchReport, GameReport, PatternDetector};
+use shakmaty::{fen::Fen, CastlingMode, Chess, Color};
 use std::env;
+use std::io::{self, BufRead, Write};
 use std::process;
+use std::time::{Duration, Instant};
+
+/// Depth used by `eval-batch` when `--depth` is not given
+const DEFAULT_BATCH_DEPTH: u8 = 12;
+
+/// Search depth for the key positions of each game in `analyze`
+const ANALYZE_DEPTH: u8 = 12;
+
+/// Database `doctor` and `export-csv` use when no file is given; the web
+/// server's
+const DEFAULT_DB_PATH: &str = "chess_analyzer.db";
+
+/// Search depth for the forced-move ratio in `analyze`; every position is
+/// searched, so it is kept shallower
+const FORCED_MOVE_DEPTH: u8 = 10;
+
+/// How to launch the engine: `$STOCKFISH_PATH` plus `--engine-args`
+/// (or `$STOCKFISH_ARGS` when the flag is absent)
+struct EngineLaunch {
+    path: String,
+    args: Vec<String>,
+}
+
+impl EngineLaunch {
+    fn start(&self) -> Result<StockfishEngine, EngineError> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        StockfishEngine::new_with_args(&self.path, &args)
+    }
+
+    fn start_detector(&self) -> chess_analyzer::Result<PatternDetector> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        PatternDetector::with_engine_args(&self.path, &args)
+    }
+}
 
 fn main() {
-    println!("♟️  Chess Analyzer");
-    println!("==================");
-    println!();
+    let mut args: Vec<String> = env::args().collect();
 
-    let args: Vec<String> = env::args().collect();
+    let engine_launch = match take_engine_args(&mut args) {
+        Ok(extra) => EngineLaunch {
+            path: engine_path(),
+            args: extra.unwrap_or_else(engine_args),
+        },
+        Err(e) => {
+            eprintln!("❌ Error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    // eval-batch output is meant for other programs, so skip the banner
+    if args.get(1).map(String::as_str) != Some("eval-batch") {
+        println!("♟️  Chess Analyzer");
+        println!("==================");
+        println!();
+    }
 
     if args.len() < 2 {
         print_usage(&args[0]);
@@ -24,7 +74,7 @@ fn main() {
                 println!("Usage: {} analyze <pgn_file>", args[0]);
                 process::exit(1);
             }
-            analyze_games(&args[2]);
+            analyze_games(&args[2], &engine_launch);
         }
         "eval" => {
             if args.len() < 3 {
@@ -32,10 +82,53 @@ fn main() {
                 println!("Usage: {} eval \"<fen>\"", args[0]);
                 process::exit(1);
             }
-            eval_position(&args[2]);
+            eval_position(&args[2], &engine_launch);
+        }
+        "eval-batch" => {
+            let depth = match parse_depth_flag(&args[2..]) {
+                Ok(d) => d,
+                Err(e) => {
+                    eprintln!("❌ Error: {}", e);
+                    eprintln!("Usage: {} eval-batch [--depth <n>] < fens.txt", args[0]);
+                    process::exit(1);
+                }
+            };
+            eval_batch(depth, &engine_launch);
         }
         "test-engine" => {
-            test_engine();
+            test_engine(&engine_launch);
+        }
+        "doctor" => {
+            let options = match parse_doctor_args(&args[2..]) {
+                Ok(options) => options,
+                Err(e) => {
+                    println!("❌ Error: {}", e);
+                    println!("Usage: {} doctor [db_file] [--fix] [--prune <cp>] [--dedupe]", args[0]);
+                    process::exit(1);
+                }
+            };
+            doctor(&options);
+        }
+        "export-csv" => {
+            let path = args.get(2).map_or(DEFAULT_DB_PATH, String::as_str);
+            export_csv(path);
+        }
+        "scout" => {
+            let usage = || println!("Usage: {} scout <opponent> [db_file] [--user <name>]", args[0]);
+            if args.len() < 3 {
+                println!("❌ Error: Please provide an opponent's username");
+                usage();
+                process::exit(1);
+            }
+            let (path, user) = match parse_scout_args(&args[3..]) {
+                Ok(rest) => rest,
+                Err(e) => {
+                    println!("❌ Error: {}", e);
+                    usage();
+                    process::exit(1);
+                }
+            };
+            scout(&args[2], path.unwrap_or(DEFAULT_DB_PATH), user);
         }
         _ => {
             print_usage(&args[0]);
@@ -48,20 +141,180 @@ fn print_usage(program: &str) {
     println!("Usage: {} <command> [arguments]", program);
     println!();
     println!("Commands:");
-    println!("  analyze <pgn_file>   Analyze games from a PGN file");
+    println!("  analyze <pgn_file>   Analyze games from a PGN file, searching every");
+    println!("                       move of both sides for patterns (about three");
+    println!("                       times as long as the position summaries alone)");
     println!("  eval \"<fen>\"         Evaluate a position (FEN string)");
+    println!("  eval-batch [--depth <n>]");
+    println!("                       Evaluate one FEN per line from stdin, printing");
+    println!("                       fen<TAB>eval<TAB>bestmove (default depth {})", DEFAULT_BATCH_DEPTH);
     println!("  test-engine          Test Stockfish connection");
+    println!("  doctor [db_file] [--fix] [--prune <cp>] [--dedupe]");
+    println!("                       Check a database (default {}) for games without", DEFAULT_DB_PATH);
+    println!("                       moves, orphaned patterns and suspect results;");
+    println!("                       --fix deletes the orphaned patterns, --prune those");
+    println!("                       losing less than <cp> centipawns, and --dedupe");
+    println!("                       all but the worst of patterns with the same");
+    println!("                       position and best move");
+    println!("  export-csv [db_file] Write the detected patterns to stdout as CSV");
+    println!("  scout <opponent> [db_file] [--user <name>]");
+    println!("                       Summarize an opponent's openings, results and");
+    println!("                       mistakes in their games against you (default user:");
+    println!("                       the last account synced)");
     println!();
     println!("Examples:");
     println!("  {} analyze games.pgn", program);
     println!("  {} eval \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"", program);
+    println!("  {} eval-batch --depth 16 < positions.txt > evals.tsv", program);
+    println!();
+    println!("Set STOCKFISH_PATH to use an engine binary that is not on PATH.");
+    println!("Pass --engine-args \"<args>\" (or set STOCKFISH_ARGS) to give it command-line arguments.");
+}
+
+/// Removes `--engine-args "<args>"` from anywhere in `args` and splits its value
+fn take_engine_args(args: &mut Vec<String>) -> Result<Option<Vec<String>>, String> {
+    let index = match args.iter().position(|a| a == "--engine-args") {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    if index + 1 >= args.len() {
+        return Err("--engine-args needs a value".to_string());
+    }
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(value.split_whitespace().map(String::from).collect()))
+}
+
+/// The optional database path and `--user` value after `scout <opponent>`
+fn parse_scout_args(args: &[String]) -> Result<(Option<&str>, Option<&str>), String> {
+    let (mut path, mut user) = (None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--user" => user = Some(iter.next().ok_or("--user needs a value")?.as_str()),
+            other if other.starts_with("--") => return Err(format!("unknown argument '{}'", other)),
+            other if path.is_none() => path = Some(other),
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    Ok((path, user))
+}
+
+/// What `doctor` was asked to check and clean up
+struct DoctorArgs<'a> {
+    path: &'a str,
+    fix: bool,
+    prune: Option<i32>,
+    dedupe: bool,
+}
+
+fn parse_doctor_args(args: &[String]) -> Result<DoctorArgs<'_>, String> {
+    let mut options = DoctorArgs { path: DEFAULT_DB_PATH, fix: false, prune: None, dedupe: false };
+    let mut path_given = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--fix" => options.fix = true,
+            "--dedupe" => options.dedupe = true,
+            "--prune" => {
+                let value = iter.next().ok_or("--prune needs a value")?;
+                options.prune = match value.parse() {
+                    Ok(cp) if cp > 0 => Some(cp),
+                    _ => return Err(format!("invalid centipawn threshold '{}'", value)),
+                };
+            }
+            other if other.starts_with("--") => return Err(format!("unknown argument '{}'", other)),
+            other if !path_given => {
+                options.path = other;
+                path_given = true;
+            }
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_depth_flag(args: &[String]) -> Result<u8, String> {
+    let mut depth = DEFAULT_BATCH_DEPTH;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--depth" => {
+                let value = iter.next().ok_or("--depth needs a value")?;
+                depth = match value.parse() {
+                    Ok(d) if d > 0 => d,
+                    _ => return Err(format!("invalid depth '{}'", value)),
+                };
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+    Ok(depth)
+}
+
+/// Evaluates FENs from stdin with a single engine instance.
+///
+/// Lines that aren't a legal position are echoed back with `ERROR` in the
+/// eval column and `-` as the best move, and the reason goes to stderr.
+fn eval_batch(depth: u8, engine_launch: &EngineLaunch) {
+    let mut engine = match engine_launch.start() {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("❌ Failed to start Stockfish: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for (line_no, line) in stdin.lock().lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("❌ Failed to read stdin: {}", e);
+                break;
+            }
+        };
+        let fen = line.trim();
+        if fen.is_empty() {
+            continue;
+        }
+
+        let result = validate_fen(fen)
+            .and_then(|_| engine.set_position(Some(fen), None).map_err(|e| e.to_string()))
+            .and_then(|_| engine.analyze(depth).map_err(|e| e.to_string()));
+
+        let written = match result {
+            Ok(analysis) => writeln!(out, "{}\t{}\t{}", fen, analysis.evaluation, analysis.best_move),
+            Err(e) => {
+                eprintln!("line {}: {}", line_no + 1, e);
+                writeln!(out, "{}\tERROR\t-", fen)
+            }
+        };
+
+        // Stop quietly if the reader went away (e.g. piped into head)
+        if written.and_then(|_| out.flush()).is_err() {
+            break;
+        }
+    }
 }
 
-fn test_engine() {
+/// Checks that a FEN describes a legal position before it reaches the engine
+fn validate_fen(fen: &str) -> Result<(), String> {
+    let parsed: Fen = fen.parse().map_err(|e| format!("invalid FEN: {}", e))?;
+    parsed
+        .into_position::<Chess>(CastlingMode::Standard)
+        .map(|_| ())
+        .map_err(|e| format!("invalid position: {}", e))
+}
+
+fn test_engine(engine_launch: &EngineLaunch) {
     println!("🔧 Testing Stockfish connection...");
     println!();
 
-    match StockfishEngine::new("stockfish") {
+    match engine_launch.start() {
         Ok(mut engine) => {
             println!("✅ Stockfish started successfully!");
             println!();
@@ -114,7 +367,145 @@ fn test_engine() {
     }
 }
 
-fn eval_position(fen: &str) {
+/// Opens an existing database, exiting with an error rather than letting
+/// `Database::open` create an empty one at a mistyped path
+fn open_existing_db(path: &str) -> Database {
+    if !std::path::Path::new(path).is_file() {
+        eprintln!("❌ Error: {} does not exist", path);
+        process::exit(1);
+    }
+    match Database::open(path) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("❌ Error: could not open {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn doctor(options: &DoctorArgs) {
+    println!("🩺 Checking {}...", options.path);
+    println!();
+
+    let db = open_existing_db(options.path);
+    let report = match db.check_integrity() {
+        Ok(r) => r,
+        Err(e) => {
+            println!("❌ Error: integrity check failed: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let ids = |ids: &[i64]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ");
+    if !report.games_missing_moves.is_empty() {
+        println!("⚠️  {} game(s) have no moves and can't be analyzed: {}",
+            report.games_missing_moves.len(), ids(&report.games_missing_moves));
+    }
+    if !report.orphan_patterns.is_empty() {
+        println!("⚠️  {} pattern(s) belong to games that no longer exist: {}",
+            report.orphan_patterns.len(), ids(&report.orphan_patterns));
+    }
+    if !report.suspect_results.is_empty() {
+        println!("⚠️  {} game(s) have a result the final position contradicts: {}",
+            report.suspect_results.len(), ids(&report.suspect_results));
+    }
+
+    if report.is_clean() {
+        println!("✅ No problems found");
+    } else if options.fix {
+        match db.delete_orphan_patterns() {
+            Ok(deleted) => println!("✅ Deleted {} orphaned pattern(s)", deleted),
+            Err(e) => {
+                println!("❌ Error: could not delete orphaned patterns: {}", e);
+                process::exit(1);
+            }
+        }
+    } else if !report.orphan_patterns.is_empty() {
+        println!();
+        println!("Run with --fix to delete the orphaned patterns.");
+    }
+
+    if let Some(min_cp_loss) = options.prune {
+        match db.prune_low_value_patterns(min_cp_loss) {
+            Ok(deleted) => println!("✅ Pruned {} pattern(s) losing less than {} cp", deleted, min_cp_loss),
+            Err(e) => {
+                println!("❌ Error: could not prune patterns: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    if options.dedupe {
+        match db.dedupe_patterns() {
+            Ok(deleted) => println!("✅ Removed {} duplicate pattern(s)", deleted),
+            Err(e) => {
+                println!("❌ Error: could not remove duplicate patterns: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn export_csv(path: &str) {
+    let db = open_existing_db(path);
+    let stdout = io::stdout();
+    if let Err(e) = db.export_patterns_csv(stdout.lock()) {
+        eprintln!("❌ Error: export failed: {}", e);
+        process::exit(1);
+    }
+}
+
+fn scout(opponent: &str, path: &str, user: Option<&str>) {
+    let db = open_existing_db(path);
+    let username = match user.map(String::from).map_or_else(|| db.last_synced_username(), |u| Ok(Some(u))) {
+        Ok(Some(u)) => u,
+        Ok(None) => {
+            println!("❌ Error: no synced account in {}; pass --user <name>", path);
+            process::exit(1);
+        }
+        Err(e) => {
+            println!("❌ Error: {}", e);
+            process::exit(1);
+        }
+    };
+    let report = match db.scout_report(&username, opponent) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("❌ Error: {}", e);
+            process::exit(1);
+        }
+    };
+
+    println!("🔍 {} against {}", report.opponent, username);
+    println!();
+    if report.games == 0 {
+        println!("No games between {} and {} in {}", username, opponent, path);
+        return;
+    }
+    println!("   Games: {} ({}W / {}L / {}D for {})", report.games, report.wins, report.losses, report.draws, report.opponent);
+
+    for (label, openings) in [("as White", &report.openings_as_white), ("as Black", &report.openings_as_black)] {
+        if openings.is_empty() {
+            continue;
+        }
+        println!();
+        println!("   Openings {}:", label);
+        for opening in openings.iter().take(5) {
+            println!("     {} {} ({} game(s))", opening.eco, opening.name.as_deref().unwrap_or(""), opening.games);
+        }
+    }
+
+    println!();
+    if report.mistakes.is_empty() {
+        println!("   No analyzed mistakes yet; analyze these games with the opponent's side included");
+    } else {
+        println!("   Common mistakes:");
+        for (pattern, count) in report.mistakes.iter().take(5) {
+            println!("     {}: {}", pattern, count);
+        }
+    }
+}
+
+fn eval_position(fen: &str, engine_launch: &EngineLaunch) {
     println!("📊 Evaluating position...");
     println!("   FEN: {}", fen);
     println!();
@@ -126,7 +517,7 @@ fn eval_position(fen: &str) {
         process::exit(1);
     }
 
-    match StockfishEngine::new("stockfish") {
+    match engine_launch.start() {
         Ok(mut engine) => {
             engine.set_position(Some(fen), None).unwrap();
 
@@ -150,7 +541,7 @@ fn eval_position(fen: &str) {
     }
 }
 
-fn analyze_games(file_path: &str) {
+fn analyze_games(file_path: &str, engine_launch: &EngineLaunch) {
     println!("📂 Loading: {}", file_path);
     println!();
 
@@ -167,7 +558,7 @@ fn analyze_games(file_path: &str) {
     println!();
 
     // Start engine
-    let mut engine = match StockfishEngine::new("stockfish") {
+    let mut engine = match engine_launch.start() {
         Ok(e) => {
             println!("✅ Stockfish engine ready");
             println!();
@@ -190,8 +581,29 @@ fn analyze_games(file_path: &str) {
         }
     };
 
+    // Pattern detection runs on its own engine process
+    let mut detector = match engine_launch.start_detector() {
+        Ok(d) => {
+            println!("🔎 Searching every move of both sides for patterns too; this takes");
+            println!("   about three times as long as the position summaries alone");
+            println!();
+            Some(d)
+        }
+        Err(e) => {
+            println!("⚠️  Pattern detection not available: {}", e);
+            println!();
+            None
+        }
+    };
+    // White's and Black's report for each game, in that order
+    let mut reports: Vec<GameReport> = Vec::new();
+
+    let run_start = Instant::now();
+
     // Analyze each game
     for (index, game) in games.iter().enumerate() {
+        let game_start = Instant::now();
+
         println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
         println!("📋 Game {}: {}", index + 1, game.summary());
         println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
@@ -213,74 +625,104 @@ fn analyze_games(file_path: &str) {
 
         // Starting position
         engine.set_position(None, None).unwrap();
-        if let Ok(analysis) = engine.analyze(12) {
-            println!("      Start: {} ({})", analysis.evaluation, analysis.best_move);
+        if let Ok(analysis) = engine.analyze(ANALYZE_DEPTH) {
+            println!("      Start: {} ({}) {}", analysis.evaluation, analysis.best_move, search_stats(&analysis));
         }
 
         // Position after opening (move 10)
         if game.moves.len() >= 20 {
-            let opening_moves: Vec<String> = game.moves.iter().take(20).cloned().collect();
+            let opening_moves = san_line_to_uci(&game.moves[..20]);
             engine.set_position(None, Some(&opening_moves)).unwrap();
-            if let Ok(analysis) = engine.analyze(12) {
-                println!("      Move 10: {} (best: {})", analysis.evaluation, analysis.best_move);
+            if let Ok(analysis) = engine.analyze(ANALYZE_DEPTH) {
+                println!("      Move 10: {} (best: {}) {}", analysis.evaluation, analysis.best_move, search_stats(&analysis));
             }
         }
 
         // Final position
         if !game.moves.is_empty() {
-            let all_moves: Vec<String> = convert_san_to_uci(&game.moves);
+            let all_moves: Vec<String> = san_line_to_uci(&game.moves);
             if !all_moves.is_empty() {
                 engine.set_position(None, Some(&all_moves)).unwrap();
-                if let Ok(analysis) = engine.analyze(12) {
-                    println!("      Final: {}", analysis.evaluation);
+                if let Ok(analysis) = engine.analyze(ANALYZE_DEPTH) {
+                    println!("      Final: {} {}", analysis.evaluation, search_stats(&analysis));
                 }
             }
         }
 
+        // How much each side had to decide for itself
+        let white = forced_move_ratio(&mut engine, &game.moves, Color::White, FORCED_MOVE_DEPTH);
+        let black = forced_move_ratio(&mut engine, &game.moves, Color::Black, FORCED_MOVE_DEPTH);
+        if let (Ok(white), Ok(black)) = (white, black) {
+            println!("      Forced moves: White {:.0}%, Black {:.0}%", white * 100.0, black * 100.0);
+        }
+
+        if let Some(detector) = detector.as_mut() {
+            // By colour, so a game between two "?" players still gets a
+            // report for each side
+            for (side, color) in [("White", Color::White), ("Black", Color::Black)] {
+                match detector.analyze_game_report_as(&game.moves, color) {
+                    Ok(report) => {
+                        let acpl = report.acpl.map_or("-".to_string(), |a| format!("{:.0}", a));
+                        println!("      {} patterns: {} (ACPL {})", side, report.patterns.len(), acpl);
+                        reports.push(report);
+                    }
+                    Err(e) => {
+                        println!("      {} patterns: failed ({})", side, e);
+                        reports.push(GameReport::default());
+                    }
+                }
+            }
+        }
+
+        let done = index + 1;
+        let remaining = games.len() - done;
+        let average = run_start.elapsed() / done as u32;
+        print!("   ⏱  elapsed={} avg={}", format_secs(game_start.elapsed()), format_secs(average));
+        if remaining > 0 {
+            print!(" eta={} remaining={}", format_secs(average * remaining as u32), remaining);
+        }
+        println!();
         println!();
     }
 
-    println!("✅ Analysis complete!");
+    if !reports.is_empty() {
+        print_batch_report(&PatternDetector::batch_report(&reports), &games);
+    }
+    println!("✅ Analysis complete! {} game(s) in {}", games.len(), format_secs(run_start.elapsed()));
 }
 
-/// src/main.rs - Replace the broken convert_san_to_uci function
-
-/// Converts SAN moves to UCI format by replaying through positions
-fn convert_san_to_uci(san_moves: &[String]) -> Vec<String> {
-    use shakmaty::{san::San, uci::Uci, Chess, Position};
-    
-    let mut position = Chess::default();
-    let mut uci_moves = Vec::new();
-    
-    for san_str in san_moves {
-        // Parse SAN string
-        let san: San = match san_str.parse() {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Warning: Could not parse SAN '{}': {}", san_str, e);
-                break;
-            }
-        };
-        
-        // Convert to move in current position
-        let mv = match san.to_move(&position) {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("Warning: Invalid move '{}': {}", san_str, e);
-                break;
-            }
-        };
-        
-        // Convert to UCI notation
-        let uci = Uci::from_standard(&mv);
-        uci_moves.push(uci.to_string());
-        
-        // Apply move
-        position = match position.play(mv) {
-            Ok(p) => p,
-            Err(_) => break,
-        };
+/// The batch takeaway; `batch` is over White's then Black's report for
+/// each of `games`
+fn print_batch_report(batch: &BatchReport, games: &[PgnGame]) {
+    let label = |index: usize| {
+        let side = if index.is_multiple_of(2) { "White" } else { "Black" };
+        format!("Game {} as {} ({})", index / 2 + 1, side, games[index / 2].summary())
+    };
+
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("📈 Summary");
+    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+    println!("   Blunders: {}  Mistakes: {}  Inaccuracies: {}", batch.blunders, batch.mistakes, batch.inaccuracies);
+    if let Some(acpl) = batch.average_acpl {
+        println!("   Average ACPL: {:.1}", acpl);
+    }
+    if let Some(pattern) = &batch.most_common_pattern {
+        println!("   Most common pattern: {}", pattern.display_name());
     }
-    
-    uci_moves
-}
\ No newline at end of file
+    if let Some(worst) = batch.worst_game {
+        println!("   Worst: {}", label(worst));
+    }
+    if let Some(best) = batch.best_game {
+        println!("   Best: {}", label(best));
+    }
+    println!();
+}
+
+/// Search statistics as `key=value` pairs, e.g. `[depth=12 nodes=48213 time=35ms]`
+fn search_stats(analysis: &PositionAnalysis) -> String {
+    format!("[depth={} nodes={} time={}ms]", analysis.depth, analysis.nodes, analysis.time_ms)
+}
+
+fn format_secs(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
@@ -0,0 +1,97 @@
+//! Stopping analysis through an `AbortFlag` against a mock engine that
+//! searches for real time and honours `stop`
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use chess_analyzer::engine::{AbortFlag, EngineError, StockfishEngine};
+use chess_analyzer::{Error, PatternDetector};
+use common::{engine_script, temp_dir};
+
+/// Each search reports a deeper iteration every 20ms up to depth 10 and
+/// then its bestmove, unless `stop` ends it first
+fn slow_engine(dir: &Path) -> (PathBuf, PathBuf) {
+    engine_script(dir, "slow-engine", r#"go*) (
+            i=1
+            while [ $i -le 10 ]; do
+                echo "info depth $i score cp 20 nodes 1000 time 5 pv e2e4 e7e5"
+                i=$((i + 1))
+                sleep 0.02
+            done
+            echo "bestmove e2e4"
+        ) &
+        search=$! ;;
+        stop) kill $search 2>/dev/null; wait $search 2>/dev/null; echo "bestmove e2e4" ;;"#)
+}
+
+fn searches(log: &Path) -> usize {
+    fs::read_to_string(log).unwrap_or_default().lines().filter(|l| l.starts_with("go")).count()
+}
+
+#[test]
+fn test_abort_halts_analysis_mid_batch() {
+    let dir = temp_dir("abort-batch");
+    let (engine, log) = slow_engine(&dir);
+    let mut detector = PatternDetector::with_engine(engine.to_str().unwrap()).unwrap();
+    let flag = AbortFlag::new();
+    detector.set_abort_flag(flag.clone());
+
+    // Abort once the second game's first search is under way
+    let watcher = {
+        let (flag, log) = (flag.clone(), log.clone());
+        thread::spawn(move || {
+            while searches(&log) < 3 {
+                thread::sleep(Duration::from_millis(5));
+            }
+            flag.abort();
+        })
+    };
+
+    let moves: Vec<String> = ["e4", "e5"].iter().map(|m| m.to_string()).collect();
+    let results: Vec<_> = (0..3)
+        .map(|_| detector.analyze_game_report(&moves, "alice", "alice"))
+        .collect();
+    watcher.join().unwrap();
+
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(Error::Aborted)));
+    assert!(matches!(results[2], Err(Error::Aborted)));
+    // The third game never reached the engine
+    assert_eq!(searches(&log), 3);
+    assert!(fs::read_to_string(&log).unwrap().lines().any(|l| l == "stop"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_engine_is_clean_after_abort() {
+    let dir = temp_dir("abort-engine");
+    let (engine, _) = slow_engine(&dir);
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+    let flag = AbortFlag::new();
+    stockfish.set_abort_flag(flag.clone());
+
+    let aborter = {
+        let flag = flag.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            flag.abort();
+        })
+    };
+    assert!(matches!(stockfish.analyze(10), Err(EngineError::Aborted)));
+    aborter.join().unwrap();
+
+    // Nothing from the stopped search leaks into the next one
+    flag.reset();
+    let analysis = stockfish.analyze(10).unwrap();
+    assert_eq!(analysis.depth, 10);
+    assert_eq!(analysis.best_move, "e2e4");
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,61 @@
+//! End-to-end test for `analyze` progress output
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::{mock_engine, temp_dir};
+
+const TWO_GAMES: &str = r#"[Event "Club night"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1-0
+
+[Event "Club night"]
+[White "Bob"]
+[Black "Alice"]
+[Result "0-1"]
+
+1. d4 d5 2. c4 e6 0-1
+"#;
+
+#[test]
+fn test_analyze_reports_search_stats_and_eta() {
+    let dir = temp_dir("analyze-eta");
+    let (engine, _) = mock_engine(&dir);
+    let pgn = dir.join("games.pgn");
+    fs::write(&pgn, TWO_GAMES).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chess-analyzer"))
+        .arg("analyze")
+        .arg(&pgn)
+        .env("STOCKFISH_PATH", &engine)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.contains("Start: +0.31 (e2e4) [depth=8 nodes=1000 time=5ms]"));
+    assert!(stdout.contains("Final: +0.31 [depth=8 nodes=1000 time=5ms]"));
+    // The mock reports a single line, so every move looks forced
+    assert!(stdout.contains("Forced moves: White 100%, Black 100%"));
+
+    let timings: Vec<&str> = stdout.lines().filter(|l| l.contains("elapsed=")).collect();
+    assert_eq!(timings.len(), 2);
+    assert!(timings[0].contains("eta=") && timings[0].ends_with("remaining=1"));
+    assert!(!timings[1].contains("eta="));
+    assert!(stdout.contains("2 game(s) in"));
+
+    // The mock's flat +0.31 makes most moves look like 62cp inaccuracies
+    assert_eq!(stdout.lines().filter(|l| l.contains(" patterns: 2 (ACPL ")).count(), 4);
+    assert!(stdout.contains("Blunders: 0  Mistakes: 0  Inaccuracies: 8"));
+    assert!(stdout.contains("Worst: Game 1 as Black (Alice vs Bob - 1-0)"));
+    assert!(stdout.contains("Best: Game 1 as White (Alice vs Bob - 1-0)"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
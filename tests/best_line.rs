@@ -0,0 +1,45 @@
+//! `StockfishEngine::best_line_san` against a scripted engine whose
+//! principal variation is always the Four Knights' opening moves
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use chess_analyzer::engine::{EngineError, Evaluation, StockfishEngine};
+use common::{scripted_engine, temp_dir};
+
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[test]
+fn test_line_in_san_with_evals() {
+    let dir = temp_dir("best-line");
+    let (engine, log) = scripted_engine(&dir, &[
+        "info depth 12 score cp 31 nodes 1000 time 5 pv e2e4 e7e5 g1f3 b8c6",
+        "bestmove e2e4 ponder e7e5",
+    ]);
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+
+    let line = stockfish.best_line_san(START, 12, 2).unwrap();
+    let san: Vec<&str> = line.iter().map(|ply| ply.san.as_str()).collect();
+    assert_eq!(san, ["e4", "e5", "Nf3"]);
+    assert_eq!(line[2].uci, "g1f3");
+    // The engine always says +31 for the side to move
+    let evals: Vec<Evaluation> = line.iter().map(|ply| ply.evaluation.clone()).collect();
+    assert_eq!(evals, [Evaluation::Centipawns(-31), Evaluation::Centipawns(31), Evaluation::Centipawns(-31)]);
+
+    // The line stops where the principal variation does
+    assert_eq!(stockfish.best_line_san(START, 12, 10).unwrap().len(), 4);
+
+    assert!(matches!(
+        stockfish.best_line_san("not a fen", 12, 2),
+        Err(EngineError::InvalidInput(_))
+    ));
+
+    drop(stockfish);
+    let searches = fs::read_to_string(&log).unwrap().lines().filter(|l| *l == "go depth 12").count();
+    assert_eq!(searches, 4 + 5);
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,43 @@
+//! `StockfishEngine::is_best_move_san` against the mock engine, whose best
+//! move is always e2e4
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use chess_analyzer::engine::{EngineError, StockfishEngine};
+use common::{mock_engine, temp_dir};
+
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[test]
+fn test_san_matches_uci_best_move() {
+    let dir = temp_dir("best-move-san");
+    let (engine, _) = mock_engine(&dir);
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+
+    assert!(stockfish.is_best_move_san(START, "e4", 8).unwrap());
+    assert!(stockfish.is_best_move_san(START, "e4!", 8).unwrap());
+    stockfish.set_position(Some(START), None).unwrap();
+    assert!(stockfish.is_best_move("e2e4", 8).unwrap());
+
+    assert!(!stockfish.is_best_move_san(START, "d4", 8).unwrap());
+    assert!(!stockfish.is_best_move_san(START, "Nf3", 8).unwrap());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_illegal_san_is_an_error() {
+    let dir = temp_dir("best-move-san-illegal");
+    let (engine, _) = mock_engine(&dir);
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+
+    assert!(matches!(stockfish.is_best_move_san(START, "Ke2", 8), Err(EngineError::InvalidInput(_))));
+    assert!(matches!(stockfish.is_best_move_san(START, "xyz", 8), Err(EngineError::InvalidInput(_))));
+    assert!(matches!(stockfish.is_best_move_san("not a fen", "e4", 8), Err(EngineError::InvalidInput(_))));
+
+    let _ = fs::remove_dir_all(&dir);
+}
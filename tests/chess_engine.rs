@@ -0,0 +1,107 @@
+//! `PatternDetector` driven through the `ChessEngine` trait by an
+//! in-process engine, with no engine process at all
+
+use std::sync::{Arc, Mutex};
+
+use chess_analyzer::engine::{best_line_san, ChessEngine, EngineError, Evaluation, PositionAnalysis};
+use chess_analyzer::PatternDetector;
+
+/// Scores every position 0 for the side to move, except the one after
+/// 2.Qh5, where Black is 400 up. Keeps the positions it was asked about.
+struct TableEngine {
+    moves: Vec<String>,
+    searched: Arc<Mutex<Vec<String>>>,
+}
+
+impl TableEngine {
+    fn new() -> (Self, Arc<Mutex<Vec<String>>>) {
+        let searched = Arc::new(Mutex::new(Vec::new()));
+        (Self { moves: Vec::new(), searched: searched.clone() }, searched)
+    }
+
+    fn line(&self, best_move: &str, cp: i32) -> PositionAnalysis {
+        PositionAnalysis {
+            best_move: best_move.to_string(),
+            evaluation: Evaluation::Centipawns(cp),
+            depth: 10,
+            pv: vec![best_move.to_string()],
+            time_ms: 1,
+            nodes: 100,
+            ponder: None,
+        }
+    }
+}
+
+impl ChessEngine for TableEngine {
+    fn set_position(&mut self, fen: Option<&str>, moves: Option<&[String]>) -> Result<(), EngineError> {
+        self.moves = fen.into_iter().map(String::from).chain(moves.unwrap_or_default().iter().cloned()).collect();
+        Ok(())
+    }
+
+    fn analyze(&mut self, _depth: u8) -> Result<PositionAnalysis, EngineError> {
+        let history = self.moves.join(" ");
+        self.searched.lock().unwrap().push(history.clone());
+        let cp = if history == "e2e4 e7e5 d1h5" { 400 } else { 0 };
+        Ok(self.line("g1f3", cp))
+    }
+
+    fn analyze_multipv(&mut self, _depth: u8, lines: u8) -> Result<Vec<PositionAnalysis>, EngineError> {
+        // One good move, then a cliff
+        Ok((0..lines).map(|i| self.line("g1f3", if i == 0 { 0 } else { -500 })).collect())
+    }
+}
+
+fn moves(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn test_detector_runs_on_a_custom_engine() {
+    let (engine, searched) = TableEngine::new();
+    let mut detector = PatternDetector::with_chess_engine(engine);
+
+    let report = detector.analyze_game_report(&moves("e4 e5 Qh5 Nc6 Bc4"), "alice", "alice").unwrap();
+    assert_eq!(report.patterns.len(), 1);
+    assert_eq!(report.patterns[0].player_move, "Qh5");
+    assert_eq!(report.patterns[0].cp_loss, 400);
+    assert!(searched.lock().unwrap().iter().any(|h| h == "e2e4 e7e5 d1h5"));
+
+    // MultiPV goes through the trait too: every move was the only one
+    let ratio = detector.forced_move_ratio(&moves("e4 e5 Nf3"), "alice", "alice", 10).unwrap();
+    assert_eq!(ratio, 1.0);
+
+    // The engine has no process to renice
+    assert!(detector.set_engine_priority(10).is_err());
+}
+
+#[test]
+fn test_best_line_through_the_trait() {
+    let (mut engine, searched) = TableEngine::new();
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    // The table always wants Nf3, so the line stops once it's illegal
+    let line = best_line_san(&mut engine, start, 10, 3).unwrap();
+    let san: Vec<&str> = line.iter().map(|ply| ply.san.as_str()).collect();
+    assert_eq!(san, ["Nf3"]);
+    assert!(!searched.lock().unwrap().is_empty());
+
+    assert!(matches!(best_line_san(&mut engine, "not a fen", 10, 2), Err(EngineError::InvalidInput(_))));
+}
+
+#[test]
+fn test_stored_uci_goes_to_the_engine() {
+    let (engine, searched) = TableEngine::new();
+    let mut detector = PatternDetector::with_chess_engine(engine);
+    let uci = moves("e2e4 e7e5 d1h5 b8c6 f1c4");
+
+    // The SAN is only shown, so a garbled move still gets judged
+    let report = detector.analyze_stored_game_report(&moves("e4 e5 Qh5?? Nc6 Bc4"), &uci, "alice", "alice").unwrap();
+    assert_eq!(report.patterns.len(), 1);
+    assert_eq!(report.patterns[0].player_move, "Qh5??");
+    assert!(searched.lock().unwrap().iter().any(|h| h == "e2e4 e7e5 d1h5"));
+
+    // A line that doesn't match the SAN is ignored
+    let report = detector.analyze_stored_game_report(&moves("e4 e5 Qh5 Nc6 Bc4"), &uci[..3], "alice", "alice").unwrap();
+    assert_eq!(report.patterns.len(), 1);
+    assert_eq!(report.patterns[0].player_move, "Qh5");
+}
@@ -0,0 +1,57 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// A fresh scratch directory for one test
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a shell script that speaks just enough UCI for `StockfishEngine`
+/// and logs every command it receives, after an `args ...` line with its
+/// command-line arguments.
+pub fn mock_engine(dir: &Path) -> (PathBuf, PathBuf) {
+    scripted_engine(dir, &[
+        "info depth 8 score cp 31 nodes 1000 time 5 pv e2e4 e7e5",
+        "bestmove e2e4 ponder e7e5",
+    ])
+}
+
+/// Like `mock_engine`, but answers every `go` with `search_output`
+pub fn scripted_engine(dir: &Path, search_output: &[&str]) -> (PathBuf, PathBuf) {
+    let search: Vec<String> = search_output.iter().map(|line| format!("echo \"{}\"", line)).collect();
+    engine_script(dir, "mock-engine", &format!("go*) {} ;;", search.join("; ")))
+}
+
+/// Writes `<name>.sh`, a UCI engine answering `uci` and `isready` and
+/// otherwise running the `case` arms in `case_body` on each command. It
+/// logs every command, after an `args ...` line, to the returned log.
+pub fn engine_script(dir: &Path, name: &str, case_body: &str) -> (PathBuf, PathBuf) {
+    let script = dir.join(format!("{}.sh", name));
+    let log = dir.join("commands.log");
+    let body = format!(
+        r#"#!/bin/sh
+echo "args $*" >> "{log}"
+while read -r cmd; do
+    echo "$cmd" >> "{log}"
+    case "$cmd" in
+        uci) echo "id name MockFish"; echo "uciok" ;;
+        isready) echo "readyok" ;;
+        {case_body}
+        quit) exit 0 ;;
+    esac
+done
+"#,
+        log = log.display(),
+    );
+    fs::write(&script, body).unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    (script, log)
+}
@@ -0,0 +1,77 @@
+//! `doctor` against a database with a moveless game and a suspect result
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use chess_analyzer::parser::pgn::parse_pgn_string;
+use chess_analyzer::Database;
+use common::temp_dir;
+
+const GAMES: &str = r#"[Event "Club night"]
+[White "Alice"]
+[Black "Bob"]
+[Result "*"]
+
+*
+
+[Event "Club night"]
+[White "Carol"]
+[Black "Dave"]
+[Result "1/2-1/2"]
+
+1. f3 e5 2. g4 Qh4# 1/2-1/2
+"#;
+
+fn doctor(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_chess-analyzer"))
+        .arg("doctor")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn test_doctor_reports_each_problem() {
+    let dir = temp_dir("doctor");
+    let path = dir.join("games.db");
+    let path = path.to_str().unwrap();
+
+    drop(Database::open(path).unwrap());
+    assert!(doctor(&[path]).contains("No problems found"));
+
+    let db = Database::open(path).unwrap();
+    assert_eq!(db.insert_pgn_games(&parse_pgn_string(GAMES).unwrap()).unwrap(), 2);
+    drop(db);
+
+    let stdout = doctor(&[path, "--fix"]);
+    assert!(stdout.contains("1 game(s) have no moves and can't be analyzed: 1"));
+    assert!(stdout.contains("1 game(s) have a result the final position contradicts: 2"));
+    assert!(!stdout.contains("belong to games"));
+    assert!(stdout.contains("Deleted 0 orphaned pattern(s)"));
+
+    // The threshold after --prune isn't taken for the database file
+    let stdout = doctor(&["--prune", "100", path, "--dedupe"]);
+    assert!(stdout.contains("Pruned 0 pattern(s) losing less than 100 cp"));
+    assert!(stdout.contains("Removed 0 duplicate pattern(s)"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_doctor_missing_file() {
+    let dir = temp_dir("doctor-missing");
+    let output = Command::new(env!("CARGO_BIN_EXE_chess-analyzer"))
+        .arg("doctor")
+        .arg(dir.join("nope.db"))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    // Checking must never create the file it was pointed at
+    assert!(!dir.join("nope.db").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,56 @@
+//! Command-line arguments handed to the engine binary
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use chess_analyzer::engine::StockfishEngine;
+use common::{mock_engine, temp_dir};
+
+fn launch_args(log: &std::path::Path) -> String {
+    fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .next()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_new_with_args_passes_arguments() {
+    let dir = temp_dir("engine-args-lib");
+    let (engine, log) = mock_engine(&dir);
+
+    let mut stockfish = StockfishEngine::new_with_args(
+        engine.to_str().unwrap(),
+        &["--eval-file", "nn-custom.nnue"],
+    )
+    .unwrap();
+    stockfish.set_position(None, None).unwrap();
+    assert_eq!(stockfish.analyze(8).unwrap().best_move, "e2e4");
+    drop(stockfish);
+
+    assert_eq!(launch_args(&log), "args --eval-file nn-custom.nnue");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_engine_args_flag() {
+    let dir = temp_dir("engine-args-cli");
+    let (engine, log) = mock_engine(&dir);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chess-analyzer"))
+        .args(["--engine-args", "--threads 2 --eval-file nn.nnue", "test-engine"])
+        .env("STOCKFISH_PATH", &engine)
+        .env("STOCKFISH_ARGS", "--ignored")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("Stockfish started successfully"));
+
+    assert_eq!(launch_args(&log), "args --threads 2 --eval-file nn.nnue");
+    let _ = fs::remove_dir_all(&dir);
+}
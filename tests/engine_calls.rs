@@ -0,0 +1,39 @@
+//! Engine cost of analyzing one side of a game
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use chess_analyzer::PatternDetector;
+use common::{mock_engine, temp_dir};
+
+/// Ruy Lopez, Breyer Variation: 40 plies, none of them ending the game
+const BREYER: &str = "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7 Re1 b5 Bb3 d6 c3 O-O \
+    h3 Nb8 d4 Nbd7 Nbd2 Bb7 Bc2 Re8 Nf1 Bf8 Ng3 g6 a4 c5 d5 c4 Bg5 h6 Be3 Nc5 Qd2 h5 Bg5 Be7";
+
+fn searches(log: &std::path::Path) -> usize {
+    fs::read_to_string(log)
+        .unwrap()
+        .lines()
+        .filter(|cmd| cmd.starts_with("go"))
+        .count()
+}
+
+#[test]
+fn test_two_searches_per_player_move() {
+    let moves: Vec<String> = BREYER.split_whitespace().map(String::from).collect();
+    assert_eq!(moves.len(), 40);
+
+    let dir = temp_dir("engine-calls");
+    let (engine, log) = mock_engine(&dir);
+    let mut detector = PatternDetector::with_engine(engine.to_str().unwrap()).unwrap();
+
+    detector.analyze_game_report(&moves, "alice", "alice").unwrap();
+    assert_eq!(searches(&log), 40);
+
+    // Dropping the last ply takes away one of Black's moves, and none of White's
+    detector.analyze_game_report(&moves[..39], "bob", "alice").unwrap();
+    assert_eq!(searches(&log), 40 + 38);
+}
@@ -0,0 +1,49 @@
+//! End-to-end test for `eval-batch`, driven by a scripted stand-in engine
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use common::{mock_engine, temp_dir};
+
+#[test]
+fn test_eval_batch_pipes_three_fens() {
+    let dir = temp_dir("eval-batch");
+    let (engine, log) = mock_engine(&dir);
+
+    let input = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\n\
+                 not a fen\n\
+                 r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3\n";
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chess-analyzer"))
+        .args(["eval-batch", "--depth", "8"])
+        .env("STOCKFISH_PATH", &engine)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines, vec![
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\t+0.31\te2e4",
+        "not a fen\tERROR\t-",
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3\t+0.31\te2e4",
+    ]);
+    assert!(String::from_utf8(output.stderr).unwrap().contains("line 2"));
+
+    // One engine for the whole batch, and the bad line never reached it
+    let commands = fs::read_to_string(&log).unwrap();
+    assert_eq!(commands.lines().filter(|c| *c == "uci").count(), 1);
+    assert_eq!(commands.lines().filter(|c| c.starts_with("go depth 8")).count(), 2);
+
+    let _ = fs::remove_dir_all(&dir);
+}
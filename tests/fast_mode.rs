@@ -0,0 +1,50 @@
+//! Two-pass `FastMode` analysis against a mock engine that only sees a
+//! loss in one position
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chess_analyzer::patterns::{DetectorConfig, FastMode};
+use chess_analyzer::PatternDetector;
+use common::{engine_script, temp_dir};
+
+/// Scores every position 0 for the side to move, except the one after
+/// 2.Qh5, where Black is 400 up
+fn judging_engine(dir: &Path) -> (PathBuf, PathBuf) {
+    engine_script(dir, "judging-engine", r#"*"moves e2e4 e7e5 d1h5") score=400 ;;
+        position*) score=0 ;;
+        go*) echo "info depth 8 score cp $score nodes 1000 time 5 pv e2e4"; echo "bestmove e2e4" ;;"#)
+}
+
+fn searches(log: &Path, depth: u8) -> usize {
+    let go = format!("go depth {}", depth);
+    fs::read_to_string(log).unwrap().lines().filter(|l| *l == go).count()
+}
+
+#[test]
+fn test_only_flagged_moves_are_searched_deeply() {
+    let dir = temp_dir("fast-mode");
+    let (engine, log) = judging_engine(&dir);
+    let mut detector = PatternDetector::with_engine(engine.to_str().unwrap()).unwrap();
+    let fast = FastMode { screen_depth: 6, deep_depth: 14, threshold_cp: 100 };
+    detector.set_config(DetectorConfig { fast_mode: Some(fast), ..DetectorConfig::default() });
+
+    let moves: Vec<String> = "e4 e5 Qh5 Nc6 Bc4".split_whitespace().map(String::from).collect();
+    let report = detector.analyze_game_report(&moves, "alice", "alice").unwrap();
+    drop(detector);
+
+    assert_eq!(report.patterns.len(), 1);
+    assert_eq!(report.patterns[0].player_move, "Qh5");
+    assert_eq!(report.patterns[0].cp_loss, 400);
+
+    // Three moves screened before and after; only Qh5 searched again
+    assert_eq!(searches(&log, 6), 6);
+    assert_eq!(searches(&log, 14), 2);
+    assert_eq!(searches(&log, 12), 0);
+
+    let _ = fs::remove_dir_all(&dir);
+}
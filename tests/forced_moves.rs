@@ -0,0 +1,45 @@
+//! `PatternDetector::forced_move_ratio` against a scripted MultiPV engine
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use chess_analyzer::PatternDetector;
+use common::{scripted_engine, temp_dir};
+
+/// Every position searched gets the same two lines, the second trailing
+/// the best by `cp_behind`
+fn ratio(name: &str, cp_behind: i32) -> (f32, Vec<String>) {
+    let dir = temp_dir(name);
+    let second = format!("info depth 10 multipv 2 score cp {} nodes 900 time 4 pv a2a3", 31 - cp_behind);
+    let (engine, log) = scripted_engine(&dir, &[
+        "info depth 10 multipv 1 score cp 31 nodes 900 time 4 pv e2e4 e7e5",
+        &second,
+        "bestmove e2e4 ponder e7e5",
+    ]);
+
+    let moves: Vec<String> = "e4 e5 Nf3 Nc6 Bb5".split_whitespace().map(String::from).collect();
+    let mut detector = PatternDetector::with_engine(engine.to_str().unwrap()).unwrap();
+    let ratio = detector.forced_move_ratio(&moves, "alice", "alice", 10).unwrap();
+    drop(detector);
+
+    let commands = fs::read_to_string(&log).unwrap().lines().map(String::from).collect();
+    let _ = fs::remove_dir_all(&dir);
+    (ratio, commands)
+}
+
+#[test]
+fn test_forced_move_ratio() {
+    let (forced, commands) = ratio("forced-moves-only", 400);
+    assert_eq!(forced, 1.0);
+    // One two-line search for each of White's three moves, and MultiPV
+    // is put back afterwards
+    assert_eq!(commands.iter().filter(|c| *c == "setoption name MultiPV value 2").count(), 3);
+    assert_eq!(commands.iter().filter(|c| c.starts_with("go depth 10")).count(), 3);
+    assert!(commands.iter().any(|c| c == "setoption name MultiPV value 1"));
+
+    let (free, _) = ratio("forced-moves-free", 20);
+    assert_eq!(free, 0.0);
+}
@@ -0,0 +1,55 @@
+//! Pondering against a scripted engine that only answers a `go ponder`
+//! once it is told how the opponent replied
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chess_analyzer::engine::{EngineError, Evaluation, StockfishEngine};
+use common::{engine_script, temp_dir};
+
+fn pondering_engine(dir: &Path) -> (PathBuf, PathBuf) {
+    engine_script(dir, "pondering-engine", r#""go ponder"*) echo "info depth 10 score cp 20 nodes 900 time 4 pv g1f3 b8c6" ;;
+        ponderhit) echo "info depth 12 score cp 25 nodes 1800 time 9 pv g1f3 b8c6"; echo "bestmove g1f3 ponder b8c6" ;;
+        stop) echo "bestmove g1f3" ;;
+        go*) echo "info depth 12 score cp 31 nodes 1000 time 5 pv e2e4 e7e5"; echo "bestmove e2e4 ponder e7e5" ;;"#)
+}
+
+#[test]
+fn test_ponderhit_and_miss() {
+    let dir = temp_dir("ponder");
+    let (engine, log) = pondering_engine(&dir);
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+
+    stockfish.set_position(None, None).unwrap();
+    let first = stockfish.analyze(12).unwrap();
+    assert_eq!((first.best_move.as_str(), first.ponder.as_deref()), ("e2e4", Some("e7e5")));
+
+    // We play e2e4 and think on the expected e7e5; the opponent plays it
+    stockfish.set_position(None, Some(&["e2e4".to_string()])).unwrap();
+    stockfish.go_ponder("e7e5", 12).unwrap();
+    assert!(matches!(stockfish.go_ponder("e7e5", 12), Err(EngineError::ProtocolError(_))));
+    let hit = stockfish.ponderhit().unwrap();
+    assert_eq!(hit.best_move, "g1f3");
+    assert_eq!(hit.evaluation, Evaluation::Centipawns(25));
+    assert_eq!(hit.ponder.as_deref(), Some("b8c6"));
+
+    // This time they play c7c5 instead; the ponder search is dropped
+    stockfish.set_position(None, Some(&["e2e4".to_string(), "e7e5".to_string(), "g1f3".to_string()])).unwrap();
+    stockfish.go_ponder("b8c6", 12).unwrap();
+    stockfish.stop_ponder().unwrap();
+    assert!(matches!(stockfish.ponderhit(), Err(EngineError::ProtocolError(_))));
+    assert_eq!(stockfish.analyze(12).unwrap().best_move, "e2e4");
+    drop(stockfish);
+
+    let commands = fs::read_to_string(&log).unwrap();
+    let commands: Vec<&str> = commands.lines().collect();
+    assert!(commands.contains(&"position startpos moves e2e4 e7e5"));
+    assert!(commands.contains(&"position startpos moves e2e4 e7e5 g1f3 b8c6"));
+    assert_eq!(commands.iter().filter(|c| **c == "go ponder depth 12").count(), 2);
+
+    let _ = fs::remove_dir_all(&dir);
+}
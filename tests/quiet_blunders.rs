@@ -0,0 +1,116 @@
+//! `tag_quiet_blunders` against an in-process engine with fixed MultiPV
+//! scores, telling a calm-position blunder from a time-scramble one
+
+use chess_analyzer::engine::{ChessEngine, EngineError, Evaluation, PositionAnalysis};
+use chess_analyzer::patterns::{tag_quiet_blunders, DetectedPattern, PatternType, Severity};
+
+/// Scores its MultiPV lines `scores`, best first, whatever the position
+struct FixedLines {
+    scores: Vec<i32>,
+    searches: usize,
+}
+
+impl FixedLines {
+    fn new(scores: &[i32]) -> Self {
+        Self { scores: scores.to_vec(), searches: 0 }
+    }
+}
+
+impl ChessEngine for FixedLines {
+    fn set_position(&mut self, _fen: Option<&str>, _moves: Option<&[String]>) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    fn analyze(&mut self, depth: u8) -> Result<PositionAnalysis, EngineError> {
+        Ok(self.analyze_multipv(depth, 1)?.remove(0))
+    }
+
+    fn analyze_multipv(&mut self, depth: u8, lines: u8) -> Result<Vec<PositionAnalysis>, EngineError> {
+        self.searches += 1;
+        Ok(self.scores.iter().take(lines as usize).map(|&cp| PositionAnalysis {
+            best_move: "g1f3".to_string(),
+            evaluation: Evaluation::Centipawns(cp),
+            depth,
+            pv: vec!["g1f3".to_string()],
+            time_ms: 1,
+            nodes: 100,
+            ponder: None,
+        }).collect())
+    }
+}
+
+/// White's 6.Bd3?? dropping the bishop in a level Italian
+fn blunder(fen_before: &str) -> DetectedPattern {
+    DetectedPattern {
+        move_number: 6,
+        ply: 10,
+        pattern_type: PatternType::HangingPiece,
+        severity: Severity::Blunder,
+        cp_loss: 320,
+        player_move: "Bd3".to_string(),
+        best_move: "O-O".to_string(),
+        fen_before: fen_before.to_string(),
+        fen_after: String::new(),
+        description: String::new(),
+        quiet: false,
+    }
+}
+
+const LEVEL: &str = "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQK2R w KQkq - 1 6";
+
+/// Centiseconds left after each of the first eleven plies of a 3+0 game
+fn clocks(white_left_after_ply_10: u32) -> Vec<u32> {
+    let mut clocks: Vec<u32> = (0..11).map(|ply| 18_000 - ply * 300).collect();
+    clocks[10] = white_left_after_ply_10;
+    clocks
+}
+
+#[test]
+fn test_quiet_blunder_versus_time_scramble() {
+    let mut engine = FixedLines::new(&[30, 20, 10, 0]);
+
+    // Two and a half minutes left: nothing but the calculation to blame
+    let mut patterns = vec![blunder(LEVEL)];
+    assert_eq!(tag_quiet_blunders(&mut engine, &mut patterns, Some(&clocks(15_000)), 10).unwrap(), 1);
+    assert!(patterns[0].quiet);
+    // The tactical label stays
+    assert_eq!(patterns[0].pattern_type, PatternType::HangingPiece);
+
+    // Four seconds left: the same move is a time-scramble blunder
+    let mut patterns = vec![blunder(LEVEL)];
+    let searches = engine.searches;
+    assert_eq!(tag_quiet_blunders(&mut engine, &mut patterns, Some(&clocks(400)), 10).unwrap(), 0);
+    assert!(!patterns[0].quiet);
+    assert_eq!(engine.searches, searches, "a time-trouble blunder needs no search");
+
+    // Without clocks only the position is judged
+    let mut patterns = vec![blunder(LEVEL)];
+    assert_eq!(tag_quiet_blunders(&mut engine, &mut patterns, None, 10).unwrap(), 1);
+
+    // Clocks that stop short of the move don't vouch for it
+    let mut patterns = vec![blunder(LEVEL)];
+    assert_eq!(tag_quiet_blunders(&mut engine, &mut patterns, Some(&[18_000, 18_000]), 10).unwrap(), 0);
+}
+
+#[test]
+fn test_sharp_lopsided_and_lesser_mistakes_stay_as_they_are() {
+    // One good move and the rest lose: a critical moment, not a calm one
+    let mut sharp = FixedLines::new(&[40, -350, -400, -500]);
+    let mut patterns = vec![blunder(LEVEL)];
+    assert_eq!(tag_quiet_blunders(&mut sharp, &mut patterns, None, 10).unwrap(), 0);
+
+    // Calm, but already well ahead by the engine
+    let mut ahead = FixedLines::new(&[400, 390, 380, 370]);
+    assert_eq!(tag_quiet_blunders(&mut ahead, &mut patterns, None, 10).unwrap(), 0);
+
+    // Calm and level by the engine, but White is a knight up on the board
+    let mut calm = FixedLines::new(&[30, 20, 10, 0]);
+    let knight_up = "r1bqk2r/pppp1ppp/5n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQK2R w KQkq - 0 6";
+    let mut patterns = vec![blunder(knight_up)];
+    assert_eq!(tag_quiet_blunders(&mut calm, &mut patterns, None, 10).unwrap(), 0);
+
+    // Only blunders are candidates
+    let mut patterns = vec![DetectedPattern { severity: Severity::Mistake, cp_loss: 150, ..blunder(LEVEL) }];
+    assert_eq!(tag_quiet_blunders(&mut calm, &mut patterns, None, 10).unwrap(), 0);
+    assert_eq!(calm.searches, 0);
+}
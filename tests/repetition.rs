@@ -0,0 +1,57 @@
+//! The detector sends the whole game to the engine, so a move that allows
+//! a threefold repetition isn't judged on its bare FEN
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chess_analyzer::engine::{Evaluation, StockfishEngine};
+use chess_analyzer::PatternDetector;
+use common::{engine_script, temp_dir};
+
+/// The position after White's second Ng1, a move from a third repetition
+const AFTER_SECOND_NG1: &str = "rnbqkb1r/pppppppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 7 4";
+
+/// Scores everything 0 for the side to move except `AFTER_SECOND_NG1`
+/// given as a bare FEN, which it takes at face value as good for Black;
+/// with the moves that led there it sees Ng8 repeating and scores it drawn
+fn repetition_aware_engine(dir: &Path) -> (PathBuf, PathBuf) {
+    let cases = format!(
+        r#""position fen {}") score=300 ;;
+        position*) score=0 ;;
+        go*) echo "info depth 12 score cp $score nodes 1000 time 5 pv g8f6"; echo "bestmove g8f6" ;;"#,
+        AFTER_SECOND_NG1
+    );
+    engine_script(dir, "repetition-engine", &cases)
+}
+
+#[test]
+fn test_repetition_is_not_a_blunder() {
+    let dir = temp_dir("repetition");
+    let (engine, log) = repetition_aware_engine(&dir);
+    let moves: Vec<String> = "Nf3 Nf6 Ng1 Ng8 Nf3 Nf6 Ng1".split_whitespace().map(String::from).collect();
+
+    // Analyzed from the bare FEN the move looks like it throws 300 cp away
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+    stockfish.set_position(Some(AFTER_SECOND_NG1), None).unwrap();
+    assert_eq!(stockfish.analyze(12).unwrap().evaluation, Evaluation::Centipawns(300));
+    let history: Vec<String> = "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1".split_whitespace().map(String::from).collect();
+    stockfish.set_position(None, Some(&history)).unwrap();
+    assert_eq!(stockfish.analyze(12).unwrap().evaluation, Evaluation::Centipawns(0));
+    drop(stockfish);
+
+    let mut detector = PatternDetector::with_engine(engine.to_str().unwrap()).unwrap();
+    let report = detector.analyze_game_report(&moves, "alice", "alice").unwrap();
+    drop(detector);
+    assert!(report.patterns.is_empty(), "{:?}", report.patterns);
+
+    let commands = fs::read_to_string(&log).unwrap();
+    assert!(commands.lines().any(|l| l == "position startpos moves g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1"));
+    // Only the direct check above sent a FEN
+    assert_eq!(commands.lines().filter(|l| l.starts_with("position fen")).count(), 1);
+
+    let _ = fs::remove_dir_all(&dir);
+}
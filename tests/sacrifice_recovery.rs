@@ -0,0 +1,89 @@
+//! `DetectorConfig::sacrifice_recovery` against an in-process engine that
+//! thinks 2.c4 drops a pawn until it looks further down its own line
+
+use chess_analyzer::engine::{ChessEngine, EngineError, Evaluation, PositionAnalysis};
+use chess_analyzer::patterns::{DetectorConfig, RecoveryWindow};
+use chess_analyzer::PatternDetector;
+
+/// Until White's bishop takes back on c4, Black is a pawn and a half up
+/// from 2.c4 on. After 2.c4 it gives a line in which White regains the
+/// pawn, or, with `keeps_pawn`, one in which Black holds on to it. Every
+/// other position is level with no line to speak of.
+struct GambitEngine {
+    moves: Vec<String>,
+    keeps_pawn: bool,
+}
+
+impl GambitEngine {
+    fn new(keeps_pawn: bool) -> Self {
+        Self { moves: Vec::new(), keeps_pawn }
+    }
+}
+
+const GAMBIT: &str = "d2d4 d7d5 c2c4";
+
+impl ChessEngine for GambitEngine {
+    fn set_position(&mut self, _fen: Option<&str>, moves: Option<&[String]>) -> Result<(), EngineError> {
+        self.moves = moves.unwrap_or_default().to_vec();
+        Ok(())
+    }
+
+    fn analyze(&mut self, depth: u8) -> Result<PositionAnalysis, EngineError> {
+        let history = self.moves.join(" ");
+        let pv = match history.as_str() {
+            GAMBIT if self.keeps_pawn => "d5c4 g1f3 b7b5 a2a4 c7c6",
+            GAMBIT => "d5c4 e2e3 g8f6 f1c4",
+            _ => "",
+        };
+        let pawn_down = history.starts_with(GAMBIT) && !history.contains("f1c4");
+        let white_to_move = self.moves.len().is_multiple_of(2);
+        let cp = match (pawn_down, white_to_move) {
+            (false, _) => 0,
+            (true, true) => -150,
+            (true, false) => 150,
+        };
+        Ok(PositionAnalysis {
+            best_move: "g1f3".to_string(),
+            evaluation: Evaluation::Centipawns(cp),
+            depth,
+            pv: pv.split_whitespace().map(String::from).collect(),
+            time_ms: 1,
+            nodes: 100,
+            ponder: None,
+        })
+    }
+
+    fn analyze_multipv(&mut self, depth: u8, _lines: u8) -> Result<Vec<PositionAnalysis>, EngineError> {
+        Ok(vec![self.analyze(depth)?])
+    }
+}
+
+fn moves() -> Vec<String> {
+    "d4 d5 c4 dxc4 e3".split_whitespace().map(String::from).collect()
+}
+
+fn c4_patterns(keeps_pawn: bool, recovery: Option<RecoveryWindow>) -> usize {
+    let mut detector = PatternDetector::with_chess_engine(GambitEngine::new(keeps_pawn));
+    detector.set_config(DetectorConfig { sacrifice_recovery: recovery, ..DetectorConfig::default() });
+    let report = detector.analyze_game_report(&moves(), "alice", "alice").unwrap();
+    report.patterns.iter().filter(|p| p.player_move == "c4").count()
+}
+
+#[test]
+fn test_gambit_that_wins_its_pawn_back() {
+    // Judged on the dip alone, the gambit is a mistake
+    assert_eq!(c4_patterns(false, None), 1);
+
+    // Four plies on, Bxc4 has the pawn back
+    let window = RecoveryWindow { plies: 4, threshold_cp: 50 };
+    assert_eq!(c4_patterns(false, Some(window)), 0);
+
+    // A window too short to reach the recapture doesn't see it
+    let short = RecoveryWindow { plies: 2, threshold_cp: 50 };
+    assert_eq!(c4_patterns(false, Some(short)), 1);
+}
+
+#[test]
+fn test_pawn_that_stays_lost() {
+    assert_eq!(c4_patterns(true, Some(RecoveryWindow::default())), 1);
+}
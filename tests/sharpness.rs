@@ -0,0 +1,59 @@
+//! `position_sharpness` against a scripted MultiPV engine
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use chess_analyzer::engine::StockfishEngine;
+use chess_analyzer::patterns::position_sharpness;
+use common::{scripted_engine, temp_dir};
+
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Sharpness of `fen` when the engine scores its four lines `scores`,
+/// and the commands it received
+fn sharpness(name: &str, fen: &str, scores: [&str; 4]) -> (f32, Vec<String>) {
+    let dir = temp_dir(name);
+    let lines: Vec<String> = scores
+        .iter()
+        .enumerate()
+        .map(|(i, score)| format!("info depth 10 multipv {} score {} nodes 900 time 4 pv e2e4", i + 1, score))
+        .collect();
+    let mut output: Vec<&str> = lines.iter().map(String::as_str).collect();
+    output.push("bestmove e2e4");
+    let (engine, log) = scripted_engine(&dir, &output);
+
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+    let sharpness = position_sharpness(&mut stockfish, fen, 10).unwrap();
+    drop(stockfish);
+
+    let commands = fs::read_to_string(&log).unwrap().lines().map(String::from).collect();
+    let _ = fs::remove_dir_all(&dir);
+    (sharpness, commands)
+}
+
+#[test]
+fn test_one_good_move_among_losing_ones() {
+    let (sharp, commands) = sharpness("sharpness-wide", START, ["cp 250", "cp -300", "cp -350", "mate -3"]);
+    assert!(sharp > 0.5, "{}", sharp);
+    assert!(commands.iter().any(|c| c == "setoption name MultiPV value 4"));
+    assert_eq!(commands.iter().filter(|c| c.starts_with("go depth 10")).count(), 1);
+
+    // Finding mate when everything else only keeps the win is hardly sharp
+    let (won, _) = sharpness("sharpness-won", START, ["mate 4", "cp 900", "cp 850", "cp 800"]);
+    assert!(won < 0.1, "{}", won);
+}
+
+#[test]
+fn test_quiet_and_forced_positions() {
+    let (quiet, _) = sharpness("sharpness-quiet", START, ["cp 30", "cp 25", "cp 20", "cp 10"]);
+    assert!(quiet < 0.05, "{}", quiet);
+
+    // Only Kxf7 is legal, so there is nothing to search
+    let fen = "rnbqkbnr/pppppQpp/8/8/8/8/PPPP1PPP/RNB1KBNR b KQkq - 0 1";
+    let (none, commands) = sharpness("sharpness-forced", fen, ["cp 0", "cp 0", "cp 0", "cp 0"]);
+    assert_eq!(none, 0.0);
+    assert!(!commands.iter().any(|c| c.starts_with("go")));
+}
@@ -0,0 +1,53 @@
+//! `StockfishEngine::analyze_until_stable` against a scripted info stream
+
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+use chess_analyzer::engine::{Evaluation, StockfishEngine};
+use common::{scripted_engine, temp_dir};
+
+const SEARCH: &[&str] = &[
+    "info depth 1 score cp 18 nodes 20 time 1 pv d2d4",
+    "info depth 2 score cp 35 nodes 80 time 1 pv e2e4 e7e5",
+    "info depth 3 seldepth 4 score cp 30 lowerbound nodes 150 time 2 pv e2e4",
+    "info depth 3 score cp 31 nodes 200 time 2 pv e2e4 e7e5",
+    "info depth 3 currmove g1f3 currmovenumber 2",
+    "info depth 4 multipv 2 score cp -5 nodes 400 time 3 pv a2a3",
+    "info depth 4 multipv 1 score cp 27 nodes 400 time 3 pv e2e4 c7c5",
+    "info depth 5 score cp 60 nodes 900 time 6 pv g1f3 d7d5",
+    "bestmove g1f3 ponder d7d5",
+];
+
+fn commands(log: &std::path::Path) -> Vec<String> {
+    fs::read_to_string(log).unwrap().lines().map(String::from).collect()
+}
+
+#[test]
+fn test_stops_once_best_move_settles() {
+    let dir = temp_dir("stable-depth");
+    let (engine, log) = scripted_engine(&dir, SEARCH);
+    let mut stockfish = StockfishEngine::new(engine.to_str().unwrap()).unwrap();
+
+    // e2e4 holds at depths 2-4 (35, 31, 27 are within the margin); the
+    // bound, currmove and second MultiPV lines don't count
+    stockfish.set_position(None, None).unwrap();
+    let analysis = stockfish.analyze_until_stable(20, 3).unwrap();
+    assert_eq!(analysis.depth, 4);
+    assert_eq!(analysis.best_move, "e2e4");
+    assert_eq!(analysis.evaluation, Evaluation::Centipawns(27));
+    assert_eq!(analysis.pv, ["e2e4", "c7c5"]);
+
+    // Never stable enough: the full search's own best move comes back
+    stockfish.set_position(None, None).unwrap();
+    let analysis = stockfish.analyze_until_stable(20, 5).unwrap();
+    assert_eq!(analysis.depth, 5);
+    assert_eq!(analysis.best_move, "g1f3");
+
+    // Only the settled search was cut short
+    drop(stockfish);
+    assert_eq!(commands(&log).iter().filter(|c| *c == "stop").count(), 1);
+    let _ = fs::remove_dir_all(&dir);
+}